@@ -1,3 +1,4 @@
+mount --size 2M tmpfs /tmp
 shell /ini/palettes/gruvbox-dark.sh
 read /ini/fonts/zap-light-8x16.psf => /dev/vga/font
 read /ini/banner.txt
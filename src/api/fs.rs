@@ -15,6 +15,13 @@ pub enum IO {
     Write,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
 pub trait FileIO {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()>;
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()>;
@@ -93,10 +100,50 @@ pub fn is_device(path: &str) -> bool {
     }
 }
 
+pub fn is_link(path: &str) -> bool {
+    read_link(path).is_ok()
+}
+
 pub fn delete(path: &str) -> Result<(), ()> {
     syscall::delete(path)
 }
 
+// Create a hard link to a file
+pub fn link(target: &str, path: &str) -> Result<(), ()> {
+    syscall::link(target, path)
+}
+
+// Create a symbolic link to a path
+pub fn symlink(target: &str, path: &str) -> Result<(), ()> {
+    let flags = OpenFlag::Create | OpenFlag::Link;
+    if let Some(handle) = syscall::open(path, flags) {
+        let res = syscall::write(handle, target.as_bytes());
+        syscall::close(handle);
+        if res == Some(target.len()) {
+            return Ok(());
+        }
+    }
+    Err(())
+}
+
+// Read the target of a symbolic link
+pub fn read_link(path: &str) -> Result<String, ()> {
+    let flags = OpenFlag::Link as u8;
+    if let Some(handle) = syscall::open(path, flags) {
+        let mut res = Vec::new();
+        let mut buf = [0; 256];
+        while let Some(bytes) = syscall::read(handle, &mut buf) {
+            if bytes == 0 {
+                break;
+            }
+            res.extend_from_slice(&buf[0..bytes]);
+        }
+        syscall::close(handle);
+        return Ok(String::from_utf8_lossy(&res).to_string());
+    }
+    Err(())
+}
+
 pub fn open_file(path: &str) -> Option<usize> {
     let flags = 0;
     syscall::open(path, flags)
@@ -138,34 +185,66 @@ pub fn create_device(path: &str, name: &str) -> Option<usize> {
     None
 }
 
+// The name of a network config device can be followed by the name of an
+// interface like in "net-ip-eth1"
+fn net_device(name: &str) -> Option<(&str, &str)> {
+    for dev in ["net-gw", "net-ip", "net-mac", "net-usage"] {
+        if let Some(iface) = name.strip_prefix(dev) {
+            if let Some(iface) = iface.strip_prefix('-') {
+                return Some((dev, iface));
+            }
+        }
+    }
+    None
+}
+
 fn device_buffer(name: &str) -> Result<Vec<u8>, ()> {
     let arg = if name.starts_with("ata-") {
         "ata"
+    } else if let Some((dev, _)) = net_device(name) {
+        dev
     } else {
         name
     };
     let dev = device_type(arg)?;
     let mut buf = dev.buf();
-    if name.starts_with("ata-") {
-        match name {
-            "ata-0-0" => {
+    if let Some((_, iface)) = net_device(name) {
+        let n = iface.len();
+        if n == 0 || n >= buf.len() {
+            return Err(());
+        }
+        buf[1..(n + 1)].copy_from_slice(iface.as_bytes());
+    }
+    if let Some(drive) = name.strip_prefix("ata-") {
+        // The name of a drive is "ata-<bus>-<dsk>" with an optional
+        // partition number like in "ata-0-0p1"
+        let (dsk, part) = match drive.split_once('p') {
+            Some((dsk, part)) => match part.parse() {
+                Ok(part) if part > 0 => (dsk, part),
+                _ => return Err(()),
+            },
+            None => (drive, 0),
+        };
+        match dsk {
+            "0-0" => {
                 buf[1] = 0;
                 buf[2] = 0
             }
-            "ata-0-1" => {
+            "0-1" => {
                 buf[1] = 0;
                 buf[2] = 1
             }
-            "ata-1-0" => {
+            "1-0" => {
                 buf[1] = 1;
                 buf[2] = 0
             }
-            "ata-1-1" => {
+            "1-1" => {
                 buf[1] = 1;
                 buf[2] = 1
             }
             _ => return Err(()),
         }
+        buf[3] = part;
     }
     Ok(buf)
 }
@@ -182,6 +261,7 @@ fn device_type(name: &str) -> Result<DeviceType, ()> {
         "clk-rtc" => Ok(DeviceType::RTC),
         "net-tcp" => Ok(DeviceType::TcpSocket),
         "net-udp" => Ok(DeviceType::UdpSocket),
+        "net-icmp" => Ok(DeviceType::IcmpSocket),
         "net-gw" => Ok(DeviceType::NetGw),
         "net-ip" => Ok(DeviceType::NetIp),
         "net-mac" => Ok(DeviceType::NetMac),
@@ -284,7 +364,7 @@ pub fn read_dir(path: &str) -> Result<Vec<FileInfo>, ()> {
                 let mut i = 0;
                 let n = buf.len();
                 while i < n {
-                    let j = i + 14 + buf[i + 13] as usize;
+                    let j = i + 30 + buf[i + 29] as usize;
                     if j > n {
                         break;
                     }
@@ -331,6 +411,20 @@ fn test_is_absolute_path() {
     assert_eq!(is_absolute_path("binary"), false);
 }
 
+#[test_case]
+fn test_device_buffer() {
+    let buf = device_buffer("net-ip").unwrap();
+    assert_eq!(buf[0], DeviceType::NetIp as u8);
+    assert!(buf[1..].iter().all(|&b| b == 0));
+
+    let buf = device_buffer("net-ip-eth1").unwrap();
+    assert_eq!(buf[0], DeviceType::NetIp as u8);
+    assert_eq!(&buf[1..6], b"eth1\0");
+
+    assert!(device_buffer("net-ip-").is_err());
+    assert!(device_buffer("net-tcp-eth1").is_err());
+}
+
 #[test_case]
 fn test_fs() {
     use crate::sys::fs::{dismount, format_mem, mount_mem};
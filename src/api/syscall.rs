@@ -1,13 +1,13 @@
-use crate::api::fs::IO;
+use crate::api::fs::{SeekFrom, IO};
 use crate::api::process::ExitCode;
 use crate::sys::fs::{FileInfo, FileType};
+use crate::sys::net::SocketOption;
 use crate::sys::syscall::number::*;
 use crate::syscall;
 
 use core::convert::TryFrom;
-use core::convert::TryInto;
 use core::sync::atomic::{fence, Ordering};
-use smoltcp::wire::{IpAddress, Ipv4Address};
+use smoltcp::wire::{IpAddress, Ipv6Address};
 
 pub fn exit(code: ExitCode) {
     unsafe { syscall!(EXIT, code as usize) };
@@ -28,6 +28,19 @@ pub fn delete(path: &str) -> Result<(), ()> {
     }
 }
 
+pub fn link(target: &str, path: &str) -> Result<(), ()> {
+    let target_ptr = target.as_ptr() as usize;
+    let target_len = target.len();
+    let path_ptr = path.as_ptr() as usize;
+    let path_len = path.len();
+    let res = unsafe { syscall!(LINK, target_ptr, target_len, path_ptr, path_len) } as isize;
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
 pub fn info(path: &str) -> Option<FileInfo> {
     let path_ptr = path.as_ptr() as usize;
     let path_len = path.len();
@@ -92,6 +105,20 @@ pub fn write(handle: usize, buf: &[u8]) -> Option<usize> {
     }
 }
 
+pub fn seek(handle: usize, pos: SeekFrom) -> Option<usize> {
+    let (offset, whence) = match pos {
+        SeekFrom::Start(i) => (i as usize, 0),
+        SeekFrom::Current(i) => (i as usize, 1),
+        SeekFrom::End(i) => (i as usize, 2),
+    };
+    let res = unsafe { syscall!(SEEK, handle, offset, whence) } as isize;
+    if res >= 0 {
+        Some(res as usize)
+    } else {
+        None
+    }
+}
+
 pub fn close(handle: usize) {
     unsafe { syscall!(CLOSE, handle) };
 }
@@ -125,12 +152,32 @@ pub fn poll(list: &[(usize, IO)]) -> Option<(usize, IO)> {
     }
 }
 
+// Wait until one of the file handles is ready, or until the timeout expires
+pub fn poll_timeout(list: &[(usize, IO)], timeout: f64) -> Option<(usize, IO)> {
+    let ptr = list.as_ptr() as usize;
+    let len = list.len();
+    let timeout = timeout.to_bits();
+    let idx = unsafe { syscall!(WAIT, ptr, len, timeout) } as isize;
+    if 0 <= idx && idx < len as isize {
+        Some(list[idx as usize])
+    } else {
+        None
+    }
+}
+
 pub fn connect(handle: usize, addr: IpAddress, port: u16) -> Result<(), ()> {
-    let IpAddress::Ipv4(addr) = addr; // Only IPv4 is supported
-    let tmp = addr.octets();
-    let buf = tmp.as_slice();
+    let mut buf = [0; 16];
+    let len = match addr {
+        IpAddress::Ipv4(addr) => {
+            buf[0..4].copy_from_slice(&addr.octets());
+            4
+        }
+        IpAddress::Ipv6(addr) => {
+            buf.copy_from_slice(&addr.octets());
+            16
+        }
+    };
     let ptr = buf.as_ptr() as usize;
-    let len = buf.len();
     let res = unsafe { syscall!(CONNECT, handle, ptr, len, port) } as isize;
     if res >= 0 {
         Ok(())
@@ -148,19 +195,30 @@ pub fn listen(handle: usize, port: u16) -> Result<(), ()> {
     }
 }
 
-pub fn accept(handle: usize) -> Result<IpAddress, ()> {
-    let addr = Ipv4Address::new(0, 0, 0, 0);
-    let tmp = addr.octets();
-    let buf = tmp.as_slice();
-    let ptr = buf.as_ptr() as usize;
+// Return the handle of the accepted connection with its remote address
+pub fn accept(handle: usize) -> Result<(usize, IpAddress), ()> {
+    let mut buf = [0u8; 16];
+    let ptr = buf.as_mut_ptr() as usize;
     let len = buf.len();
     let res = unsafe { syscall!(ACCEPT, handle, ptr, len) } as isize;
+    if res < 0 {
+        return Err(());
+    }
+    let addr = Ipv6Address::from_octets(buf);
+    match addr.to_ipv4_mapped() {
+        Some(addr) => Ok((res as usize, IpAddress::Ipv4(addr))),
+        None => Ok((res as usize, IpAddress::Ipv6(addr))),
+    }
+}
+
+// The timeouts are given in seconds with the bits of a `f64`
+pub fn option(handle: usize, option: SocketOption, value: usize) -> Result<(), ()> {
+    let res = unsafe { syscall!(OPTION, handle, option as usize, value) } as isize;
     if res >= 0 {
-        if let Ok(buf) = buf.try_into() {
-            return Ok(IpAddress::from(Ipv4Address::from_octets(buf)));
-        }
+        Ok(())
+    } else {
+        Err(())
     }
-    Err(())
 }
 
 pub fn alloc(size: usize, align: usize) -> *mut u8 {
@@ -218,3 +276,35 @@ fn test_file() {
 
     dismount();
 }
+
+#[test_case]
+fn test_seek() {
+    use crate::sys::fs::{dismount, format_mem, mount_mem, OpenFlag};
+    use alloc::vec;
+
+    mount_mem();
+    format_mem();
+
+    let flags = OpenFlag::Create as u8;
+    let handle = open("/test", flags).unwrap();
+    assert_eq!(write(handle, b"Hello, world!"), Some(13));
+
+    assert_eq!(seek(handle, SeekFrom::Start(7)), Some(7));
+    let mut buf = vec![0; 5];
+    assert_eq!(read(handle, &mut buf), Some(5));
+    assert_eq!(buf, b"world");
+
+    assert_eq!(seek(handle, SeekFrom::Current(-12)), Some(0));
+    assert_eq!(seek(handle, SeekFrom::End(-6)), Some(7));
+    assert_eq!(seek(handle, SeekFrom::End(0)), Some(13));
+
+    // Offsets outside of the file are rejected
+    assert_eq!(seek(handle, SeekFrom::End(1)), None);
+    assert_eq!(seek(handle, SeekFrom::Current(-14)), None);
+    assert_eq!(seek(handle, SeekFrom::Start(14)), None);
+
+    close(handle);
+    assert_eq!(seek(handle, SeekFrom::Start(0)), None);
+
+    dismount();
+}
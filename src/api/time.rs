@@ -2,10 +2,17 @@ use crate::api::clock;
 use crate::sys;
 
 use alloc::{
+    format,
     string::{String, ToString},
     vec::Vec,
 };
-use time::{Duration, OffsetDateTime, UtcOffset};
+use core::convert::TryFrom;
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
 
 pub fn now() -> OffsetDateTime {
     now_utc().to_offset(offset())
@@ -55,6 +62,45 @@ pub fn format_offset_time(time: OffsetDateTime) -> String {
     s
 }
 
+// Format a timestamp like "Sun, 06 Nov 1994 08:49:37 GMT" for HTTP headers
+pub fn format_http_date(ts: i64) -> String {
+    let time = from_timestamp_utc(ts);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[time.weekday().number_days_from_monday() as usize],
+        time.day(),
+        MONTHS[time.month() as usize - 1],
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+pub fn parse_http_date(s: &str) -> Option<i64> {
+    let fields: Vec<_> = s.split_whitespace().collect();
+    if fields.len() != 6 || fields[5] != "GMT" {
+        return None;
+    }
+    let day = fields[1].parse().ok()?;
+    let month = MONTHS.iter().position(|&m| m == fields[2])? as u8 + 1;
+    let year = fields[3].parse().ok()?;
+    let hms: Vec<u8> = fields[4]
+        .split(':')
+        .filter_map(|n| n.parse().ok())
+        .collect();
+    if hms.len() != 3 {
+        return None;
+    }
+    let date = Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()?;
+    let time = Time::from_hms(hms[0], hms[1], hms[2]).ok()?;
+    Some(
+        PrimitiveDateTime::new(date, time)
+            .assume_utc()
+            .unix_timestamp(),
+    )
+}
+
 pub fn format_primitive_time(time: time::PrimitiveDateTime) -> String {
     // time.format(...) is not available without std.
     // Manually format the time.
@@ -82,3 +128,11 @@ pub fn parse_primitive_date_time(s: &str) -> Vec<u8> {
     }
     v
 }
+
+#[test_case]
+fn test_http_date() {
+    let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+    assert_eq!(format_http_date(784111777), date);
+    assert_eq!(parse_http_date(date), Some(784111777));
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+}
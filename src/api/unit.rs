@@ -31,6 +31,28 @@ fn readable_size(bytes: usize, divisor: usize) -> String {
     format!("{:.2$}{}", s, units[i], p)
 }
 
+// Parse a size in bytes with an optional binary unit like "512K" or "4M"
+pub fn parse_size(s: &str) -> Option<usize> {
+    let units = ["K", "M", "G", "T"];
+    let s = s.trim_end_matches('B');
+    for (i, unit) in units.iter().enumerate() {
+        if let Some(n) = s.strip_suffix(unit) {
+            let n: usize = n.parse().ok()?;
+            return n.checked_mul(1 << (10 * (i + 1)));
+        }
+    }
+    s.parse().ok()
+}
+
+#[test_case]
+fn test_parse_size() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("512K"), Some(512 << 10));
+    assert_eq!(parse_size("4M"), Some(4 << 20));
+    assert_eq!(parse_size("4MB"), Some(4 << 20));
+    assert_eq!(parse_size("4X"), None);
+}
+
 #[test_case]
 fn test_binary_size() {
     let unit = SizeUnit::Binary;
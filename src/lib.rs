@@ -39,7 +39,10 @@ pub fn init(boot_info: &'static BootInfo) {
     sys::pci::init(); // Require MEM
     sys::net::init(); // Require PCI
     sys::ata::init();
-    sys::fs::init(); // Require ATA
+    sys::ahci::init(); // Require PCI
+    sys::virtio::blk::init(); // Require PCI
+    sys::fs::init(); // Require ATA, AHCI, and VIRTIO
+    sys::net::dhcp::init(); // Require NET and FS
 }
 
 #[allow(dead_code)]
@@ -26,7 +26,7 @@ fn main(boot_info: &'static BootInfo) -> ! {
 
 fn user_boot() {
     let script = "/ini/boot.sh";
-    if sys::fs::File::open(script).is_some() {
+    if sys::fs::open(script, 0).is_some() {
         usr::shell::main(&["shell", script]).ok();
     } else {
         if sys::fs::is_mounted() {
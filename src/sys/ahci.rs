@@ -0,0 +1,464 @@
+use crate::sys;
+use crate::sys::mem::PhysBuf;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::convert::TryInto;
+use core::fmt;
+use core::hint::spin_loop;
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+// Serial ATA Advanced Host Controller Interface (AHCI) 1.3.1
+// (2012)
+
+pub const BLOCK_SIZE: usize = 512;
+
+// Maximum number of blocks transferred by a command, limited by the size of
+// the buffer of a port
+const MAX_COUNT: usize = 128;
+
+// Generic host control registers
+const HBA_GHC: u64 = 0x04; // Global host control
+const HBA_PI: u64 = 0x0C; // Ports implemented
+
+// Port registers, at 0x100 + 0x80 * port
+const PX_CLB: u64 = 0x00; // Command list base address
+const PX_CLBU: u64 = 0x04; // Command list base address upper 32 bits
+const PX_FB: u64 = 0x08; // FIS base address
+const PX_FBU: u64 = 0x0C; // FIS base address upper 32 bits
+const PX_IS: u64 = 0x10; // Interrupt status
+const PX_CMD: u64 = 0x18; // Command and status
+const PX_TFD: u64 = 0x20; // Task file data
+const PX_SIG: u64 = 0x24; // Signature
+const PX_SSTS: u64 = 0x28; // SATA status
+const PX_SERR: u64 = 0x30; // SATA error
+const PX_CI: u64 = 0x38; // Command issue
+
+const SATA_SIGNATURE: u32 = 0x0000_0101;
+
+// Layout of the memory of a port, with the command list aligned to 1 KB, the
+// received FIS to 256 bytes, and the command table to 128 bytes.
+const CL_SIZE: usize = 1024;
+const FIS_SIZE: usize = 256;
+const CT_SIZE: usize = 256;
+const CT_PRDT: usize = 0x80; // Offset of the PRDT in the command table
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy)]
+enum Command {
+    ReadDmaExt = 0x25,
+    WriteDmaExt = 0x35,
+    FlushCacheExt = 0xEA,
+    Identify = 0xEC,
+}
+
+// Bits of the command and status register of a port
+const CMD_ST: usize = 0; // Start
+const CMD_FRE: usize = 4; // FIS receive enable
+const CMD_FR: usize = 14; // FIS receive running
+const CMD_CR: usize = 15; // Command list running
+
+// Task file error status in the interrupt status register of a port
+const IS_TFES: usize = 30;
+
+// Bits of the status in the task file data register of a port
+const TFD_ERR: usize = 0; // Error
+const TFD_DRQ: usize = 3; // Data request
+const TFD_BSY: usize = 7; // Busy
+
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pub port: u8,
+    model: String,
+    serial: String,
+    block_count: u64,
+}
+
+impl Disk {
+    pub fn open(port: u8) -> Option<Self> {
+        list().into_iter().find(|disk| disk.port == port)
+    }
+
+    pub const fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+}
+
+impl fmt::Display for Disk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (size, unit) = sys::ata::humanized_size(self.block_count);
+        write!(f, "{} {} ({} {})", self.model, self.serial, size, unit)
+    }
+}
+
+struct Port {
+    id: u8,
+    addr: u64, // Virtual address of the registers of the port
+    mem: PhysBuf,
+    buf: PhysBuf,
+    disk: Option<Disk>,
+}
+
+impl Port {
+    fn new(id: u8, abar: u64) -> Self {
+        let phys = PhysAddr::new(abar + 0x100 + 0x80 * id as u64);
+        let addr = sys::mem::phys_to_virt(phys).as_u64();
+        let mem = PhysBuf::new(CL_SIZE + CL_SIZE + FIS_SIZE + CT_SIZE);
+        let buf = PhysBuf::new(MAX_COUNT * BLOCK_SIZE);
+        Self {
+            id,
+            addr,
+            mem,
+            buf,
+            disk: None,
+        }
+    }
+
+    fn read_register(&self, offset: u64) -> u32 {
+        unsafe { core::ptr::read_volatile((self.addr + offset) as *const u32) }
+    }
+
+    fn write_register(&self, offset: u64, data: u32) {
+        unsafe { core::ptr::write_volatile((self.addr + offset) as *mut u32, data) }
+    }
+
+    // Offset of the command list in the memory of the port, followed by the
+    // received FIS and the command table
+    fn cl_offset(&self) -> usize {
+        let n = CL_SIZE as u64;
+        ((n - self.mem.addr() % n) % n) as usize
+    }
+
+    fn fis_offset(&self) -> usize {
+        self.cl_offset() + CL_SIZE
+    }
+
+    fn ct_offset(&self) -> usize {
+        self.fis_offset() + FIS_SIZE
+    }
+
+    // Check that a SATA drive is present and active on the port
+    fn is_sata(&self) -> bool {
+        let ssts = self.read_register(PX_SSTS);
+        let det = ssts.get_bits(0..4); // Device detection
+        let ipm = ssts.get_bits(8..12); // Interface power management
+        det == 3 && ipm == 1 && self.read_register(PX_SIG) == SATA_SIGNATURE
+    }
+
+    fn poll(&self, offset: u64, bit: usize, val: bool) -> Result<(), ()> {
+        let start = sys::clk::boot_time();
+        while self.read_register(offset).get_bit(bit) != val {
+            if sys::clk::boot_time() - start > 1.0 {
+                debug!(
+                    "AHCI {} hanged while polling register {:#X}",
+                    self.id, offset
+                );
+                return Err(());
+            }
+            spin_loop();
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ()> {
+        let mut cmd = self.read_register(PX_CMD);
+        cmd.set_bit(CMD_ST, false);
+        cmd.set_bit(CMD_FRE, false);
+        self.write_register(PX_CMD, cmd);
+        self.poll(PX_CMD, CMD_CR, false)?;
+        self.poll(PX_CMD, CMD_FR, false)
+    }
+
+    fn start(&self) -> Result<(), ()> {
+        self.poll(PX_CMD, CMD_CR, false)?;
+        let mut cmd = self.read_register(PX_CMD);
+        cmd.set_bit(CMD_FRE, true);
+        self.write_register(PX_CMD, cmd);
+        cmd.set_bit(CMD_ST, true);
+        self.write_register(PX_CMD, cmd);
+        Ok(())
+    }
+
+    // Give the command list and the received FIS area to the port
+    fn setup(&mut self, is_64bit: bool) -> Result<(), ()> {
+        self.stop()?;
+        self.mem.fill(0);
+        let cl = self.mem.addr() + self.cl_offset() as u64;
+        let fis = self.mem.addr() + self.fis_offset() as u64;
+        let end = self.buf.addr() + self.buf.len() as u64;
+        if !is_64bit && (cl + (CL_SIZE + FIS_SIZE + CT_SIZE) as u64 > 1 << 32 || end > 1 << 32) {
+            return Err(());
+        }
+        self.write_register(PX_CLB, cl as u32);
+        self.write_register(PX_CLBU, (cl >> 32) as u32);
+        self.write_register(PX_FB, fis as u32);
+        self.write_register(PX_FBU, (fis >> 32) as u32);
+        self.write_register(PX_SERR, u32::MAX); // Clear errors
+        self.write_register(PX_IS, u32::MAX); // Clear interrupts
+        self.start()
+    }
+
+    // Send a command with a transfer of `count` blocks between the buffer of
+    // the port and the drive, and wait for its completion.
+    fn command(&mut self, cmd: Command, block: u64, count: usize) -> Result<(), ()> {
+        debug_assert!(count <= MAX_COUNT);
+        let is_write = matches!(cmd, Command::WriteDmaExt);
+        let len = count * BLOCK_SIZE;
+
+        // Wait for the drive to be ready
+        let start = sys::clk::boot_time();
+        loop {
+            let tfd = self.read_register(PX_TFD);
+            if !tfd.get_bit(TFD_BSY) && !tfd.get_bit(TFD_DRQ) {
+                break;
+            }
+            if sys::clk::boot_time() - start > 1.0 {
+                debug!("AHCI {} hanged while waiting for the drive", self.id);
+                return Err(());
+            }
+            spin_loop();
+        }
+
+        // Header of the first slot of the command list, pointing to the
+        // command table with the buffer of the port in its PRDT.
+        let ct = self.mem.addr() + self.ct_offset() as u64;
+        let buf = self.buf.addr();
+        let i = self.cl_offset();
+        command_header(&mut self.mem[i..(i + 16)], ct, len > 0, is_write);
+        let i = self.ct_offset();
+        command_table(&mut self.mem[i..(i + CT_SIZE)], cmd, block, count, buf);
+
+        self.write_register(PX_IS, u32::MAX);
+        self.write_register(PX_CI, 1);
+        let start = sys::clk::boot_time();
+        while self.read_register(PX_CI).get_bit(0) {
+            if self.read_register(PX_IS).get_bit(IS_TFES) {
+                break;
+            }
+            if sys::clk::boot_time() - start > 1.0 {
+                debug!("AHCI {} hanged while waiting for {:?}", self.id, cmd);
+                return Err(());
+            }
+            spin_loop();
+        }
+        let tfd = self.read_register(PX_TFD);
+        if self.read_register(PX_IS).get_bit(IS_TFES) || tfd.get_bit(TFD_ERR) {
+            debug!("AHCI {} {:?} command errored", self.id, cmd);
+            debug!("AHCI task file data: 0b{:08b}", tfd.get_bits(0..8));
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn identify(&mut self) -> Result<Disk, ()> {
+        self.command(Command::Identify, 0, 1)?;
+        let res: Vec<u16> = self.buf[0..BLOCK_SIZE]
+            .chunks(2)
+            .map(|w| u16::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        let buf = res
+            .iter()
+            .map(|w| w.to_be_bytes())
+            .collect::<Vec<_>>()
+            .concat();
+        let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
+        let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
+        let block_count = if res[83].get_bit(10) {
+            // Words 100 to 103 for drives supporting LBA48
+            (0..4).fold(0, |n, i| n | ((res[100 + i] as u64) << (16 * i)))
+        } else {
+            // Words 60 to 61 for the others
+            (res[60] as u64) | ((res[61] as u64) << 16)
+        };
+        Ok(Disk {
+            port: self.id,
+            model,
+            serial,
+            block_count,
+        })
+    }
+
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        let count = buf.len() / BLOCK_SIZE;
+        self.command(Command::ReadDmaExt, block, count)?;
+        buf.copy_from_slice(&self.buf[0..buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, block: u64, buf: &[u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        let count = buf.len() / BLOCK_SIZE;
+        self.buf[0..buf.len()].copy_from_slice(buf);
+        self.command(Command::WriteDmaExt, block, count)
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        self.command(Command::FlushCacheExt, 0, 0)
+    }
+}
+
+// Fill the header of a slot of the command list
+fn command_header(buf: &mut [u8], ct: u64, has_prdt: bool, is_write: bool) {
+    let mut flags = 5u32; // Length of the command FIS in dwords
+    flags.set_bit(6, is_write);
+    flags.set_bits(16..32, if has_prdt { 1 } else { 0 }); // PRDT length
+    buf[0..4].copy_from_slice(&flags.to_le_bytes());
+    buf[4..8].copy_from_slice(&0u32.to_le_bytes()); // Byte count
+    buf[8..12].copy_from_slice(&(ct as u32).to_le_bytes());
+    buf[12..16].copy_from_slice(&((ct >> 32) as u32).to_le_bytes());
+}
+
+// Fill a command table with a register host to device FIS and a single entry
+// in the PRDT describing the transfer of `count` blocks from or to `data`.
+fn command_table(buf: &mut [u8], cmd: Command, block: u64, count: usize, data: u64) {
+    let lba = block.to_le_bytes();
+    let n = (count as u16).to_le_bytes();
+    buf[0..CT_PRDT].fill(0);
+    buf[0] = 0x27; // Type
+    buf[1] = 0x80; // Command
+    buf[2] = cmd as u8;
+    buf[4..7].copy_from_slice(&lba[0..3]);
+    buf[7] = 1 << 6; // LBA mode
+    buf[8..11].copy_from_slice(&lba[3..6]);
+    buf[12..14].copy_from_slice(&n);
+
+    let len = (count * BLOCK_SIZE) as u32;
+    let dbc = len.saturating_sub(1); // Byte count minus one
+    let prdt = &mut buf[CT_PRDT..(CT_PRDT + 16)];
+    prdt[0..4].copy_from_slice(&(data as u32).to_le_bytes());
+    prdt[4..8].copy_from_slice(&((data >> 32) as u32).to_le_bytes());
+    prdt[8..12].copy_from_slice(&0u32.to_le_bytes());
+    prdt[12..16].copy_from_slice(&dbc.to_le_bytes());
+}
+
+static PORTS: Mutex<Vec<Port>> = Mutex::new(Vec::new());
+
+fn find_port(ports: &mut [Port], port: u8) -> Result<&mut Port, ()> {
+    ports.iter_mut().find(|p| p.id == port).ok_or(())
+}
+
+pub fn init() {
+    // The controller has the class of mass storage controllers and the
+    // subclass of SATA controllers with the AHCI programming interface.
+    let controller = sys::pci::list()
+        .into_iter()
+        .find(|dev| dev.class == 0x01 && dev.subclass == 0x06 && dev.prog == 0x01);
+    let mut dev = match controller {
+        Some(dev) => dev,
+        None => return,
+    };
+    dev.enable_bus_mastering();
+
+    // The registers of the HBA are in the memory space given by BAR5
+    let abar = (dev.base_addresses[5] & 0xFFFF_FFF0) as u64;
+    let hba = sys::mem::phys_to_virt(PhysAddr::new(abar)).as_u64();
+    let read = |offset: u64| unsafe { core::ptr::read_volatile((hba + offset) as *const u32) };
+    let write = |offset: u64, data: u32| unsafe {
+        core::ptr::write_volatile((hba + offset) as *mut u32, data)
+    };
+    let is_64bit = read(0).get_bit(31); // Supports 64-bit addressing
+    let mut ghc = read(HBA_GHC);
+    ghc.set_bit(31, true); // AHCI enable
+    write(HBA_GHC, ghc);
+    log!("AHCI Controller at {:#X}", abar);
+
+    let pi = read(HBA_PI);
+    let mut ports = PORTS.lock();
+    for id in 0..32 {
+        if !pi.get_bit(id) {
+            continue;
+        }
+        let mut port = Port::new(id as u8, abar);
+        if !port.is_sata() || port.setup(is_64bit).is_err() {
+            continue;
+        }
+        if let Ok(disk) = port.identify() {
+            log!("AHCI {} {}", id, disk);
+            port.disk = Some(disk);
+            ports.push(port);
+        }
+    }
+}
+
+pub fn list() -> Vec<Disk> {
+    PORTS.lock().iter().filter_map(|p| p.disk.clone()).collect()
+}
+
+// Read blocks into a buffer with a multiple of the block size, in transfers
+// of at most 128 blocks.
+pub fn read(port: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    let mut ports = PORTS.lock();
+    let port = find_port(&mut ports, port)?;
+    for (i, chunk) in buf.chunks_mut(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        port.read(block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn write(port: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+    let mut ports = PORTS.lock();
+    let port = find_port(&mut ports, port)?;
+    for (i, chunk) in buf.chunks(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        port.write(block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn flush(port: u8) -> Result<(), ()> {
+    let mut ports = PORTS.lock();
+    find_port(&mut ports, port)?.flush()
+}
+
+// Write the caches of all the disks to their storage
+pub fn flush_all() {
+    for disk in list() {
+        if flush(disk.port).is_err() {
+            debug!("AHCI {} could not flush cache", disk.port);
+        }
+    }
+}
+
+#[test_case]
+fn test_command_header() {
+    let mut buf = [0xFF; 16];
+    command_header(&mut buf, 0x1_2345_6780, true, true);
+    assert_eq!(buf[0..4], [5, 0b0100_0000, 1, 0]);
+    assert_eq!(buf[4..8], [0; 4]);
+    assert_eq!(buf[8..12], [0x80, 0x67, 0x45, 0x23]);
+    assert_eq!(buf[12..16], [1, 0, 0, 0]);
+
+    // A flush has no data to transfer and no entry in the PRDT
+    command_header(&mut buf, 0x1000, false, false);
+    assert_eq!(buf[0..4], [5, 0, 0, 0]);
+}
+
+#[test_case]
+fn test_command_table() {
+    let mut buf = [0xFF; CT_SIZE];
+    let block = 0x0605_0403_0201;
+    command_table(&mut buf, Command::ReadDmaExt, block, 128, 0x1_0000_2000);
+
+    // Register host to device FIS
+    assert_eq!(buf[0..4], [0x27, 0x80, 0x25, 0]);
+    assert_eq!(buf[4..8], [0x01, 0x02, 0x03, 1 << 6]);
+    assert_eq!(buf[8..12], [0x04, 0x05, 0x06, 0]);
+    assert_eq!(buf[12..14], [128, 0]);
+    assert!(buf[14..CT_PRDT].iter().all(|&b| b == 0));
+
+    // Entry of the PRDT with the byte count minus one
+    let prdt = &buf[CT_PRDT..(CT_PRDT + 16)];
+    assert_eq!(prdt[0..4], [0x00, 0x20, 0x00, 0x00]);
+    assert_eq!(prdt[4..8], [1, 0, 0, 0]);
+    assert_eq!(prdt[8..12], [0; 4]);
+    assert_eq!(prdt[12..16], (128 * 512 - 1u32).to_le_bytes());
+}
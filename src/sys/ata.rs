@@ -1,13 +1,16 @@
-use crate::api::fs::{FileIO, IO};
+use crate::api::fs::{FileIO, SeekFrom, IO};
 use crate::sys;
+use crate::sys::mem::PhysBuf;
 
 use alloc::borrow::ToOwned;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use bit_field::BitField;
 use core::convert::TryInto;
 use core::fmt;
 use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
@@ -18,6 +21,22 @@ use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
 pub const BLOCK_SIZE: usize = 512;
 
+// Size of the blocks of ATAPI devices like CD-ROM drives
+pub const ATAPI_BLOCK_SIZE: usize = 2048;
+
+// Maximum number of blocks transferred by a packet command
+const ATAPI_MAX_COUNT: usize = 32;
+
+// Maximum number of blocks transferred by a command
+const MAX_COUNT: usize = 256;
+
+// Blocks after this limit can only be addressed with the 48-bit commands
+const LBA28_LIMIT: u64 = 1 << 28;
+
+// Maximum number of blocks transferred by a DMA command, limited by the
+// size of the DMA buffer
+const DMA_COUNT: usize = 128;
+
 // Keep track of the last selected bus and drive pair to speed up operations
 pub static LAST_SELECTED: Mutex<Option<(u8, u8)>> = Mutex::new(None);
 
@@ -25,7 +44,16 @@ pub static LAST_SELECTED: Mutex<Option<(u8, u8)>> = Mutex::new(None);
 #[derive(Debug, Clone, Copy)]
 enum Command {
     Read = 0x20,
+    ReadExt = 0x24,
     Write = 0x30,
+    WriteExt = 0x34,
+    ReadDma = 0xC8,
+    ReadDmaExt = 0x25,
+    WriteDma = 0xCA,
+    WriteDmaExt = 0x35,
+    FlushCache = 0xE7,
+    Packet = 0xA0,
+    IdentifyPacket = 0xA1,
     Identify = 0xEC,
 }
 
@@ -50,6 +78,131 @@ enum Status {
     BSY = 7,  // Busy
 }
 
+// Programming Interface for Bus Master IDE Controller (1994)
+
+// Size of an entry of the Physical Region Descriptor Table
+const PRD_SIZE: usize = 8;
+
+// Set in the last entry of the table
+const PRD_EOT: u16 = 0x8000;
+
+// Regions of memory described by the PRDT must not cross a 64 KB boundary
+const PRD_BOUNDARY: u64 = 0x10000;
+
+// Set when an interrupt is received from the primary or secondary bus
+static INTERRUPTS: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+#[derive(Clone)]
+struct Dma {
+    command_register: Port<u8>,
+    status_register: Port<u8>,
+    prdt_register: Port<u32>,
+
+    // The PRDT and the buffer are allocated once in physical memory below 4
+    // GB, the data is copied between the buffer and the caller.
+    prdt: PhysBuf,
+    buf: PhysBuf,
+    is_enabled: bool,
+}
+
+impl Dma {
+    pub fn new(io_base: u16) -> Option<Self> {
+        // The buffer can cross a single 64 KB boundary so the table needs two
+        // entries, with some padding to align it.
+        let prdt = PhysBuf::new(4 * PRD_SIZE);
+        let buf = PhysBuf::new(DMA_COUNT * BLOCK_SIZE);
+        let limit = u32::MAX as u64;
+        if prdt.addr() + prdt.len() as u64 > limit || buf.addr() + buf.len() as u64 > limit {
+            return None;
+        }
+        Some(Self {
+            command_register: Port::new(io_base),
+            status_register: Port::new(io_base + 2),
+            prdt_register: Port::new(io_base + 4),
+            prdt,
+            buf,
+            is_enabled: true,
+        })
+    }
+
+    // Offset of the table in its buffer, aligned to not cross a 64 KB
+    // boundary
+    fn prdt_offset(&self) -> usize {
+        let n = 2 * PRD_SIZE as u64;
+        ((n - self.prdt.addr() % n) % n) as usize
+    }
+
+    // Fill the table with the regions of the buffer used by a transfer of
+    // `len` bytes in the given direction.
+    fn setup(&mut self, len: usize, is_read: bool) {
+        debug_assert!(0 < len && len <= self.buf.len());
+        let mut regions = Vec::new();
+        let mut addr = self.buf.addr();
+        let end = addr + len as u64;
+        while addr < end {
+            let boundary = (addr / PRD_BOUNDARY + 1) * PRD_BOUNDARY;
+            let n = end.min(boundary) - addr;
+            regions.push((addr as u32, n as u16)); // 0 => 64 KB
+            addr += n;
+        }
+
+        let offset = self.prdt_offset();
+        let last = regions.len() - 1;
+        for (i, (addr, n)) in regions.into_iter().enumerate() {
+            let flags = if i == last { PRD_EOT } else { 0 };
+            let j = offset + i * PRD_SIZE;
+            self.prdt[j..(j + 4)].copy_from_slice(&addr.to_le_bytes());
+            self.prdt[(j + 4)..(j + 6)].copy_from_slice(&n.to_le_bytes());
+            self.prdt[(j + 6)..(j + 8)].copy_from_slice(&flags.to_le_bytes());
+        }
+
+        // Bit 3 => Read or write control (set when writing to memory)
+        let command = if is_read { 1 << 3 } else { 0 };
+        let addr = self.prdt.addr() as u32 + offset as u32;
+        unsafe {
+            self.prdt_register.write(addr);
+            self.command_register.write(command);
+
+            // Clear the error and interrupt bits by writing them
+            let status = self.status_register.read();
+            self.status_register.write(status | 0b110);
+        }
+    }
+
+    // Bit 0 => Active, bit 1 => Error, bit 2 => Interrupt
+    fn status(&mut self) -> u8 {
+        unsafe { self.status_register.read() }
+    }
+
+    fn start(&mut self) {
+        unsafe {
+            let command = self.command_register.read();
+            self.command_register.write(command | 1);
+        }
+    }
+
+    // Stop the transfer and return the status before clearing it
+    fn stop(&mut self) -> u8 {
+        unsafe {
+            let command = self.command_register.read();
+            self.command_register.write(command & !1);
+            let status = self.status_register.read();
+            self.status_register.write(status | 0b110);
+            status
+        }
+    }
+}
+
+impl fmt::Debug for Dma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dma")
+            .field("prdt", &self.prdt.addr())
+            .field("buf", &self.buf.addr())
+            .field("is_enabled", &self.is_enabled)
+            .finish()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Bus {
@@ -70,6 +223,8 @@ pub struct Bus {
     alternate_status_register: PortReadOnly<u8>,
     control_register: PortWriteOnly<u8>,
     drive_blockess_register: PortReadOnly<u8>,
+
+    dma: Option<Dma>,
 }
 
 impl Bus {
@@ -90,6 +245,7 @@ impl Bus {
             alternate_status_register: PortReadOnly::new(ctrl_base + 0),
             control_register: PortWriteOnly::new(ctrl_base + 0),
             drive_blockess_register: PortReadOnly::new(ctrl_base + 1),
+            dma: None,
         }
     }
 
@@ -168,15 +324,15 @@ impl Bus {
         Ok(())
     }
 
-    fn write_command_params(&mut self, drive: u8, block: u32) -> Result<(), ()> {
+    fn write_command_params(&mut self, drive: u8, block: u64, count: usize) -> Result<(), ()> {
         let lba = true;
-        let mut bytes = block.to_le_bytes();
+        let mut bytes = (block as u32).to_le_bytes();
         bytes[3].set_bit(4, drive > 0);
         bytes[3].set_bit(5, true);
         bytes[3].set_bit(6, lba);
         bytes[3].set_bit(7, true);
         unsafe {
-            self.sector_count_register.write(1);
+            self.sector_count_register.write(count as u8); // 0 => 256
             self.lba0_register.write(bytes[0]);
             self.lba1_register.write(bytes[1]);
             self.lba2_register.write(bytes[2]);
@@ -185,6 +341,25 @@ impl Bus {
         Ok(())
     }
 
+    // The registers of the 48-bit commands are FIFOs of two bytes where the
+    // high bytes are written first.
+    fn write_command_params_ext(&mut self, drive: u8, block: u64, count: usize) -> Result<(), ()> {
+        let bytes = block.to_le_bytes();
+        let count = (count as u16).to_le_bytes(); // 0 => 65536
+        unsafe {
+            self.sector_count_register.write(count[1]);
+            self.lba0_register.write(bytes[3]);
+            self.lba1_register.write(bytes[4]);
+            self.lba2_register.write(bytes[5]);
+            self.sector_count_register.write(count[0]);
+            self.lba0_register.write(bytes[0]);
+            self.lba1_register.write(bytes[1]);
+            self.lba2_register.write(bytes[2]);
+            self.drive_register.write(0xE0 | (drive << 4));
+        }
+        Ok(())
+    }
+
     fn write_command(&mut self, cmd: Command) -> Result<(), ()> {
         unsafe { self.command_register.write(cmd as u8) }
         self.wait(400); // Wait at least 400 ns
@@ -204,19 +379,47 @@ impl Bus {
         Ok(())
     }
 
-    fn setup_pio(&mut self, drive: u8, block: u32) -> Result<(), ()> {
+    // Setup a transfer of blocks and return `true` if it needs a 48-bit
+    // command.
+    fn setup_transfer(&mut self, drive: u8, block: u64, count: usize) -> Result<bool, ()> {
+        debug_assert!(0 < count && count <= MAX_COUNT);
         self.select_drive(drive)?;
-        self.write_command_params(drive, block)?;
-        Ok(())
+        let is_ext = block + count as u64 > LBA28_LIMIT;
+        if is_ext {
+            self.write_command_params_ext(drive, block, count)?;
+        } else {
+            self.write_command_params(drive, block, count)?;
+        }
+        Ok(is_ext)
     }
 
-    fn read(&mut self, drive: u8, block: u32, buf: &mut [u8]) -> Result<(), ()> {
-        debug_assert!(buf.len() == BLOCK_SIZE);
-        self.setup_pio(drive, block)?;
-        self.write_command(Command::Read)?;
-        for chunk in buf.chunks_mut(2) {
-            let data = self.read_data().to_le_bytes();
-            chunk.clone_from_slice(&data);
+    // Wait for the drive to be ready to transfer the next block
+    fn wait_data(&mut self) -> Result<(), ()> {
+        self.wait(400); // Wait at least 400 ns
+        self.poll(Status::BSY, false)?;
+        if self.is_error() {
+            return Err(());
+        }
+        self.poll(Status::DRQ, true)
+    }
+
+    fn read_pio(&mut self, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        let count = buf.len() / BLOCK_SIZE;
+        let is_ext = self.setup_transfer(drive, block, count)?;
+        self.write_command(if is_ext {
+            Command::ReadExt
+        } else {
+            Command::Read
+        })?;
+        for (i, sector) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+            if i > 0 {
+                self.wait_data()?;
+            }
+            for chunk in sector.chunks_mut(2) {
+                let data = self.read_data().to_le_bytes();
+                chunk.clone_from_slice(&data);
+            }
         }
         if self.is_error() {
             debug!("ATA read: data error");
@@ -227,14 +430,26 @@ impl Bus {
         }
     }
 
-    fn write(&mut self, drive: u8, block: u32, buf: &[u8]) -> Result<(), ()> {
-        debug_assert!(buf.len() == BLOCK_SIZE);
-        self.setup_pio(drive, block)?;
-        self.write_command(Command::Write)?;
-        for chunk in buf.chunks(2) {
-            let data = u16::from_le_bytes(chunk.try_into().unwrap());
-            self.write_data(data);
+    fn write_pio(&mut self, drive: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        let count = buf.len() / BLOCK_SIZE;
+        let is_ext = self.setup_transfer(drive, block, count)?;
+        self.write_command(if is_ext {
+            Command::WriteExt
+        } else {
+            Command::Write
+        })?;
+        for (i, sector) in buf.chunks(BLOCK_SIZE).enumerate() {
+            if i > 0 {
+                self.wait_data()?;
+            }
+            for chunk in sector.chunks(2) {
+                let data = u16::from_le_bytes(chunk.try_into().unwrap());
+                self.write_data(data);
+            }
         }
+        self.wait(400); // Wait at least 400 ns
+        self.poll(Status::BSY, false)?;
         if self.is_error() {
             debug!("ATA write: data error");
             self.debug();
@@ -244,18 +459,141 @@ impl Bus {
         }
     }
 
+    fn is_dma(&self) -> bool {
+        self.dma.as_ref().is_some_and(|dma| dma.is_enabled)
+    }
+
+    fn set_dma(&mut self, enabled: bool) -> Result<(), ()> {
+        let dma = self.dma.as_mut().ok_or(())?;
+        dma.is_enabled = enabled;
+        Ok(())
+    }
+
+    // Start a DMA transfer of at most `DMA_COUNT` blocks
+    fn start_dma(&mut self, drive: u8, block: u64, len: usize, is_read: bool) -> Result<(), ()> {
+        let count = len / BLOCK_SIZE;
+        self.dma.as_mut().ok_or(())?.setup(len, is_read);
+        let is_ext = self.setup_transfer(drive, block, count)?;
+        let cmd = match (is_read, is_ext) {
+            (true, false) => Command::ReadDma,
+            (true, true) => Command::ReadDmaExt,
+            (false, false) => Command::WriteDma,
+            (false, true) => Command::WriteDmaExt,
+        };
+        INTERRUPTS[self.id as usize].store(false, Ordering::SeqCst);
+        unsafe { self.command_register.write(cmd as u8) }
+        self.dma.as_mut().ok_or(())?.start();
+        Ok(())
+    }
+
+    // The status of the controller is also checked because its interrupt
+    // bit stays set after the IRQ has been handled.
+    fn is_dma_done(&mut self) -> bool {
+        if INTERRUPTS[self.id as usize].load(Ordering::SeqCst) {
+            return true;
+        }
+        self.dma.as_mut().is_some_and(|dma| {
+            let status = dma.status();
+            status.get_bit(1) || status.get_bit(2) // Error or interrupt
+        })
+    }
+
+    fn stop_dma(&mut self) -> Result<u8, ()> {
+        Ok(self.dma.as_mut().ok_or(())?.stop())
+    }
+
+    fn check_dma(&mut self, status: u8) -> Result<(), ()> {
+        self.poll(Status::BSY, false)?;
+        if status.get_bit(1) || self.is_error() {
+            debug!("ATA DMA: transfer error");
+            self.debug();
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Write the cache of the drive to the disk
+    fn flush(&mut self, drive: u8) -> Result<(), ()> {
+        self.select_drive(drive)?;
+        unsafe { self.command_register.write(Command::FlushCache as u8) }
+        self.wait(400); // Wait at least 400 ns
+        if self.status() == 0 {
+            return Err(()); // Drive does not exist
+        }
+        self.poll(Status::BSY, false)?;
+        if self.is_error() {
+            debug!("ATA flush: cache error");
+            self.debug();
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn identify_packet_drive(&mut self, drive: u8) -> Result<[u16; 256], ()> {
+        self.select_drive(drive)?;
+        self.write_command(Command::IdentifyPacket)?;
+        Ok([(); 256].map(|_| self.read_data()))
+    }
+
+    // Send a SCSI command packet of 12 bytes to an ATAPI device and read its
+    // response into a buffer. The data is transferred by the device in parts
+    // of at most one block, with their size given in the LBA registers.
+    fn packet(&mut self, drive: u8, packet: &[u8; 12], buf: &mut [u8]) -> Result<(), ()> {
+        self.select_drive(drive)?;
+        let limit = (ATAPI_BLOCK_SIZE as u16).to_le_bytes();
+        unsafe {
+            self.features_register.write(0); // PIO
+            self.lba1_register.write(limit[0]);
+            self.lba2_register.write(limit[1]);
+        }
+        self.write_command(Command::Packet)?;
+        for chunk in packet.chunks(2) {
+            self.write_data(u16::from_le_bytes([chunk[0], chunk[1]]));
+        }
+        let mut i = 0;
+        while i < buf.len() {
+            self.wait_data()?;
+            let n = u16::from_le_bytes([self.lba1(), self.lba2()]) as usize;
+            if n == 0 {
+                return Err(());
+            }
+            for _ in 0..(n / 2) {
+                let data = self.read_data().to_le_bytes();
+                if i + 2 <= buf.len() {
+                    buf[i..(i + 2)].copy_from_slice(&data);
+                }
+                i += 2;
+            }
+        }
+        self.wait(400); // Wait at least 400 ns
+        self.poll(Status::BSY, false)?;
+        if self.is_error() {
+            debug!("ATAPI packet {:#X}: data error", packet[0]);
+            self.debug();
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
     fn identify_drive(&mut self, drive: u8) -> Result<IdentifyResponse, ()> {
         if self.check_floating_bus().is_err() {
             return Ok(IdentifyResponse::None);
         }
         self.select_drive(drive)?;
-        self.write_command_params(drive, 0)?;
+        self.write_command_params(drive, 0, 1)?;
         if self.write_command(Command::Identify).is_err() {
             if self.status() == 0 {
                 return Ok(IdentifyResponse::None);
-            } else {
-                return Err(());
             }
+            // ATAPI devices abort the command with their signature in the
+            // LBA registers.
+            if (self.lba1(), self.lba2()) == (0x14, 0xEB) {
+                return Ok(IdentifyResponse::Atapi);
+            }
+            return Err(());
         }
         match (self.lba1(), self.lba2()) {
             (0x00, 0x00) => Ok(IdentifyResponse::Ata([(); 256].map(|_| self.read_data()))),
@@ -294,12 +632,53 @@ lazy_static! {
     pub static ref BUSES: Mutex<Vec<Bus>> = Mutex::new(Vec::new());
 }
 
+// Reading the status register of the bus acknowledges its interrupt
+fn interrupt_handler(bus: usize) {
+    let mut status_register: PortReadOnly<u8> = PortReadOnly::new(IO_BASES[bus] + 7);
+    unsafe { status_register.read() };
+    INTERRUPTS[bus].store(true, Ordering::SeqCst);
+}
+
+fn primary_interrupt_handler() {
+    interrupt_handler(0);
+}
+
+fn secondary_interrupt_handler() {
+    interrupt_handler(1);
+}
+
+const IO_BASES: [u16; 2] = [0x1F0, 0x170];
+
 pub fn init() {
     {
         let mut buses = BUSES.lock();
-        buses.push(Bus::new(0, 0x1F0, 0x3F6, 14));
-        buses.push(Bus::new(1, 0x170, 0x376, 15));
+        buses.push(Bus::new(0, IO_BASES[0], 0x3F6, 14));
+        buses.push(Bus::new(1, IO_BASES[1], 0x376, 15));
+
+        // Bus-master IDE controller with its registers in the I/O space
+        // given by BAR4, the first 8 bytes for the primary bus and the next
+        // 8 bytes for the secondary bus.
+        let controller = sys::pci::list()
+            .into_iter()
+            .find(|dev| dev.class == 0x01 && dev.subclass == 0x01 && dev.prog.get_bit(7));
+        if let Some(mut dev) = controller {
+            let bar4 = dev.base_addresses[4];
+            if bar4.get_bit(0) {
+                dev.enable_bus_mastering();
+                let io_base = (bar4 as u16) & 0xFFFC;
+                for (i, bus) in buses.iter_mut().enumerate() {
+                    bus.dma = Dma::new(io_base + 8 * i as u16);
+                }
+                log!("ATA Bus-master DMA at {:#X}", io_base);
+            }
+        }
+
+        for bus in buses.iter_mut() {
+            unsafe { bus.control_register.write(0) } // Clear nIEN bit
+        }
     }
+    sys::idt::set_irq_handler(14, primary_interrupt_handler);
+    sys::idt::set_irq_handler(15, secondary_interrupt_handler);
 
     for drive in list() {
         log!("ATA {}:{} {}", drive.bus, drive.dsk, drive);
@@ -310,10 +689,12 @@ pub fn init() {
 pub struct Drive {
     pub bus: u8,
     pub dsk: u8,
+    pub part: u8,
     model: String,
     serial: String,
-    block_count: u32,
-    block_index: u32,
+    block_start: u64,
+    block_count: u64,
+    block_index: u64,
 }
 
 impl Drive {
@@ -328,14 +709,22 @@ impl Drive {
             let buf = res.map(u16::to_be_bytes).concat();
             let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
             let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
-            let block_count = u32::from_be_bytes(buf[120..124].try_into().unwrap()).rotate_left(16);
+            let block_count = if res[83].get_bit(10) {
+                // Words 100 to 103 for drives supporting LBA48
+                (0..4).fold(0, |n, i| n | ((res[100 + i] as u64) << (16 * i)))
+            } else {
+                // Words 60 to 61 for the others
+                u32::from_be_bytes(buf[120..124].try_into().unwrap()).rotate_left(16) as u64
+            };
             let block_index = 0;
 
             Some(Self {
                 bus,
                 dsk,
+                part: 0,
                 model,
                 serial,
+                block_start: 0,
                 block_count,
                 block_index,
             })
@@ -344,60 +733,355 @@ impl Drive {
         }
     }
 
+    // Open a partition of a drive, or the whole drive with the number 0
+    pub fn open_partition(bus: u8, dsk: u8, part: u8) -> Option<Self> {
+        let mut drive = Self::open(bus, dsk)?;
+        if part > 0 {
+            let partition = partitions(bus, dsk).into_iter().find(|p| p.index == part)?;
+            drive.part = part;
+            drive.block_start = partition.block_start;
+            drive.block_count = partition.block_count;
+        }
+        Some(drive)
+    }
+
     pub const fn block_size(&self) -> u32 {
         BLOCK_SIZE as u32
     }
 
-    pub fn block_count(&self) -> u32 {
+    // Address of the first block of the partition on the drive
+    pub fn block_start(&self) -> u64 {
+        self.block_start
+    }
+
+    pub fn block_count(&self) -> u64 {
         self.block_count
     }
+}
 
-    fn humanized_size(&self) -> (usize, String) {
-        let size = self.block_size() as usize;
-        let count = self.block_count() as usize;
-        let bytes = size * count;
-        if bytes >> 20 < 1000 {
-            (bytes >> 20, String::from("MB".to_owned()))
-        } else {
-            (bytes >> 30, String::from("GB".to_owned()))
+impl Drive {
+    // Move to a block of the drive or partition with an offset in bytes that
+    // must be a multiple of the block size, and return the new offset.
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, ()> {
+        let size = (self.block_count * BLOCK_SIZE as u64) as i64;
+        let offset = match pos {
+            SeekFrom::Start(i) => i as i64,
+            SeekFrom::Current(i) => i + (self.block_index * BLOCK_SIZE as u64) as i64,
+            SeekFrom::End(i) => i + size,
+        };
+        if offset < 0 || offset > size || offset % BLOCK_SIZE as i64 != 0 {
+            return Err(());
         }
+        self.block_index = offset as u64 / BLOCK_SIZE as u64;
+        Ok(offset as u64)
+    }
+
+    // Number of blocks that can be transferred with a buffer from the
+    // current block
+    fn transfer_count(&self, len: usize) -> Result<u64, ()> {
+        if len % BLOCK_SIZE != 0 {
+            return Err(());
+        }
+        let n = (len / BLOCK_SIZE) as u64;
+        Ok(n.min(self.block_count - self.block_index))
     }
 }
 
+// The blocks of a drive are read and written sequentially from its current
+// block, with buffers of a multiple of the block size.
 impl FileIO for Drive {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        if self.block_index == self.block_count {
+        let count = self.transfer_count(buf.len())?;
+        if count == 0 {
             return Ok(0);
         }
-
-        let mut buses = BUSES.lock();
-        let _ = buses[self.bus as usize].read(self.dsk, self.block_index, buf);
-        let n = buf.len();
-        self.block_index += 1;
+        let n = count as usize * BLOCK_SIZE;
+        let block = self.block_start + self.block_index;
+        read(self.bus, self.dsk, block, &mut buf[0..n])?;
+        self.block_index += count;
         Ok(n)
     }
 
-    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
-        Err(())
+    // A drive cannot be written while it or one of its partitions is mounted
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        if sys::fs::is_disk_mounted(&sys::fs::ata_path(self.bus, self.dsk, self.part)) {
+            return Err(());
+        }
+        let count = self.transfer_count(buf.len())?;
+        if count == 0 {
+            return Err(()); // End of drive
+        }
+        let n = count as usize * BLOCK_SIZE;
+        let block = self.block_start + self.block_index;
+        write(self.bus, self.dsk, block, &buf[0..n])?;
+        self.block_index += count;
+        Ok(n)
     }
 
-    fn close(&mut self) {}
+    fn close(&mut self) {
+        flush(self.bus, self.dsk).ok();
+    }
 
     fn poll(&mut self, event: IO) -> bool {
         match event {
             IO::Read => true,
-            IO::Write => false,
+            IO::Write => self.block_index < self.block_count,
         }
     }
 }
 
+pub fn humanized_size(block_count: u64) -> (usize, String) {
+    let bytes = BLOCK_SIZE * block_count as usize;
+    if bytes >> 20 < 1000 {
+        (bytes >> 20, String::from("MB".to_owned()))
+    } else {
+        (bytes >> 30, String::from("GB".to_owned()))
+    }
+}
+
 impl fmt::Display for Drive {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (size, unit) = self.humanized_size();
+        let (size, unit) = humanized_size(self.block_count);
         write!(f, "{} {} ({} {})", self.model, self.serial, size, unit)
     }
 }
 
+// An ATAPI device with a medium of blocks of 2048 bytes, like a CD-ROM drive
+#[derive(Clone, Debug)]
+pub struct Cdrom {
+    pub bus: u8,
+    pub dsk: u8,
+    model: String,
+    serial: String,
+    block_count: u64,
+}
+
+impl Cdrom {
+    pub fn open(bus: u8, dsk: u8) -> Option<Self> {
+        let res = {
+            let mut buses = BUSES.lock();
+            match buses[bus as usize].identify_drive(dsk) {
+                Ok(IdentifyResponse::Atapi) => buses[bus as usize].identify_packet_drive(dsk),
+                _ => return None,
+            }
+        };
+        let res = res.ok()?;
+        let buf = res.map(u16::to_be_bytes).concat();
+        let model = String::from_utf8_lossy(&buf[54..94]).trim().into();
+        let serial = String::from_utf8_lossy(&buf[20..40]).trim().into();
+
+        // READ CAPACITY returns the address of the last block, which fails
+        // when there is no medium in the drive.
+        let mut packet = [0; 12];
+        packet[0] = 0x25;
+        let mut buf = [0; 8];
+        let block_count = match BUSES.lock()[bus as usize].packet(dsk, &packet, &mut buf) {
+            Ok(()) => u32::from_be_bytes(buf[0..4].try_into().unwrap()) as u64 + 1,
+            Err(()) => 0,
+        };
+        Some(Self {
+            bus,
+            dsk,
+            model,
+            serial,
+            block_count,
+        })
+    }
+
+    pub const fn block_size(&self) -> u32 {
+        ATAPI_BLOCK_SIZE as u32
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+}
+
+impl fmt::Display for Cdrom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = (ATAPI_BLOCK_SIZE / BLOCK_SIZE) as u64;
+        let (size, unit) = humanized_size(self.block_count * n);
+        write!(f, "{} {} ({} {})", self.model, self.serial, size, unit)
+    }
+}
+
+// A partition of a drive found in its MBR or GPT partition table. The
+// partitions are numbered from 1 in the order of the entries of the table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    pub index: u8,
+    pub kind: String,
+    pub block_start: u64,
+    pub block_count: u64,
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (size, unit) = humanized_size(self.block_count);
+        write!(f, "{} ({} {})", self.kind, size, unit)
+    }
+}
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_PROTECTIVE: u8 = 0xEE;
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MAX_ENTRIES: u32 = 128;
+
+fn mbr_kind(kind: u8) -> String {
+    match kind {
+        0x01 => "FAT12".into(),
+        0x04 | 0x06 | 0x0E => "FAT16".into(),
+        0x05 | 0x0F => "Extended".into(),
+        0x07 => "NTFS".into(),
+        0x0B | 0x0C => "FAT32".into(),
+        0x82 => "Linux swap".into(),
+        0x83 => "Linux".into(),
+        0xEF => "EFI System".into(),
+        _ => format!("Type 0x{:02X}", kind),
+    }
+}
+
+fn gpt_kind(guid: &str) -> String {
+    match guid {
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => "EFI System".into(),
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7" => "Basic data".into(),
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => "Linux".into(),
+        "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F" => "Linux swap".into(),
+        "21686148-6449-6E6F-744E-656564454649" => "BIOS boot".into(),
+        _ => "Unknown".into(),
+    }
+}
+
+// The first three fields of a GUID are stored in little endian
+fn parse_guid(buf: &[u8]) -> String {
+    let mut guid = format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+        u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+        u16::from_le_bytes(buf[4..6].try_into().unwrap()),
+        u16::from_le_bytes(buf[6..8].try_into().unwrap()),
+        buf[8],
+        buf[9],
+    );
+    for b in &buf[10..16] {
+        guid.push_str(&format!("{:02X}", b));
+    }
+    guid
+}
+
+// Parse the primary partitions of an MBR, or return `None` if the block is
+// not an MBR or if it is the protective MBR of a GPT disk. The extended
+// partitions are skipped because they only contain other partition tables.
+fn parse_mbr(buf: &[u8]) -> Option<Vec<Partition>> {
+    if buf[510..512] != MBR_SIGNATURE {
+        return None;
+    }
+    let mut res = Vec::new();
+    for i in 0..4 {
+        let entry = &buf[(446 + i * 16)..(446 + (i + 1) * 16)];
+        let kind = entry[4];
+        let block_start = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+        let block_count = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+        if kind == MBR_PROTECTIVE {
+            return None;
+        }
+        if kind == 0 || block_count == 0 || MBR_EXTENDED.contains(&kind) {
+            continue;
+        }
+        res.push(Partition {
+            index: i as u8 + 1,
+            kind: mbr_kind(kind),
+            block_start: block_start as u64,
+            block_count: block_count as u64,
+        });
+    }
+    Some(res)
+}
+
+// Parse the entries of a GPT after its header
+fn parse_gpt(bus: u8, dsk: u8, header: &[u8]) -> Vec<Partition> {
+    let mut res = Vec::new();
+    if &header[0..8] != GPT_SIGNATURE {
+        return res;
+    }
+    let entries_addr = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let entries_count = u32::from_le_bytes(header[80..84].try_into().unwrap());
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as usize;
+    if entry_size < 128 || BLOCK_SIZE % entry_size != 0 {
+        return res;
+    }
+    let entries_per_block = BLOCK_SIZE / entry_size;
+    let mut buf = [0; BLOCK_SIZE];
+    for i in 0..entries_count.min(GPT_MAX_ENTRIES) as usize {
+        if i % entries_per_block == 0 {
+            let addr = entries_addr + (i / entries_per_block) as u64;
+            if read(bus, dsk, addr, &mut buf).is_err() {
+                break;
+            }
+        }
+        let j = (i % entries_per_block) * entry_size;
+        let entry = &buf[j..(j + entry_size)];
+        if entry[0..16].iter().all(|b| *b == 0) {
+            continue;
+        }
+        let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        if last < first {
+            continue;
+        }
+        res.push(Partition {
+            index: i as u8 + 1,
+            kind: gpt_kind(&parse_guid(&entry[0..16])),
+            block_start: first,
+            block_count: last - first + 1,
+        });
+    }
+    res
+}
+
+pub fn partitions(bus: u8, dsk: u8) -> Vec<Partition> {
+    let mut buf = [0; BLOCK_SIZE];
+    if read(bus, dsk, 0, &mut buf).is_err() {
+        return Vec::new();
+    }
+    if let Some(partitions) = parse_mbr(&buf) {
+        return partitions;
+    }
+    if buf[510..512] != MBR_SIGNATURE || read(bus, dsk, 1, &mut buf).is_err() {
+        return Vec::new();
+    }
+    parse_gpt(bus, dsk, &buf)
+}
+
+pub fn cdroms() -> Vec<Cdrom> {
+    let mut res = Vec::new();
+    for bus in 0..2 {
+        for dsk in 0..2 {
+            if let Some(cdrom) = Cdrom::open(bus, dsk) {
+                res.push(cdrom)
+            }
+        }
+    }
+    res
+}
+
+// Read blocks of 2048 bytes from an ATAPI device with the READ (12) command
+pub fn read_cdrom(bus: u8, dsk: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    debug_assert!(buf.len() % ATAPI_BLOCK_SIZE == 0);
+    let mut buses = BUSES.lock();
+    let n = ATAPI_MAX_COUNT * ATAPI_BLOCK_SIZE;
+    for (i, chunk) in buf.chunks_mut(n).enumerate() {
+        let block = block + (i * ATAPI_MAX_COUNT) as u64;
+        let count = (chunk.len() / ATAPI_BLOCK_SIZE) as u32;
+        let mut packet = [0; 12];
+        packet[0] = 0xA8;
+        packet[2..6].copy_from_slice(&(block as u32).to_be_bytes());
+        packet[6..10].copy_from_slice(&count.to_be_bytes());
+        buses[bus as usize].packet(dsk, &packet, chunk)?;
+    }
+    Ok(())
+}
+
 pub fn list() -> Vec<Drive> {
     let mut res = Vec::new();
     for bus in 0..2 {
@@ -410,12 +1094,185 @@ pub fn list() -> Vec<Drive> {
     res
 }
 
-pub fn read(bus: u8, drive: u8, block: u32, buf: &mut [u8]) -> Result<(), ()> {
+// Wait for the end of a DMA transfer, halting the CPU between interrupts
+// without holding the lock of the buses. The kernel is not preempted so no
+// other command can be sent to the bus before the end of the transfer.
+fn wait_dma(bus: usize) -> Result<(), ()> {
+    let start = sys::clk::boot_time();
+    while !BUSES.lock()[bus].is_dma_done() {
+        if sys::clk::boot_time() - start > 1.0 {
+            debug!("ATA hanged while waiting for DMA interrupt");
+            BUSES.lock()[bus].debug();
+            return Err(());
+        }
+        sys::clk::halt();
+    }
+    Ok(())
+}
+
+fn transfer_dma(bus: usize, drive: u8, block: u64, len: usize, is_read: bool) -> Result<(), ()> {
+    BUSES.lock()[bus].start_dma(drive, block, len, is_read)?;
+    let res = wait_dma(bus);
+    let mut buses = BUSES.lock();
+    let status = buses[bus].stop_dma()?;
+    res?;
+    buses[bus].check_dma(status)
+}
+
+fn read_dma(bus: usize, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    for (i, chunk) in buf.chunks_mut(DMA_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * DMA_COUNT) as u64;
+        transfer_dma(bus, drive, block, chunk.len(), true)?;
+        let buses = BUSES.lock();
+        let dma = buses[bus].dma.as_ref().ok_or(())?;
+        chunk.copy_from_slice(&dma.buf[..chunk.len()]);
+    }
+    Ok(())
+}
+
+fn write_dma(bus: usize, drive: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+    for (i, chunk) in buf.chunks(DMA_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * DMA_COUNT) as u64;
+        {
+            let mut buses = BUSES.lock();
+            let dma = buses[bus].dma.as_mut().ok_or(())?;
+            dma.buf[..chunk.len()].copy_from_slice(chunk);
+        }
+        transfer_dma(bus, drive, block, chunk.len(), false)?;
+    }
+    Ok(())
+}
+
+// Read blocks into a buffer with a multiple of the block size, using DMA
+// when it's available and falling back to PIO for the rest of the session
+// of the bus if it fails, in transfers of at most 256 blocks.
+pub fn read(bus: u8, drive: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    if is_dma(bus) {
+        if read_dma(bus as usize, drive, block, buf).is_ok() {
+            return Ok(());
+        }
+        debug!("ATA {}: DMA read failed, falling back to PIO", bus);
+        set_dma(bus, false)?;
+    }
+    let mut buses = BUSES.lock();
+    for (i, chunk) in buf.chunks_mut(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        buses[bus as usize].read_pio(drive, block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn write(bus: u8, drive: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+    if is_dma(bus) {
+        if write_dma(bus as usize, drive, block, buf).is_ok() {
+            return Ok(());
+        }
+        debug!("ATA {}: DMA write failed, falling back to PIO", bus);
+        set_dma(bus, false)?;
+    }
     let mut buses = BUSES.lock();
-    buses[bus as usize].read(drive, block, buf)
+    for (i, chunk) in buf.chunks(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        buses[bus as usize].write_pio(drive, block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn flush(bus: u8, drive: u8) -> Result<(), ()> {
+    let mut buses = BUSES.lock();
+    buses[bus as usize].flush(drive)
+}
+
+pub fn is_dma(bus: u8) -> bool {
+    let buses = BUSES.lock();
+    buses[bus as usize].is_dma()
 }
 
-pub fn write(bus: u8, drive: u8, block: u32, buf: &[u8]) -> Result<(), ()> {
+// Enable or disable DMA transfers on a bus with a bus-master controller
+pub fn set_dma(bus: u8, enabled: bool) -> Result<(), ()> {
     let mut buses = BUSES.lock();
-    buses[bus as usize].write(drive, block, buf)
+    buses[bus as usize].set_dma(enabled)
+}
+
+// Write the caches of all the drives to their disks
+pub fn flush_all() {
+    for drive in list() {
+        if flush(drive.bus, drive.dsk).is_err() {
+            debug!("ATA {}:{} could not flush cache", drive.bus, drive.dsk);
+        }
+    }
+}
+
+#[test_case]
+fn test_parse_mbr() {
+    let mut buf = [0; BLOCK_SIZE];
+    assert_eq!(parse_mbr(&buf), None);
+
+    buf[510..512].copy_from_slice(&MBR_SIGNATURE);
+    assert_eq!(parse_mbr(&buf), Some(Vec::new()));
+
+    // Second entry of the table
+    buf[466] = 0x0C;
+    buf[470..474].copy_from_slice(&2048u32.to_le_bytes());
+    buf[474..478].copy_from_slice(&4096u32.to_le_bytes());
+    let partitions = parse_mbr(&buf).unwrap();
+    assert_eq!(partitions.len(), 1);
+    assert_eq!(partitions[0].index, 2);
+    assert_eq!(partitions[0].kind, "FAT32");
+    assert_eq!(partitions[0].block_start, 2048);
+    assert_eq!(partitions[0].block_count, 4096);
+
+    // Extended partition
+    buf[482] = 0x0F;
+    buf[486..490].copy_from_slice(&8192u32.to_le_bytes());
+    buf[490..494].copy_from_slice(&4096u32.to_le_bytes());
+    assert_eq!(parse_mbr(&buf).unwrap().len(), 1);
+
+    // Protective MBR of a GPT disk
+    buf[450] = MBR_PROTECTIVE;
+    buf[454..458].copy_from_slice(&1u32.to_le_bytes());
+    buf[458..462].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(parse_mbr(&buf), None);
+}
+
+#[test_case]
+fn test_parse_guid() {
+    let buf = [
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+        0x3B,
+    ];
+    assert_eq!(parse_guid(&buf), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+    assert_eq!(gpt_kind(&parse_guid(&buf)), "EFI System");
+}
+
+#[test_case]
+fn test_drive_seek() {
+    let mut drive = Drive {
+        bus: 0,
+        dsk: 0,
+        part: 1,
+        model: String::new(),
+        serial: String::new(),
+        block_start: 2048,
+        block_count: 4,
+        block_index: 0,
+    };
+    let n = BLOCK_SIZE as u64;
+    assert_eq!(drive.seek(SeekFrom::Start(2 * n)), Ok(2 * n));
+    assert_eq!(drive.seek(SeekFrom::Current(n as i64)), Ok(3 * n));
+    assert_eq!(drive.seek(SeekFrom::Current(-3 * n as i64)), Ok(0));
+    assert_eq!(drive.seek(SeekFrom::End(-(n as i64))), Ok(3 * n));
+    assert_eq!(drive.block_index, 3);
+    assert_eq!(drive.transfer_count(2 * BLOCK_SIZE), Ok(1));
+
+    // The end of the partition can be reached but not crossed
+    assert_eq!(drive.seek(SeekFrom::End(0)), Ok(4 * n));
+    assert_eq!(drive.transfer_count(BLOCK_SIZE), Ok(0));
+    assert_eq!(drive.seek(SeekFrom::End(n as i64)), Err(()));
+    assert_eq!(drive.seek(SeekFrom::Current(-5 * n as i64)), Err(()));
+
+    // Offsets must be aligned on blocks
+    assert_eq!(drive.seek(SeekFrom::Start(1)), Err(()));
+    assert_eq!(drive.transfer_count(1), Err(()));
+    assert_eq!(drive.block_index, 4);
 }
@@ -1,14 +1,18 @@
 use super::boot;
 use super::timer;
 
+use crate::sys;
+
 use x86_64::instructions::interrupts;
 
 /// Halts the CPU until the next interrupt.
 ///
-/// This function preserves interrupt state.
+/// This function preserves interrupt state, and polls the network interfaces
+/// in the background with interrupts enabled.
 pub fn halt() {
     let disabled = !interrupts::are_enabled();
     interrupts::enable_and_hlt();
+    sys::net::poll();
     if disabled {
         interrupts::disable();
     }
@@ -1,4 +1,5 @@
 use super::block::Block;
+use super::journal;
 use super::super_block;
 use super::super_block::SuperBlock;
 
@@ -11,38 +12,44 @@ pub const BITMAP_SIZE: usize = 8 * super::BLOCK_SIZE;
 pub struct BitmapBlock {}
 
 impl BitmapBlock {
-    fn indexes(addr: u32) -> (u32, usize) {
-        let sb = SuperBlock::read();
+    fn indexes(volume: usize, addr: u32) -> (u32, usize) {
+        let sb = SuperBlock::read(volume);
         let i = addr - sb.data_area();
         let n = sb.block_size();
         (sb.bitmap_area() + (i / n / 8), (i % (n * 8)) as usize)
     }
 
-    pub fn alloc(addr: u32) -> Result<(), &'static str> {
-        let (a, i) = Self::indexes(addr);
-        let mut block = Block::read(a);
+    pub fn alloc(volume: usize, addr: u32) -> Result<(), &'static str> {
+        let (a, i) = Self::indexes(volume, addr);
+        let mut block = Block::read(volume, a);
         let bitmap = block.data_mut();
         if !bitmap[i / 8].get_bit(i % 8) {
             bitmap[i / 8].set_bit(i % 8, true);
-            block.write();
-            super_block::inc_alloc_count();
-            Ok(())
+            journal::transaction(volume, || {
+                block.write();
+                super_block::inc_alloc_count(volume);
+                Ok(())
+            })
+            .map_err(|_| "Allocation failed: transaction aborted")
         } else {
             Err("Allocation failed: block already allocated")
         }
     }
 
-    pub fn free(addr: u32) {
-        let (a, i) = Self::indexes(addr);
-        let mut block = Block::read(a);
+    pub fn free(volume: usize, addr: u32) -> Result<(), ()> {
+        let (a, i) = Self::indexes(volume, addr);
+        let mut block = Block::read(volume, a);
         let bitmap = block.data_mut();
         bitmap[i / 8].set_bit(i % 8, false);
-        block.write();
-        super_block::dec_alloc_count();
+        journal::transaction(volume, || {
+            block.write();
+            super_block::dec_alloc_count(volume);
+            Ok(())
+        })
     }
 
-    pub fn next_free_addr() -> Option<u32> {
-        let sb = SuperBlock::read();
+    pub fn next_free_addr(volume: usize) -> Option<u32> {
+        let sb = SuperBlock::read(volume);
         if sb.alloc_count() == sb.block_count() {
             return None;
         }
@@ -50,7 +57,7 @@ impl BitmapBlock {
         let n = sb.block_size();
         let m = sb.block_count() / n / 8;
         for i in 0..m {
-            let block = Block::read(sb.bitmap_area() + i);
+            let block = Block::read(volume, sb.bitmap_area() + i);
             let bitmap = block.data();
             for j in 0..n {
                 for k in 0..8 {
@@ -65,11 +72,11 @@ impl BitmapBlock {
     }
 }
 
-pub fn free_all() {
-    let sb = SuperBlock::read();
+pub fn free_all(volume: usize) {
+    let sb = SuperBlock::read(volume);
     let a = sb.bitmap_area();
     let b = sb.data_area();
     for addr in a..b {
-        Block::new(addr).write();
+        Block::new(volume, addr).write();
     }
 }
@@ -1,6 +1,6 @@
 use super::bitmap_block::BitmapBlock;
-use super::block_device::BlockDeviceIO;
-use super::block_device::BLOCK_DEVICE;
+use super::block_device::{self, BlockDeviceIO};
+use super::journal;
 
 use core::convert::TryInto;
 
@@ -8,6 +8,7 @@ const DATA_OFFSET: usize = 4;
 
 #[derive(Clone)]
 pub struct Block {
+    volume: usize,
     addr: u32,
     buf: [u8; super::BLOCK_SIZE],
 }
@@ -16,44 +17,61 @@ pub struct Block {
 // 0..4 => next block address
 // 4..512 => block data
 impl Block {
-    pub fn new(addr: u32) -> Self {
+    pub fn new(volume: usize, addr: u32) -> Self {
         let buf = [0; super::BLOCK_SIZE];
-        Self { addr, buf }
+        Self { volume, addr, buf }
     }
 
-    pub fn alloc() -> Option<Self> {
-        match BitmapBlock::next_free_addr() {
-            None => None,
-            Some(addr) => {
-                let _ = BitmapBlock::alloc(addr);
+    pub fn alloc(volume: usize) -> Option<Self> {
+        journal::transaction(volume, || {
+            let addr = BitmapBlock::next_free_addr(volume).ok_or(())?;
+            BitmapBlock::alloc(volume, addr).map_err(|_| ())?;
 
-                // Initialize block
-                let mut block = Block::read(addr);
-                for i in 0..super::BLOCK_SIZE {
-                    block.buf[i] = 0;
-                }
-                block.write();
-
-                Some(block)
+            // Initialize block
+            let mut block = Block::read(volume, addr);
+            for i in 0..super::BLOCK_SIZE {
+                block.buf[i] = 0;
             }
-        }
+            block.write();
+
+            Ok(block)
+        })
+        .ok()
     }
 
-    pub fn read(addr: u32) -> Self {
+    pub fn read(volume: usize, addr: u32) -> Self {
+        if let Some(buf) = journal::cached(volume, addr) {
+            return Self { volume, addr, buf };
+        }
         let mut buf = [0; super::BLOCK_SIZE];
-        if let Some(ref mut block_device) = *BLOCK_DEVICE.lock() {
-            if block_device.read(addr, &mut buf).is_err() {
-                debug!("MFS: could not read block {:#X}", addr);
-            }
+        if let Some(Err(())) =
+            block_device::with_device(volume, |dev| dev.read(addr.into(), &mut buf))
+        {
+            debug!("MFS: could not read block {:#X}", addr);
         }
-        Self { addr, buf }
+        Self { volume, addr, buf }
     }
 
     pub fn write(&self) {
-        if let Some(ref mut block_device) = *BLOCK_DEVICE.lock() {
-            if block_device.write(self.addr, &self.buf).is_err() {
-                debug!("MFS: could not write block {:#X}", self.addr);
-            }
+        if journal::record(self.volume, self.addr, &self.buf) {
+            return;
+        }
+        self.write_in_place();
+    }
+
+    // Write a block of file data in place without journaling it, before the
+    // metadata written during the current transaction is committed
+    pub fn write_data(&self) {
+        journal::forget(self.volume, self.addr);
+        self.write_in_place();
+    }
+
+    fn write_in_place(&self) {
+        let (addr, buf) = (self.addr, &self.buf);
+        if let Some(Err(())) =
+            block_device::with_device(self.volume, |dev| dev.write(addr.into(), buf))
+        {
+            debug!("MFS: could not write block {:#X}", addr);
         }
     }
 
@@ -81,19 +99,19 @@ pub struct LinkedBlock {
 }
 
 impl LinkedBlock {
-    pub fn new(addr: u32) -> Self {
+    pub fn new(volume: usize, addr: u32) -> Self {
         Self {
-            block: Block::new(addr),
+            block: Block::new(volume, addr),
         }
     }
 
-    pub fn alloc() -> Option<Self> {
-        Block::alloc().map(|block| Self { block })
+    pub fn alloc(volume: usize) -> Option<Self> {
+        Block::alloc(volume).map(|block| Self { block })
     }
 
-    pub fn read(addr: u32) -> Self {
+    pub fn read(volume: usize, addr: u32) -> Self {
         Self {
-            block: Block::read(addr),
+            block: Block::read(volume, addr),
         }
     }
 
@@ -101,6 +119,10 @@ impl LinkedBlock {
         self.block.write()
     }
 
+    pub fn write_data(&self) {
+        self.block.write_data()
+    }
+
     pub fn addr(&self) -> u32 {
         self.block.addr()
     }
@@ -118,16 +140,20 @@ impl LinkedBlock {
     }
 
     pub fn next(&self) -> Option<Self> {
-        let addr = u32::from_be_bytes(self.block.buf[0..4].try_into().unwrap());
+        let addr = self.next_addr();
         if addr == 0 {
             None
         } else {
-            Some(Self::read(addr))
+            Some(Self::read(self.block.volume, addr))
         }
     }
 
+    pub fn next_addr(&self) -> u32 {
+        u32::from_be_bytes(self.block.buf[0..4].try_into().unwrap())
+    }
+
     pub fn alloc_next(&mut self) -> Option<Self> {
-        let new_block = LinkedBlock::alloc()?;
+        let new_block = LinkedBlock::alloc(self.block.volume)?;
         self.set_next_addr(new_block.addr());
         self.write();
         Some(new_block)
@@ -1,39 +1,118 @@
 use super::bitmap_block::BitmapBlock;
 use super::dir::Dir;
+use super::fat::Fat;
+use super::iso9660::Iso9660;
+use super::journal;
+use super::mfs::Mfs;
 use super::super_block::SuperBlock;
+use super::vfs;
 
 use crate::sys;
 
+use alloc::collections::btree_map::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Mutex;
 
-pub static BLOCK_DEVICE: Mutex<Option<BlockDevice>> = Mutex::new(None);
+// The block devices of the mounted MFS volumes are indexed by an id given to
+// the functions of MFS, and the volume mounted on the root of the filesystem
+// is also used by the functions formatting or checking the disk.
+pub static BLOCK_DEVICES: Mutex<BTreeMap<usize, BlockDevice>> = Mutex::new(BTreeMap::new());
+
+static ROOT_VOLUME: AtomicUsize = AtomicUsize::new(0);
+static NEXT_VOLUME: AtomicUsize = AtomicUsize::new(1);
+
+pub fn root_volume() -> usize {
+    ROOT_VOLUME.load(Ordering::SeqCst)
+}
+
+// Run `f` on the block device of a volume
+pub fn with_device<T>(volume: usize, f: impl FnOnce(&mut BlockDevice) -> T) -> Option<T> {
+    BLOCK_DEVICES.lock().get_mut(&volume).map(f)
+}
+
+pub fn has_device(volume: usize) -> bool {
+    BLOCK_DEVICES.lock().contains_key(&volume)
+}
+
+// Add the block device of a new volume and return its id
+pub fn add_volume(dev: BlockDevice) -> usize {
+    let volume = NEXT_VOLUME.fetch_add(1, Ordering::SeqCst);
+    BLOCK_DEVICES.lock().insert(volume, dev);
+    journal::mount(volume);
+    volume
+}
+
+pub fn remove_volume(volume: usize) {
+    journal::dismount(volume);
+    BLOCK_DEVICES.lock().remove(&volume);
+}
+
+// Mount the volume of a block device on an existing directory
+fn mount_volume(dev: BlockDevice, source: &str, path: &str) -> Result<(), ()> {
+    let volume = add_volume(dev);
+    let fs = Mfs::new(volume, source);
+    vfs::mount(path, Arc::new(fs))
+}
+
+// Mount the volume of a block device on the root of the filesystem
+fn mount_root(dev: BlockDevice, source: &str) {
+    let volume = add_volume(dev);
+    ROOT_VOLUME.store(volume, Ordering::SeqCst);
+    let fs = Mfs::new(volume, source);
+    if vfs::mount("/", Arc::new(fs)).is_err() {
+        remove_volume(volume);
+    }
+}
 
 pub enum BlockDevice {
     Mem(MemBlockDevice),
     Ata(AtaBlockDevice),
+    Ahci(AhciBlockDevice),
+    Virtio(VirtioBlockDevice),
 }
 
 pub trait BlockDeviceIO {
-    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), ()>;
-    fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()>;
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ()>;
+    fn write(&mut self, addr: u64, buf: &[u8]) -> Result<(), ()>;
+    // Wait until the blocks written are stored on the device
+    fn sync(&mut self) -> Result<(), ()>;
     fn block_size(&self) -> usize;
-    fn block_count(&self) -> usize;
+    fn block_count(&self) -> u64;
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
 
 impl BlockDeviceIO for BlockDevice {
-    fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
         match self {
             BlockDevice::Mem(dev) => dev.read(addr, buf),
             BlockDevice::Ata(dev) => dev.read(addr, buf),
+            BlockDevice::Ahci(dev) => dev.read(addr, buf),
+            BlockDevice::Virtio(dev) => dev.read(addr, buf),
         }
     }
 
-    fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), ()> {
+    fn write(&mut self, addr: u64, buf: &[u8]) -> Result<(), ()> {
         match self {
             BlockDevice::Mem(dev) => dev.write(addr, buf),
             BlockDevice::Ata(dev) => dev.write(addr, buf),
+            BlockDevice::Ahci(dev) => dev.write(addr, buf),
+            BlockDevice::Virtio(dev) => dev.write(addr, buf),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), ()> {
+        match self {
+            BlockDevice::Mem(dev) => dev.sync(),
+            BlockDevice::Ata(dev) => dev.sync(),
+            BlockDevice::Ahci(dev) => dev.sync(),
+            BlockDevice::Virtio(dev) => dev.sync(),
         }
     }
 
@@ -41,42 +120,70 @@ impl BlockDeviceIO for BlockDevice {
         match self {
             BlockDevice::Mem(dev) => dev.block_size(),
             BlockDevice::Ata(dev) => dev.block_size(),
+            BlockDevice::Ahci(dev) => dev.block_size(),
+            BlockDevice::Virtio(dev) => dev.block_size(),
         }
     }
 
-    fn block_count(&self) -> usize {
+    fn block_count(&self) -> u64 {
         match self {
             BlockDevice::Mem(dev) => dev.block_count(),
             BlockDevice::Ata(dev) => dev.block_count(),
+            BlockDevice::Ahci(dev) => dev.block_count(),
+            BlockDevice::Virtio(dev) => dev.block_count(),
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        match self {
+            BlockDevice::Mem(dev) => dev.is_read_only(),
+            BlockDevice::Ata(dev) => dev.is_read_only(),
+            BlockDevice::Ahci(dev) => dev.is_read_only(),
+            BlockDevice::Virtio(dev) => dev.is_read_only(),
         }
     }
 }
 
+// The blocks reserved for the kernel at the beginning of a disk are not kept
+// in memory or in a partition, they are read as zeros.
+const RESERVED_BLOCKS: usize = crate::KERNEL_SIZE / super::BLOCK_SIZE;
+
 pub struct MemBlockDevice {
     dev: Vec<[u8; super::BLOCK_SIZE]>,
 }
 
 impl MemBlockDevice {
     pub fn new(len: usize) -> Self {
+        let len = len.saturating_sub(RESERVED_BLOCKS);
         let dev = vec![[0; super::BLOCK_SIZE]; len];
         Self { dev }
     }
 }
 
 impl BlockDeviceIO for MemBlockDevice {
-    fn read(&mut self, block_index: u32, buf: &mut [u8]) -> Result<(), ()> {
-        if block_index as usize >= self.dev.len() {
+    fn read(&mut self, block_index: u64, buf: &mut [u8]) -> Result<(), ()> {
+        let i = block_index as usize;
+        if i < RESERVED_BLOCKS {
+            buf.fill(0);
+            return Ok(());
+        }
+        if i >= RESERVED_BLOCKS + self.dev.len() {
             return Err(());
         }
-        buf[..].clone_from_slice(&self.dev[block_index as usize][..]);
+        buf[..].clone_from_slice(&self.dev[i - RESERVED_BLOCKS][..]);
         Ok(())
     }
 
-    fn write(&mut self, block_index: u32, buf: &[u8]) -> Result<(), ()> {
-        if block_index as usize >= self.dev.len() {
+    fn write(&mut self, block_index: u64, buf: &[u8]) -> Result<(), ()> {
+        let i = block_index as usize;
+        if i < RESERVED_BLOCKS || i >= RESERVED_BLOCKS + self.dev.len() {
             return Err(());
         }
-        self.dev[block_index as usize][..].clone_from_slice(buf);
+        self.dev[i - RESERVED_BLOCKS][..].clone_from_slice(buf);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), ()> {
         Ok(())
     }
 
@@ -84,8 +191,8 @@ impl BlockDeviceIO for MemBlockDevice {
         super::BLOCK_SIZE
     }
 
-    fn block_count(&self) -> usize {
-        self.dev.len()
+    fn block_count(&self) -> u64 {
+        (RESERVED_BLOCKS + self.dev.len()) as u64
     }
 }
 
@@ -93,15 +200,35 @@ pub fn mount_mem() {
     let mem = sys::mem::memory_free() / 2;
     let len = mem / super::BLOCK_SIZE; // TODO: take a size argument
     let dev = MemBlockDevice::new(len);
-    *BLOCK_DEVICE.lock() = Some(BlockDevice::Mem(dev));
+    mount_root(BlockDevice::Mem(dev), "mem");
+}
+
+// Minimum size of a volume in memory, to fit the journal and a few files
+pub const MIN_MEM_SIZE: usize = 64 << 10;
+
+// Mount a new volume of the given size in memory on a directory, that will
+// be discarded when it is unmounted.
+pub fn mount_mem_at(path: &str, size: usize) -> Result<(), ()> {
+    if size < MIN_MEM_SIZE || size > sys::mem::memory_free() / 2 {
+        return Err(());
+    }
+    let len = RESERVED_BLOCKS + size / super::BLOCK_SIZE;
+    let volume = add_volume(BlockDevice::Mem(MemBlockDevice::new(len)));
+    format_mem_volume(volume);
+    vfs::mount(path, Arc::new(Mfs::new(volume, "tmpfs")))
 }
 
 pub fn format_mem() {
     debug_assert!(is_mounted());
-    if let Some(sb) = SuperBlock::new() {
+    format_mem_volume(root_volume());
+}
+
+pub fn format_mem_volume(volume: usize) {
+    if let Some(sb) = SuperBlock::new(volume) {
         sb.write();
-        let root = Dir::root();
-        let _ = BitmapBlock::alloc(root.addr());
+        journal::format(volume);
+        let root = Dir::root(volume);
+        let _ = BitmapBlock::alloc(volume, root.addr());
     }
 }
 
@@ -109,29 +236,43 @@ const ATA_CACHE_SIZE: usize = 1024;
 
 #[derive(Clone)]
 pub struct AtaBlockDevice {
-    cache: [Option<(u32, Vec<u8>)>; ATA_CACHE_SIZE],
+    cache: [Option<(u64, Vec<u8>)>; ATA_CACHE_SIZE],
     dev: sys::ata::Drive,
 }
 
 impl AtaBlockDevice {
-    pub fn new(bus: u8, dsk: u8) -> Option<Self> {
-        sys::ata::Drive::open(bus, dsk).map(|dev| {
+    pub fn new(bus: u8, dsk: u8, part: u8) -> Option<Self> {
+        sys::ata::Drive::open_partition(bus, dsk, part).map(|dev| {
             let cache = [(); ATA_CACHE_SIZE].map(|_| None);
             Self { dev, cache }
         })
     }
 
+    // Translate the address of a block of the volume into its address on the
+    // drive. A volume on a partition starts with its superblock, so there is
+    // no address for the blocks reserved for the kernel.
+    fn drive_addr(&self, block_addr: u64) -> Option<u64> {
+        let reserved = RESERVED_BLOCKS as u64;
+        if self.dev.part == 0 {
+            Some(block_addr)
+        } else if block_addr < reserved {
+            None
+        } else {
+            Some(self.dev.block_start() + block_addr - reserved)
+        }
+    }
+
     /*
     pub fn len(&self) -> usize {
         self.block_size() * self.block_count()
     }
     */
 
-    fn hash(&self, block_addr: u32) -> usize {
+    fn hash(&self, block_addr: u64) -> usize {
         (block_addr as usize) % self.cache.len()
     }
 
-    fn cached_block(&self, block_addr: u32) -> Option<&[u8]> {
+    fn cached_block(&self, block_addr: u64) -> Option<&[u8]> {
         let h = self.hash(block_addr);
         if let Some((cached_addr, cached_buf)) = &self.cache[h] {
             if block_addr == *cached_addr {
@@ -141,69 +282,316 @@ impl AtaBlockDevice {
         None
     }
 
-    fn set_cached_block(&mut self, block_addr: u32, buf: &[u8]) {
+    fn set_cached_block(&mut self, block_addr: u64, buf: &[u8]) {
         let h = self.hash(block_addr);
         self.cache[h] = Some((block_addr, buf.to_vec()));
     }
 
-    fn unset_cached_block(&mut self, block_addr: u32) {
+    fn unset_cached_block(&mut self, block_addr: u64) {
         let h = self.hash(block_addr);
         self.cache[h] = None;
     }
 }
 
+// Only the blocks read one at a time are cached, the larger transfers of the
+// `disk` command are not.
 impl BlockDeviceIO for AtaBlockDevice {
-    fn read(&mut self, block_addr: u32, buf: &mut [u8]) -> Result<(), ()> {
-        if let Some(cached) = self.cached_block(block_addr) {
+    fn read(&mut self, block_addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        let is_block = buf.len() == self.block_size();
+        if let Some(cached) = self.cached_block(block_addr).filter(|_| is_block) {
             buf.copy_from_slice(cached);
             return Ok(());
         }
 
-        sys::ata::read(self.dev.bus, self.dev.dsk, block_addr, buf)?;
-        self.set_cached_block(block_addr, buf);
+        match self.drive_addr(block_addr) {
+            Some(addr) => sys::ata::read(self.dev.bus, self.dev.dsk, addr, buf)?,
+            None => buf.fill(0),
+        }
+        if is_block {
+            self.set_cached_block(block_addr, buf);
+        }
         Ok(())
     }
 
-    fn write(&mut self, block_addr: u32, buf: &[u8]) -> Result<(), ()> {
-        sys::ata::write(self.dev.bus, self.dev.dsk, block_addr, buf)?;
-        self.unset_cached_block(block_addr);
+    fn write(&mut self, block_addr: u64, buf: &[u8]) -> Result<(), ()> {
+        let addr = self.drive_addr(block_addr).ok_or(())?;
+        sys::ata::write(self.dev.bus, self.dev.dsk, addr, buf)?;
+        let n = buf.len().div_ceil(self.block_size()) as u64;
+        for i in 0..n {
+            self.unset_cached_block(block_addr + i);
+        }
         Ok(())
     }
 
+    fn sync(&mut self) -> Result<(), ()> {
+        sys::ata::flush(self.dev.bus, self.dev.dsk)
+    }
+
     fn block_size(&self) -> usize {
         self.dev.block_size() as usize
     }
 
-    fn block_count(&self) -> usize {
-        self.dev.block_count() as usize
+    fn block_count(&self) -> u64 {
+        if self.dev.part == 0 {
+            self.dev.block_count()
+        } else {
+            RESERVED_BLOCKS as u64 + self.dev.block_count()
+        }
+    }
+}
+
+// Path of the device of an ATA drive, or of one of its partitions
+pub fn ata_path(bus: u8, dsk: u8, part: u8) -> String {
+    if part == 0 {
+        format!("/dev/ata/{}/{}", bus, dsk)
+    } else {
+        format!("/dev/ata/{}/{}p{}", bus, dsk, part)
     }
 }
 
-pub fn mount_ata(bus: u8, dsk: u8) {
-    *BLOCK_DEVICE.lock() = AtaBlockDevice::new(bus, dsk).map(BlockDevice::Ata);
+// Mount the volume of an ATA drive, or of one of its partitions with a
+// number greater than 0, on the root of the filesystem.
+pub fn mount_ata(bus: u8, dsk: u8, part: u8) {
+    match AtaBlockDevice::new(bus, dsk, part) {
+        Some(dev) => {
+            let source = ata_path(bus, dsk, part);
+            mount_root(BlockDevice::Ata(dev), &source);
+        }
+        None => dismount(),
+    }
 }
 
-pub fn format_ata() {
-    if let Some(sb) = SuperBlock::new() {
+// Split the path of an ATA partition like "/dev/ata/0/0p1" into the path of
+// its drive and its number, with an empty number for a whole drive or disk.
+fn split_partition(source: &str) -> (&str, &str) {
+    match source.rsplit_once('p') {
+        Some((disk, part)) if source.starts_with("/dev/ata/") => (disk, part),
+        _ => (source, ""),
+    }
+}
+
+fn is_overlapping(a: &str, b: &str) -> bool {
+    let (disk_a, part_a) = split_partition(a);
+    let (disk_b, part_b) = split_partition(b);
+    disk_a == disk_b && (part_a.is_empty() || part_b.is_empty() || part_a == part_b)
+}
+
+// Check if a drive or disk is the source of a mounted filesystem, including
+// the root, where a whole drive overlaps all of its partitions.
+pub fn is_disk_mounted(source: &str) -> bool {
+    vfs::has_source(|mounted| is_overlapping(mounted, source))
+}
+
+// Mount the MFS or FAT volume of an ATA drive or partition on a directory
+pub fn mount_ata_at(bus: u8, dsk: u8, part: u8, path: &str) -> Result<(), ()> {
+    let source = ata_path(bus, dsk, part);
+    if is_disk_mounted(&source) {
+        return Err(());
+    }
+    if SuperBlock::check_ata(bus, dsk, part) {
+        let dev = AtaBlockDevice::new(bus, dsk, part).ok_or(())?;
+        mount_volume(BlockDevice::Ata(dev), &source, path)
+    } else {
+        let fs = Fat::open(bus, dsk, part, &source).ok_or(())?;
+        vfs::mount(path, Arc::new(fs))
+    }
+}
+
+// The blocks of an AHCI disk are not cached because its transfers are made
+// with DMA by the controller.
+#[derive(Clone)]
+pub struct AhciBlockDevice {
+    dev: sys::ahci::Disk,
+}
+
+impl AhciBlockDevice {
+    pub fn new(port: u8) -> Option<Self> {
+        sys::ahci::Disk::open(port).map(|dev| Self { dev })
+    }
+}
+
+impl BlockDeviceIO for AhciBlockDevice {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        sys::ahci::read(self.dev.port, addr, buf)
+    }
+
+    fn write(&mut self, addr: u64, buf: &[u8]) -> Result<(), ()> {
+        sys::ahci::write(self.dev.port, addr, buf)
+    }
+
+    fn sync(&mut self) -> Result<(), ()> {
+        sys::ahci::flush(self.dev.port)
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.block_size() as usize
+    }
+
+    fn block_count(&self) -> u64 {
+        self.dev.block_count()
+    }
+}
+
+// Path of the device of an AHCI disk
+pub fn ahci_path(port: u8) -> String {
+    format!("/dev/ahci/{}", port)
+}
+
+// Mount the volume of an AHCI disk on the root of the filesystem
+pub fn mount_ahci(port: u8) {
+    match AhciBlockDevice::new(port) {
+        Some(dev) => mount_root(BlockDevice::Ahci(dev), &ahci_path(port)),
+        None => dismount(),
+    }
+}
+
+// Mount the MFS volume of an AHCI disk on a directory
+pub fn mount_ahci_at(port: u8, path: &str) -> Result<(), ()> {
+    let source = ahci_path(port);
+    if is_disk_mounted(&source) {
+        return Err(());
+    }
+    if !SuperBlock::check_ahci(port) {
+        return Err(());
+    }
+    let dev = AhciBlockDevice::new(port).ok_or(())?;
+    mount_volume(BlockDevice::Ahci(dev), &source, path)
+}
+
+// The blocks of a VIRTIO disk are not cached because its transfers are made
+// with DMA by the device.
+#[derive(Clone)]
+pub struct VirtioBlockDevice {
+    dev: sys::virtio::blk::Disk,
+}
+
+impl VirtioBlockDevice {
+    pub fn new(id: u8) -> Option<Self> {
+        sys::virtio::blk::Disk::open(id).map(|dev| Self { dev })
+    }
+}
+
+impl BlockDeviceIO for VirtioBlockDevice {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+        sys::virtio::blk::read(self.dev.id, addr, buf)
+    }
+
+    fn write(&mut self, addr: u64, buf: &[u8]) -> Result<(), ()> {
+        sys::virtio::blk::write(self.dev.id, addr, buf)
+    }
+
+    fn sync(&mut self) -> Result<(), ()> {
+        sys::virtio::blk::flush(self.dev.id)
+    }
+
+    fn block_size(&self) -> usize {
+        self.dev.block_size() as usize
+    }
+
+    fn block_count(&self) -> u64 {
+        self.dev.block_count()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.dev.is_read_only()
+    }
+}
+
+// Path of the device of a VIRTIO disk
+pub fn virtio_path(id: u8) -> String {
+    format!("/dev/virtio/{}", id)
+}
+
+// Mount the volume of a VIRTIO disk on the root of the filesystem
+pub fn mount_virtio(id: u8) {
+    match VirtioBlockDevice::new(id) {
+        Some(dev) => mount_root(BlockDevice::Virtio(dev), &virtio_path(id)),
+        None => dismount(),
+    }
+}
+
+// Mount the MFS volume of a VIRTIO disk on a directory
+pub fn mount_virtio_at(id: u8, path: &str) -> Result<(), ()> {
+    let source = virtio_path(id);
+    if is_disk_mounted(&source) {
+        return Err(());
+    }
+    if !SuperBlock::check_virtio(id) {
+        return Err(());
+    }
+    let dev = VirtioBlockDevice::new(id).ok_or(())?;
+    mount_volume(BlockDevice::Virtio(dev), &source, path)
+}
+
+// Path of the device of an ATAPI drive
+pub fn cdrom_path(bus: u8, dsk: u8) -> String {
+    format!("/dev/cd/{}/{}", bus, dsk)
+}
+
+// Mount the ISO9660 volume of the medium in an ATAPI drive on a directory
+pub fn mount_cdrom_at(bus: u8, dsk: u8, path: &str) -> Result<(), ()> {
+    let source = cdrom_path(bus, dsk);
+    if is_disk_mounted(&source) {
+        return Err(());
+    }
+    let fs = Iso9660::open(bus, dsk, &source).ok_or(())?;
+    vfs::mount(path, Arc::new(fs))
+}
+
+// Mount the volume of a block device on the root of the filesystem and
+// format it
+pub fn format_device(dev: BlockDevice, source: &str) -> Result<(), ()> {
+    if dev.is_read_only() {
+        return Err(());
+    }
+    mount_root(dev, source);
+    if !is_mounted() {
+        return Err(());
+    }
+    format_disk();
+    Ok(())
+}
+
+pub fn format_disk() {
+    let volume = root_volume();
+    if let Some(sb) = SuperBlock::new(volume) {
         // Write super_block
         sb.write();
 
         // Write zeros into block bitmaps
-        super::bitmap_block::free_all();
+        super::bitmap_block::free_all(volume);
+
+        // Clear the journal
+        journal::format(volume);
 
         // Allocate root dir
         debug_assert!(is_mounted());
-        let root = Dir::root();
-        let _ = BitmapBlock::alloc(root.addr());
+        let root = Dir::root(volume);
+        let _ = BitmapBlock::alloc(volume, root.addr());
     }
 }
 
 pub fn is_mounted() -> bool {
-    BLOCK_DEVICE.lock().is_some()
+    has_device(root_volume())
 }
 
+// Unmount all the filesystems
 pub fn dismount() {
-    *BLOCK_DEVICE.lock() = None;
+    vfs::umount_all();
+}
+
+#[test_case]
+fn test_is_overlapping() {
+    assert!(is_overlapping("/dev/ata/0/0", "/dev/ata/0/0"));
+    assert!(is_overlapping("/dev/ata/0/0", "/dev/ata/0/0p1"));
+    assert!(is_overlapping("/dev/ata/0/0p1", "/dev/ata/0/0"));
+    assert!(is_overlapping("/dev/ata/0/0p1", "/dev/ata/0/0p1"));
+    assert!(!is_overlapping("/dev/ata/0/0p1", "/dev/ata/0/0p2"));
+    assert!(!is_overlapping("/dev/ata/0/0", "/dev/ata/0/1"));
+    assert!(!is_overlapping("/dev/ata/0/0", "/dev/ata/1/0p1"));
+    assert!(is_overlapping("/dev/ahci/0", "/dev/ahci/0"));
+    assert!(!is_overlapping("/dev/ahci/0", "/dev/ahci/1"));
+    assert!(!is_overlapping("mem", "/dev/ata/0/0"));
 }
 
 #[test_case]
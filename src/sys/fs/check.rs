@@ -0,0 +1,422 @@
+use super::block::{Block, LinkedBlock};
+use super::block_device;
+use super::dir::Dir;
+use super::dir_entry::DirEntry;
+use super::inode::Inode;
+use super::super_block::SuperBlock;
+use super::FileType;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::fmt;
+
+const LOST_AND_FOUND: &str = "/lost+found";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    // A block address outside of the data area
+    InvalidBlock(String, u32),
+    // A reachable block that is marked as free in the bitmap
+    FreeBlock(String, u32),
+    // A block already reached from another entry
+    CrossLinkedBlock(String, u32),
+    // A block already reached from the same entry
+    Cycle(String, u32),
+    // A file larger than its list of blocks
+    InvalidSize(String, u32, u32),
+    // A list of allocated blocks that can't be reached from the root dir
+    LostBlocks(u32, u32),
+    // A superblock allocation count not matching the bitmap
+    InvalidAllocCount(u32, u32),
+    // An inode number of links not matching the number of entries
+    InvalidLinkCount(u32, u32, u32),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::InvalidBlock(path, addr) => {
+                write!(f, "'{}' has an invalid block {:#X}", path, addr)
+            }
+            Problem::FreeBlock(path, addr) => {
+                write!(f, "'{}' uses the free block {:#X}", path, addr)
+            }
+            Problem::CrossLinkedBlock(path, addr) => {
+                write!(f, "'{}' shares the block {:#X}", path, addr)
+            }
+            Problem::Cycle(path, addr) => {
+                write!(f, "'{}' loops back to block {:#X}", path, addr)
+            }
+            Problem::InvalidSize(path, size, max) => {
+                write!(
+                    f,
+                    "'{}' has a size of {} bytes for {} bytes of blocks",
+                    path, size, max
+                )
+            }
+            Problem::LostBlocks(addr, count) => {
+                write!(f, "{} unreachable block(s) starting at {:#X}", count, addr)
+            }
+            Problem::InvalidAllocCount(found, expected) => {
+                write!(f, "allocation count is {} instead of {}", found, expected)
+            }
+            Problem::InvalidLinkCount(addr, found, expected) => {
+                write!(
+                    f,
+                    "inode {:#X} has {} links instead of {}",
+                    addr, found, expected
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub repaired: usize,
+    pub dirs: usize,
+    pub files: usize,
+    pub blocks: usize,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+struct Checker {
+    volume: usize,
+    repair: bool,
+    data_area: u32,
+    block_count: u32,
+    bitmap: Vec<u8>,
+    reached: BTreeSet<u32>,
+    links: BTreeMap<u32, u32>,
+    report: Report,
+}
+
+impl Checker {
+    fn new(volume: usize, repair: bool) -> Self {
+        let sb = SuperBlock::read(volume);
+        Self {
+            volume,
+            repair,
+            data_area: sb.data_area(),
+            block_count: sb.block_count(),
+            bitmap: read_bitmap(volume, &sb),
+            reached: BTreeSet::new(),
+            links: BTreeMap::new(),
+            report: Report::default(),
+        }
+    }
+
+    fn is_valid(&self, addr: u32) -> bool {
+        self.data_area <= addr && addr < self.block_count
+    }
+
+    fn is_alloc(&self, addr: u32) -> bool {
+        let i = (addr - self.data_area) as usize;
+        self.bitmap[i / 8].get_bit(i % 8)
+    }
+
+    // Follow the linked list of blocks starting at `addr` and return the
+    // number of valid blocks, truncating the list at the first invalid
+    // block when repairing. Return `None` if the first block is invalid.
+    fn check_blocks(&mut self, path: &str, addr: u32) -> Option<u32> {
+        let mut chain = BTreeSet::new();
+        let mut prev: Option<LinkedBlock> = None;
+        let mut addr = addr;
+        loop {
+            let problem = if !self.is_valid(addr) {
+                Some(Problem::InvalidBlock(path.into(), addr))
+            } else if chain.contains(&addr) {
+                Some(Problem::Cycle(path.into(), addr))
+            } else if self.reached.contains(&addr) {
+                Some(Problem::CrossLinkedBlock(path.into(), addr))
+            } else if !self.is_alloc(addr) {
+                Some(Problem::FreeBlock(path.into(), addr))
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                self.report.problems.push(problem);
+                return match prev {
+                    Some(mut block) => {
+                        if self.repair {
+                            block.set_next_addr(0);
+                            block.write();
+                            self.report.repaired += 1;
+                        }
+                        Some(chain.len() as u32)
+                    }
+                    None => None,
+                };
+            }
+
+            chain.insert(addr);
+            self.reached.insert(addr);
+            self.report.blocks += 1;
+
+            let block = LinkedBlock::read(self.volume, addr);
+            match block.next_addr() {
+                0 => return Some(chain.len() as u32),
+                next => {
+                    addr = next;
+                    prev = Some(block);
+                }
+            }
+        }
+    }
+
+    fn check_dir(&mut self, dir: &mut Dir, path: &str) {
+        self.report.dirs += 1;
+        let entries: Vec<DirEntry> = dir.entries().collect();
+        for entry in entries {
+            let sep = if path.ends_with('/') { "" } else { "/" };
+            let entry_path = format!("{}{}{}", path, sep, entry.name());
+            if let Some(addr) = entry.inode() {
+                let links = self.links.entry(addr).or_insert(0);
+                *links += 1;
+                if *links > 1 {
+                    // The blocks were checked from another hard link
+                    self.report.files += 1;
+                    continue;
+                }
+                if self.check_blocks(&entry_path, addr).is_none() {
+                    self.links.remove(&addr);
+                    if self.repair && dir.unlink_entry(&entry.name()).is_ok() {
+                        self.report.repaired += 1;
+                    }
+                    continue;
+                }
+            }
+            let before = self.report.problems.len();
+            let count = match self.check_blocks(&entry_path, entry.addr()) {
+                Some(count) => count,
+                None => {
+                    // The entry is dangling
+                    if self.repair && dir.unlink_entry(&entry.name()).is_ok() {
+                        self.report.repaired += 1;
+                    }
+                    continue;
+                }
+            };
+            let is_valid = self.repair || self.report.problems.len() == before;
+            match entry.kind() {
+                FileType::Dir if is_valid => {
+                    let mut subdir = Dir::from(entry);
+                    self.check_dir(&mut subdir, &entry_path);
+                }
+                FileType::Dir => {
+                    // Reading the entries of a broken dir could loop forever
+                }
+                _ => {
+                    self.report.files += 1;
+                    let max = count * (super::BLOCK_SIZE as u32 - 4);
+                    if entry.size() > max {
+                        let size = entry.size();
+                        let problem = Problem::InvalidSize(entry_path, size, max);
+                        self.report.problems.push(problem);
+                        if self.repair {
+                            dir.update_entry(&entry.name(), max);
+                            self.report.repaired += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_links(&mut self) {
+        for (&addr, &expected) in self.links.iter() {
+            let mut inode = Inode::read(self.volume, addr);
+            let found = inode.links();
+            if found != expected {
+                let problem = Problem::InvalidLinkCount(addr, found, expected);
+                self.report.problems.push(problem);
+                if self.repair {
+                    inode.set_links(expected);
+                    inode.write();
+                    self.report.repaired += 1;
+                }
+            }
+        }
+    }
+
+    // Return the lists of allocated blocks that can't be reached
+    fn lost_blocks(&self) -> Vec<Vec<u32>> {
+        let mut lost = BTreeSet::new();
+        for addr in self.data_area..self.block_count {
+            if self.is_alloc(addr) && !self.reached.contains(&addr) {
+                lost.insert(addr);
+            }
+        }
+
+        // The heads of the lists are the lost blocks that are not referenced
+        // by other lost blocks, and the remaining blocks are in cycles.
+        let mut linked = BTreeSet::new();
+        for addr in lost.iter() {
+            linked.insert(LinkedBlock::read(self.volume, *addr).next_addr());
+        }
+        let mut heads: Vec<u32> = lost
+            .iter()
+            .filter(|a| !linked.contains(*a))
+            .copied()
+            .collect();
+        heads.extend(lost.iter().filter(|a| linked.contains(*a)));
+
+        let mut res = Vec::new();
+        for head in heads {
+            let mut list = Vec::new();
+            let mut addr = head;
+            while lost.remove(&addr) {
+                list.push(addr);
+                addr = LinkedBlock::read(self.volume, addr).next_addr();
+            }
+            if !list.is_empty() {
+                res.push(list);
+            }
+        }
+        res
+    }
+
+    fn recover(&mut self, list: &[u32]) -> Result<(), ()> {
+        let mut dir = match Dir::open(self.volume, LOST_AND_FOUND) {
+            Some(dir) => dir,
+            None => Dir::create(self.volume, LOST_AND_FOUND).ok_or(())?,
+        };
+
+        // Terminate the list of blocks properly
+        let last = list[list.len() - 1];
+        let mut block = LinkedBlock::read(self.volume, last);
+        if block.next_addr() != 0 {
+            block.set_next_addr(0);
+            block.write();
+        }
+
+        let addr = list[0];
+        let name = format!("{:08x}", addr);
+        let size = (list.len() * (super::BLOCK_SIZE - 4)) as u32;
+        dir.insert_entry(FileType::File, &name, addr, size)
+            .ok_or(())?;
+        Ok(())
+    }
+
+    fn check_alloc_count(&mut self) {
+        let mut sb = SuperBlock::read(self.volume);
+        let bitmap = read_bitmap(self.volume, &sb);
+        let used: u32 = bitmap.iter().map(|byte| byte.count_ones()).sum();
+        let expected = sb.data_area() + used;
+        let found = sb.alloc_count();
+        if found != expected {
+            self.report
+                .problems
+                .push(Problem::InvalidAllocCount(found, expected));
+            if self.repair {
+                sb.set_alloc_count(expected);
+                sb.write();
+                self.report.repaired += 1;
+            }
+        }
+    }
+}
+
+fn read_bitmap(volume: usize, sb: &SuperBlock) -> Vec<u8> {
+    let mut bitmap = Vec::new();
+    for addr in sb.bitmap_area()..sb.data_area() {
+        bitmap.extend_from_slice(Block::read(volume, addr).data());
+    }
+
+    // Ignore the bits after the end of the disk
+    let n = (sb.block_count() - sb.data_area()) as usize;
+    for i in n..(bitmap.len() * 8) {
+        bitmap[i / 8].set_bit(i % 8, false);
+    }
+    bitmap
+}
+
+// Walk the filesystem from the root dir, checking the lists of blocks of
+// every entry against the bitmap and the superblock, and optionally repair
+// the problems found by truncating broken lists, removing dangling entries,
+// and moving unreachable blocks into `/lost+found`.
+pub fn check(repair: bool) -> Option<Report> {
+    let volume = block_device::root_volume();
+    if !block_device::has_device(volume) {
+        return None;
+    }
+
+    let mut checker = Checker::new(volume, repair);
+    let mut root = Dir::root(volume);
+    if checker.check_blocks("/", root.addr()).is_some() {
+        checker.check_dir(&mut root, "/");
+    }
+    checker.check_links();
+
+    for list in checker.lost_blocks() {
+        let problem = Problem::LostBlocks(list[0], list.len() as u32);
+        checker.report.problems.push(problem);
+        if repair && checker.recover(&list).is_ok() {
+            checker.report.repaired += 1;
+        }
+    }
+
+    checker.check_alloc_count();
+    Some(checker.report)
+}
+
+#[test_case]
+fn test_check() {
+    use super::file::File;
+    use super::FileIO;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    let mut file = File::create(volume, "/test").unwrap();
+    file.write(&[1; 1000]).unwrap();
+    assert!(Dir::create(volume, "/tmp").is_some());
+    assert!(super::link("/test", "/tmp/test").is_ok());
+    assert!(check(false).unwrap().is_clean());
+
+    // Leak a block
+    let addr = Block::alloc(volume).unwrap().addr();
+    let report = check(false).unwrap();
+    assert_eq!(report.problems, [Problem::LostBlocks(addr, 1)]);
+
+    // Recover the leaked block
+    let report = check(true).unwrap();
+    assert_eq!(report.repaired, 1);
+    assert!(check(false).unwrap().is_clean());
+    assert!(Dir::open(volume, LOST_AND_FOUND)
+        .unwrap()
+        .find(&format!("{:08x}", addr))
+        .is_some());
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_check_dangling_entry() {
+    use super::bitmap_block::BitmapBlock;
+    use super::file::File;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    let file = File::create(volume, "/test").unwrap();
+    assert!(BitmapBlock::free(volume, file.addr()).is_ok());
+    let report = check(true).unwrap();
+    assert!(!report.is_clean());
+    assert!(File::open(volume, "/test").is_none());
+    assert!(check(false).unwrap().is_clean());
+
+    super::dismount();
+}
@@ -10,6 +10,7 @@ use crate::sys::console::Console;
 use crate::sys::net::gw::NetGw;
 use crate::sys::net::ip::NetIp;
 use crate::sys::net::mac::NetMac;
+use crate::sys::net::socket::icmp::IcmpSocket;
 use crate::sys::net::socket::tcp::TcpSocket;
 use crate::sys::net::socket::udp::UdpSocket;
 use crate::sys::net::usage::NetUsage;
@@ -17,6 +18,7 @@ use crate::sys::rng::Random;
 use crate::sys::speaker::Speaker;
 use crate::sys::vga::{VgaBuffer, VgaFont, VgaMode, VgaPalette};
 
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
@@ -44,6 +46,7 @@ pub enum DeviceType {
     NetIp = 16,
     NetMac = 17,
     NetUsage = 18,
+    IcmpSocket = 19,
 }
 
 impl TryFrom<&[u8]> for DeviceType {
@@ -70,6 +73,7 @@ impl TryFrom<&[u8]> for DeviceType {
             16 => Ok(DeviceType::NetIp),
             17 => Ok(DeviceType::NetMac),
             18 => Ok(DeviceType::NetUsage),
+            19 => Ok(DeviceType::IcmpSocket),
             _ => Err(()),
         }
     }
@@ -95,6 +99,7 @@ impl DeviceType {
             DeviceType::NetIp => NetIp::size(),
             DeviceType::NetMac => NetMac::size(),
             DeviceType::NetUsage => NetUsage::size(),
+            DeviceType::IcmpSocket => IcmpSocket::size(),
             _ => 1,
         };
         let mut res = vec![0; len];
@@ -103,6 +108,14 @@ impl DeviceType {
     }
 }
 
+// The name of the interface of a network device is stored after its type,
+// and the first interface is used when it's empty.
+fn iface_name(buf: &[u8]) -> String {
+    let buf = &buf[1..];
+    let n = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[0..n]).into()
+}
+
 #[derive(Debug, Clone)]
 pub enum Device {
     Null,
@@ -124,6 +137,7 @@ pub enum Device {
     NetIp(NetIp),
     NetMac(NetMac),
     NetUsage(NetUsage),
+    IcmpSocket(IcmpSocket),
 }
 
 impl TryFrom<&[u8]> for Device {
@@ -138,21 +152,23 @@ impl TryFrom<&[u8]> for Device {
             DeviceType::BootTime => Ok(Device::BootTime(BootTime::new())),
             DeviceType::EpochTime => Ok(Device::EpochTime(EpochTime::new())),
             DeviceType::RTC => Ok(Device::RTC(RTC::new())),
-            DeviceType::TcpSocket => Ok(Device::TcpSocket(TcpSocket::new())),
-            DeviceType::UdpSocket => Ok(Device::UdpSocket(UdpSocket::new())),
+            DeviceType::TcpSocket => TcpSocket::new().map(Device::TcpSocket).ok_or(()),
+            DeviceType::UdpSocket => UdpSocket::new().map(Device::UdpSocket).ok_or(()),
             DeviceType::VgaBuffer => Ok(Device::VgaBuffer(VgaBuffer::new())),
             DeviceType::VgaFont => Ok(Device::VgaFont(VgaFont::new())),
             DeviceType::VgaMode => Ok(Device::VgaMode(VgaMode::new())),
             DeviceType::VgaPalette => Ok(Device::VgaPalette(VgaPalette::new())),
             DeviceType::Speaker => Ok(Device::Speaker(Speaker::new())),
-            DeviceType::NetGw => Ok(Device::NetGw(NetGw::new())),
-            DeviceType::NetIp => Ok(Device::NetIp(NetIp::new())),
-            DeviceType::NetMac => Ok(Device::NetMac(NetMac::new())),
-            DeviceType::NetUsage => Ok(Device::NetUsage(NetUsage::new())),
-            DeviceType::Drive if buf.len() > 2 => {
+            DeviceType::NetGw => Ok(Device::NetGw(NetGw::new(&iface_name(buf)))),
+            DeviceType::NetIp => Ok(Device::NetIp(NetIp::new(&iface_name(buf)))),
+            DeviceType::NetMac => Ok(Device::NetMac(NetMac::new(&iface_name(buf)))),
+            DeviceType::NetUsage => Ok(Device::NetUsage(NetUsage::new(&iface_name(buf)))),
+            DeviceType::IcmpSocket => IcmpSocket::new().map(Device::IcmpSocket).ok_or(()),
+            DeviceType::Drive if buf.len() > 3 => {
                 let bus = buf[1];
                 let dsk = buf[2];
-                if let Some(drive) = Drive::open(bus, dsk) {
+                let part = buf[3];
+                if let Some(drive) = Drive::open_partition(bus, dsk, part) {
                     Ok(Device::Drive(drive))
                 } else {
                     Err(())
@@ -164,11 +180,11 @@ impl TryFrom<&[u8]> for Device {
 }
 
 impl Device {
-    pub fn create(pathname: &str) -> Option<Self> {
+    pub fn create(volume: usize, pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(mut dir) = Dir::open(dirname) {
+        if let Some(mut dir) = Dir::open(volume, dirname) {
             if let Some(dir_entry) = dir.create_device(filename) {
                 return Some(Device::File(dir_entry.into()));
             }
@@ -176,14 +192,14 @@ impl Device {
         None
     }
 
-    pub fn open(pathname: &str) -> Option<Self> {
-        let pathname = realpath(pathname);
+    pub fn open(volume: usize, pathname: &str) -> Option<Self> {
+        let pathname = super::link::resolve(volume, pathname)?;
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(dir) = Dir::open(dirname) {
+        if let Some(dir) = Dir::open(volume, dirname) {
             if let Some(dir_entry) = dir.find(filename) {
                 if dir_entry.is_device() {
-                    let block = LinkedBlock::read(dir_entry.addr());
+                    let block = LinkedBlock::read(volume, dir_entry.addr());
                     let data = block.data();
                     return data.try_into().ok();
                 }
@@ -213,6 +229,7 @@ impl Device {
             Device::NetIp(_) => NetIp::size(),
             Device::NetMac(_) => NetMac::size(),
             Device::NetUsage(_) => NetUsage::size(),
+            Device::IcmpSocket(_) => IcmpSocket::size(),
         }
     }
 }
@@ -239,6 +256,7 @@ impl FileIO for Device {
             Device::NetIp(io) => io.read(buf),
             Device::NetMac(io) => io.read(buf),
             Device::NetUsage(io) => io.read(buf),
+            Device::IcmpSocket(io) => io.read(buf),
         }
     }
 
@@ -263,6 +281,7 @@ impl FileIO for Device {
             Device::NetIp(io) => io.write(buf),
             Device::NetMac(io) => io.write(buf),
             Device::NetUsage(io) => io.write(buf),
+            Device::IcmpSocket(io) => io.write(buf),
         }
     }
 
@@ -287,6 +306,7 @@ impl FileIO for Device {
             Device::NetIp(io) => io.close(),
             Device::NetMac(io) => io.close(),
             Device::NetUsage(io) => io.close(),
+            Device::IcmpSocket(io) => io.close(),
         }
     }
 
@@ -311,6 +331,7 @@ impl FileIO for Device {
             Device::NetIp(io) => io.poll(event),
             Device::NetMac(io) => io.poll(event),
             Device::NetUsage(io) => io.poll(event),
+            Device::IcmpSocket(io) => io.poll(event),
         }
     }
 }
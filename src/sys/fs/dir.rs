@@ -1,10 +1,13 @@
 use super::bitmap_block::BitmapBlock;
 use super::block::LinkedBlock;
+use super::block_device;
 use super::dir_entry::DirEntry;
+use super::inode::{Inode, INODE_KIND};
+use super::journal;
 use super::read_dir::ReadDir;
 use super::super_block::SuperBlock;
 use super::FileType;
-use super::{dirname, filename, realpath, FileIO, IO};
+use super::{dirname, filename, realpath, truncate, FileIO, IO};
 use crate::sys;
 
 use alloc::boxed::Box;
@@ -17,6 +20,7 @@ pub struct Dir {
     addr: u32,
     size: u32,
     entry_index: u32,
+    volume: usize,
 }
 
 impl From<DirEntry> for Dir {
@@ -27,20 +31,22 @@ impl From<DirEntry> for Dir {
             addr: entry.addr(),
             size: entry.size(),
             entry_index: 0,
+            volume: entry.dir().volume(),
         }
     }
 }
 
 impl Dir {
-    pub fn root() -> Self {
+    pub fn root(volume: usize) -> Self {
         let name = String::new();
-        let addr = SuperBlock::read().data_area();
+        let addr = SuperBlock::read(volume).data_area();
         let mut root = Self {
             parent: None,
             name,
             addr,
             size: 0,
             entry_index: 0,
+            volume,
         };
         root.update_size();
         root
@@ -50,11 +56,11 @@ impl Dir {
         self.parent.is_none()
     }
 
-    pub fn create(pathname: &str) -> Option<Self> {
+    pub fn create(volume: usize, pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(mut dir) = Dir::open(dirname) {
+        if let Some(mut dir) = Dir::open(volume, dirname) {
             if let Some(dir_entry) = dir.create_dir(filename) {
                 return Some(dir_entry.into());
             }
@@ -62,13 +68,13 @@ impl Dir {
         None
     }
 
-    pub fn open(pathname: &str) -> Option<Self> {
-        if !super::is_mounted() {
+    pub fn open(volume: usize, pathname: &str) -> Option<Self> {
+        if !block_device::has_device(volume) {
             return None;
         }
 
-        let mut dir = Dir::root();
-        let pathname = realpath(pathname);
+        let mut dir = Dir::root(volume);
+        let pathname = super::link::resolve(volume, pathname)?;
 
         if pathname == "/" {
             return Some(dir);
@@ -87,6 +93,10 @@ impl Dir {
         self.addr
     }
 
+    pub fn volume(&self) -> usize {
+        self.volume
+    }
+
     pub fn find(&self, name: &str) -> Option<DirEntry> {
         self.entries().find(|entry| entry.name() == name)
     }
@@ -103,104 +113,228 @@ impl Dir {
         self.create_entry(FileType::Device, name)
     }
 
+    pub fn create_link(&mut self, name: &str) -> Option<DirEntry> {
+        self.create_entry(FileType::Link, name)
+    }
+
     fn create_entry(&mut self, kind: FileType, name: &str) -> Option<DirEntry> {
-        if self.find(name).is_some() {
-            return None;
-        }
+        journal::transaction(self.volume, || {
+            if self.find(name).is_some() {
+                return Err(());
+            }
 
-        // Read the whole dir to add an entry at the end
-        let mut entries = self.entries();
-        while entries.next().is_some() {}
-
-        // Allocate a new block for the dir if no space left for adding
-        // the new entry.
-        let space_left = entries.block.data().len() - entries.block_offset();
-        let entry_len = DirEntry::empty_len() + name.len();
-        if entry_len > space_left {
-            match entries.block.alloc_next() {
-                None => return None, // Disk is full
-                Some(block) => {
-                    entries.block = block;
-                    entries.block_offset = 0;
+            // Create a new entry
+            let entry_block = LinkedBlock::alloc(self.volume).ok_or(())?;
+            let entry = self.insert_entry(kind, name, entry_block.addr(), 0);
+            if entry.is_none() {
+                BitmapBlock::free(self.volume, entry_block.addr())?;
+            }
+            entry.ok_or(())
+        })
+        .ok()
+    }
+
+    // Add an entry pointing to blocks that are already allocated
+    pub fn insert_entry(
+        &mut self,
+        kind: FileType,
+        name: &str,
+        addr: u32,
+        size: u32,
+    ) -> Option<DirEntry> {
+        self.append_entry(kind as u8, name, addr, size)
+            .map(|(time, name)| DirEntry::new(self.clone(), kind, addr, size, time, &name))
+    }
+
+    // Add a hard link to an inode
+    pub fn insert_inode(&mut self, name: &str, inode: &Inode) -> Option<DirEntry> {
+        let kind = inode.kind();
+        let addr = inode.data_addr();
+        let size = inode.size();
+        let time = inode.modified();
+        self.append_entry(INODE_KIND, name, inode.addr(), size)
+            .map(|(_, name)| {
+                DirEntry::new(self.clone(), kind, addr, size, time, &name)
+                    .with_inode(inode.addr())
+                    .with_times(inode.created(), inode.accessed())
+            })
+    }
+
+    fn append_entry(
+        &mut self,
+        kind: u8,
+        name: &str,
+        addr: u32,
+        size: u32,
+    ) -> Option<(u64, String)> {
+        journal::transaction(self.volume, || {
+            if self.find(name).is_some() {
+                return Err(());
+            }
+
+            // Read the whole dir to add an entry at the end
+            let mut entries = self.entries();
+            while entries.next().is_some() {}
+
+            // Allocate a new block for the dir if no space left for adding
+            // the new entry.
+            let space_left = entries.block.data().len() - entries.block_offset();
+            let entry_len = DirEntry::empty_len(self.volume) + name.len();
+            if entry_len > space_left {
+                match entries.block.alloc_next() {
+                    None => return Err(()), // Disk is full
+                    Some(block) => {
+                        entries.block = block;
+                        entries.block_offset = 0;
+                    }
                 }
             }
-        }
 
-        // Create a new entry
-        let entry_block = match LinkedBlock::alloc() {
-            None => return None,
-            Some(block) => block,
-        };
-        let entry_kind = kind as u8;
-        let entry_addr = entry_block.addr();
-        let entry_size = 0u32;
-        let entry_time = sys::clk::epoch_time() as u64;
-        let entry_name = truncate(name, u8::MAX as usize);
-        let n = entry_name.len();
-        let i = entries.block_offset();
-        let data = entries.block.data_mut();
-
-        data[i] = entry_kind;
-        data[(i + 1)..(i + 5)].clone_from_slice(&entry_addr.to_be_bytes());
-        data[(i + 5)..(i + 9)].clone_from_slice(&entry_size.to_be_bytes());
-        data[(i + 9)..(i + 17)].clone_from_slice(&entry_time.to_be_bytes());
-        data[i + 17] = n as u8;
-        data[(i + 18)..(i + 18 + n)].clone_from_slice(entry_name.as_bytes());
-
-        entries.block.write();
-        self.update_size();
-
-        Some(DirEntry::new(
-            self.clone(),
-            kind,
-            entry_addr,
-            entry_size,
-            entry_time,
-            &entry_name,
-        ))
+            let entry_kind = kind;
+            let entry_addr = addr;
+            let entry_size = size;
+            let entry_time = sys::clk::epoch_time() as u64;
+            let entry_name = truncate(name, u8::MAX as usize);
+            let n = entry_name.len();
+            let i = entries.block_offset();
+            let data = entries.block.data_mut();
+
+            data[i] = entry_kind;
+            data[(i + 1)..(i + 5)].clone_from_slice(&entry_addr.to_be_bytes());
+            data[(i + 5)..(i + 9)].clone_from_slice(&entry_size.to_be_bytes());
+            data[(i + 9)..(i + 17)].clone_from_slice(&entry_time.to_be_bytes());
+            if DirEntry::has_times(self.volume) {
+                data[(i + 17)..(i + 25)].clone_from_slice(&entry_time.to_be_bytes());
+                data[(i + 25)..(i + 33)].clone_from_slice(&entry_time.to_be_bytes());
+            }
+            let j = i + DirEntry::empty_len(self.volume);
+            data[j - 1] = n as u8;
+            data[j..(j + n)].clone_from_slice(entry_name.as_bytes());
+
+            entries.block.write();
+            self.update_size();
+
+            Ok((entry_time, entry_name))
+        })
+        .ok()
     }
 
     // FIXME: Deleting an entry is done by setting the entry address to 0
     // TODO: If the entry is a directory, remove its entries recursively
     pub fn delete_entry(&mut self, name: &str) -> Result<(), ()> {
+        journal::transaction(self.volume, || {
+            let entry = self.unlink_entry(name)?;
+
+            // Keep the blocks of a file that has other hard links
+            if let Some(addr) = entry.inode() {
+                let mut inode = Inode::read(self.volume, addr);
+                if inode.links() > 1 {
+                    inode.set_links(inode.links() - 1);
+                    inode.write();
+                    return Ok(());
+                }
+                BitmapBlock::free(self.volume, addr)?;
+            }
+
+            // Freeing entry blocks
+            let mut free_block = LinkedBlock::read(self.volume, entry.addr());
+            loop {
+                BitmapBlock::free(self.volume, free_block.addr())?;
+                match free_block.next() {
+                    // FIXME: read after free?
+                    Some(next_block) => free_block = next_block,
+                    None => break,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Remove an entry from the dir without freeing its blocks
+    pub fn unlink_entry(&mut self, name: &str) -> Result<DirEntry, ()> {
+        journal::transaction(self.volume, || {
+            let mut entries = self.entries();
+            for entry in &mut entries {
+                if entry.name() == name {
+                    // Zeroing entry addr
+                    let i = entries.block_offset() - entry.len();
+                    let data = entries.block.data_mut();
+                    data[i + 1] = 0;
+                    data[i + 2] = 0;
+                    data[i + 3] = 0;
+                    data[i + 4] = 0;
+                    entries.block.write();
+                    self.update_size();
+                    return Ok(entry);
+                }
+            }
+            Err(())
+        })
+    }
+
+    // Update the size and the modification time of an entry, or of its inode
+    // if it is a hard link
+    pub fn update_entry(&self, name: &str, size: u32) {
+        let time = sys::clk::epoch_time() as u64;
         let mut entries = self.entries();
         for entry in &mut entries {
             if entry.name() == name {
-                // Zeroing entry addr
+                if let Some(addr) = entry.inode() {
+                    let mut inode = Inode::read(self.volume, addr);
+                    inode.set_size(size);
+                    inode.set_modified(time);
+                    inode.write();
+                    break;
+                }
                 let i = entries.block_offset() - entry.len();
                 let data = entries.block.data_mut();
-                data[i + 1] = 0;
-                data[i + 2] = 0;
-                data[i + 3] = 0;
-                data[i + 4] = 0;
+                data[(i + 5)..(i + 9)].clone_from_slice(&size.to_be_bytes());
+                data[(i + 9)..(i + 17)].clone_from_slice(&time.to_be_bytes());
                 entries.block.write();
-                self.update_size();
-
-                // Freeing entry blocks
-                let mut free_block = LinkedBlock::read(entry.addr());
-                loop {
-                    BitmapBlock::free(free_block.addr());
-                    match free_block.next() {
-                        // FIXME: read after free?
-                        Some(next_block) => free_block = next_block,
-                        None => break,
-                    }
-                }
-                return Ok(());
+                break;
             }
         }
-        Err(())
     }
 
-    pub fn update_entry(&self, name: &str, size: u32) {
+    // Update the access time of an entry only if it is older than its
+    // modification time or than a day, to avoid writing the dir each time
+    // the file is read.
+    pub fn access_entry(&self, name: &str) {
+        if !DirEntry::has_times(self.volume) {
+            return;
+        }
         let time = sys::clk::epoch_time() as u64;
+        let mut entries = self.entries();
+        for entry in &mut entries {
+            if entry.name() == name {
+                let accessed = entry.accessed();
+                if accessed >= entry.modified() && accessed + 24 * 60 * 60 > time {
+                    break;
+                }
+                if let Some(addr) = entry.inode() {
+                    let mut inode = Inode::read(self.volume, addr);
+                    inode.set_accessed(time);
+                    inode.write();
+                    break;
+                }
+                let i = entries.block_offset() - entry.len();
+                let data = entries.block.data_mut();
+                data[(i + 25)..(i + 33)].clone_from_slice(&time.to_be_bytes());
+                entries.block.write();
+                break;
+            }
+        }
+    }
+
+    // Point an existing entry to an inode
+    pub fn relink_entry(&self, name: &str, inode: &Inode) {
         let mut entries = self.entries();
         for entry in &mut entries {
             if entry.name() == name {
                 let i = entries.block_offset() - entry.len();
                 let data = entries.block.data_mut();
-                data[(i + 5)..(i + 9)].clone_from_slice(&size.to_be_bytes());
-                data[(i + 9)..(i + 17)].clone_from_slice(&time.to_be_bytes());
+                data[i] = INODE_KIND;
+                data[(i + 1)..(i + 5)].clone_from_slice(&inode.addr().to_be_bytes());
                 entries.block.write();
                 break;
             }
@@ -215,11 +349,11 @@ impl Dir {
         self.size as usize
     }
 
-    pub fn delete(pathname: &str) -> Result<(), ()> {
+    pub fn delete(volume: usize, pathname: &str) -> Result<(), ()> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(mut dir) = Dir::open(dirname) {
+        if let Some(mut dir) = Dir::open(volume, dirname) {
             dir.delete_entry(filename)
         } else {
             Err(())
@@ -268,26 +402,18 @@ impl FileIO for Dir {
     }
 }
 
-// Truncate to the given number of bytes at most
-// while respecting char boundaries.
-fn truncate(s: &str, max: usize) -> String {
-    s.char_indices()
-        .take_while(|(i, _)| *i <= max)
-        .map(|(_, c)| c)
-        .collect()
-}
-
 #[test_case]
 fn test_dir_create() {
     super::mount_mem();
     super::format_mem();
-    assert!(Dir::open("/test").is_none());
-    assert!(Dir::create("/test").is_some());
-    assert!(Dir::open("/test").is_some());
-
-    assert!(Dir::open("/test/test").is_none());
-    assert!(Dir::create("/test/test").is_some());
-    assert!(Dir::open("/test/test").is_some());
+    let volume = super::block_device::root_volume();
+    assert!(Dir::open(volume, "/test").is_none());
+    assert!(Dir::create(volume, "/test").is_some());
+    assert!(Dir::open(volume, "/test").is_some());
+
+    assert!(Dir::open(volume, "/test/test").is_none());
+    assert!(Dir::create(volume, "/test/test").is_some());
+    assert!(Dir::open(volume, "/test/test").is_some());
     super::dismount();
 }
 
@@ -295,10 +421,11 @@ fn test_dir_create() {
 fn test_dir_delete() {
     super::mount_mem();
     super::format_mem();
-    assert!(Dir::open("/test").is_none());
-    assert!(Dir::create("/test").is_some());
-    assert!(Dir::open("/test").is_some());
-    assert!(Dir::delete("/test").is_ok());
-    assert!(Dir::open("/test").is_none());
+    let volume = super::block_device::root_volume();
+    assert!(Dir::open(volume, "/test").is_none());
+    assert!(Dir::create(volume, "/test").is_some());
+    assert!(Dir::open(volume, "/test").is_some());
+    assert!(Dir::delete(volume, "/test").is_ok());
+    assert!(Dir::open(volume, "/test").is_none());
     super::dismount();
 }
@@ -1,4 +1,5 @@
 use super::dir::Dir;
+use super::super_block;
 use super::{dirname, filename, realpath, FileType};
 
 use alloc::borrow::ToOwned;
@@ -10,20 +11,23 @@ use core::convert::TryInto;
 pub struct DirEntry {
     dir: Dir,
     addr: u32,
+    inode: Option<u32>,
 
     // FileInfo
     kind: FileType,
     size: u32,
-    time: u64,
+    created: u64,
+    modified: u64,
+    accessed: u64,
     name: String,
 }
 
 impl DirEntry {
-    pub fn open(pathname: &str) -> Option<Self> {
+    pub fn open(volume: usize, pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(dir) = Dir::open(dirname) {
+        if let Some(dir) = Dir::open(volume, dirname) {
             return dir.find(filename);
         }
         None
@@ -35,22 +39,46 @@ impl DirEntry {
             dir,
             kind,
             addr,
+            inode: None,
             size,
-            time,
+            created: time,
+            modified: time,
+            accessed: time,
             name,
         }
     }
 
-    pub fn empty_len() -> usize {
-        1 + 4 + 4 + 8 + 1
+    pub fn with_inode(mut self, addr: u32) -> Self {
+        self.inode = Some(addr);
+        self
+    }
+
+    pub fn with_times(mut self, created: u64, accessed: u64) -> Self {
+        self.created = created;
+        self.accessed = accessed;
+        self
+    }
+
+    // Entries have separate creation, modification, and access times since
+    // the version 5 of the filesystem, and only a modification time before.
+    pub fn has_times(volume: usize) -> bool {
+        super_block::mounted_version(volume) >= 5
+    }
+
+    pub fn empty_len(volume: usize) -> usize {
+        if Self::has_times(volume) {
+            1 + 4 + 4 + 8 + 8 + 8 + 1
+        } else {
+            1 + 4 + 4 + 8 + 1
+        }
     }
 
     pub fn len(&self) -> usize {
-        Self::empty_len() + self.name.len()
+        Self::empty_len(self.dir.volume()) + self.name.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        Self::empty_len() == self.len()
+        Self::empty_len(self.dir.volume()) == self.len()
     }
 
     pub fn kind(&self) -> FileType {
@@ -69,10 +97,18 @@ impl DirEntry {
         self.kind == FileType::Device
     }
 
+    pub fn is_link(&self) -> bool {
+        self.kind == FileType::Link
+    }
+
     pub fn addr(&self) -> u32 {
         self.addr
     }
 
+    pub fn inode(&self) -> Option<u32> {
+        self.inode
+    }
+
     pub fn dir(&self) -> Dir {
         self.dir.clone()
     }
@@ -85,8 +121,16 @@ impl DirEntry {
         self.size
     }
 
-    pub fn time(&self) -> u64 {
-        self.time
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn modified(&self) -> u64 {
+        self.modified
+    }
+
+    pub fn accessed(&self) -> u64 {
+        self.accessed
     }
 
     pub fn info(&self) -> FileInfo {
@@ -94,7 +138,9 @@ impl DirEntry {
             kind: self.kind,
             name: self.name(),
             size: self.size(),
-            time: self.time,
+            created: self.created,
+            modified: self.modified,
+            accessed: self.accessed,
         }
     }
 }
@@ -103,7 +149,9 @@ impl DirEntry {
 pub struct FileInfo {
     kind: FileType,
     size: u32,
-    time: u64,
+    created: u64,
+    modified: u64,
+    accessed: u64,
     name: String,
 }
 
@@ -113,20 +161,42 @@ impl FileInfo {
             kind: FileType::File,
             name: String::new(),
             size: 0,
-            time: 0,
+            created: 0,
+            modified: 0,
+            accessed: 0,
         }
     }
 
-    pub fn root() -> Self {
+    pub fn from_parts(
+        kind: FileType,
+        name: &str,
+        size: u32,
+        created: u64,
+        modified: u64,
+        accessed: u64,
+    ) -> Self {
+        let name = name.into();
+        Self {
+            kind,
+            name,
+            size,
+            created,
+            modified,
+            accessed,
+        }
+    }
+
+    pub fn root(volume: usize) -> Self {
         let kind = FileType::Dir;
         let name = String::new();
-        let size = Dir::root().size() as u32;
-        let time = 0;
+        let size = Dir::root(volume).size() as u32;
         Self {
             kind,
             name,
             size,
-            time,
+            created: 0,
+            modified: 0,
+            accessed: 0,
         }
     }
 
@@ -134,8 +204,16 @@ impl FileInfo {
         self.size
     }
 
-    pub fn time(&self) -> u64 {
-        self.time
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn modified(&self) -> u64 {
+        self.modified
+    }
+
+    pub fn accessed(&self) -> u64 {
+        self.accessed
     }
 
     pub fn name(&self) -> String {
@@ -158,12 +236,18 @@ impl FileInfo {
         self.kind == FileType::Device
     }
 
+    pub fn is_link(&self) -> bool {
+        self.kind == FileType::Link
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         debug_assert!(self.name.len() < 256);
         let mut res = Vec::new();
         res.push(self.kind as u8);
         res.extend_from_slice(&self.size.to_be_bytes());
-        res.extend_from_slice(&self.time.to_be_bytes());
+        res.extend_from_slice(&self.created.to_be_bytes());
+        res.extend_from_slice(&self.modified.to_be_bytes());
+        res.extend_from_slice(&self.accessed.to_be_bytes());
         res.push(self.name.len() as u8);
         res.extend_from_slice(self.name.as_bytes());
         res
@@ -174,14 +258,39 @@ impl From<&[u8]> for FileInfo {
     fn from(buf: &[u8]) -> Self {
         let kind = (buf[0] as usize).try_into().unwrap();
         let size = u32::from_be_bytes(buf[1..5].try_into().unwrap());
-        let time = u64::from_be_bytes(buf[5..13].try_into().unwrap());
-        let i = 14 + buf[13] as usize;
-        let name = String::from_utf8_lossy(&buf[14..i]).into();
+        let created = u64::from_be_bytes(buf[5..13].try_into().unwrap());
+        let modified = u64::from_be_bytes(buf[13..21].try_into().unwrap());
+        let accessed = u64::from_be_bytes(buf[21..29].try_into().unwrap());
+        let i = 30 + buf[29] as usize;
+        let name = String::from_utf8_lossy(&buf[30..i]).into();
         Self {
             kind,
             name,
             size,
-            time,
+            created,
+            modified,
+            accessed,
         }
     }
 }
+
+#[test_case]
+fn test_file_info() {
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+    assert!(super::file::File::create(volume, "/test").is_some());
+
+    let entry = DirEntry::open(volume, "/test").unwrap();
+    assert_eq!(entry.created(), entry.modified());
+    assert_eq!(entry.created(), entry.accessed());
+
+    let info = entry.info();
+    let copy = FileInfo::from(info.as_bytes().as_slice());
+    assert_eq!(copy.name(), "test");
+    assert_eq!(copy.created(), info.created());
+    assert_eq!(copy.modified(), info.modified());
+    assert_eq!(copy.accessed(), info.accessed());
+
+    super::dismount();
+}
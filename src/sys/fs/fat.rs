@@ -0,0 +1,675 @@
+use super::vfs::FileSystem;
+use super::{truncate, FileIO, FileInfo, FileType, OpenFlag, Resource, IO};
+use crate::sys;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::convert::TryInto;
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+// Read-only driver of the FAT16 and FAT32 filesystems with long file names.
+// The blocks are read directly from the ATA drive, and the clusters of a
+// file are found by following its chain in the first FAT.
+
+const BLOCK_SIZE: usize = super::BLOCK_SIZE;
+const ENTRY_SIZE: usize = 32;
+
+const ATTR_DIR: u8 = 0x10;
+const ATTR_VOLUME: u8 = 0x08;
+const ATTR_LFN: u8 = 0x0F;
+
+const LAST_LFN: u8 = 0x40;
+const DELETED: u8 = 0xE5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FatType {
+    Fat16,
+    Fat32,
+}
+
+// Layout of a FAT volume, with addresses of blocks relative to its first
+// block on the drive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Volume {
+    bus: u8,
+    dsk: u8,
+    block_start: u64,
+    kind: FatType,
+    cluster_size: u32, // Number of blocks in a cluster
+    cluster_count: u32,
+    fat_addr: u32,
+    root_addr: u32,
+    root_size: u32, // Number of blocks of the root dir of FAT16
+    root_cluster: u32,
+    data_addr: u32,
+    info_addr: u32,
+}
+
+impl Volume {
+    fn open(bus: u8, dsk: u8, part: u8) -> Option<Self> {
+        let drive = sys::ata::Drive::open_partition(bus, dsk, part)?;
+        let block_start = drive.block_start();
+        let mut buf = [0; BLOCK_SIZE];
+        sys::ata::read(bus, dsk, block_start, &mut buf).ok()?;
+        if buf[510..512] != [0x55, 0xAA] {
+            return None;
+        }
+
+        let block_size = u16::from_le_bytes(buf[11..13].try_into().unwrap()) as usize;
+        let cluster_size = buf[13] as u32;
+        let reserved = u16::from_le_bytes(buf[14..16].try_into().unwrap()) as u32;
+        let fat_count = buf[16] as u32;
+        let root_count = u16::from_le_bytes(buf[17..19].try_into().unwrap()) as u32;
+        let total16 = u16::from_le_bytes(buf[19..21].try_into().unwrap()) as u32;
+        let fat_size16 = u16::from_le_bytes(buf[22..24].try_into().unwrap()) as u32;
+        let total32 = u32::from_le_bytes(buf[32..36].try_into().unwrap());
+        let fat_size32 = u32::from_le_bytes(buf[36..40].try_into().unwrap());
+        let root_cluster = u32::from_le_bytes(buf[44..48].try_into().unwrap());
+        let info_addr = u16::from_le_bytes(buf[48..50].try_into().unwrap()) as u32;
+
+        let is_valid = block_size == BLOCK_SIZE
+            && cluster_size.is_power_of_two()
+            && reserved > 0
+            && fat_count > 0;
+        if !is_valid {
+            return None;
+        }
+
+        let total = if total16 > 0 { total16 } else { total32 };
+        let fat_size = if fat_size16 > 0 {
+            fat_size16
+        } else {
+            fat_size32
+        };
+        let root_size = (root_count * ENTRY_SIZE as u32).div_ceil(BLOCK_SIZE as u32);
+        let fat_addr = reserved;
+        let root_addr = fat_addr + fat_count * fat_size;
+        let data_addr = root_addr + root_size;
+        if data_addr >= total || total as u64 > drive.block_count() {
+            return None;
+        }
+        let cluster_count = (total - data_addr) / cluster_size;
+
+        // The type of a FAT is only given by its number of clusters
+        let kind = if cluster_count < 4085 {
+            return None; // FAT12 is not supported
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        Some(Self {
+            bus,
+            dsk,
+            block_start,
+            kind,
+            cluster_size,
+            cluster_count,
+            fat_addr,
+            root_addr,
+            root_size,
+            root_cluster,
+            data_addr,
+            info_addr,
+        })
+    }
+
+    fn read(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        sys::ata::read(self.bus, self.dsk, self.block_start + addr as u64, buf)
+    }
+
+    fn cluster_bytes(&self) -> u32 {
+        self.cluster_size * BLOCK_SIZE as u32
+    }
+
+    fn cluster_addr(&self, cluster: u32) -> u32 {
+        self.data_addr + (cluster - 2) * self.cluster_size
+    }
+
+    fn is_cluster(&self, cluster: u32) -> bool {
+        2 <= cluster && cluster < self.cluster_count + 2
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Option<u32> {
+        let n = match self.kind {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let offset = cluster * n;
+        let addr = self.fat_addr + offset / BLOCK_SIZE as u32;
+        let i = (offset as usize) % BLOCK_SIZE;
+        let mut buf = [0; BLOCK_SIZE];
+        self.read(addr, &mut buf).ok()?;
+        Some(match self.kind {
+            FatType::Fat16 => u16::from_le_bytes(buf[i..(i + 2)].try_into().unwrap()) as u32,
+            FatType::Fat32 => u32::from_le_bytes(buf[i..(i + 4)].try_into().unwrap()) & 0x0FFF_FFFF,
+        })
+    }
+
+    // Return the next cluster of a chain, or `None` at the end of the chain
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let next = self.fat_entry(cluster)?;
+        if self.is_cluster(next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    // Read all the clusters of a chain, or the root dir of FAT16 with the
+    // cluster 0.
+    fn read_chain(&self, cluster: u32) -> Vec<u8> {
+        if cluster == 0 && self.kind == FatType::Fat16 {
+            let mut res = vec![0; self.root_size as usize * BLOCK_SIZE];
+            for (i, buf) in res.chunks_mut(BLOCK_SIZE).enumerate() {
+                if self.read(self.root_addr + i as u32, buf).is_err() {
+                    break;
+                }
+            }
+            return res;
+        }
+
+        let mut res = Vec::new();
+        let mut cluster = if cluster == 0 {
+            self.root_cluster
+        } else {
+            cluster
+        };
+        let mut buf = vec![0; BLOCK_SIZE];
+        for _ in 0..self.cluster_count {
+            if !self.is_cluster(cluster) {
+                break;
+            }
+            let addr = self.cluster_addr(cluster);
+            for i in 0..self.cluster_size {
+                if self.read(addr + i, &mut buf).is_err() {
+                    return res;
+                }
+                res.extend_from_slice(&buf);
+            }
+            match self.next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => break,
+            }
+        }
+        res
+    }
+
+    fn entries(&self, cluster: u32) -> Vec<Entry> {
+        parse_entries(&self.read_chain(cluster))
+    }
+
+    fn find(&self, path: &str) -> Option<Entry> {
+        let mut entry = Entry::root();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if entry.kind != FileType::Dir {
+                return None;
+            }
+            entry = self
+                .entries(entry.cluster)
+                .into_iter()
+                .find(|e| e.name.eq_ignore_ascii_case(name))?;
+        }
+        Some(entry)
+    }
+
+    // Size of the list of file infos returned when reading a dir
+    fn dir_size(&self, cluster: u32) -> u32 {
+        let entries = self.entries(cluster);
+        entries
+            .iter()
+            .map(|e| e.info().as_bytes().len() as u32)
+            .sum()
+    }
+
+    // Count the free clusters with the FSInfo block of FAT32 if it is
+    // available, or by reading the whole FAT.
+    fn free_count(&self) -> u32 {
+        let mut buf = [0; BLOCK_SIZE];
+        if self.kind == FatType::Fat32 && self.read(self.info_addr, &mut buf).is_ok() {
+            let free = u32::from_le_bytes(buf[488..492].try_into().unwrap());
+            if &buf[0..4] == b"RRaA" && &buf[484..488] == b"rrAa" && free <= self.cluster_count {
+                return free;
+            }
+        }
+
+        let n = match self.kind {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let mut free = 0;
+        let mut addr = None;
+        for cluster in 2..(self.cluster_count + 2) {
+            let offset = (cluster * n) as usize;
+            let block = self.fat_addr + (offset / BLOCK_SIZE) as u32;
+            if addr != Some(block) {
+                if self.read(block, &mut buf).is_err() {
+                    break;
+                }
+                addr = Some(block);
+            }
+            let i = offset % BLOCK_SIZE;
+            if buf[i..(i + n as usize)].iter().all(|b| *b == 0) {
+                free += 1;
+            }
+        }
+        free
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: FileType,
+    name: String,
+    cluster: u32,
+    size: u32,
+    created: u64,
+    modified: u64,
+    accessed: u64,
+}
+
+impl Entry {
+    fn root() -> Self {
+        Self {
+            kind: FileType::Dir,
+            name: String::new(),
+            cluster: 0,
+            size: 0,
+            created: 0,
+            modified: 0,
+            accessed: 0,
+        }
+    }
+
+    fn info(&self) -> FileInfo {
+        FileInfo::from_parts(
+            self.kind,
+            &self.name,
+            self.size,
+            self.created,
+            self.modified,
+            self.accessed,
+        )
+    }
+}
+
+fn le16(buf: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes(buf[i..(i + 2)].try_into().unwrap())
+}
+
+// Convert a date and a time of FAT, in local time assumed to be UTC like
+// the RTC, into a Unix timestamp.
+fn timestamp(date: u16, time: u16) -> u64 {
+    let year = 1980 + (date >> 9) as i32;
+    let month = match Month::try_from(((date >> 5) & 0xF) as u8) {
+        Ok(month) => month,
+        Err(_) => return 0,
+    };
+    let date = match Date::from_calendar_date(year, month, (date & 0x1F) as u8) {
+        Ok(date) => date,
+        Err(_) => return 0,
+    };
+    let (h, m, s) = (time >> 11, (time >> 5) & 0x3F, (time & 0x1F) * 2);
+    let time = Time::from_hms(h as u8, m as u8, s as u8).unwrap_or(Time::MIDNIGHT);
+    PrimitiveDateTime::new(date, time)
+        .assume_utc()
+        .unix_timestamp() as u64
+}
+
+fn checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, b| {
+        (sum >> 1).wrapping_add(sum << 7).wrapping_add(*b)
+    })
+}
+
+// Short names are made of a base of 8 chars and an extension of 3 chars,
+// padded with spaces, that can be shown in lowercase with flags used by
+// Windows NT.
+fn short_name(entry: &[u8]) -> String {
+    let mut name = entry[0..11].to_vec();
+    if name[0] == 0x05 {
+        name[0] = DELETED;
+    }
+    let lower = |s: &[u8], flag: u8| {
+        let s = String::from_utf8_lossy(s);
+        let s = s.trim_end();
+        if entry[12] & flag != 0 {
+            s.to_lowercase()
+        } else {
+            s.into()
+        }
+    };
+    let base = lower(&name[0..8], 0x08);
+    let ext = lower(&name[8..11], 0x10);
+    if ext.is_empty() {
+        base
+    } else {
+        base + "." + &ext
+    }
+}
+
+// Parse the entries of a dir, with their long file names given by the LFN
+// entries stored before them in reverse order.
+fn parse_entries(data: &[u8]) -> Vec<Entry> {
+    let mut res = Vec::new();
+    let mut lfn: Vec<(u8, Vec<u16>)> = Vec::new();
+    let mut lfn_checksum = 0;
+    for entry in data.chunks_exact(ENTRY_SIZE) {
+        if entry[0] == 0 {
+            break;
+        }
+        if entry[0] == DELETED {
+            lfn.clear();
+            continue;
+        }
+        let attr = entry[11];
+        if attr == ATTR_LFN {
+            if entry[0] & LAST_LFN != 0 {
+                lfn.clear();
+                lfn_checksum = entry[13];
+            }
+            let chars = [&entry[1..11], &entry[14..26], &entry[28..32]]
+                .concat()
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|c| *c != 0x0000 && *c != 0xFFFF)
+                .collect();
+            lfn.push((entry[0] & 0x1F, chars));
+            continue;
+        }
+        if attr & ATTR_VOLUME != 0 || entry[0] == b'.' {
+            lfn.clear();
+            continue;
+        }
+
+        let name = if !lfn.is_empty() && lfn_checksum == checksum(&entry[0..11]) {
+            lfn.sort_by_key(|(i, _)| *i);
+            let chars: Vec<u16> = lfn.iter().flat_map(|(_, c)| c.clone()).collect();
+            String::from_utf16_lossy(&chars)
+        } else {
+            short_name(entry)
+        };
+        lfn.clear();
+
+        let kind = if attr & ATTR_DIR != 0 {
+            FileType::Dir
+        } else {
+            FileType::File
+        };
+        let cluster = ((le16(entry, 20) as u32) << 16) | le16(entry, 26) as u32;
+        let size = if kind == FileType::Dir {
+            0
+        } else {
+            u32::from_le_bytes(entry[28..32].try_into().unwrap())
+        };
+        res.push(Entry {
+            kind,
+            name: truncate(&name, u8::MAX as usize),
+            cluster,
+            size,
+            created: timestamp(le16(entry, 16), le16(entry, 14)),
+            modified: timestamp(le16(entry, 24), le16(entry, 22)),
+            accessed: timestamp(le16(entry, 18), 0),
+        });
+    }
+    res
+}
+
+// A file or a dir opened on a FAT volume
+#[derive(Debug, Clone)]
+pub struct FatFile {
+    volume: Volume,
+    entry: Entry,
+    offset: u32,
+    cluster: u32,       // Current cluster of a file
+    cluster_index: u32, // Index of the current cluster in its chain
+}
+
+impl FatFile {
+    fn new(volume: Volume, entry: Entry) -> Self {
+        let cluster = entry.cluster;
+        Self {
+            volume,
+            entry,
+            offset: 0,
+            cluster,
+            cluster_index: 0,
+        }
+    }
+
+    pub fn kind(&self) -> FileType {
+        self.entry.kind
+    }
+
+    // Find the cluster of the current offset, following the chain from the
+    // current cluster when possible.
+    fn seek_cluster(&mut self) -> Option<u32> {
+        let i = self.offset / self.volume.cluster_bytes();
+        if i < self.cluster_index {
+            self.cluster = self.entry.cluster;
+            self.cluster_index = 0;
+        }
+        while self.cluster_index < i {
+            self.cluster = self.volume.next_cluster(self.cluster)?;
+            self.cluster_index += 1;
+        }
+        if self.volume.is_cluster(self.cluster) {
+            Some(self.cluster)
+        } else {
+            None
+        }
+    }
+
+    fn read_file(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut block = [0; BLOCK_SIZE];
+        let mut i = 0;
+        while i < buf.len() && self.offset < self.entry.size {
+            let cluster = match self.seek_cluster() {
+                Some(cluster) => cluster,
+                None => break,
+            };
+            let offset = self.offset % self.volume.cluster_bytes();
+            let addr = self.volume.cluster_addr(cluster) + offset / BLOCK_SIZE as u32;
+            self.volume.read(addr, &mut block)?;
+            let j = offset as usize % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - j)
+                .min(buf.len() - i)
+                .min((self.entry.size - self.offset) as usize);
+            buf[i..(i + n)].copy_from_slice(&block[j..(j + n)]);
+            self.offset += n as u32;
+            i += n;
+        }
+        Ok(i)
+    }
+
+    // The offset of a dir is the index of its next entry
+    fn read_dir(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut i = 0;
+        let entries = self.volume.entries(self.entry.cluster);
+        for entry in entries.iter().skip(self.offset as usize) {
+            let bytes = entry.info().as_bytes();
+            let j = i + bytes.len();
+            if j <= buf.len() {
+                buf[i..j].copy_from_slice(&bytes);
+                self.offset += 1;
+                i = j;
+            } else {
+                break;
+            }
+        }
+        Ok(i)
+    }
+}
+
+impl FileIO for FatFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        match self.entry.kind {
+            FileType::Dir => self.read_dir(buf),
+            _ => self.read_file(buf),
+        }
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => match self.entry.kind {
+                FileType::Dir => {
+                    let n = self.volume.entries(self.entry.cluster).len();
+                    (self.offset as usize) < n
+                }
+                _ => self.offset < self.entry.size,
+            },
+            IO::Write => false,
+        }
+    }
+}
+
+pub struct Fat {
+    volume: Volume,
+    source: String,
+    free_count: u32,
+}
+
+impl Fat {
+    // Open the FAT volume of an ATA drive, or of one of its partitions
+    pub fn open(bus: u8, dsk: u8, part: u8, source: &str) -> Option<Self> {
+        let volume = Volume::open(bus, dsk, part)?;
+        let free_count = volume.free_count();
+        let source = source.into();
+        Some(Self {
+            volume,
+            source,
+            free_count,
+        })
+    }
+}
+
+impl FileSystem for Fat {
+    fn name(&self) -> String {
+        match self.volume.kind {
+            FatType::Fat16 => "fat16".into(),
+            FatType::Fat32 => "fat32".into(),
+        }
+    }
+
+    fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    fn open(&self, path: &str, flags: u8) -> Option<Resource> {
+        let is_writing = [
+            OpenFlag::Append,
+            OpenFlag::Create,
+            OpenFlag::Truncate,
+            OpenFlag::Device,
+            OpenFlag::Link,
+        ]
+        .iter()
+        .any(|flag| flag.is_set(flags));
+        if is_writing {
+            return None;
+        }
+        let entry = self.volume.find(path)?;
+        if OpenFlag::Dir.is_set(flags) != (entry.kind == FileType::Dir) {
+            return None;
+        }
+        let file = FatFile::new(self.volume.clone(), entry);
+        Some(Resource::Fat(file))
+    }
+
+    fn delete(&self, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn info(&self, path: &str) -> Option<FileInfo> {
+        let mut entry = self.volume.find(path)?;
+        if entry.kind == FileType::Dir {
+            entry.size = self.volume.dir_size(entry.cluster);
+        }
+        Some(entry.info())
+    }
+
+    fn link(&self, _target: &str, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn read_link(&self, _path: &str) -> Option<String> {
+        None
+    }
+
+    fn size(&self) -> usize {
+        self.volume.cluster_count as usize * self.volume.cluster_bytes() as usize
+    }
+
+    fn used(&self) -> usize {
+        let n = self.volume.cluster_count - self.free_count;
+        n as usize * self.volume.cluster_bytes() as usize
+    }
+
+    fn is_open(&self, res: &Resource) -> bool {
+        match res {
+            Resource::Fat(file) => file.volume == self.volume,
+            _ => false,
+        }
+    }
+
+    fn unmount(&self) {}
+}
+
+#[test_case]
+fn test_timestamp() {
+    // 2024-03-15 12:34:56
+    let date = ((2024 - 1980) << 9) | (3 << 5) | 15;
+    let time = (12 << 11) | (34 << 5) | (56 / 2);
+    assert_eq!(timestamp(date, time), 1710506096);
+    assert_eq!(timestamp(0, 0), 0);
+}
+
+#[test_case]
+fn test_parse_entries() {
+    let mut data = [0; 4 * ENTRY_SIZE];
+
+    // Short entry of "README.TXT" in lowercase
+    let short = b"README  TXT";
+    data[96..107].copy_from_slice(short);
+    data[96 + 12] = 0x08 | 0x10;
+    data[96 + 26] = 3; // Cluster
+    data[96 + 28] = 42; // Size
+    assert_eq!(parse_entries(&data[96..])[0].name, "readme.txt");
+
+    // Long file name "Hello, World.txt" in two LFN entries
+    let name: Vec<u16> = "Hello, World.txt".encode_utf16().collect();
+    let mut chars = [0xFFFF; 26];
+    chars[..name.len()].copy_from_slice(&name);
+    chars[name.len()] = 0;
+    for (i, part) in chars.chunks(13).enumerate() {
+        let j = (1 - i) * ENTRY_SIZE + ENTRY_SIZE;
+        let entry = &mut data[j..(j + ENTRY_SIZE)];
+        entry[0] = (i as u8 + 1) | if i == 1 { LAST_LFN } else { 0 };
+        entry[11] = ATTR_LFN;
+        entry[13] = checksum(short);
+        let bytes: Vec<u8> = part.iter().flat_map(|c| c.to_le_bytes()).collect();
+        entry[1..11].copy_from_slice(&bytes[0..10]);
+        entry[14..26].copy_from_slice(&bytes[10..22]);
+        entry[28..32].copy_from_slice(&bytes[22..26]);
+    }
+
+    // Deleted entry
+    data[0] = DELETED;
+    data[11] = ATTR_DIR;
+
+    let entries = parse_entries(&data);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "Hello, World.txt");
+    assert_eq!(entries[0].kind, FileType::File);
+    assert_eq!(entries[0].cluster, 3);
+    assert_eq!(entries[0].size, 42);
+}
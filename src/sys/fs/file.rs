@@ -1,19 +1,15 @@
 use super::bitmap_block::BitmapBlock;
 use super::block::LinkedBlock;
+use super::block_device;
 use super::dir::Dir;
 use super::dir_entry::DirEntry;
-use super::{dirname, filename, realpath, FileIO, IO};
+use super::journal;
+use super::{dirname, filename, realpath, FileIO, SeekFrom, IO};
 
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::vec;
 
-pub enum SeekFrom {
-    Start(u32),
-    Current(i32),
-    End(i32),
-}
-
 #[derive(Debug, Clone)]
 pub struct File {
     parent: Option<Box<Dir>>,
@@ -21,6 +17,7 @@ pub struct File {
     addr: u32,
     size: u32,
     offset: u32,
+    volume: usize,
 }
 
 impl From<DirEntry> for File {
@@ -31,6 +28,7 @@ impl From<DirEntry> for File {
             addr: entry.addr(),
             size: entry.size(),
             offset: 0,
+            volume: entry.dir().volume(),
         }
     }
 }
@@ -43,14 +41,15 @@ impl File {
             addr: 0,
             size: 0,
             offset: 0,
+            volume: block_device::root_volume(),
         }
     }
 
-    pub fn create(pathname: &str) -> Option<Self> {
+    pub fn create(volume: usize, pathname: &str) -> Option<Self> {
         let pathname = realpath(pathname);
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(mut dir) = Dir::open(dirname) {
+        if let Some(mut dir) = Dir::open(volume, dirname) {
             if let Ok(dir_entry) = dir.create_file(filename) {
                 return Some(dir_entry.into());
             }
@@ -58,13 +57,41 @@ impl File {
         None
     }
 
-    pub fn open(pathname: &str) -> Option<Self> {
-        let pathname = realpath(pathname);
+    pub fn open(volume: usize, pathname: &str) -> Option<Self> {
+        let pathname = super::link::resolve(volume, pathname)?;
         let dirname = dirname(&pathname);
         let filename = filename(&pathname);
-        if let Some(dir) = Dir::open(dirname) {
+        if let Some(dir) = Dir::open(volume, dirname) {
             if let Some(dir_entry) = dir.find(filename) {
                 if dir_entry.is_file() {
+                    dir.access_entry(filename);
+                    return Some(dir_entry.into());
+                }
+            }
+        }
+        None
+    }
+
+    pub fn create_link(volume: usize, pathname: &str) -> Option<Self> {
+        let pathname = realpath(pathname);
+        let dirname = dirname(&pathname);
+        let filename = filename(&pathname);
+        if let Some(mut dir) = Dir::open(volume, dirname) {
+            if let Some(dir_entry) = dir.create_link(filename) {
+                return Some(dir_entry.into());
+            }
+        }
+        None
+    }
+
+    // Open a symbolic link instead of its target
+    pub fn open_link(volume: usize, pathname: &str) -> Option<Self> {
+        let pathname = realpath(pathname);
+        let dirname = dirname(&pathname);
+        let filename = filename(&pathname);
+        if let Some(dir) = Dir::open(volume, dirname) {
+            if let Some(dir_entry) = dir.find(filename) {
+                if dir_entry.is_link() {
                     return Some(dir_entry.into());
                 }
             }
@@ -82,11 +109,11 @@ impl File {
 
     pub fn seek(&mut self, pos: SeekFrom) -> Result<u32, ()> {
         let offset = match pos {
-            SeekFrom::Start(i) => i as i32,
-            SeekFrom::Current(i) => i + self.offset as i32,
-            SeekFrom::End(i) => i + self.size as i32,
+            SeekFrom::Start(i) => i as i64,
+            SeekFrom::Current(i) => i + self.offset as i64,
+            SeekFrom::End(i) => i + self.size as i64,
         };
-        if offset < 0 || offset > self.size as i32 {
+        if offset < 0 || offset > self.size as i64 {
             // TODO: offset > size?
             return Err(());
         }
@@ -109,67 +136,35 @@ impl File {
         self.addr
     }
 
-    pub fn delete(pathname: &str) -> Result<(), ()> {
-        let pathname = realpath(pathname);
-        let dirname = dirname(&pathname);
-        let filename = filename(&pathname);
-        if let Some(mut dir) = Dir::open(dirname) {
-            dir.delete_entry(filename)
-        } else {
-            Err(())
-        }
-    }
-}
-
-impl FileIO for File {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        let buf_len = buf.len();
-        let mut addr = self.addr;
-        let mut bytes = 0; // Number of bytes read
-        let mut pos = 0; // Position in the file
-        loop {
-            let block = LinkedBlock::read(addr);
-            let data = block.data();
-            let data_len = data.len();
-            for i in 0..data_len {
-                if pos == self.offset {
-                    if bytes == buf_len || pos as usize == self.size() {
-                        return Ok(bytes);
-                    }
-                    buf[bytes] = data[i];
-                    bytes += 1;
-                    self.offset += 1;
-                }
-                pos += 1;
-            }
-            match block.next() {
-                Some(next_block) => addr = next_block.addr(),
-                None => return Ok(bytes),
-            }
-        }
+    pub fn volume(&self) -> usize {
+        self.volume
     }
 
-    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+    // The data blocks are written in place, except an existing block whose
+    // link to the next block is changed, so that the blocks allocated or
+    // freed by an aborted write are never linked to the file.
+    fn write_blocks(&mut self, buf: &[u8]) -> Result<usize, ()> {
         let buf_len = buf.len();
         let mut addr = self.addr;
+        let mut is_new = false; // Block allocated by this write
         let mut bytes = 0; // Number of bytes written
         let mut pos = 0; // Position in the file
         while bytes < buf_len {
-            let mut block = LinkedBlock::read(addr);
-            let data = block.data_mut();
-            let data_len = data.len();
-            for i in 0..data_len {
+            let mut block = LinkedBlock::read(self.volume, addr);
+            let next_addr = block.next_addr();
+            for byte in block.data_mut() {
                 if pos == self.offset {
                     if bytes == buf_len {
                         break;
                     }
-                    data[i] = buf[bytes];
+                    *byte = buf[bytes];
                     bytes += 1;
                     self.offset += 1;
                 }
                 pos += 1;
             }
 
+            let mut next_is_new = false;
             addr = match block.next() {
                 Some(next_block) => {
                     if bytes < buf_len {
@@ -178,7 +173,7 @@ impl FileIO for File {
                         // Free next block(s)
                         let mut free_block = next_block;
                         loop {
-                            BitmapBlock::free(free_block.addr());
+                            BitmapBlock::free(self.volume, free_block.addr())?;
                             match free_block.next() {
                                 // FIXME: read after free?
                                 Some(next_block) => free_block = next_block,
@@ -190,10 +185,8 @@ impl FileIO for File {
                 }
                 None => {
                     if bytes < buf_len {
-                        match LinkedBlock::alloc() {
-                            Some(next_block) => next_block.addr(),
-                            None => return Err(()),
-                        }
+                        next_is_new = true;
+                        LinkedBlock::alloc(self.volume).ok_or(())?.addr()
                     } else {
                         0
                     }
@@ -201,7 +194,12 @@ impl FileIO for File {
             };
 
             block.set_next_addr(addr);
-            block.write();
+            if is_new || addr == next_addr {
+                block.write_data();
+            } else {
+                block.write();
+            }
+            is_new = next_is_new;
         }
         self.size = self.offset;
         if let Some(dir) = self.parent.clone() {
@@ -210,6 +208,57 @@ impl FileIO for File {
         Ok(bytes)
     }
 
+    pub fn delete(volume: usize, pathname: &str) -> Result<(), ()> {
+        let pathname = realpath(pathname);
+        let dirname = dirname(&pathname);
+        let filename = filename(&pathname);
+        if let Some(mut dir) = Dir::open(volume, dirname) {
+            dir.delete_entry(filename)
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl FileIO for File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let buf_len = buf.len();
+        let mut addr = self.addr;
+        let mut bytes = 0; // Number of bytes read
+        let mut pos = 0; // Position in the file
+        loop {
+            let block = LinkedBlock::read(self.volume, addr);
+            for &byte in block.data() {
+                if pos == self.offset {
+                    if bytes == buf_len || pos as usize == self.size() {
+                        return Ok(bytes);
+                    }
+                    buf[bytes] = byte;
+                    bytes += 1;
+                    self.offset += 1;
+                }
+                pos += 1;
+            }
+            match block.next() {
+                Some(next_block) => addr = next_block.addr(),
+                None => return Ok(bytes),
+            }
+        }
+    }
+
+    // The data of a write is written before the update of the size of the
+    // file is committed in a single transaction, leaving the file unchanged
+    // if the transaction is aborted.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let (offset, size) = (self.offset, self.size);
+        let res = journal::transaction(self.volume, || self.write_blocks(buf));
+        if res.is_err() {
+            self.offset = offset;
+            self.size = size;
+        }
+        res
+    }
+
     fn close(&mut self) {}
 
     fn poll(&mut self, event: IO) -> bool {
@@ -224,8 +273,9 @@ impl FileIO for File {
 fn test_file_create() {
     super::mount_mem();
     super::format_mem();
-    assert!(File::create("/test").is_some());
-    assert_eq!(File::create("/hello").unwrap().name(), "hello");
+    let volume = super::block_device::root_volume();
+    assert!(File::create(volume, "/test").is_some());
+    assert_eq!(File::create(volume, "/hello").unwrap().name(), "hello");
     super::dismount();
 }
 
@@ -233,7 +283,8 @@ fn test_file_create() {
 fn test_file_write() {
     super::mount_mem();
     super::format_mem();
-    let mut file = File::create("/test").unwrap();
+    let volume = super::block_device::root_volume();
+    let mut file = File::create(volume, "/test").unwrap();
     let buf = "Hello, World!".as_bytes();
     assert_eq!(file.write(&buf), Ok(buf.len()));
     super::dismount();
@@ -243,11 +294,12 @@ fn test_file_write() {
 fn test_file_open() {
     super::mount_mem();
     super::format_mem();
-    assert!(File::open("/test").is_none());
-    let mut file = File::create("/test").unwrap();
+    let volume = super::block_device::root_volume();
+    assert!(File::open(volume, "/test").is_none());
+    let mut file = File::create(volume, "/test").unwrap();
     let buf = "Hello, World!".as_bytes();
     file.write(&buf).unwrap();
-    assert!(File::open("/test").is_some());
+    assert!(File::open(volume, "/test").is_some());
     super::dismount();
 }
 
@@ -255,11 +307,12 @@ fn test_file_open() {
 fn test_file_read() {
     super::mount_mem();
     super::format_mem();
-    let mut file = File::create("/test").unwrap();
+    let volume = super::block_device::root_volume();
+    let mut file = File::create(volume, "/test").unwrap();
     let input = "Hello, World!".as_bytes();
     file.write(&input).unwrap();
 
-    let mut file = File::open("/test").unwrap();
+    let mut file = File::open(volume, "/test").unwrap();
     let mut output = [0u8; 13];
     assert_eq!(file.read(&mut output), Ok(input.len()));
     assert_eq!(input, output);
@@ -270,10 +323,11 @@ fn test_file_read() {
 fn test_file_delete() {
     super::mount_mem();
     super::format_mem();
-    assert!(File::open("/test").is_none());
-    assert!(File::create("/test").is_some());
-    assert!(File::open("/test").is_some());
-    assert!(File::delete("/test").is_ok());
-    assert!(File::open("/test").is_none());
+    let volume = super::block_device::root_volume();
+    assert!(File::open(volume, "/test").is_none());
+    assert!(File::create(volume, "/test").is_some());
+    assert!(File::open(volume, "/test").is_some());
+    assert!(File::delete(volume, "/test").is_ok());
+    assert!(File::open(volume, "/test").is_none());
     super::dismount();
 }
@@ -0,0 +1,151 @@
+use super::block::LinkedBlock;
+use super::dir_entry::DirEntry;
+use super::super_block;
+use super::FileType;
+
+use core::convert::TryInto;
+
+// Kind of the dir entries pointing to an inode instead of the first block
+// of a file.
+pub const INODE_KIND: u8 = 4;
+
+// Inodes were added in the version 4 of the filesystem, and cannot be used
+// on older filesystems that could be mounted by older kernels.
+pub fn is_supported(volume: usize) -> bool {
+    super_block::mounted_version(volume) >= 4
+}
+
+// An inode is a block shared by the hard links of a file, storing the kind,
+// the address of the first block, the size, the number of links, and the
+// times of the file, so that they can be updated once for all the links.
+#[derive(Debug, Clone)]
+pub struct Inode {
+    volume: usize,
+    addr: u32,
+    kind: FileType,
+    data_addr: u32,
+    size: u32,
+    links: u32,
+    modified: u64,
+    created: u64,
+    accessed: u64,
+}
+
+// Inode structure, in the data of a linked block after the unused address
+// of the next block:
+// 0..1 => kind
+// 1..5 => data address
+// 5..9 => size
+// 9..13 => number of links
+// 13..21 => modification time
+// 21..29 => creation time
+// 29..37 => access time
+impl Inode {
+    // Allocate an inode for the file of an entry
+    pub fn alloc(entry: &DirEntry, links: u32) -> Option<Self> {
+        let volume = entry.dir().volume();
+        LinkedBlock::alloc(volume).map(|block| {
+            let inode = Self {
+                volume,
+                addr: block.addr(),
+                kind: entry.kind(),
+                data_addr: entry.addr(),
+                size: entry.size(),
+                links,
+                modified: entry.modified(),
+                created: entry.created(),
+                accessed: entry.accessed(),
+            };
+            inode.write();
+            inode
+        })
+    }
+
+    pub fn read(volume: usize, addr: u32) -> Self {
+        let block = LinkedBlock::read(volume, addr);
+        let data = block.data();
+        let modified = u64::from_be_bytes(data[13..21].try_into().unwrap());
+        let (created, accessed) = if DirEntry::has_times(volume) {
+            (
+                u64::from_be_bytes(data[21..29].try_into().unwrap()),
+                u64::from_be_bytes(data[29..37].try_into().unwrap()),
+            )
+        } else {
+            (modified, modified)
+        };
+        Self {
+            volume,
+            addr,
+            kind: (data[0] as usize).try_into().unwrap_or(FileType::File),
+            data_addr: u32::from_be_bytes(data[1..5].try_into().unwrap()),
+            size: u32::from_be_bytes(data[5..9].try_into().unwrap()),
+            links: u32::from_be_bytes(data[9..13].try_into().unwrap()),
+            modified,
+            created,
+            accessed,
+        }
+    }
+
+    pub fn write(&self) {
+        let mut block = LinkedBlock::new(self.volume, self.addr);
+        let data = block.data_mut();
+        data[0] = self.kind as u8;
+        data[1..5].clone_from_slice(&self.data_addr.to_be_bytes());
+        data[5..9].clone_from_slice(&self.size.to_be_bytes());
+        data[9..13].clone_from_slice(&self.links.to_be_bytes());
+        data[13..21].clone_from_slice(&self.modified.to_be_bytes());
+        if DirEntry::has_times(self.volume) {
+            data[21..29].clone_from_slice(&self.created.to_be_bytes());
+            data[29..37].clone_from_slice(&self.accessed.to_be_bytes());
+        }
+        block.write();
+    }
+
+    pub fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    pub fn kind(&self) -> FileType {
+        self.kind
+    }
+
+    pub fn data_addr(&self) -> u32 {
+        self.data_addr
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    pub fn links(&self) -> u32 {
+        self.links
+    }
+
+    pub fn set_links(&mut self, links: u32) {
+        self.links = links;
+    }
+
+    pub fn modified(&self) -> u64 {
+        self.modified
+    }
+
+    pub fn set_modified(&mut self, time: u64) {
+        self.modified = time;
+    }
+
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn accessed(&self) -> u64 {
+        self.accessed
+    }
+
+    pub fn set_accessed(&mut self, time: u64) {
+        self.accessed = time;
+    }
+}
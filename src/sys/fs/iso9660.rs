@@ -0,0 +1,485 @@
+use super::vfs::FileSystem;
+use super::{truncate, FileIO, FileInfo, FileType, OpenFlag, Resource, IO};
+use crate::sys;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::convert::TryInto;
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+// Read-only driver of the ISO9660 filesystem of CD-ROMs, with the names of
+// the Rock Ridge extensions when they are available. The blocks are read
+// directly from the ATAPI drive.
+
+const BLOCK_SIZE: usize = sys::ata::ATAPI_BLOCK_SIZE;
+
+// The volume descriptors start after a system area of 16 blocks
+const DESCRIPTORS_ADDR: u32 = 16;
+const DESCRIPTORS_MAX: u32 = 64;
+
+const PRIMARY_DESCRIPTOR: u8 = 1;
+const TERMINATOR: u8 = 255;
+const SIGNATURE: &[u8; 5] = b"CD001";
+
+const FLAG_DIR: u8 = 0x02;
+
+// Layout of an ISO9660 volume with the entry of its root dir
+#[derive(Debug, Clone)]
+struct Volume {
+    bus: u8,
+    dsk: u8,
+    block_count: u32,
+    root: Entry,
+}
+
+impl Volume {
+    fn open(bus: u8, dsk: u8) -> Option<Self> {
+        let mut buf = vec![0; BLOCK_SIZE];
+        for addr in DESCRIPTORS_ADDR..(DESCRIPTORS_ADDR + DESCRIPTORS_MAX) {
+            sys::ata::read_cdrom(bus, dsk, addr as u64, &mut buf).ok()?;
+            if &buf[1..6] != SIGNATURE || buf[0] == TERMINATOR {
+                return None;
+            }
+            if buf[0] != PRIMARY_DESCRIPTOR {
+                continue;
+            }
+            let block_count = both_endian_u32(&buf[80..88]);
+            let block_size = both_endian_u16(&buf[128..132]);
+            if block_size as usize != BLOCK_SIZE {
+                return None;
+            }
+            let mut root = parse_record(&buf[156..190])?;
+            root.name = String::new();
+            return Some(Self {
+                bus,
+                dsk,
+                block_count,
+                root,
+            });
+        }
+        None
+    }
+
+    fn read(&self, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+        sys::ata::read_cdrom(self.bus, self.dsk, addr as u64, buf)
+    }
+
+    // Read the extent of a dir, rounded up to a multiple of the block size
+    fn read_extent(&self, entry: &Entry) -> Vec<u8> {
+        let n = (entry.size as usize).div_ceil(BLOCK_SIZE);
+        let mut res = vec![0; n * BLOCK_SIZE];
+        if self.read(entry.extent, &mut res).is_err() {
+            res.clear();
+        }
+        res
+    }
+
+    fn entries(&self, entry: &Entry) -> Vec<Entry> {
+        parse_entries(&self.read_extent(entry))
+    }
+
+    fn find(&self, path: &str) -> Option<Entry> {
+        let mut entry = self.root.clone();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if entry.kind != FileType::Dir {
+                return None;
+            }
+            entry = self
+                .entries(&entry)
+                .into_iter()
+                .find(|e| e.name.eq_ignore_ascii_case(name))?;
+        }
+        Some(entry)
+    }
+
+    // Size of the list of file infos returned when reading a dir
+    fn dir_size(&self, entry: &Entry) -> u32 {
+        let entries = self.entries(entry);
+        entries
+            .iter()
+            .map(|e| e.info().as_bytes().len() as u32)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    kind: FileType,
+    name: String,
+    extent: u32,
+    size: u32,
+    time: u64,
+}
+
+impl Entry {
+    fn info(&self) -> FileInfo {
+        let size = if self.kind == FileType::Dir {
+            0
+        } else {
+            self.size
+        };
+        FileInfo::from_parts(self.kind, &self.name, size, self.time, self.time, self.time)
+    }
+}
+
+// Numbers are stored in both byte orders, the little endian one first
+fn both_endian_u16(buf: &[u8]) -> u16 {
+    u16::from_le_bytes(buf[0..2].try_into().unwrap())
+}
+
+fn both_endian_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes(buf[0..4].try_into().unwrap())
+}
+
+// Convert a recording date of a dir record, with an offset from UTC in
+// intervals of 15 minutes, into a Unix timestamp.
+fn timestamp(buf: &[u8]) -> u64 {
+    let year = 1900 + buf[0] as i32;
+    let month = match Month::try_from(buf[1]) {
+        Ok(month) => month,
+        Err(_) => return 0,
+    };
+    let date = match Date::from_calendar_date(year, month, buf[2]) {
+        Ok(date) => date,
+        Err(_) => return 0,
+    };
+    let time = Time::from_hms(buf[3], buf[4], buf[5]).unwrap_or(Time::MIDNIGHT);
+    let offset = (buf[6] as i8) as i64 * 15 * 60;
+    let time = PrimitiveDateTime::new(date, time)
+        .assume_utc()
+        .unix_timestamp()
+        - offset;
+    time.max(0) as u64
+}
+
+// The names of ISO9660 are in uppercase and end with a version number, they
+// are shown in lowercase without it like on other systems.
+fn iso_name(buf: &[u8]) -> String {
+    let name = String::from_utf8_lossy(buf);
+    let name = name.split(';').next().unwrap_or("");
+    name.trim_end_matches('.').to_lowercase()
+}
+
+// Find the alternate name of the Rock Ridge extensions in the system use
+// area of a dir record, made of NM entries with a flag to continue the name
+// in the next one.
+fn rock_ridge_name(buf: &[u8]) -> Option<String> {
+    let mut name = Vec::new();
+    let mut i = 0;
+    while i + 4 <= buf.len() {
+        let len = buf[i + 2] as usize;
+        if len < 4 || i + len > buf.len() {
+            break;
+        }
+        if &buf[i..(i + 2)] == b"NM" && len >= 5 {
+            name.extend_from_slice(&buf[(i + 5)..(i + len)]);
+            if buf[i + 4] & 1 == 0 {
+                break;
+            }
+        }
+        i += len;
+    }
+    if name.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&name).into())
+    }
+}
+
+fn parse_record(buf: &[u8]) -> Option<Entry> {
+    let len = *buf.first()? as usize;
+    if len < 34 || len > buf.len() {
+        return None;
+    }
+    let name_len = buf[32] as usize;
+    if 33 + name_len > len {
+        return None;
+    }
+    let name = &buf[33..(33 + name_len)];
+    let su = 33 + name_len + (1 - name_len % 2); // Padded to an even offset
+    let name = match rock_ridge_name(&buf[su.min(len)..len]) {
+        Some(name) => name,
+        None => iso_name(name),
+    };
+    let kind = if buf[25] & FLAG_DIR != 0 {
+        FileType::Dir
+    } else {
+        FileType::File
+    };
+    Some(Entry {
+        kind,
+        name: truncate(&name, u8::MAX as usize),
+        extent: both_endian_u32(&buf[2..10]),
+        size: both_endian_u32(&buf[10..18]),
+        time: timestamp(&buf[18..25]),
+    })
+}
+
+// Parse the records of a dir, which don't cross the boundaries of blocks,
+// without the entries of the dir itself and of its parent.
+fn parse_entries(data: &[u8]) -> Vec<Entry> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let len = data[i] as usize;
+        if len == 0 {
+            i = (i / BLOCK_SIZE + 1) * BLOCK_SIZE; // Next block
+            continue;
+        }
+        if i + len > data.len() {
+            break;
+        }
+        let record = &data[i..(i + len)];
+        let is_special = record.len() > 33 && record[32] == 1 && record[33] <= 1;
+        if !is_special {
+            if let Some(entry) = parse_record(record) {
+                res.push(entry);
+            }
+        }
+        i += len;
+    }
+    res
+}
+
+// A file or a dir opened on an ISO9660 volume
+#[derive(Debug, Clone)]
+pub struct IsoFile {
+    volume: Volume,
+    entry: Entry,
+    offset: u32,
+}
+
+impl IsoFile {
+    fn new(volume: Volume, entry: Entry) -> Self {
+        Self {
+            volume,
+            entry,
+            offset: 0,
+        }
+    }
+
+    pub fn kind(&self) -> FileType {
+        self.entry.kind
+    }
+
+    // The data of a file is stored in contiguous blocks
+    fn read_file(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut block = vec![0; BLOCK_SIZE];
+        let mut i = 0;
+        while i < buf.len() && self.offset < self.entry.size {
+            let addr = self.entry.extent + self.offset / BLOCK_SIZE as u32;
+            self.volume.read(addr, &mut block)?;
+            let j = self.offset as usize % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - j)
+                .min(buf.len() - i)
+                .min((self.entry.size - self.offset) as usize);
+            buf[i..(i + n)].copy_from_slice(&block[j..(j + n)]);
+            self.offset += n as u32;
+            i += n;
+        }
+        Ok(i)
+    }
+
+    // The offset of a dir is the index of its next entry
+    fn read_dir(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let mut i = 0;
+        let entries = self.volume.entries(&self.entry);
+        for entry in entries.iter().skip(self.offset as usize) {
+            let bytes = entry.info().as_bytes();
+            let j = i + bytes.len();
+            if j <= buf.len() {
+                buf[i..j].copy_from_slice(&bytes);
+                self.offset += 1;
+                i = j;
+            } else {
+                break;
+            }
+        }
+        Ok(i)
+    }
+}
+
+impl FileIO for IsoFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        match self.entry.kind {
+            FileType::Dir => self.read_dir(buf),
+            _ => self.read_file(buf),
+        }
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => match self.entry.kind {
+                FileType::Dir => {
+                    let n = self.volume.entries(&self.entry).len();
+                    (self.offset as usize) < n
+                }
+                _ => self.offset < self.entry.size,
+            },
+            IO::Write => false,
+        }
+    }
+}
+
+pub struct Iso9660 {
+    volume: Volume,
+    source: String,
+}
+
+impl Iso9660 {
+    // Open the ISO9660 volume of the medium in an ATAPI drive
+    pub fn open(bus: u8, dsk: u8, source: &str) -> Option<Self> {
+        let volume = Volume::open(bus, dsk)?;
+        let source = source.into();
+        Some(Self { volume, source })
+    }
+}
+
+impl FileSystem for Iso9660 {
+    fn name(&self) -> String {
+        "iso9660".into()
+    }
+
+    fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    fn open(&self, path: &str, flags: u8) -> Option<Resource> {
+        let is_writing = [
+            OpenFlag::Append,
+            OpenFlag::Create,
+            OpenFlag::Truncate,
+            OpenFlag::Device,
+            OpenFlag::Link,
+        ]
+        .iter()
+        .any(|flag| flag.is_set(flags));
+        if is_writing {
+            return None;
+        }
+        let entry = self.volume.find(path)?;
+        if OpenFlag::Dir.is_set(flags) != (entry.kind == FileType::Dir) {
+            return None;
+        }
+        let file = IsoFile::new(self.volume.clone(), entry);
+        Some(Resource::Iso(file))
+    }
+
+    fn delete(&self, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn info(&self, path: &str) -> Option<FileInfo> {
+        let entry = self.volume.find(path)?;
+        let size = if entry.kind == FileType::Dir {
+            self.volume.dir_size(&entry)
+        } else {
+            entry.size
+        };
+        let time = entry.time;
+        Some(FileInfo::from_parts(
+            entry.kind,
+            &entry.name,
+            size,
+            time,
+            time,
+            time,
+        ))
+    }
+
+    fn link(&self, _target: &str, _path: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn read_link(&self, _path: &str) -> Option<String> {
+        None
+    }
+
+    fn size(&self) -> usize {
+        self.volume.block_count as usize * BLOCK_SIZE
+    }
+
+    // The whole volume is used
+    fn used(&self) -> usize {
+        self.size()
+    }
+
+    fn is_open(&self, res: &Resource) -> bool {
+        match res {
+            Resource::Iso(file) => {
+                (file.volume.bus, file.volume.dsk) == (self.volume.bus, self.volume.dsk)
+            }
+            _ => false,
+        }
+    }
+
+    fn unmount(&self) {}
+}
+
+// Build a dir record with the fields in both byte orders
+#[cfg(test)]
+fn test_record(name: &[u8], su: &[u8], extent: u32, size: u32) -> Vec<u8> {
+    let pad = 1 - name.len() % 2;
+    let len = 33 + name.len() + pad + su.len();
+    let mut record = vec![0; len];
+    record[0] = len as u8;
+    record[2..6].copy_from_slice(&extent.to_le_bytes());
+    record[6..10].copy_from_slice(&extent.to_be_bytes());
+    record[10..14].copy_from_slice(&size.to_le_bytes());
+    record[14..18].copy_from_slice(&size.to_be_bytes());
+    record[32] = name.len() as u8;
+    record[33..(33 + name.len())].copy_from_slice(name);
+    record[(33 + name.len() + pad)..].copy_from_slice(su);
+    record
+}
+
+#[test_case]
+fn test_both_endian() {
+    assert_eq!(both_endian_u16(&[0x00, 0x08, 0x08, 0x00]), 2048);
+    assert_eq!(
+        both_endian_u32(&[0x78, 0x56, 0x34, 0x12, 0x12, 0x34, 0x56, 0x78]),
+        0x12345678
+    );
+
+    let record = test_record(b"A.TXT;1", &[], 0x01020304, 0x0A0B0C0D);
+    let entry = parse_record(&record).unwrap();
+    assert_eq!(entry.extent, 0x01020304);
+    assert_eq!(entry.size, 0x0A0B0C0D);
+}
+
+#[test_case]
+fn test_iso_name() {
+    assert_eq!(iso_name(b"README.TXT;1"), "readme.txt");
+    assert_eq!(iso_name(b"README.TXT;12"), "readme.txt");
+    assert_eq!(iso_name(b"NOEXT.;1"), "noext");
+    assert_eq!(iso_name(b"BOOT"), "boot");
+}
+
+#[test_case]
+fn test_record_padding() {
+    let nm = b"NM\x09\x01\x00Boot";
+
+    // A name of even length is followed by a padding byte before the system
+    // use area with the Rock Ridge name.
+    let record = test_record(b"BOOT", nm, 20, 0);
+    assert_eq!(record[37], 0);
+    assert_eq!(&record[38..40], b"NM");
+    assert_eq!(parse_record(&record).unwrap().name, "Boot");
+
+    // A name of odd length has no padding
+    let record = test_record(b"BOOT.;1", nm, 20, 0);
+    assert_eq!(&record[40..42], b"NM");
+    assert_eq!(parse_record(&record).unwrap().name, "Boot");
+
+    // The ISO name is used without the extensions
+    let record = test_record(b"BOOT.;1", &[], 20, 0);
+    assert_eq!(parse_record(&record).unwrap().name, "boot");
+}
@@ -0,0 +1,348 @@
+use super::block_device::{self, BlockDeviceIO};
+use super::super_block::SuperBlock;
+
+use alloc::collections::btree_map::BTreeMap;
+use core::convert::TryInto;
+use spin::Mutex;
+
+// The journal is a write-ahead log of blocks stored in a reserved area after
+// the superblock. The writes made during a transaction are kept in memory
+// until the transaction is committed, then they are written to the journal,
+// followed by a descriptor block listing their addresses, before being
+// written in place. A committed transaction found in the journal at mount
+// time is replayed, so the blocks of an interrupted transaction are either
+// all written or none of them are. A transaction returning an error, or
+// writing more blocks than the journal can hold, is aborted without writing
+// anything. Only the metadata of the filesystem is journaled: the data of
+// the files is written in place before the commit of its metadata.
+
+pub const JOURNAL_SIZE: u32 = 64;
+
+const SIGNATURE: &[u8; 8] = b"WFOS JNL";
+const MAX_BLOCKS: usize = (JOURNAL_SIZE - 1) as usize;
+
+static JOURNALS: Mutex<BTreeMap<usize, Journal>> = Mutex::new(BTreeMap::new());
+
+struct Journal {
+    area: Option<u32>,
+    depth: usize,
+    aborted: bool,
+    blocks: BTreeMap<u32, [u8; super::BLOCK_SIZE]>,
+}
+
+impl Journal {
+    const fn new() -> Self {
+        Self {
+            area: None,
+            depth: 0,
+            aborted: false,
+            blocks: BTreeMap::new(),
+        }
+    }
+}
+
+// Run `f` on the journal of a volume
+fn with_journal<T>(volume: usize, f: impl FnOnce(&mut Journal) -> T) -> T {
+    let mut journals = JOURNALS.lock();
+    f(journals.entry(volume).or_insert_with(Journal::new))
+}
+
+// Descriptor block structure:
+// 0..8 => signature
+// 8..12 => number of blocks
+// 12..n => addresses of the blocks
+fn descriptor(blocks: &BTreeMap<u32, [u8; super::BLOCK_SIZE]>) -> [u8; super::BLOCK_SIZE] {
+    let mut buf = [0; super::BLOCK_SIZE];
+    buf[0..8].clone_from_slice(SIGNATURE);
+    buf[8..12].clone_from_slice(&(blocks.len() as u32).to_be_bytes());
+    for (i, addr) in blocks.keys().enumerate() {
+        let j = 12 + i * 4;
+        buf[j..(j + 4)].clone_from_slice(&addr.to_be_bytes());
+    }
+    buf
+}
+
+fn read_raw(volume: usize, addr: u32, buf: &mut [u8]) -> Result<(), ()> {
+    block_device::with_device(volume, |dev| dev.read(addr.into(), buf)).unwrap_or(Err(()))
+}
+
+fn write_raw(volume: usize, addr: u32, buf: &[u8]) {
+    if let Some(Err(())) = block_device::with_device(volume, |dev| dev.write(addr.into(), buf)) {
+        debug!("MFS: could not write block {:#X}", addr);
+    }
+}
+
+// Make sure that the writes before are stored on the device before the
+// writes after
+fn barrier(volume: usize) {
+    if let Some(Err(())) = block_device::with_device(volume, |dev| dev.sync()) {
+        debug!("MFS: could not sync device");
+    }
+}
+
+fn flush(volume: usize, area: u32, blocks: &BTreeMap<u32, [u8; super::BLOCK_SIZE]>) {
+    if blocks.is_empty() {
+        return;
+    }
+
+    // Write the blocks to the journal
+    for (i, buf) in blocks.values().enumerate() {
+        write_raw(volume, area + 1 + i as u32, buf);
+    }
+    barrier(volume);
+
+    // Commit the transaction
+    write_raw(volume, area, &descriptor(blocks));
+    barrier(volume);
+
+    // Write the blocks in place
+    for (addr, buf) in blocks.iter() {
+        write_raw(volume, *addr, buf);
+    }
+    barrier(volume);
+
+    // Clear the journal
+    write_raw(volume, area, &[0; super::BLOCK_SIZE]);
+}
+
+pub fn begin(volume: usize) {
+    with_journal(volume, |journal| journal.depth += 1);
+}
+
+// Commit the current transaction, unless it was aborted, when the outermost
+// transaction ends
+pub fn commit(volume: usize) -> Result<(), ()> {
+    with_journal(volume, |journal| {
+        debug_assert!(journal.depth > 0);
+        journal.depth = journal.depth.saturating_sub(1);
+        if journal.depth > 0 {
+            return Ok(());
+        }
+        let blocks = core::mem::take(&mut journal.blocks);
+        if core::mem::take(&mut journal.aborted) {
+            return Err(());
+        }
+        if let Some(area) = journal.area {
+            flush(volume, area, &blocks);
+        }
+        Ok(())
+    })
+}
+
+// Drop the writes of the current transaction, including the ones made by
+// the outer transactions, when the outermost transaction ends
+pub fn abort(volume: usize) {
+    with_journal(volume, |journal| {
+        debug_assert!(journal.depth > 0);
+        journal.depth = journal.depth.saturating_sub(1);
+        journal.blocks.clear();
+        journal.aborted = journal.depth > 0;
+    })
+}
+
+// Group the writes made by `f` into a single transaction that is aborted if
+// `f` returns an error
+pub fn transaction<T>(volume: usize, f: impl FnOnce() -> Result<T, ()>) -> Result<T, ()> {
+    begin(volume);
+    match f() {
+        Ok(res) => commit(volume).map(|_| res),
+        Err(()) => {
+            abort(volume);
+            Err(())
+        }
+    }
+}
+
+// Keep the write of a block in the current transaction if there is one
+pub fn record(volume: usize, addr: u32, buf: &[u8]) -> bool {
+    with_journal(volume, |journal| {
+        if journal.area.is_none() || journal.depth == 0 {
+            return false;
+        }
+
+        // The writes of a transaction too large for the journal are dropped
+        // until the transaction is aborted
+        if journal.aborted {
+            return true;
+        }
+        if journal.blocks.len() == MAX_BLOCKS && !journal.blocks.contains_key(&addr) {
+            debug!("MFS: transaction too large for the journal");
+            journal.aborted = true;
+            journal.blocks.clear();
+            return true;
+        }
+
+        let mut block = [0; super::BLOCK_SIZE];
+        block.clone_from_slice(buf);
+        journal.blocks.insert(addr, block);
+        true
+    })
+}
+
+// Return the content of a block written during the current transaction
+pub fn cached(volume: usize, addr: u32) -> Option<[u8; super::BLOCK_SIZE]> {
+    with_journal(volume, |journal| journal.blocks.get(&addr).copied())
+}
+
+// Drop the write of a block from the current transaction before the block
+// is written in place, to avoid an older version of the block overwriting
+// it at commit time
+pub fn forget(volume: usize, addr: u32) {
+    with_journal(volume, |journal| journal.blocks.remove(&addr));
+}
+
+// Replay a transaction that was committed but not fully written in place
+fn replay(volume: usize, area: u32) -> usize {
+    let mut buf = [0; super::BLOCK_SIZE];
+    if read_raw(volume, area, &mut buf).is_err() || &buf[0..8] != SIGNATURE {
+        return 0;
+    }
+    let n = u32::from_be_bytes(buf[8..12].try_into().unwrap()) as usize;
+    let n = n.min(MAX_BLOCKS);
+    let mut block = [0; super::BLOCK_SIZE];
+    for i in 0..n {
+        let j = 12 + i * 4;
+        let addr = u32::from_be_bytes(buf[j..(j + 4)].try_into().unwrap());
+        if read_raw(volume, area + 1 + i as u32, &mut block).is_ok() {
+            write_raw(volume, addr, &block);
+        }
+    }
+    write_raw(volume, area, &[0; super::BLOCK_SIZE]);
+    n
+}
+
+pub fn mount(volume: usize) {
+    let area = if SuperBlock::is_formatted(volume) {
+        SuperBlock::read(volume).journal_area()
+    } else {
+        None
+    };
+    if let Some(area) = area {
+        let n = replay(volume, area);
+        if n > 0 {
+            log!("MFS Journal replayed {} blocks", n);
+        }
+    }
+    with_journal(volume, |journal| {
+        journal.area = area;
+        journal.depth = 0;
+        journal.aborted = false;
+        journal.blocks.clear();
+    });
+}
+
+pub fn dismount(volume: usize) {
+    JOURNALS.lock().remove(&volume);
+}
+
+// Clear the journal area of a new filesystem
+pub fn format(volume: usize) {
+    if let Some(area) = SuperBlock::read(volume).journal_area() {
+        write_raw(volume, area, &[0; super::BLOCK_SIZE]);
+    }
+    mount(volume);
+}
+
+#[test_case]
+fn test_journal_replay() {
+    use super::block::Block;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    let area = SuperBlock::read(volume).journal_area().unwrap();
+    let addr = SuperBlock::read(volume).data_area() + 42;
+
+    // Simulate a crash after the commit of a transaction
+    let mut blocks = BTreeMap::new();
+    blocks.insert(addr, [0xAA; super::BLOCK_SIZE]);
+    write_raw(volume, area + 1, &[0xAA; super::BLOCK_SIZE]);
+    write_raw(volume, area, &descriptor(&blocks));
+    assert_eq!(Block::read(volume, addr).data()[0], 0);
+
+    assert_eq!(replay(volume, area), 1);
+    assert_eq!(Block::read(volume, addr).data()[0], 0xAA);
+    assert_eq!(replay(volume, area), 0);
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_journal_transaction() {
+    use super::block::Block;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    let addr = SuperBlock::read(volume).data_area() + 42;
+    let res = transaction(volume, || {
+        let mut block = Block::new(volume, addr);
+        block.data_mut()[0] = 0xAA;
+        block.write();
+
+        // The write is visible inside the transaction but not on disk yet
+        let mut buf = [0; super::BLOCK_SIZE];
+        read_raw(volume, addr, &mut buf).unwrap();
+        assert_eq!(buf[0], 0);
+        assert_eq!(Block::read(volume, addr).data()[0], 0xAA);
+        Ok(())
+    });
+    assert_eq!(res, Ok(()));
+    let mut buf = [0; super::BLOCK_SIZE];
+    read_raw(volume, addr, &mut buf).unwrap();
+    assert_eq!(buf[0], 0xAA);
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_journal_overflow() {
+    use super::block::Block;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    // A transaction too large for the journal is aborted
+    let addr = SuperBlock::read(volume).data_area() + 42;
+    let res = transaction(volume, || {
+        for i in 0..(MAX_BLOCKS as u32 + 1) {
+            let mut block = Block::new(volume, addr + i);
+            block.data_mut()[0] = 0xAA;
+            block.write();
+        }
+        Ok(())
+    });
+    assert_eq!(res, Err(()));
+    assert_eq!(Block::read(volume, addr).data()[0], 0);
+    assert_eq!(Block::read(volume, addr + MAX_BLOCKS as u32).data()[0], 0);
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_journal_abort() {
+    use super::block::Block;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    // A transaction returning an error is aborted, with its inner ones
+    let addr = SuperBlock::read(volume).data_area() + 42;
+    let res: Result<(), ()> = transaction(volume, || {
+        transaction(volume, || {
+            let mut block = Block::new(volume, addr);
+            block.data_mut()[0] = 0xAA;
+            block.write();
+            Ok(())
+        })?;
+        Err(())
+    });
+    assert_eq!(res, Err(()));
+    assert_eq!(Block::read(volume, addr).data()[0], 0);
+
+    super::dismount();
+}
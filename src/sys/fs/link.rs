@@ -0,0 +1,194 @@
+use super::block_device;
+use super::dir::Dir;
+use super::dir_entry::DirEntry;
+use super::file::File;
+use super::inode::{self, Inode};
+use super::journal;
+use super::{dirname, filename, realpath, FileIO};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// Maximum number of symbolic links followed while resolving a path
+pub const MAX_LINKS: usize = 16;
+
+pub fn join(dirname: &str, filename: &str) -> String {
+    let sep = if dirname.ends_with('/') { "" } else { "/" };
+    format!("{}{}{}", dirname, sep, filename)
+}
+
+pub fn read_link(entry: &DirEntry) -> String {
+    let mut file = File::from(entry.clone());
+    file.read_to_string()
+}
+
+// Transform a path into an absolute path without symbolic links, or return
+// `None` if one of its parents doesn't exist or if there is a loop of links.
+// The last name of the path doesn't have to exist.
+pub fn resolve(volume: usize, pathname: &str) -> Option<String> {
+    if !block_device::has_device(volume) {
+        return None;
+    }
+
+    let mut pathname = realpath(pathname);
+    let mut links = 0;
+    'resolve: loop {
+        let names: Vec<String> = pathname
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .map(String::from)
+            .collect();
+        let n = names.len();
+        let mut dir = Dir::root(volume);
+        let mut res = String::from("/");
+        for (i, name) in names.iter().enumerate() {
+            if name == ".." {
+                res = dirname(&res).into();
+                dir = Dir::open(volume, &res)?;
+                continue;
+            }
+            let entry = match dir.find(name) {
+                Some(entry) => entry,
+                None if i == n - 1 => return Some(join(&res, name)),
+                None => return None,
+            };
+            if entry.is_link() {
+                links += 1;
+                if links > MAX_LINKS {
+                    return None;
+                }
+                let target = read_link(&entry);
+                let mut path = if target.starts_with('/') {
+                    target
+                } else {
+                    join(&res, &target)
+                };
+                for name in &names[(i + 1)..] {
+                    path = join(&path, name);
+                }
+                pathname = path;
+                continue 'resolve;
+            }
+            res = join(&res, name);
+            if i < n - 1 {
+                if entry.is_dir() {
+                    dir = entry.into();
+                } else {
+                    return None;
+                }
+            }
+        }
+        return Some(res);
+    }
+}
+
+// Add a hard link to a file by moving its address, size, and number of
+// links into an inode shared by all its entries.
+pub fn link(volume: usize, target: &str, pathname: &str) -> Result<(), ()> {
+    if !inode::is_supported(volume) {
+        return Err(());
+    }
+    let target = resolve(volume, target).ok_or(())?;
+    let entry = DirEntry::open(volume, &target).ok_or(())?;
+    if !entry.is_file() {
+        return Err(());
+    }
+    let pathname = realpath(pathname);
+    let mut dir = Dir::open(volume, dirname(&pathname)).ok_or(())?;
+    let name = filename(&pathname);
+    if dir.find(name).is_some() {
+        return Err(());
+    }
+
+    journal::transaction(volume, || {
+        let inode = match entry.inode() {
+            Some(addr) => {
+                let mut inode = Inode::read(volume, addr);
+                inode.set_links(inode.links() + 1);
+                inode.write();
+                inode
+            }
+            None => {
+                let inode = Inode::alloc(&entry, 2).ok_or(())?;
+                entry.dir().relink_entry(&entry.name(), &inode);
+                inode
+            }
+        };
+        dir.insert_inode(name, &inode).map(|_| ()).ok_or(())
+    })
+}
+
+// Create a symbolic link to a target path
+pub fn symlink(volume: usize, target: &str, pathname: &str) -> Result<(), ()> {
+    let mut file = File::create_link(volume, pathname).ok_or(())?;
+    file.write(target.as_bytes())?;
+    Ok(())
+}
+
+#[test_case]
+fn test_symlink() {
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+
+    let mut file = File::create(volume, "/test").unwrap();
+    file.write(b"Hello, World!").unwrap();
+    assert!(Dir::create(volume, "/tmp").is_some());
+    assert!(symlink(volume, "/test", "/tmp/link").is_ok());
+    assert!(symlink(volume, "../tmp", "/tmp/dir").is_ok());
+    assert_eq!(resolve(volume, "/tmp/link"), Some("/test".into()));
+    assert_eq!(resolve(volume, "/tmp/dir/dir/link"), Some("/test".into()));
+    assert_eq!(
+        File::open(volume, "/tmp/link").unwrap().read_to_string(),
+        "Hello, World!"
+    );
+
+    // Loop of links
+    assert!(symlink(volume, "/tmp/b", "/tmp/a").is_ok());
+    assert!(symlink(volume, "/tmp/a", "/tmp/b").is_ok());
+    assert_eq!(resolve(volume, "/tmp/a"), None);
+    assert!(File::open(volume, "/tmp/a").is_none());
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_hard_link() {
+    use super::super_block::SuperBlock;
+
+    super::mount_mem();
+    super::format_mem();
+    let volume = super::block_device::root_volume();
+    let count = SuperBlock::read(volume).alloc_count();
+
+    let mut file = File::create(volume, "/a").unwrap();
+    file.write(b"Hello").unwrap();
+    assert!(link(volume, "/a", "/b").is_ok());
+    let mut file = File::open(volume, "/b").unwrap();
+    file.seek(super::SeekFrom::End(0)).unwrap();
+    file.write(b", World!").unwrap();
+    assert_eq!(
+        File::open(volume, "/a").unwrap().read_to_string(),
+        "Hello, World!"
+    );
+
+    // The size and the time of the links are kept in their inode
+    let (a, b) = (
+        DirEntry::open(volume, "/a").unwrap(),
+        DirEntry::open(volume, "/b").unwrap(),
+    );
+    assert_eq!(a.size(), b.size());
+    assert_eq!(a.modified(), b.modified());
+    assert_eq!(a.created(), b.created());
+
+    assert!(File::delete(volume, "/a").is_ok());
+    assert_eq!(
+        File::open(volume, "/b").unwrap().read_to_string(),
+        "Hello, World!"
+    );
+    assert!(File::delete(volume, "/b").is_ok());
+    assert_eq!(SuperBlock::read(volume).alloc_count(), count);
+
+    super::dismount();
+}
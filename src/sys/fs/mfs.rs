@@ -0,0 +1,150 @@
+use super::block_device::{remove_volume, with_device, BlockDevice};
+use super::dir_entry::DirEntry;
+use super::super_block::SuperBlock;
+use super::vfs::FileSystem;
+use super::{Device, Dir, File, FileInfo, OpenFlag, Resource, SeekFrom};
+
+use alloc::string::String;
+
+// Driver of a MFS volume
+pub struct Mfs {
+    volume: usize,
+    source: String,
+}
+
+impl Mfs {
+    pub fn new(volume: usize, source: &str) -> Self {
+        let source = source.into();
+        Self { volume, source }
+    }
+}
+
+impl FileSystem for Mfs {
+    fn name(&self) -> String {
+        let is_mem = with_device(self.volume, |dev| matches!(dev, BlockDevice::Mem(_)));
+        if is_mem == Some(true) {
+            "tmpfs".into()
+        } else {
+            "mfs".into()
+        }
+    }
+
+    fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    fn open(&self, path: &str, flags: u8) -> Option<Resource> {
+        open(self.volume, path, flags)
+    }
+
+    fn delete(&self, path: &str) -> Result<(), ()> {
+        delete(self.volume, path)
+    }
+
+    fn info(&self, path: &str) -> Option<FileInfo> {
+        info(self.volume, path)
+    }
+
+    fn link(&self, target: &str, path: &str) -> Result<(), ()> {
+        super::link::link(self.volume, target, path)
+    }
+
+    fn read_link(&self, path: &str) -> Option<String> {
+        let entry = DirEntry::open(self.volume, path)?;
+        if entry.is_link() {
+            Some(super::link::read_link(&entry))
+        } else {
+            None
+        }
+    }
+
+    fn size(&self) -> usize {
+        if SuperBlock::is_formatted(self.volume) {
+            SuperBlock::read(self.volume).block_count() as usize * super::BLOCK_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn used(&self) -> usize {
+        if SuperBlock::is_formatted(self.volume) {
+            SuperBlock::read(self.volume).alloc_count() as usize * super::BLOCK_SIZE
+        } else {
+            0
+        }
+    }
+
+    fn is_open(&self, res: &Resource) -> bool {
+        match res {
+            Resource::Dir(dir) => dir.volume() == self.volume,
+            Resource::File(file) => file.volume() == self.volume,
+            Resource::Device(Device::File(file)) => file.volume() == self.volume,
+            _ => false,
+        }
+    }
+
+    fn unmount(&self) {
+        remove_volume(self.volume);
+    }
+}
+
+fn open(volume: usize, path: &str, flags: u8) -> Option<Resource> {
+    if OpenFlag::Dir.is_set(flags) {
+        let res = Dir::open(volume, path);
+        if res.is_none() && OpenFlag::Create.is_set(flags) {
+            Dir::create(volume, path)
+        } else {
+            res
+        }
+        .map(Resource::Dir)
+    } else if OpenFlag::Device.is_set(flags) {
+        let res = Device::open(volume, path);
+        if res.is_none() && OpenFlag::Create.is_set(flags) {
+            Device::create(volume, path)
+        } else {
+            res
+        }
+        .map(Resource::Device)
+    } else if OpenFlag::Link.is_set(flags) {
+        let res = File::open_link(volume, path);
+        if res.is_none() && OpenFlag::Create.is_set(flags) {
+            File::create_link(volume, path)
+        } else {
+            res
+        }
+        .map(Resource::File)
+    } else {
+        let mut res = File::open(volume, path);
+        if res.is_none() && OpenFlag::Create.is_set(flags) {
+            File::create(volume, path)
+        } else {
+            if OpenFlag::Append.is_set(flags) {
+                if let Some(ref mut file) = res {
+                    file.seek(SeekFrom::End(0)).ok();
+                }
+            }
+            res
+        }
+        .map(Resource::File)
+    }
+}
+
+fn delete(volume: usize, path: &str) -> Result<(), ()> {
+    // Delete symbolic links instead of their targets
+    if let Some(entry) = DirEntry::open(volume, path) {
+        if entry.is_dir() {
+            return Dir::delete(volume, path);
+        } else {
+            return File::delete(volume, path);
+        }
+    }
+    Err(())
+}
+
+fn info(volume: usize, pathname: &str) -> Option<FileInfo> {
+    let pathname = super::link::resolve(volume, pathname)?;
+    if pathname == "/" {
+        return Some(FileInfo::root(volume));
+    }
+    DirEntry::open(volume, &pathname).map(|e| e.info())
+}
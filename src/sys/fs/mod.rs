@@ -1,32 +1,49 @@
 mod bitmap_block;
 mod block;
 mod block_device;
+mod check;
 mod device;
 mod dir;
 mod dir_entry;
+mod fat;
 mod file;
+mod inode;
+mod iso9660;
+mod journal;
+mod link;
+mod mfs;
 mod read_dir;
 mod super_block;
+mod vfs;
 
 use crate::sys;
 
-pub use crate::api::fs::{dirname, filename, realpath, FileIO, IO};
+pub use crate::api::fs::{dirname, filename, realpath, FileIO, SeekFrom, IO};
 pub use crate::sys::ata::BLOCK_SIZE;
 pub use bitmap_block::BITMAP_SIZE;
-pub use block_device::{dismount, format_ata, format_mem, is_mounted, mount_ata, mount_mem};
+pub use block_device::{
+    ahci_path, ata_path, cdrom_path, dismount, format_device, format_mem, is_disk_mounted,
+    is_mounted, mount_ahci, mount_ahci_at, mount_ata, mount_ata_at, mount_cdrom_at, mount_mem,
+    mount_mem_at, mount_virtio, mount_virtio_at, virtio_path, AhciBlockDevice, AtaBlockDevice,
+    BlockDevice, BlockDeviceIO, VirtioBlockDevice,
+};
+pub use check::{check, Problem, Report};
 pub use device::{Device, DeviceType};
 pub use dir::Dir;
 pub use dir_entry::FileInfo;
-pub use file::{File, SeekFrom};
+pub use fat::{Fat, FatFile};
+pub use file::File;
+pub use iso9660::{Iso9660, IsoFile};
+pub use link::symlink;
+pub use vfs::{mount, mounts, normalize, resolve, umount, FileSystem, MountInfo};
 
-use dir_entry::DirEntry;
 use super_block::SuperBlock;
 
 use alloc::string::{String, ToString};
 use core::convert::TryFrom;
 use core::ops::BitOr;
 
-pub const VERSION: u8 = 2;
+pub const VERSION: u8 = 5;
 
 // TODO: Move that to API
 #[derive(Clone, Copy)]
@@ -39,6 +56,7 @@ pub enum OpenFlag {
     Truncate = 16,
     Dir = 32,
     Device = 64,
+    Link = 128,
 }
 
 impl OpenFlag {
@@ -56,54 +74,19 @@ impl BitOr for OpenFlag {
 }
 
 pub fn open(path: &str, flags: u8) -> Option<Resource> {
-    if OpenFlag::Dir.is_set(flags) {
-        let res = Dir::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            Dir::create(path)
-        } else {
-            res
-        }
-        .map(Resource::Dir)
-    } else if OpenFlag::Device.is_set(flags) {
-        let res = Device::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            Device::create(path)
-        } else {
-            res
-        }
-        .map(Resource::Device)
-    } else {
-        let mut res = File::open(path);
-        if res.is_none() && OpenFlag::Create.is_set(flags) {
-            File::create(path)
-        } else {
-            if OpenFlag::Append.is_set(flags) {
-                if let Some(ref mut file) = res {
-                    file.seek(SeekFrom::End(0)).ok();
-                }
-            }
-            res
-        }
-        .map(Resource::File)
-    }
+    vfs::open(path, flags)
 }
 
 pub fn delete(path: &str) -> Result<(), ()> {
-    if let Some(info) = info(path) {
-        if info.is_dir() {
-            return Dir::delete(path);
-        } else if info.is_file() || info.is_device() {
-            return File::delete(path);
-        }
-    }
-    Err(())
+    vfs::delete(path)
 }
 
-pub fn info(pathname: &str) -> Option<FileInfo> {
-    if pathname == "/" {
-        return Some(FileInfo::root());
-    }
-    DirEntry::open(pathname).map(|e| e.info())
+pub fn info(path: &str) -> Option<FileInfo> {
+    vfs::info(path)
+}
+
+pub fn link(target: &str, path: &str) -> Result<(), ()> {
+    vfs::link(target, path)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -111,6 +94,7 @@ pub enum FileType {
     Dir = 0,
     File = 1,
     Device = 2,
+    Link = 3,
 }
 
 impl TryFrom<usize> for FileType {
@@ -121,6 +105,7 @@ impl TryFrom<usize> for FileType {
             0 => Ok(FileType::Dir),
             1 => Ok(FileType::File),
             2 => Ok(FileType::Device),
+            3 => Ok(FileType::Link),
             _ => Err(()),
         }
     }
@@ -131,6 +116,8 @@ pub enum Resource {
     Dir(Dir),
     File(File),
     Device(Device),
+    Fat(FatFile),
+    Iso(IsoFile),
 }
 
 impl Resource {
@@ -139,6 +126,17 @@ impl Resource {
             Resource::Dir(_) => FileType::Dir,
             Resource::File(_) => FileType::File,
             Resource::Device(_) => FileType::Device,
+            Resource::Fat(io) => io.kind(),
+            Resource::Iso(io) => io.kind(),
+        }
+    }
+
+    // Move the offset of a file or a drive and return the new offset
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, ()> {
+        match self {
+            Resource::File(io) => io.seek(pos).map(|offset| offset as u64),
+            Resource::Device(Device::Drive(io)) => io.seek(pos),
+            _ => Err(()),
         }
     }
 }
@@ -149,6 +147,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.read(buf),
             Resource::File(io) => io.read(buf),
             Resource::Device(io) => io.read(buf),
+            Resource::Fat(io) => io.read(buf),
+            Resource::Iso(io) => io.read(buf),
         }
     }
 
@@ -157,6 +157,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.write(buf),
             Resource::File(io) => io.write(buf),
             Resource::Device(io) => io.write(buf),
+            Resource::Fat(io) => io.write(buf),
+            Resource::Iso(io) => io.write(buf),
         }
     }
 
@@ -165,6 +167,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.close(),
             Resource::File(io) => io.close(),
             Resource::Device(io) => io.close(),
+            Resource::Fat(io) => io.close(),
+            Resource::Iso(io) => io.close(),
         }
     }
 
@@ -173,6 +177,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.poll(event),
             Resource::File(io) => io.poll(event),
             Resource::Device(io) => io.poll(event),
+            Resource::Fat(io) => io.poll(event),
+            Resource::Iso(io) => io.poll(event),
         }
     }
 }
@@ -190,12 +196,22 @@ pub fn canonicalize(path: &str) -> Result<String, ()> {
     }
 }
 
+// Truncate a name to the given number of bytes at most while respecting
+// char boundaries
+pub fn truncate(name: &str, max: usize) -> String {
+    let mut n = name.len().min(max);
+    while !name.is_char_boundary(n) {
+        n -= 1;
+    }
+    name[..n].into()
+}
+
 pub fn disk_size() -> usize {
-    (SuperBlock::read().block_count() as usize) * BLOCK_SIZE
+    (SuperBlock::read(block_device::root_volume()).block_count() as usize) * BLOCK_SIZE
 }
 
 pub fn disk_used() -> usize {
-    (SuperBlock::read().alloc_count() as usize) * BLOCK_SIZE
+    (SuperBlock::read(block_device::root_volume()).alloc_count() as usize) * BLOCK_SIZE
 }
 
 pub fn disk_free() -> usize {
@@ -203,13 +219,37 @@ pub fn disk_free() -> usize {
 }
 
 pub fn init() {
-    for bus in 0..2 {
-        for dsk in 0..2 {
-            if SuperBlock::check_ata(bus, dsk) {
-                log!("MFS Superblock found in ATA {}:{}", bus, dsk);
-                mount_ata(bus, dsk);
+    for drive in sys::ata::list() {
+        let (bus, dsk) = (drive.bus, drive.dsk);
+        let parts = sys::ata::partitions(bus, dsk);
+        for part in core::iter::once(0).chain(parts.iter().map(|p| p.index)) {
+            if SuperBlock::check_ata(bus, dsk, part) {
+                log!("MFS Superblock found in {}", ata_path(bus, dsk, part));
+                mount_ata(bus, dsk, part);
                 return;
             }
         }
     }
+    for disk in sys::ahci::list() {
+        if SuperBlock::check_ahci(disk.port) {
+            log!("MFS Superblock found in {}", ahci_path(disk.port));
+            mount_ahci(disk.port);
+            return;
+        }
+    }
+    for disk in sys::virtio::blk::list() {
+        if SuperBlock::check_virtio(disk.id) {
+            log!("MFS Superblock found in {}", virtio_path(disk.id));
+            mount_virtio(disk.id);
+            return;
+        }
+    }
+}
+
+#[test_case]
+fn test_truncate() {
+    assert_eq!(truncate("file.txt", 255), "file.txt");
+    assert_eq!(truncate("file.txt", 4), "file");
+    assert_eq!(truncate("café", 4), "caf");
+    assert_eq!(truncate("café", 5), "café");
 }
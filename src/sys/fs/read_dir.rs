@@ -1,6 +1,7 @@
 use super::block::LinkedBlock;
 use super::dir::Dir;
 use super::dir_entry::DirEntry;
+use super::inode::{Inode, INODE_KIND};
 use super::FileType;
 
 use alloc::string::String;
@@ -17,7 +18,7 @@ impl From<Dir> for ReadDir {
     fn from(dir: Dir) -> Self {
         Self {
             dir: dir.clone(),
-            block: LinkedBlock::read(dir.addr()),
+            block: LinkedBlock::read(dir.volume(), dir.addr()),
             block_offset: 0,
             block_index: 0,
         }
@@ -69,19 +70,22 @@ impl Iterator for ReadDir {
     type Item = DirEntry;
 
     fn next(&mut self) -> Option<DirEntry> {
+        let volume = self.dir.volume();
         loop {
             loop {
                 let offset = self.block_offset; // Backup cursor position
 
                 // Switch to next block if no space left for another entry
-                if offset >= self.block.len() - DirEntry::empty_len() {
+                if offset >= self.block.len() - DirEntry::empty_len(volume) {
                     break;
                 }
 
                 let entry_kind = match self.read_u8() {
-                    0 => FileType::Dir,
-                    1 => FileType::File,
-                    2 => FileType::Device,
+                    0 => Some(FileType::Dir),
+                    1 => Some(FileType::File),
+                    2 => Some(FileType::Device),
+                    3 => Some(FileType::Link),
+                    INODE_KIND => None,
                     _ => {
                         self.block_offset = offset; // Rewind the cursor
                         break;
@@ -91,6 +95,11 @@ impl Iterator for ReadDir {
                 let entry_addr = self.read_u32();
                 let entry_size = self.read_u32();
                 let entry_time = self.read_u64();
+                let (entry_created, entry_accessed) = if DirEntry::has_times(volume) {
+                    (self.read_u64(), self.read_u64())
+                } else {
+                    (entry_time, entry_time)
+                };
 
                 let n = self.read_u8() as usize;
                 if n == 0 || n >= self.block.len() - self.block_offset {
@@ -107,14 +116,26 @@ impl Iterator for ReadDir {
                 }
 
                 let dir = self.dir.clone();
-                return Some(DirEntry::new(
-                    dir,
-                    entry_kind,
-                    entry_addr,
-                    entry_size,
-                    entry_time,
-                    &entry_name,
-                ));
+                return Some(match entry_kind {
+                    Some(kind) => {
+                        DirEntry::new(dir, kind, entry_addr, entry_size, entry_time, &entry_name)
+                            .with_times(entry_created, entry_accessed)
+                    }
+                    None => {
+                        // The entry is a hard link to an inode
+                        let inode = Inode::read(volume, entry_addr);
+                        DirEntry::new(
+                            dir,
+                            inode.kind(),
+                            inode.data_addr(),
+                            inode.size(),
+                            inode.modified(),
+                            &entry_name,
+                        )
+                        .with_inode(entry_addr)
+                        .with_times(inode.created(), inode.accessed())
+                    }
+                });
             }
 
             match self.block.next() {
@@ -1,14 +1,22 @@
 use super::block::Block;
 use super::block_device::BlockDeviceIO;
+use super::journal::JOURNAL_SIZE;
 use crate::sys;
 use crate::KERNEL_SIZE;
+
+use alloc::collections::btree_map::BTreeMap;
 use core::convert::TryInto;
+use spin::Mutex;
 
 const SUPERBLOCK_ADDR: u32 = (KERNEL_SIZE / super::BLOCK_SIZE) as u32;
 const SIGNATURE: &[u8; 8] = b"WFOS FS\0";
 
+// Versions of the mounted volumes, needed to know which structures they have
+static VERSIONS: Mutex<BTreeMap<usize, u8>> = Mutex::new(BTreeMap::new());
+
 #[derive(Debug)]
 pub struct SuperBlock {
+    volume: usize,
     signature: &'static [u8; 8],
     version: u8,
     block_size: u32,
@@ -17,39 +25,72 @@ pub struct SuperBlock {
 }
 
 impl SuperBlock {
-    pub fn check_ata(bus: u8, dsk: u8) -> bool {
+    // Check the superblock of an ATA drive, or of one of its partitions,
+    // where it is the first block instead of the block after the kernel.
+    pub fn check_ata(bus: u8, dsk: u8, part: u8) -> bool {
+        let addr = if part == 0 {
+            SUPERBLOCK_ADDR as u64
+        } else {
+            match sys::ata::Drive::open_partition(bus, dsk, part) {
+                Some(drive) => drive.block_start(),
+                None => return false,
+            }
+        };
+        let mut buf = [0u8; super::BLOCK_SIZE];
+        if sys::ata::read(bus, dsk, addr, &mut buf).is_err() {
+            return false;
+        }
+        is_supported(&buf)
+    }
+
+    pub fn check_ahci(port: u8) -> bool {
+        let mut buf = [0u8; super::BLOCK_SIZE];
+        if sys::ahci::read(port, SUPERBLOCK_ADDR as u64, &mut buf).is_err() {
+            return false;
+        }
+        is_supported(&buf)
+    }
+
+    pub fn check_virtio(id: u8) -> bool {
         let mut buf = [0u8; super::BLOCK_SIZE];
-        if sys::ata::read(bus, dsk, SUPERBLOCK_ADDR, &mut buf).is_err() {
+        if sys::virtio::blk::read(id, SUPERBLOCK_ADDR as u64, &mut buf).is_err() {
             return false;
         }
-        &buf[0..8] == SIGNATURE
+        is_supported(&buf)
     }
 
-    pub fn new() -> Option<Self> {
-        if let Some(ref dev) = *super::block_device::BLOCK_DEVICE.lock() {
+    // NOTE: FS must be mounted
+    pub fn is_formatted(volume: usize) -> bool {
+        &Block::read(volume, SUPERBLOCK_ADDR).data()[0..8] == SIGNATURE
+    }
+
+    pub fn new(volume: usize) -> Option<Self> {
+        super::block_device::with_device(volume, |dev| {
             let mut sb = Self {
+                volume,
                 signature: SIGNATURE,
                 version: super::VERSION,
                 block_size: dev.block_size() as u32,
-                block_count: dev.block_count() as u32,
+                // MFS can't address more than 2^32 blocks (2 TiB)
+                block_count: dev.block_count().min(u32::MAX as u64) as u32,
                 alloc_count: 0,
             };
 
             // Reserved blocks
             sb.alloc_count = sb.data_area();
 
-            Some(sb)
-        } else {
-            None
-        }
+            sb
+        })
     }
 
     // NOTE: FS must be mounted
-    pub fn read() -> Self {
-        let block = Block::read(SUPERBLOCK_ADDR);
+    pub fn read(volume: usize) -> Self {
+        let block = Block::read(volume, SUPERBLOCK_ADDR);
         let data = block.data();
         debug_assert_eq!(&data[0..8], SIGNATURE);
+        VERSIONS.lock().insert(volume, data[8]);
         Self {
+            volume,
             signature: SIGNATURE,
             version: data[8],
             block_size: 2 << (8 + data[9] as u32),
@@ -59,11 +100,12 @@ impl SuperBlock {
     }
 
     pub fn write(&self) {
-        let mut block = Block::new(SUPERBLOCK_ADDR);
+        let mut block = Block::new(self.volume, SUPERBLOCK_ADDR);
         let data = block.data_mut();
 
         data[0..8].clone_from_slice(self.signature);
         data[8] = self.version;
+        VERSIONS.lock().insert(self.volume, self.version);
 
         let size = self.block_size;
         debug_assert!(size >= 512);
@@ -87,8 +129,23 @@ impl SuperBlock {
         self.alloc_count
     }
 
+    pub fn set_alloc_count(&mut self, count: u32) {
+        self.alloc_count = count;
+    }
+
+    pub fn journal_area(&self) -> Option<u32> {
+        if self.version < 3 {
+            None // Journal added in v3
+        } else {
+            Some(SUPERBLOCK_ADDR + 2)
+        }
+    }
+
     pub fn bitmap_area(&self) -> u32 {
-        SUPERBLOCK_ADDR + 2
+        match self.journal_area() {
+            Some(addr) => addr + JOURNAL_SIZE,
+            None => SUPERBLOCK_ADDR + 2,
+        }
     }
 
     pub fn data_area(&self) -> u32 {
@@ -113,14 +170,27 @@ impl SuperBlock {
     }
 }
 
-pub fn inc_alloc_count() {
-    let mut sb = SuperBlock::read();
+// Check the signature of a superblock and refuse the versions of the
+// filesystem that are newer than the version of the kernel
+fn is_supported(buf: &[u8]) -> bool {
+    &buf[0..8] == SIGNATURE && (1..=super::VERSION).contains(&buf[8])
+}
+
+pub fn mounted_version(volume: usize) -> u8 {
+    match VERSIONS.lock().get(&volume) {
+        Some(version) => *version,
+        None => super::VERSION,
+    }
+}
+
+pub fn inc_alloc_count(volume: usize) {
+    let mut sb = SuperBlock::read(volume);
     sb.alloc_count += 1;
     sb.write();
 }
 
-pub fn dec_alloc_count() {
-    let mut sb = SuperBlock::read();
+pub fn dec_alloc_count(volume: usize) {
+    let mut sb = SuperBlock::read(volume);
     sb.alloc_count = sb.alloc_count.saturating_sub(1);
     sb.write();
 }
@@ -0,0 +1,357 @@
+use super::link::{join, MAX_LINKS};
+use super::{dirname, filename, realpath, FileInfo, OpenFlag, Resource};
+use crate::sys;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+// The mount table maps the paths where filesystems are mounted to their
+// drivers. The operations on a path are given to the filesystem mounted on
+// its longest prefix, with the rest of the path as an absolute path inside
+// the filesystem. The drivers are shared with the callers, so that the table
+// is not locked during their operations, which can look up other paths.
+
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+
+pub trait FileSystem: Send + Sync {
+    // Name of the driver of the filesystem
+    fn name(&self) -> String;
+    // Path of the device of the filesystem
+    fn source(&self) -> String;
+    fn open(&self, path: &str, flags: u8) -> Option<Resource>;
+    fn delete(&self, path: &str) -> Result<(), ()>;
+    fn info(&self, path: &str) -> Option<FileInfo>;
+    fn link(&self, target: &str, path: &str) -> Result<(), ()>;
+    // Target of a symbolic link, or `None` if the path is not a link
+    fn read_link(&self, path: &str) -> Option<String>;
+    fn size(&self) -> usize;
+    fn used(&self) -> usize;
+    // Check if a resource was opened on the filesystem
+    fn is_open(&self, res: &Resource) -> bool;
+    fn unmount(&self);
+}
+
+struct Mount {
+    path: String,
+    fs: Arc<dyn FileSystem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    pub path: String,
+    pub name: String,
+    pub source: String,
+    pub size: usize,
+    pub used: usize,
+}
+
+// Transform a path into an absolute path without "." and ".." names
+pub fn normalize(pathname: &str) -> String {
+    let pathname = realpath(pathname);
+    let mut names = Vec::new();
+    for name in pathname.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            _ => names.push(name),
+        }
+    }
+    format!("/{}", names.join("/"))
+}
+
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    prefix == "/" || path == prefix || path.starts_with(&format!("{}/", prefix))
+}
+
+// Find the mount of a path and return its index with the path inside it
+fn find(mounts: &[Mount], path: &str) -> Option<(usize, String)> {
+    let path = normalize(path);
+    let i = mounts
+        .iter()
+        .enumerate()
+        .filter(|(_, mount)| is_prefix(&mount.path, &path))
+        .max_by_key(|(_, mount)| mount.path.len())
+        .map(|(i, _)| i)?;
+    let n = if mounts[i].path == "/" {
+        0
+    } else {
+        mounts[i].path.len()
+    };
+    let path = if path.len() > n { &path[n..] } else { "/" };
+    Some((i, path.into()))
+}
+
+// Return the filesystem of a path with the path inside it
+fn lookup(path: &str) -> Option<(Arc<dyn FileSystem>, String)> {
+    let mounts = MOUNTS.lock();
+    let (i, path) = find(&mounts, path)?;
+    Some((mounts[i].fs.clone(), path))
+}
+
+// Run `f` on the filesystem of a path with the path inside it
+pub fn with<T>(path: &str, f: impl FnOnce(&dyn FileSystem, &str) -> T) -> Option<T> {
+    let (fs, path) = lookup(path)?;
+    Some(f(fs.as_ref(), &path))
+}
+
+// Mount a filesystem on an existing directory, or replace the filesystem
+// mounted on the root.
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> Result<(), ()> {
+    let path = normalize(path);
+    if path != "/" {
+        let is_dir = with(&path, |fs, path| fs.info(path))
+            .flatten()
+            .is_some_and(|info| info.is_dir());
+        let is_mounted = MOUNTS.lock().iter().any(|mount| mount.path == path);
+        if !is_dir || is_mounted {
+            fs.unmount();
+            return Err(());
+        }
+    }
+
+    let root = {
+        let mut mounts = MOUNTS.lock();
+        let i = mounts.iter().position(|mount| mount.path == path);
+        let root = i.map(|i| mounts.remove(i));
+        mounts.push(Mount { path, fs });
+        root
+    };
+    if let Some(root) = root {
+        root.fs.unmount();
+    }
+    Ok(())
+}
+
+// Unmount a filesystem that doesn't have other filesystems mounted on it
+// or files opened by a process
+pub fn umount(path: &str) -> Result<(), ()> {
+    let path = normalize(path);
+    let fs = {
+        let mounts = MOUNTS.lock();
+        let mount = mounts.iter().find(|mount| mount.path == path).ok_or(())?;
+        let is_busy = mounts
+            .iter()
+            .any(|mount| mount.path != path && is_prefix(&path, &mount.path));
+        if is_busy {
+            return Err(());
+        }
+        mount.fs.clone()
+    };
+    if sys::process::has_handle(|res| fs.is_open(res)) {
+        return Err(());
+    }
+    let mut mounts = MOUNTS.lock();
+    let i = mounts
+        .iter()
+        .position(|mount| Arc::ptr_eq(&mount.fs, &fs))
+        .ok_or(())?;
+    mounts.remove(i);
+    drop(mounts);
+    fs.unmount();
+    Ok(())
+}
+
+pub fn umount_all() {
+    let mounts = core::mem::take(&mut *MOUNTS.lock());
+    for mount in mounts {
+        mount.fs.unmount();
+    }
+}
+
+pub fn mounts() -> Vec<MountInfo> {
+    let mounts: Vec<_> = MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| (mount.path.clone(), mount.fs.clone()))
+        .collect();
+    let mut res: Vec<_> = mounts
+        .into_iter()
+        .map(|(path, fs)| MountInfo {
+            path,
+            name: fs.name(),
+            source: fs.source(),
+            size: fs.size(),
+            used: fs.used(),
+        })
+        .collect();
+    res.sort_by(|a, b| a.path.cmp(&b.path));
+    res
+}
+
+// Check if the source of a mounted filesystem matches a predicate
+pub fn has_source(f: impl Fn(&str) -> bool) -> bool {
+    let mounts: Vec<_> = MOUNTS.lock().iter().map(|mount| mount.fs.clone()).collect();
+    mounts.iter().any(|fs| f(&fs.source()))
+}
+
+fn read_link(path: &str) -> Option<String> {
+    with(path, |fs, path| fs.read_link(path)).flatten()
+}
+
+// Transform a path into an absolute path without symbolic links, or return
+// `None` if there is a loop of links. The links are followed across the
+// mounted filesystems, with their absolute targets starting from the root
+// directory instead of the root of the filesystem of the link.
+pub fn resolve(pathname: &str) -> Option<String> {
+    let mut pathname = realpath(pathname);
+    let mut links = 0;
+    'resolve: loop {
+        let names: Vec<String> = pathname
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .map(String::from)
+            .collect();
+        let mut res = String::from("/");
+        for (i, name) in names.iter().enumerate() {
+            if name == ".." {
+                res = dirname(&res).into();
+                continue;
+            }
+            let path = join(&res, name);
+            if let Some(target) = read_link(&path) {
+                links += 1;
+                if links > MAX_LINKS {
+                    return None;
+                }
+                let mut path = if target.starts_with('/') {
+                    target
+                } else {
+                    join(&res, &target)
+                };
+                for name in &names[(i + 1)..] {
+                    path = join(&path, name);
+                }
+                pathname = path;
+                continue 'resolve;
+            }
+            res = path;
+        }
+        return Some(res);
+    }
+}
+
+// Resolve the parents of a path but not its last name, to work on a link
+// instead of its target
+fn resolve_parent(pathname: &str) -> Option<String> {
+    let pathname = realpath(pathname);
+    let name = filename(&pathname);
+    if name.is_empty() {
+        return Some(pathname);
+    }
+    resolve(dirname(&pathname)).map(|dir| join(&dir, name))
+}
+
+pub fn open(path: &str, flags: u8) -> Option<Resource> {
+    let path = if OpenFlag::Link.is_set(flags) {
+        resolve_parent(path)?
+    } else {
+        resolve(path)?
+    };
+    with(&path, |fs, path| fs.open(path, flags)).flatten()
+}
+
+// Delete symbolic links instead of their targets
+pub fn delete(path: &str) -> Result<(), ()> {
+    let path = resolve_parent(path).ok_or(())?;
+    with(&path, |fs, path| fs.delete(path)).unwrap_or(Err(()))
+}
+
+pub fn info(path: &str) -> Option<FileInfo> {
+    let path = resolve(path)?;
+    with(&path, |fs, path| fs.info(path)).flatten()
+}
+
+// Hard links can only be made inside a filesystem
+pub fn link(target: &str, path: &str) -> Result<(), ()> {
+    let target = resolve(target).ok_or(())?;
+    let path = resolve_parent(path).ok_or(())?;
+    let (fs, target) = lookup(&target).ok_or(())?;
+    let (other, path) = lookup(&path).ok_or(())?;
+    if !Arc::ptr_eq(&fs, &other) {
+        return Err(());
+    }
+    fs.link(&target, &path)
+}
+
+#[test_case]
+fn test_normalize() {
+    assert_eq!(normalize("/"), "/");
+    assert_eq!(normalize("/usr/alice/"), "/usr/alice");
+    assert_eq!(normalize("/usr/./alice/../bob"), "/usr/bob");
+    assert_eq!(normalize("/.."), "/");
+}
+
+#[test_case]
+fn test_mount() {
+    super::mount_mem();
+    super::format_mem();
+    let root = super::block_device::root_volume();
+    assert!(super::Dir::create(root, "/mnt").is_some());
+
+    // Mount another memory volume on "/mnt"
+    assert!(super::mount_mem_at("/nope", 128 << 10).is_err());
+    assert!(super::mount_mem_at("/mnt", 128 << 10).is_ok());
+    assert!(super::mount_mem_at("/mnt", 128 << 10).is_err());
+    assert_eq!(mounts().len(), 2);
+    assert_eq!(mounts()[1].name, "tmpfs");
+
+    assert!(open("/mnt/test", super::OpenFlag::Create as u8).is_some());
+    assert!(info("/mnt/test").is_some());
+    assert!(super::File::open(root, "/mnt/test").is_none()); // Root volume
+    assert!(link("/mnt/test", "/test").is_err());
+
+    assert!(umount("/").is_err());
+
+    // A filesystem with opened files can't be unmounted
+    let file = open("/mnt/test", 0).unwrap();
+    let handle = sys::process::create_handle(file).unwrap();
+    assert!(umount("/mnt").is_err());
+    sys::process::delete_handle(handle);
+
+    assert!(umount("/mnt").is_ok());
+    assert!(info("/mnt/test").is_none());
+    assert_eq!(mounts().len(), 1);
+
+    super::dismount();
+}
+
+#[test_case]
+fn test_symlink() {
+    use super::block_device::{add_volume, format_mem_volume, BlockDevice, MemBlockDevice};
+    use super::mfs::Mfs;
+    use super::{FileIO, OpenFlag};
+
+    super::mount_mem();
+    super::format_mem();
+    let root = super::block_device::root_volume();
+    let mut file = super::File::create(root, "/test").unwrap();
+    file.write(b"Hello, World!").unwrap();
+    assert!(super::Dir::create(root, "/mnt").is_some());
+
+    let len = crate::KERNEL_SIZE / super::BLOCK_SIZE + 256;
+    let volume = add_volume(BlockDevice::Mem(MemBlockDevice::new(len)));
+    format_mem_volume(volume);
+    assert!(mount("/mnt", Arc::new(Mfs::new(volume, "mem"))).is_ok());
+
+    // The targets of the links are found on the root volume
+    assert!(super::link::symlink(volume, "/test", "/abs").is_ok());
+    assert!(super::link::symlink(volume, "../test", "/rel").is_ok());
+    assert_eq!(resolve("/mnt/abs"), Some("/test".into()));
+    assert_eq!(resolve("/mnt/rel"), Some("/test".into()));
+    let mut buf = [0; 13];
+    let mut file = open("/mnt/abs", OpenFlag::Read as u8).unwrap();
+    assert_eq!(file.read(&mut buf), Ok(13));
+    assert_eq!(&buf, b"Hello, World!");
+    assert_eq!(info("/mnt/rel").unwrap().size(), 13);
+
+    // The links themselves are deleted
+    assert!(delete("/mnt/abs").is_ok());
+    assert!(info("/test").is_some());
+
+    super::dismount();
+}
@@ -38,6 +38,7 @@ macro_rules! log {
 }
 
 pub mod acpi;
+pub mod ahci;
 pub mod ata;
 pub mod clk;
 pub mod console;
@@ -57,3 +58,4 @@ pub mod serial;
 pub mod speaker;
 pub mod syscall;
 pub mod vga;
+pub mod virtio;
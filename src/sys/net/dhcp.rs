@@ -0,0 +1,344 @@
+use crate::sys;
+use crate::sys::fs::{File, FileIO, OpenFlag, Resource, SeekFrom};
+use crate::sys::net::NetInterface;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::{dhcpv4, udp};
+use smoltcp::wire::{
+    DhcpMessageType, DhcpPacket, DhcpRepr, HardwareAddress, IpAddress, IpCidr, IpEndpoint,
+    Ipv4Address, Ipv4Cidr, DHCP_CLIENT_PORT, DHCP_SERVER_PORT,
+};
+
+const LEASE_DIR: &str = "/var/dhcp";
+const DNS_FILE: &str = "/ini/dns";
+
+// Lease of an IPv4 configuration given by a DHCP server, with its durations
+// in seconds from the time it was acquired or renewed
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub address: Ipv4Cidr,
+    pub router: Option<Ipv4Address>,
+    pub dns_servers: Vec<Ipv4Address>,
+    pub server: Ipv4Address,
+    pub acquired: f64,
+    pub duration: Option<u32>,
+    pub renew: Option<u32>,
+    pub rebind: Option<u32>,
+}
+
+impl Lease {
+    fn from_config(config: &dhcpv4::Config) -> Self {
+        let repr = config
+            .packet
+            .as_ref()
+            .and_then(|packet| DhcpRepr::parse(packet).ok());
+        let duration = repr.as_ref().and_then(|repr| repr.lease_duration);
+        let renew = repr.as_ref().and_then(|repr| repr.renew_duration);
+        let rebind = repr.as_ref().and_then(|repr| repr.rebind_duration);
+        Self {
+            address: config.address,
+            router: config.router,
+            dns_servers: config.dns_servers.iter().cloned().collect(),
+            server: config.server.identifier,
+            acquired: sys::clk::epoch_time(),
+            duration,
+            // Default times of RFC 2131
+            renew: renew.or_else(|| duration.map(|d| d / 2)),
+            rebind: rebind.or_else(|| duration.map(|d| d / 8 * 7)),
+        }
+    }
+
+    // Number of seconds before the lease expires
+    pub fn expires_in(&self) -> Option<f64> {
+        let elapsed = sys::clk::epoch_time() - self.acquired;
+        self.duration.map(|d| (d as f64 - elapsed).max(0.0))
+    }
+}
+
+impl fmt::Display for Lease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "address {}", self.address)?;
+        if let Some(router) = self.router {
+            writeln!(f, "router {}", router)?;
+        }
+        for dns in &self.dns_servers {
+            writeln!(f, "dns {}", dns)?;
+        }
+        writeln!(f, "server {}", self.server)?;
+        writeln!(f, "acquired {:.0}", self.acquired)?;
+        if let Some(duration) = self.duration {
+            writeln!(f, "duration {}", duration)?;
+        }
+        if let Some(renew) = self.renew {
+            writeln!(f, "renew {}", renew)?;
+        }
+        if let Some(rebind) = self.rebind {
+            writeln!(f, "rebind {}", rebind)?;
+        }
+        Ok(())
+    }
+}
+
+// DHCP client running in the background with a socket polled with the other
+// sockets of its interface. The socket renews the lease at T1 and rebinds it
+// at T2, and the configuration is applied to the interface on every change.
+// The lease is saved later, before a syscall, because the client is also
+// polled when the CPU is halted in the middle of a filesystem operation.
+#[derive(Debug)]
+pub struct Client {
+    handle: SocketHandle,
+    pub lease: Option<Lease>,
+    unsaved: bool,
+}
+
+pub fn start(net: &mut NetInterface) {
+    if let Some(client) = &net.dhcp {
+        let socket = net.sockets.get_mut::<dhcpv4::Socket>(client.handle);
+        socket.reset();
+        return;
+    }
+    let mut socket = dhcpv4::Socket::new();
+
+    // The buffer is kept for the lifetime of the interface to get the
+    // durations of the lease from the last packet received
+    let buffer = Box::leak(vec![0; 1500].into_boxed_slice());
+    socket.set_receive_packet_buffer(buffer);
+
+    let handle = net.sockets.add(socket);
+    let lease = None;
+    let unsaved = false;
+    net.dhcp = Some(Client {
+        handle,
+        lease,
+        unsaved,
+    });
+}
+
+// Restart the DHCP clients of the interfaces having a lease saved before the
+// last shutdown
+pub fn init() {
+    for net in super::NET.lock().iter_mut() {
+        if sys::fs::info(&lease_path(&net.name)).is_some() {
+            start(net);
+        }
+    }
+}
+
+// Apply the changes of configuration of the DHCP client of an interface
+pub fn update(net: &mut NetInterface) {
+    let client = match &mut net.dhcp {
+        Some(client) => client,
+        None => return,
+    };
+    let socket = net.sockets.get_mut::<dhcpv4::Socket>(client.handle);
+    match socket.poll() {
+        None => {}
+        Some(dhcpv4::Event::Configured(config)) => {
+            let lease = Lease::from_config(&config);
+            let is_new = client
+                .lease
+                .as_ref()
+                .is_none_or(|old| old.address != lease.address || old.router != lease.router);
+            if is_new {
+                configure(&net.name, &mut net.iface, &lease);
+            }
+            client.lease = Some(lease);
+            client.unsaved = true;
+        }
+        Some(dhcpv4::Event::Deconfigured) => {
+            if client.lease.is_some() {
+                deconfigure(&net.name, &mut net.iface);
+                client.lease = None;
+            }
+        }
+    }
+}
+
+fn configure(name: &str, iface: &mut smoltcp::iface::Interface, lease: &Lease) {
+    iface.update_ip_addrs(|addrs| {
+        addrs.retain(|cidr| !matches!(cidr, IpCidr::Ipv4(_)));
+        if addrs.push(IpCidr::Ipv4(lease.address)).is_ok() {
+            log!("NET {} IP {}", name, lease.address);
+        }
+    });
+    let routes = iface.routes_mut();
+    routes.remove_default_ipv4_route();
+    if let Some(router) = lease.router {
+        if routes.add_default_ipv4_route(router).is_ok() {
+            log!("NET {} GW {}", name, router);
+        }
+    }
+}
+
+fn deconfigure(name: &str, iface: &mut smoltcp::iface::Interface) {
+    iface.update_ip_addrs(|addrs| {
+        addrs.retain(|cidr| !matches!(cidr, IpCidr::Ipv4(_)));
+    });
+    iface.routes_mut().remove_default_ipv4_route();
+    log!("NET {} IP released", name);
+}
+
+// Release the lease of an interface, stop its DHCP client, and delete its
+// saved lease
+pub fn stop(net: &mut NetInterface) {
+    if shutdown(net) {
+        delete(&net.name);
+    }
+}
+
+// Release the lease of an interface and stop its DHCP client, returning true
+// if there was a lease
+fn shutdown(net: &mut NetInterface) -> bool {
+    let client = match net.dhcp.take() {
+        Some(client) => client,
+        None => return false,
+    };
+    net.sockets.remove(client.handle);
+    match client.lease {
+        Some(lease) => {
+            release(net, &lease);
+            deconfigure(&net.name, &mut net.iface);
+            true
+        }
+        None => false,
+    }
+}
+
+fn release(net: &mut NetInterface, lease: &Lease) {
+    let mac = match net.iface.hardware_addr() {
+        HardwareAddress::Ethernet(mac) => mac,
+        #[allow(unreachable_patterns)]
+        _ => return,
+    };
+    let repr = DhcpRepr {
+        message_type: DhcpMessageType::Release,
+        transaction_id: sys::rng::get_u32(),
+        secs: 0,
+        client_hardware_address: mac,
+        client_ip: lease.address.address(),
+        your_ip: Ipv4Address::UNSPECIFIED,
+        server_ip: Ipv4Address::UNSPECIFIED,
+        router: None,
+        subnet_mask: None,
+        relay_agent_ip: Ipv4Address::UNSPECIFIED,
+        broadcast: false,
+        requested_ip: None,
+        client_identifier: Some(mac),
+        server_identifier: Some(lease.server),
+        parameter_request_list: None,
+        dns_servers: None,
+        max_size: None,
+        lease_duration: None,
+        renew_duration: None,
+        rebind_duration: None,
+        additional_options: &[],
+    };
+    let mut buf = vec![0; repr.buffer_len()];
+    if repr.emit(&mut DhcpPacket::new_unchecked(&mut buf)).is_ok() {
+        send(net, &buf, lease.server);
+    }
+}
+
+fn send(net: &mut NetInterface, buf: &[u8], server: Ipv4Address) {
+    let rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 0]);
+    let tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 1024]);
+    let mut socket = udp::Socket::new(rx_buffer, tx_buffer);
+    if socket.bind(DHCP_CLIENT_PORT).is_err() {
+        return;
+    }
+    let endpoint = IpEndpoint::new(IpAddress::Ipv4(server), DHCP_SERVER_PORT);
+    if socket.send_slice(buf, endpoint).is_err() {
+        return;
+    }
+    let handle = net.sockets.add(socket);
+    let timeout = 1.0;
+    let started = sys::clk::epoch_time();
+    while sys::clk::epoch_time() - started < timeout {
+        net.iface
+            .poll(super::time(), &mut net.device, &mut net.sockets);
+        if net.sockets.get::<udp::Socket>(handle).send_queue() == 0 {
+            break;
+        }
+        sys::clk::halt();
+    }
+    net.sockets.remove(handle);
+}
+
+fn lease_path(name: &str) -> String {
+    format!("{}/{}", LEASE_DIR, name)
+}
+
+// Open or create a file through the mounted filesystems
+fn open_file(path: &str) -> Option<File> {
+    match sys::fs::open(path, OpenFlag::Create as u8) {
+        Some(Resource::File(file)) => Some(file),
+        _ => None,
+    }
+}
+
+// Save the leases acquired or renewed since they were last saved, unless the
+// interfaces are locked by the caller of the syscall
+pub fn save_all() {
+    let mut leases = Vec::new();
+    if let Some(mut interfaces) = super::NET.try_lock() {
+        for net in interfaces.iter_mut() {
+            if let Some(client) = &mut net.dhcp {
+                if let (true, Some(lease)) = (client.unsaved, &client.lease) {
+                    leases.push((net.name.clone(), lease.clone()));
+                }
+                client.unsaved = false;
+            }
+        }
+    }
+
+    // The files are written after unlocking the interfaces
+    for (name, lease) in leases {
+        save(&name, &lease);
+    }
+}
+
+// Save the lease of an interface under `/var` and its DNS servers, which are
+// shared by every interface, in `/ini/dns`
+fn save(name: &str, lease: &Lease) {
+    if sys::fs::open(LEASE_DIR, OpenFlag::Create | OpenFlag::Dir).is_some() {
+        if let Some(mut file) = open_file(&lease_path(name)) {
+            file.write(lease.to_string().as_bytes()).ok();
+        }
+    }
+
+    let servers: Vec<_> = lease.dns_servers.iter().map(|s| s.to_string()).collect();
+    if servers.is_empty() {
+        return;
+    }
+    let servers = servers.join(",");
+    if let Some(mut file) = open_file(DNS_FILE) {
+        if file.read_to_string().trim() != servers {
+            file.seek(SeekFrom::Start(0)).ok();
+            if file.write(servers.as_bytes()).is_ok() {
+                log!("NET DNS {}", servers);
+            }
+        }
+    }
+}
+
+fn delete(name: &str) {
+    let path = lease_path(name);
+    if sys::fs::info(&path).is_some() {
+        sys::fs::delete(&path).ok();
+    }
+}
+
+// Release the leases of every interface before a shutdown, keeping their
+// files to restart the clients at boot
+pub fn release_all() {
+    save_all();
+    for net in super::NET.lock().iter_mut() {
+        shutdown(net);
+    }
+}
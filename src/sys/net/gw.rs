@@ -1,34 +1,41 @@
 use crate::api::fs::{FileIO, IO};
 
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use core::str::FromStr;
-use smoltcp::wire::Ipv4Address;
+use smoltcp::wire::IpAddress;
 
 #[derive(Debug, Clone)]
-pub struct NetGw;
+pub struct NetGw {
+    iface: String,
+}
 
 impl NetGw {
-    pub fn new() -> Self {
-        Self
+    pub fn new(iface: &str) -> Self {
+        Self {
+            iface: iface.into(),
+        }
     }
 
     pub fn size() -> usize {
-        16
+        2 * (39 + 1) // IPv4 and IPv6 gateways on separate lines
     }
 }
 
 impl FileIO for NetGw {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        if let Some((ref mut iface, _)) = *super::NET.lock() {
-            let mut n = 0;
-            iface.routes_mut().update(|storage| {
-                if let Some(route) = storage.iter().next() {
-                    let s = route.via_router.to_string();
-                    n = s.len();
-                    buf[0..n].copy_from_slice(s.as_bytes());
+        let mut net = super::NET.lock();
+        if let Some(i) = super::find_interface(&net, &self.iface) {
+            let mut routers = Vec::new();
+            net[i].iface.routes_mut().update(|storage| {
+                for route in storage.iter() {
+                    routers.push(route.via_router.to_string());
                 }
             });
-            if n > 0 {
+            if !routers.is_empty() {
+                let s = routers.join("\n");
+                let n = s.len().min(buf.len());
+                buf[0..n].copy_from_slice(&s.as_bytes()[0..n]);
                 return Ok(n);
             }
         }
@@ -36,14 +43,25 @@ impl FileIO for NetGw {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        if let Some((ref mut iface, _)) = *super::NET.lock() {
+        let mut net = super::NET.lock();
+        if let Some(i) = super::find_interface(&net, &self.iface) {
             if let Ok(s) = String::from_utf8(buf.to_vec()) {
+                let super::NetInterface { name, iface, .. } = &mut net[i];
+                let routes = iface.routes_mut();
                 if s == "0.0.0.0" {
-                    iface.routes_mut().remove_default_ipv4_route();
+                    routes.remove_default_ipv4_route();
                     return Ok(s.len());
-                } else if let Ok(ip) = Ipv4Address::from_str(&s) {
-                    iface.routes_mut().add_default_ipv4_route(ip).unwrap();
-                    log!("NET GW {}", s);
+                } else if s == "::" {
+                    routes.remove_default_ipv6_route();
+                    return Ok(s.len());
+                }
+                let res = match IpAddress::from_str(&s) {
+                    Ok(IpAddress::Ipv4(ip)) => routes.add_default_ipv4_route(ip).is_ok(),
+                    Ok(IpAddress::Ipv6(ip)) => routes.add_default_ipv6_route(ip).is_ok(),
+                    Err(_) => false,
+                };
+                if res {
+                    log!("NET {} GW {}", name, s);
                     return Ok(s.len());
                 }
             }
@@ -2,29 +2,75 @@ use crate::api::fs::{FileIO, IO};
 
 use alloc::format;
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::str::FromStr;
-use smoltcp::wire::IpCidr;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv6Address};
+
+// IPv6 address made of a prefix and the modified EUI-64 interface identifier
+// of a MAC address (RFC 4291)
+pub fn eui64(prefix: Ipv6Address, mac: EthernetAddress) -> Ipv6Address {
+    let mut octets = prefix.octets();
+    let mac = mac.as_bytes();
+    octets[8] = mac[0] ^ 0x02; // Universal/local bit
+    octets[9] = mac[1];
+    octets[10] = mac[2];
+    octets[11] = 0xFF;
+    octets[12] = 0xFE;
+    octets[13] = mac[3];
+    octets[14] = mac[4];
+    octets[15] = mac[5];
+    Ipv6Address::from_octets(octets)
+}
+
+pub fn link_local(mac: EthernetAddress) -> Ipv6Address {
+    eui64(Ipv6Address::new(0xFE80, 0, 0, 0, 0, 0, 0, 0), mac)
+}
+
+pub fn is_link_local(addr: &Ipv6Address) -> bool {
+    addr.segments()[0] & 0xFFC0 == 0xFE80
+}
+
+// The IPv4 addresses, the link-local IPv6 addresses, and the other IPv6
+// addresses of the interface are replaced independently of each other.
+fn is_same_kind(a: &IpAddress, b: &IpAddress) -> bool {
+    match (a, b) {
+        (IpAddress::Ipv4(_), IpAddress::Ipv4(_)) => true,
+        (IpAddress::Ipv6(a), IpAddress::Ipv6(b)) => is_link_local(a) == is_link_local(b),
+        _ => false,
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct NetIp;
+pub struct NetIp {
+    iface: String,
+}
 
 impl NetIp {
-    pub fn new() -> Self {
-        Self
+    pub fn new(iface: &str) -> Self {
+        Self {
+            iface: iface.into(),
+        }
     }
 
     pub fn size() -> usize {
-        16 + 1 + 3
+        4 * (39 + 1 + 3 + 1) // Addresses with their prefix on separate lines
     }
 }
 
 impl FileIO for NetIp {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        if let Some((ref mut iface, _)) = *super::NET.lock() {
-            if let Some(ip) = iface.ip_addrs().iter().next() {
-                let s = format!("{}/{}", ip.address(), ip.prefix_len());
-                let n = s.len();
-                buf[0..n].copy_from_slice(s.as_bytes());
+        let net = super::NET.lock();
+        if let Some(i) = super::find_interface(&net, &self.iface) {
+            let addrs: Vec<String> = net[i]
+                .iface
+                .ip_addrs()
+                .iter()
+                .map(|ip| format!("{}/{}", ip.address(), ip.prefix_len()))
+                .collect();
+            if !addrs.is_empty() {
+                let s = addrs.join("\n");
+                let n = s.len().min(buf.len());
+                buf[0..n].copy_from_slice(&s.as_bytes()[0..n]);
                 return Ok(n);
             }
         }
@@ -34,13 +80,18 @@ impl FileIO for NetIp {
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
         if let Ok(s) = String::from_utf8(buf.to_vec()) {
             if let Ok(addr) = IpCidr::from_str(&s) {
-                if let Some((ref mut iface, _)) = *super::NET.lock() {
+                let mut net = super::NET.lock();
+                if let Some(i) = super::find_interface(&net, &self.iface) {
+                    let super::NetInterface { name, iface, .. } = &mut net[i];
+                    let mut res = Err(());
                     iface.update_ip_addrs(|addrs| {
-                        addrs.clear();
-                        addrs.push(addr).unwrap();
-                        log!("NET IP {}", s);
+                        addrs.retain(|a| !is_same_kind(&a.address(), &addr.address()));
+                        if addrs.push(addr).is_ok() {
+                            log!("NET {} IP {}", name, s);
+                            res = Ok(buf.len());
+                        }
                     });
-                    return Ok(buf.len());
+                    return res;
                 }
             }
         }
@@ -56,3 +107,16 @@ impl FileIO for NetIp {
         }
     }
 }
+
+#[test_case]
+fn test_eui64() {
+    let mac = EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    let addr = Ipv6Address::from_str("fe80::5054:ff:fe12:3456").unwrap();
+    assert_eq!(link_local(mac), addr);
+    assert!(is_link_local(&addr));
+
+    let prefix = Ipv6Address::from_str("fec0::").unwrap();
+    let addr = Ipv6Address::from_str("fec0::5054:ff:fe12:3456").unwrap();
+    assert_eq!(eui64(prefix, mac), addr);
+    assert!(!is_link_local(&addr));
+}
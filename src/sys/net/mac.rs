@@ -1,13 +1,17 @@
 use crate::api::fs::{FileIO, IO};
 
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 
 #[derive(Debug, Clone)]
-pub struct NetMac;
+pub struct NetMac {
+    iface: String,
+}
 
 impl NetMac {
-    pub fn new() -> Self {
-        Self
+    pub fn new(iface: &str) -> Self {
+        Self {
+            iface: iface.into(),
+        }
     }
 
     pub fn size() -> usize {
@@ -17,8 +21,9 @@ impl NetMac {
 
 impl FileIO for NetMac {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        if let Some((ref mut iface, _)) = *super::NET.lock() {
-            let s = iface.hardware_addr().to_string();
+        let net = super::NET.lock();
+        if let Some(i) = super::find_interface(&net, &self.iface) {
+            let s = net[i].iface.hardware_addr().to_string();
             let n = s.len();
             buf[0..n].copy_from_slice(s.as_bytes());
             return Ok(n);
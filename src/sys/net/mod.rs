@@ -1,7 +1,10 @@
+pub mod dhcp;
 pub mod gw;
 pub mod ip;
 pub mod mac;
+pub mod neighbor;
 mod nic;
+pub mod slaac;
 pub mod socket;
 pub mod usage;
 
@@ -9,16 +12,90 @@ use crate::sys::pci::DeviceConfig;
 use crate::{sys, usr};
 
 use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use smoltcp::iface::Interface;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use smoltcp::iface::{Interface, SocketSet};
 use smoltcp::phy::DeviceCapabilities;
 use smoltcp::time::Instant;
-use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv6Address};
 use spin::Mutex;
 
-pub static NET: Mutex<Option<(Interface, EthernetDevice)>> = Mutex::new(None);
+// Each interface has its own set of sockets because the packets of a socket
+// must be sent by the interface having its local address.
+pub struct NetInterface {
+    pub name: String,
+    pub iface: Interface,
+    pub device: EthernetDevice,
+    pub sockets: SocketSet<'static>,
+    pub dhcp: Option<dhcp::Client>,
+    pub slaac: Option<slaac::Config>,
+}
+
+pub static NET: Mutex<Vec<NetInterface>> = Mutex::new(Vec::new());
+
+pub fn interfaces() -> Vec<String> {
+    NET.lock().iter().map(|net| net.name.clone()).collect()
+}
+
+// Return the index of an interface from its name, or the index of the first
+// interface with an empty name
+pub fn find_interface(interfaces: &[NetInterface], name: &str) -> Option<usize> {
+    if name.is_empty() && !interfaces.is_empty() {
+        return Some(0);
+    }
+    interfaces.iter().position(|net| net.name == name)
+}
+
+// Return the index of the interface used to send packets to an address,
+// which is the interface on the same network or else the first interface
+// with a default gateway for the family of the address
+pub fn route(interfaces: &mut [NetInterface], addr: &IpAddress) -> Option<usize> {
+    let on_link = interfaces.iter().position(|net| {
+        net.iface
+            .ip_addrs()
+            .iter()
+            .any(|cidr| cidr.contains_addr(addr))
+    });
+    if on_link.is_some() {
+        return on_link;
+    }
+    interfaces.iter_mut().position(|net| {
+        let mut found = false;
+        net.iface.routes_mut().update(|routes| {
+            found = routes.iter().any(|route| route.cidr.contains_addr(addr));
+        });
+        found
+    })
+}
+
+// Minimum number of ticks between two background polls
+const POLL_INTERVAL: usize = 10;
+
+static LAST_POLL: AtomicUsize = AtomicUsize::new(0);
+
+// Poll the interfaces in the background when the CPU is halted or before a
+// syscall to answer echo requests, keep the connections of idle sockets alive,
+// run the DHCP clients, and expire the IPv6 configurations, unless they are
+// already being polled by the holder of the lock.
+pub fn poll() {
+    let ticks = sys::clk::ticks();
+    if ticks - LAST_POLL.load(Ordering::Relaxed) < POLL_INTERVAL {
+        return;
+    }
+    LAST_POLL.store(ticks, Ordering::Relaxed);
+    if let Some(mut interfaces) = NET.try_lock() {
+        for net in interfaces.iter_mut() {
+            net.iface.poll(time(), &mut net.device, &mut net.sockets);
+            dhcp::update(net);
+            slaac::update(net);
+        }
+        socket::tcp::remove_closed(&mut interfaces);
+    }
+}
 
 #[repr(u8)]
 pub enum SocketStatus {
@@ -31,6 +108,35 @@ pub enum SocketStatus {
     MayRecv = 6,
 }
 
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketOption {
+    Blocking = 0,
+    ConnectTimeout = 1,
+    ReadTimeout = 2,
+    WriteTimeout = 3,
+    RecvBufferSize = 4,
+    SendBufferSize = 5,
+    Backlog = 6,
+}
+
+impl TryFrom<usize> for SocketOption {
+    type Error = ();
+
+    fn try_from(n: usize) -> Result<Self, Self::Error> {
+        match n {
+            0 => Ok(SocketOption::Blocking),
+            1 => Ok(SocketOption::ConnectTimeout),
+            2 => Ok(SocketOption::ReadTimeout),
+            3 => Ok(SocketOption::WriteTimeout),
+            4 => Ok(SocketOption::RecvBufferSize),
+            5 => Ok(SocketOption::SendBufferSize),
+            6 => Ok(SocketOption::Backlog),
+            _ => Err(()),
+        }
+    }
+}
+
 fn time() -> Instant {
     Instant::from_micros((sys::clk::epoch_time() * 1000000.0) as i64)
 }
@@ -40,14 +146,15 @@ pub enum EthernetDevice {
     RTL8139(nic::rtl8139::Device),
     PCNET(nic::pcnet::Device),
     E1000(nic::e1000::Device),
-    //VirtIO,
+    VirtIO(nic::virtio::Device),
+    Loopback(nic::loopback::Device),
 }
 
 pub trait EthernetDeviceIO {
     fn config(&self) -> Arc<Config>;
     fn stats(&self) -> Arc<Stats>;
     fn receive_packet(&mut self) -> Option<Vec<u8>>;
-    fn transmit_packet(&mut self, len: usize);
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()>;
     fn next_tx_buffer(&mut self, len: usize) -> &mut [u8];
 }
 
@@ -57,6 +164,8 @@ impl EthernetDeviceIO for EthernetDevice {
             EthernetDevice::RTL8139(dev) => dev.config(),
             EthernetDevice::PCNET(dev) => dev.config(),
             EthernetDevice::E1000(dev) => dev.config(),
+            EthernetDevice::VirtIO(dev) => dev.config(),
+            EthernetDevice::Loopback(dev) => dev.config(),
         }
     }
 
@@ -65,6 +174,8 @@ impl EthernetDeviceIO for EthernetDevice {
             EthernetDevice::RTL8139(dev) => dev.stats(),
             EthernetDevice::PCNET(dev) => dev.stats(),
             EthernetDevice::E1000(dev) => dev.stats(),
+            EthernetDevice::VirtIO(dev) => dev.stats(),
+            EthernetDevice::Loopback(dev) => dev.stats(),
         }
     }
 
@@ -73,14 +184,18 @@ impl EthernetDeviceIO for EthernetDevice {
             EthernetDevice::RTL8139(dev) => dev.receive_packet(),
             EthernetDevice::PCNET(dev) => dev.receive_packet(),
             EthernetDevice::E1000(dev) => dev.receive_packet(),
+            EthernetDevice::VirtIO(dev) => dev.receive_packet(),
+            EthernetDevice::Loopback(dev) => dev.receive_packet(),
         }
     }
 
-    fn transmit_packet(&mut self, len: usize) {
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()> {
         match self {
             EthernetDevice::RTL8139(dev) => dev.transmit_packet(len),
             EthernetDevice::PCNET(dev) => dev.transmit_packet(len),
             EthernetDevice::E1000(dev) => dev.transmit_packet(len),
+            EthernetDevice::VirtIO(dev) => dev.transmit_packet(len),
+            EthernetDevice::Loopback(dev) => dev.transmit_packet(len),
         }
     }
 
@@ -89,6 +204,8 @@ impl EthernetDeviceIO for EthernetDevice {
             EthernetDevice::RTL8139(dev) => dev.next_tx_buffer(len),
             EthernetDevice::PCNET(dev) => dev.next_tx_buffer(len),
             EthernetDevice::E1000(dev) => dev.next_tx_buffer(len),
+            EthernetDevice::VirtIO(dev) => dev.next_tx_buffer(len),
+            EthernetDevice::Loopback(dev) => dev.next_tx_buffer(len),
         }
     }
 }
@@ -120,6 +237,7 @@ impl<'a> smoltcp::phy::Device for EthernetDevice {
                 usr::hex::print_hex(&buffer);
             }
             self.stats().rx_add(buffer.len() as u64);
+            self.config().neighbors().update(&buffer);
             let rx = RxToken { buffer };
             let tx = TxToken {
                 device: self.clone(),
@@ -168,8 +286,10 @@ impl smoltcp::phy::TxToken for TxToken {
             debug!("NET Packet Transmitted");
             usr::hex::print_hex(buf);
         }
-        self.device.transmit_packet(len);
-        self.device.stats().tx_add(len as u64);
+        match self.device.transmit_packet(len) {
+            Ok(()) => self.device.stats().tx_add(len as u64),
+            Err(()) => self.device.stats().tx_error(),
+        }
         res
     }
 }
@@ -177,6 +297,7 @@ impl smoltcp::phy::TxToken for TxToken {
 pub struct Config {
     debug: AtomicBool,
     mac: Mutex<Option<EthernetAddress>>,
+    neighbors: neighbor::Neighbors,
 }
 
 impl Config {
@@ -184,6 +305,7 @@ impl Config {
         Self {
             debug: AtomicBool::new(false),
             mac: Mutex::new(None),
+            neighbors: neighbor::Neighbors::new(),
         }
     }
 
@@ -206,6 +328,10 @@ impl Config {
     fn update_mac(&self, mac: EthernetAddress) {
         *self.mac.lock() = Some(mac);
     }
+
+    pub fn neighbors(&self) -> &neighbor::Neighbors {
+        &self.neighbors
+    }
 }
 
 pub struct Stats {
@@ -213,6 +339,7 @@ pub struct Stats {
     tx_bytes_count: AtomicU64,
     rx_packets_count: AtomicU64,
     tx_packets_count: AtomicU64,
+    tx_errors_count: AtomicU64,
 }
 
 impl Stats {
@@ -222,6 +349,7 @@ impl Stats {
             tx_bytes_count: AtomicU64::new(0),
             rx_packets_count: AtomicU64::new(0),
             tx_packets_count: AtomicU64::new(0),
+            tx_errors_count: AtomicU64::new(0),
         }
     }
 
@@ -241,6 +369,11 @@ impl Stats {
         self.tx_packets_count.load(Ordering::Relaxed)
     }
 
+    // Number of packets dropped by the device before being transmitted
+    pub fn tx_errors_count(&self) -> u64 {
+        self.tx_errors_count.load(Ordering::Relaxed)
+    }
+
     pub fn rx_add(&self, bytes_count: u64) {
         self.rx_packets_count.fetch_add(1, Ordering::SeqCst);
         self.rx_bytes_count.fetch_add(bytes_count, Ordering::SeqCst);
@@ -250,17 +383,30 @@ impl Stats {
         self.tx_packets_count.fetch_add(1, Ordering::SeqCst);
         self.tx_bytes_count.fetch_add(bytes_count, Ordering::SeqCst);
     }
+
+    pub fn tx_error(&self) {
+        self.tx_errors_count.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
-fn find_device(vendor_id: u16, device_id: u16) -> Option<DeviceConfig> {
-    if let Some(mut dev) = sys::pci::find_device(vendor_id, device_id) {
+// Return all the devices with the given ids to have an interface for each
+// of them
+fn find_devices(vendor_id: u16, device_id: u16) -> Vec<DeviceConfig> {
+    let mut devices: Vec<_> = sys::pci::list()
+        .into_iter()
+        .filter(|dev| dev.vendor_id == vendor_id && dev.device_id == device_id)
+        .collect();
+    for dev in devices.iter_mut() {
         dev.enable_bus_mastering();
-        Some(dev)
-    } else {
-        None
     }
+    devices
 }
 
+const VIRTIO_DEVICES: [u16; 2] = [
+    0x1000, // Transitional network device
+    0x1041, // Modern network device
+];
+
 const E1000_DEVICES: [u16; 9] = [
     0x1004, // 82543GC (Intel PRO/1000 T)
     0x100C, // 82544GC (Intel PRO/1000 T)
@@ -274,30 +420,65 @@ const E1000_DEVICES: [u16; 9] = [
 ];
 
 pub fn init() {
-    let add = |mut device: EthernetDevice, name| {
-        log!("NET DRV {}", name);
+    let add = |mut device: EthernetDevice, driver| {
+        let mut interfaces = NET.lock();
+        let is_loopback = matches!(device, EthernetDevice::Loopback(_));
+        let name = if is_loopback {
+            "lo".into()
+        } else {
+            format!("eth{}", interfaces.len())
+        };
+        log!("NET {} DRV {}", name, driver);
         if let Some(mac) = device.config().mac() {
             let addr = format!("{}", mac).to_uppercase();
-            log!("NET MAC {}", addr);
+            log!("NET {} MAC {}", name, addr);
 
             let config = smoltcp::iface::Config::new(mac.into());
-            let iface = Interface::new(config, &mut device, time());
-
-            *NET.lock() = Some((iface, device));
+            let mut iface = Interface::new(config, &mut device, time());
+
+            // The link-local IPv6 address is always configured, the others
+            // are given by DHCP and SLAAC.
+            let cidrs = if is_loopback {
+                vec![
+                    IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8),
+                    IpCidr::new(IpAddress::Ipv6(Ipv6Address::LOCALHOST), 128),
+                ]
+            } else {
+                vec![IpCidr::new(IpAddress::Ipv6(ip::link_local(mac)), 64)]
+            };
+            iface.update_ip_addrs(|addrs| {
+                for cidr in cidrs {
+                    if addrs.push(cidr).is_ok() {
+                        log!("NET {} IP {}", name, cidr);
+                    }
+                }
+            });
+
+            let sockets = SocketSet::new(vec![]);
+            let dhcp = None;
+            let slaac = None;
+            interfaces.push(NetInterface {
+                name,
+                iface,
+                device,
+                sockets,
+                dhcp,
+                slaac,
+            });
         }
     };
-    if let Some(dev) = find_device(0x10EC, 0x8139) {
+    for dev in find_devices(0x10EC, 0x8139) {
         let io = dev.io_base();
         let nic = nic::rtl8139::Device::new(io);
         add(EthernetDevice::RTL8139(nic), "RTL8139");
     }
-    if let Some(dev) = find_device(0x1022, 0x2000) {
+    for dev in find_devices(0x1022, 0x2000) {
         let io = dev.io_base();
         let nic = nic::pcnet::Device::new(io);
         add(EthernetDevice::PCNET(nic), "PCNET");
     }
     for id in E1000_DEVICES {
-        if let Some(dev) = find_device(0x8086, id) {
+        for dev in find_devices(0x8086, id) {
             let io = dev.io_base();
             let mem = dev.mem_base();
             let bar = dev.bar_type();
@@ -305,4 +486,17 @@ pub fn init() {
             add(EthernetDevice::E1000(nic), "E1000");
         }
     }
+    for id in VIRTIO_DEVICES {
+        for dev in find_devices(sys::virtio::VENDOR_ID, id) {
+            if let Some(nic) = nic::virtio::Device::new(&dev) {
+                add(EthernetDevice::VirtIO(nic), "VIRTIO");
+            }
+        }
+    }
+
+    // The loopback interface is always present, after the network cards
+    add(
+        EthernetDevice::Loopback(nic::loopback::Device::new()),
+        "LOOPBACK",
+    );
 }
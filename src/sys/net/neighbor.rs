@@ -0,0 +1,135 @@
+use crate::sys;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+use smoltcp::wire::{
+    ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol, Icmpv6Packet, IpAddress,
+    IpProtocol, Ipv6Packet, NdiscRepr,
+};
+use spin::Mutex;
+
+// Same lifetime as the entries of the cache of the network stack
+const ENTRY_LIFETIME: f64 = 60.0;
+const MAX_ENTRIES: usize = 16;
+
+// The neighbor cache of the network stack is private, so the link-layer
+// addresses announced by the neighbors in ARP and NDP packets are also kept
+// here to be displayed with `net neighbor`.
+pub struct Neighbors {
+    entries: Mutex<BTreeMap<IpAddress, (EthernetAddress, f64)>>,
+}
+
+impl Neighbors {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Record the neighbor announced by a received frame
+    pub fn update(&self, buf: &[u8]) {
+        if let Some((addr, mac)) = parse(buf) {
+            if addr.is_unspecified() || !mac.is_unicast() {
+                return;
+            }
+            let now = sys::clk::boot_time();
+            let mut entries = self.entries.lock();
+            entries.retain(|_, (_, time)| now - *time < ENTRY_LIFETIME);
+            if entries.len() >= MAX_ENTRIES && !entries.contains_key(&addr) {
+                let oldest = entries
+                    .iter()
+                    .min_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))
+                    .map(|(addr, _)| *addr);
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+            entries.insert(addr, (mac, now));
+        }
+    }
+
+    // Return the neighbors that have not expired with the age of their entry
+    pub fn entries(&self) -> Vec<(IpAddress, EthernetAddress, f64)> {
+        let now = sys::clk::boot_time();
+        self.entries
+            .lock()
+            .iter()
+            .map(|(addr, (mac, time))| (*addr, *mac, now - time))
+            .filter(|(_, _, age)| *age < ENTRY_LIFETIME)
+            .collect()
+    }
+}
+
+impl Default for Neighbors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse(buf: &[u8]) -> Option<(IpAddress, EthernetAddress)> {
+    let frame = EthernetFrame::new_checked(buf).ok()?;
+    match frame.ethertype() {
+        EthernetProtocol::Arp => {
+            let packet = ArpPacket::new_checked(frame.payload()).ok()?;
+            match ArpRepr::parse(&packet).ok()? {
+                ArpRepr::EthernetIpv4 {
+                    source_hardware_addr,
+                    source_protocol_addr,
+                    ..
+                } => Some((IpAddress::Ipv4(source_protocol_addr), source_hardware_addr)),
+                _ => None,
+            }
+        }
+        EthernetProtocol::Ipv6 => {
+            let packet = Ipv6Packet::new_checked(frame.payload()).ok()?;
+            if packet.next_header() != IpProtocol::Icmpv6 {
+                return None;
+            }
+            let icmp = Icmpv6Packet::new_checked(packet.payload()).ok()?;
+            let addr = match NdiscRepr::parse(&icmp).ok()? {
+                NdiscRepr::NeighborSolicit { .. } => packet.src_addr(),
+                NdiscRepr::NeighborAdvert { target_addr, .. } => target_addr,
+                _ => return None,
+            };
+            Some((IpAddress::Ipv6(addr), frame.src_addr()))
+        }
+        _ => None,
+    }
+}
+
+#[test_case]
+fn test_neighbors() {
+    use alloc::vec;
+    use smoltcp::wire::{ArpOperation, EthernetRepr, Ipv4Address};
+
+    let mac = EthernetAddress([0x52, 0x55, 0x0A, 0x00, 0x02, 0x02]);
+    let addr = Ipv4Address::new(10, 0, 2, 2);
+    let arp = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Reply,
+        source_hardware_addr: mac,
+        source_protocol_addr: addr,
+        target_hardware_addr: EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+        target_protocol_addr: Ipv4Address::new(10, 0, 2, 15),
+    };
+    let eth = EthernetRepr {
+        src_addr: mac,
+        dst_addr: EthernetAddress::BROADCAST,
+        ethertype: EthernetProtocol::Arp,
+    };
+    let mut buf = vec![0; eth.buffer_len() + arp.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut buf);
+    eth.emit(&mut frame);
+    arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+
+    let neighbors = Neighbors::new();
+    assert!(neighbors.entries().is_empty());
+    neighbors.update(&buf);
+    let entries = neighbors.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].0, IpAddress::Ipv4(addr));
+    assert_eq!(entries[0].1, mac);
+
+    // Frames without neighbor information are ignored
+    neighbors.update(&buf[0..10]);
+    assert_eq!(neighbors.entries().len(), 1);
+}
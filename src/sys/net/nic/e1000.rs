@@ -429,7 +429,7 @@ impl EthernetDeviceIO for Device {
         None
     }
 
-    fn transmit_packet(&mut self, len: usize) {
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()> {
         let tx_id = self.tx_id.load(Ordering::SeqCst);
         let mut tx_descs = self.tx_descs.lock();
         debug_assert_eq!(tx_descs[tx_id].addr, self.tx_buffers[tx_id].addr());
@@ -441,6 +441,7 @@ impl EthernetDeviceIO for Device {
 
         // Let the hardware handle the descriptor
         self.write(REG_TDT, ((tx_id + 1) % TX_BUFFERS_COUNT) as u32);
+        Ok(())
     }
 
     fn next_tx_buffer(&mut self, len: usize) -> &mut [u8] {
@@ -316,7 +316,7 @@ impl EthernetDeviceIO for Device {
         }
     }
 
-    fn transmit_packet(&mut self, len: usize) {
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()> {
         let tx_id = self.tx_id.load(Ordering::SeqCst);
 
         // Set start and end of packet
@@ -337,6 +337,7 @@ impl EthernetDeviceIO for Device {
         if !is_buffer_owner(&self.tx_des, tx_id) {
             self.ports.write_csr_32(0, 1 << CSR0_TDMD); // Send all buffers
         }
+        Ok(())
     }
 
     fn next_tx_buffer(&mut self, len: usize) -> &mut [u8] {
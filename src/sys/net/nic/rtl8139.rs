@@ -291,7 +291,7 @@ impl EthernetDeviceIO for Device {
         Some(self.rx_buffer[(offset + 4)..(offset + n)].to_vec())
     }
 
-    fn transmit_packet(&mut self, len: usize) {
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()> {
         let tx_id = self.tx_id.load(Ordering::SeqCst);
         let mut cmd_port = self.ports.tx_cmds[tx_id].clone();
         unsafe {
@@ -316,6 +316,7 @@ impl EthernetDeviceIO for Device {
             }
         }
         //unsafe { self.ports.isr.write(0x4); }
+        Ok(())
     }
 
     fn next_tx_buffer(&mut self, len: usize) -> &mut [u8] {
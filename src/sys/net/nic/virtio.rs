@@ -0,0 +1,206 @@
+use crate::sys;
+use crate::sys::mem::PhysBuf;
+use crate::sys::net::{Config, EthernetDeviceIO, Stats};
+use crate::sys::pci::DeviceConfig;
+use crate::sys::virtio::{self, Queue, F_VERSION_1};
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use smoltcp::wire::EthernetAddress;
+use spin::Mutex;
+
+// Network device of VIRTIO 1.1
+
+// Feature bits
+const F_MAC: u64 = 1 << 5; // Device has given MAC address
+const F_ANY_LAYOUT: u64 = 1 << 27; // Header and packet in the same buffer
+
+// Device configuration
+const CONFIG_MAC: u16 = 0x00;
+
+// Queues
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+// Size of the header of the packets, with the number of merged buffers that
+// is only given by the modern interface
+const LEGACY_HEADER_SIZE: usize = 10;
+const HEADER_SIZE: usize = 12;
+
+const MTU: usize = 1536;
+const RX_BUFFERS_COUNT: usize = 32;
+const TX_BUFFERS_COUNT: usize = 8;
+
+// The buffers given to the device are identified by the id of the descriptor
+// at the head of their chain.
+struct Queues {
+    rx: Queue,
+    tx: Queue,
+    rx_ids: Vec<usize>,         // Index of the rx buffer of each descriptor
+    tx_ids: Vec<Option<usize>>, // Index of the tx buffer used by the device
+}
+
+impl Queues {
+    // Take back the tx buffers that have been sent by the device
+    fn reclaim_tx(&mut self) {
+        while let Some((id, _)) = self.tx.pop_used() {
+            self.tx_ids[id as usize] = None;
+        }
+    }
+
+    fn is_tx_pending(&self, i: usize) -> bool {
+        self.tx_ids.iter().any(|&buf| buf == Some(i))
+    }
+}
+
+#[derive(Clone)]
+pub struct Device {
+    config: Arc<Config>,
+    stats: Arc<Stats>,
+    dev: virtio::Device,
+    queues: Arc<Mutex<Queues>>,
+    header_size: usize,
+
+    rx_buffers: [PhysBuf; RX_BUFFERS_COUNT],
+    tx_buffers: [PhysBuf; TX_BUFFERS_COUNT],
+    tx_spare: PhysBuf,
+    tx_id: Arc<AtomicUsize>,
+}
+
+impl Device {
+    pub fn new(pci: &DeviceConfig) -> Option<Self> {
+        let mut dev = virtio::Device::new(pci)?;
+        let features = dev.negotiate(F_VERSION_1 | F_MAC | F_ANY_LAYOUT).ok()?;
+        let rx = dev.setup_queue(RX_QUEUE).ok()?;
+        let tx = dev.setup_queue(TX_QUEUE).ok()?;
+        let header_size = if dev.is_modern() {
+            HEADER_SIZE
+        } else {
+            LEGACY_HEADER_SIZE
+        };
+        let queues = Queues {
+            rx_ids: vec![0; rx.size() as usize],
+            tx_ids: vec![None; tx.size() as usize],
+            rx,
+            tx,
+        };
+        let device = Self {
+            config: Arc::new(Config::new()),
+            stats: Arc::new(Stats::new()),
+            dev,
+            queues: Arc::new(Mutex::new(queues)),
+            header_size,
+            rx_buffers: [(); RX_BUFFERS_COUNT].map(|_| PhysBuf::new(header_size + MTU)),
+            tx_buffers: [(); TX_BUFFERS_COUNT].map(|_| PhysBuf::new(header_size + MTU)),
+            tx_spare: PhysBuf::new(header_size + MTU),
+            tx_id: Arc::new(AtomicUsize::new(0)),
+        };
+        device.init(features);
+        Some(device)
+    }
+
+    fn init(&self, features: u64) {
+        // Read MAC addr
+        if features & F_MAC != 0 {
+            let mut mac = [0; 6];
+            for (i, b) in mac.iter_mut().enumerate() {
+                *b = self.dev.config_u8(CONFIG_MAC + i as u16);
+            }
+            self.config.update_mac(EthernetAddress::from_bytes(&mac));
+        }
+
+        // Give the rx buffers to the device
+        let mut queues = self.queues.lock();
+        for (i, buf) in self.rx_buffers.iter().enumerate() {
+            if let Ok(id) = queues.rx.add(&[(buf.addr(), buf.len() as u32, true)]) {
+                queues.rx_ids[id as usize] = i;
+            }
+        }
+        self.dev.driver_ok();
+        self.dev.notify(&queues.rx);
+    }
+}
+
+impl EthernetDeviceIO for Device {
+    fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+
+    fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    fn receive_packet(&mut self) -> Option<Vec<u8>> {
+        let mut queues = self.queues.lock();
+        let (id, len) = queues.rx.pop_used()?;
+        let i = queues.rx_ids[id as usize];
+        let buf = &self.rx_buffers[i];
+        let n = (len as usize).min(buf.len()).max(self.header_size);
+        let packet = buf[self.header_size..n].to_vec();
+
+        // Give the buffer back to the device
+        if let Ok(id) = queues.rx.add(&[(buf.addr(), buf.len() as u32, true)]) {
+            queues.rx_ids[id as usize] = i;
+            self.dev.notify(&queues.rx);
+        }
+
+        if !packet.is_empty() {
+            Some(packet)
+        } else {
+            None
+        }
+    }
+
+    fn transmit_packet(&mut self, len: usize) -> Result<(), ()> {
+        let tx_id = self.tx_id.load(Ordering::SeqCst);
+        if tx_id == TX_BUFFERS_COUNT {
+            debug!("VIRTIO NET could not transmit packet: no free buffer");
+            return Err(());
+        }
+        let buf = &mut self.tx_buffers[tx_id];
+        buf[0..self.header_size].fill(0); // No offload
+        let bufs = [(buf.addr(), (self.header_size + len) as u32, false)];
+
+        let mut queues = self.queues.lock();
+        match queues.tx.add(&bufs) {
+            Ok(id) => {
+                queues.tx_ids[id as usize] = Some(tx_id);
+                self.dev.notify(&queues.tx);
+                Ok(())
+            }
+            Err(()) => {
+                debug!("VIRTIO NET could not transmit packet: queue full");
+                Err(())
+            }
+        }
+    }
+
+    // A buffer is only reused after the device has returned it in the used
+    // ring, and when they are all still in use the packet is written into a
+    // spare buffer that will not be transmitted.
+    fn next_tx_buffer(&mut self, len: usize) -> &mut [u8] {
+        let start = sys::clk::boot_time();
+        let tx_id = loop {
+            let mut queues = self.queues.lock();
+            queues.reclaim_tx();
+            let free = (0..TX_BUFFERS_COUNT).find(|&i| !queues.is_tx_pending(i));
+            if free.is_some() || sys::clk::boot_time() - start > 1.0 {
+                break free;
+            }
+            drop(queues);
+            spin_loop();
+        };
+        self.tx_id
+            .store(tx_id.unwrap_or(TX_BUFFERS_COUNT), Ordering::SeqCst);
+
+        let n = self.header_size;
+        let buf = match tx_id {
+            Some(i) => &mut self.tx_buffers[i],
+            None => &mut self.tx_spare,
+        };
+        &mut buf[n..(n + len)]
+    }
+}
@@ -0,0 +1,95 @@
+use crate::sys;
+use crate::sys::net::NetInterface;
+
+use smoltcp::wire::{IpAddress, IpCidr, Ipv6Address, Ipv6Cidr};
+
+// Lifetime of a prefix that never expires (RFC 4861)
+const INFINITE_LIFETIME: u64 = 0xFFFF_FFFF;
+
+// IPv6 configuration of an interface made from the prefix and the router
+// advertised by a router, with the times at which they expire
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub address: Ipv6Cidr,
+    pub router: Option<Ipv6Address>,
+    pub address_expires: Option<f64>,
+    pub router_expires: Option<f64>,
+}
+
+impl Config {
+    // The lifetimes are given in seconds from now
+    pub fn new(
+        address: Ipv6Cidr,
+        valid_lifetime: u64,
+        router: Ipv6Address,
+        router_lifetime: u64,
+    ) -> Self {
+        let now = sys::clk::epoch_time();
+        let address_expires = if valid_lifetime == INFINITE_LIFETIME {
+            None
+        } else {
+            Some(now + valid_lifetime as f64)
+        };
+        let (router, router_expires) = if router_lifetime > 0 {
+            (Some(router), Some(now + router_lifetime as f64))
+        } else {
+            (None, None)
+        };
+        Self {
+            address,
+            router,
+            address_expires,
+            router_expires,
+        }
+    }
+}
+
+// Apply the configuration to an interface, replacing its previous global
+// IPv6 address and default IPv6 route
+pub fn configure(net: &mut NetInterface, config: Config) {
+    let NetInterface { name, iface, .. } = &mut *net;
+    iface.update_ip_addrs(|addrs| {
+        addrs.retain(|cidr| !is_global_ipv6(cidr));
+        if addrs.push(IpCidr::Ipv6(config.address)).is_ok() {
+            log!("NET {} IP {}", name, config.address);
+        }
+    });
+    let routes = iface.routes_mut();
+    routes.remove_default_ipv6_route();
+    if let Some(router) = config.router {
+        if routes.add_default_ipv6_route(router).is_ok() {
+            log!("NET {} GW {}", name, router);
+        }
+    }
+    net.slaac = Some(config);
+}
+
+// Remove the address and the route of an interface when their lifetimes
+// run out without being refreshed by another advertisement
+pub fn update(net: &mut NetInterface) {
+    let config = match &mut net.slaac {
+        Some(config) => config,
+        None => return,
+    };
+    let now = sys::clk::epoch_time();
+    if config.router_expires.is_some_and(|t| t <= now) {
+        net.iface.routes_mut().remove_default_ipv6_route();
+        log!("NET {} GW {} expired", net.name, config.router.unwrap());
+        config.router = None;
+        config.router_expires = None;
+    }
+    if config.address_expires.is_some_and(|t| t <= now) {
+        let addr = IpCidr::Ipv6(config.address);
+        net.iface
+            .update_ip_addrs(|addrs| addrs.retain(|cidr| *cidr != addr));
+        log!("NET {} IP {} expired", net.name, config.address);
+        net.slaac = None;
+    }
+}
+
+fn is_global_ipv6(cidr: &IpCidr) -> bool {
+    match cidr.address() {
+        IpAddress::Ipv6(addr) => !super::ip::is_link_local(&addr),
+        _ => false,
+    }
+}
@@ -0,0 +1,247 @@
+use crate::sys;
+
+use crate::api::fs::{FileIO, IO};
+use crate::sys::net::{NetInterface, SocketOption, SocketStatus};
+
+use super::{wait, SocketOptions};
+
+use alloc::vec;
+use bit_field::BitField;
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::Device;
+use smoltcp::socket::{icmp, Socket};
+use smoltcp::wire::IpAddress;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REQUEST_V6: u8 = 128;
+
+fn icmp_socket_status(socket: &icmp::Socket) -> u8 {
+    let mut status = 0;
+    status.set_bit(SocketStatus::IsOpen as usize, socket.is_open());
+    status.set_bit(SocketStatus::CanSend as usize, socket.can_send());
+    status.set_bit(SocketStatus::CanRecv as usize, socket.can_recv());
+    status
+}
+
+fn icmp_socket(options: &SocketOptions) -> icmp::Socket<'static> {
+    let icmp_rx_buffer = icmp::PacketBuffer::new(
+        vec![icmp::PacketMetadata::EMPTY],
+        vec![0; options.recv_buffer_size],
+    );
+    let icmp_tx_buffer = icmp::PacketBuffer::new(
+        vec![icmp::PacketMetadata::EMPTY],
+        vec![0; options.send_buffer_size],
+    );
+    icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
+}
+
+// A socket reading and writing raw ICMP messages with the address given to
+// `connect`. The identifier of the echo requests is replaced by the one the
+// socket is bound to, so that only the matching echo replies are received.
+// The checksums are computed by the network stack.
+#[derive(Debug, Clone)]
+pub struct IcmpSocket {
+    pub iface: usize,
+    pub handle: SocketHandle,
+    pub ident: u16,
+    pub options: SocketOptions,
+    pub remote_addr: Option<IpAddress>,
+}
+
+impl IcmpSocket {
+    pub fn size() -> usize {
+        if let Some(net) = sys::net::NET.lock().first() {
+            let mtu = net.device.capabilities().max_transmission_unit;
+            let eth_header = 14;
+            let ip_header = 20;
+            mtu - eth_header - ip_header
+        } else {
+            1
+        }
+    }
+
+    pub fn new() -> Option<Self> {
+        let iface = 0;
+        let mut net = sys::net::NET.lock();
+        let options = SocketOptions::default();
+        let handle = net.get_mut(iface)?.sockets.add(icmp_socket(&options));
+        let ident = sys::rng::get_u16();
+        let remote_addr = None;
+
+        Some(Self {
+            iface,
+            handle,
+            ident,
+            options,
+            remote_addr,
+        })
+    }
+
+    pub fn set_option(&mut self, option: SocketOption, value: usize) -> Result<(), ()> {
+        let mut options = self.options;
+        if options.set(option, value)? {
+            // The buffers can only be replaced before the socket is bound
+            let mut net = sys::net::NET.lock();
+            let sockets = &mut net.get_mut(self.iface).ok_or(())?.sockets;
+            if sockets.get::<icmp::Socket>(self.handle).is_open() {
+                return Err(());
+            }
+            sockets.remove(self.handle);
+            self.handle = sockets.add(icmp_socket(&options));
+        }
+        self.options = options;
+        Ok(())
+    }
+
+    // Move the socket to the interface used to reach an address
+    fn route(&mut self, net: &mut [NetInterface], addr: &IpAddress) {
+        if let Some(i) = sys::net::route(net, addr) {
+            if i != self.iface {
+                if let Socket::Icmp(socket) = net[self.iface].sockets.remove(self.handle) {
+                    self.handle = net[i].sockets.add(socket);
+                    self.iface = i;
+                }
+            }
+        }
+    }
+
+    pub fn connect(&mut self, addr: IpAddress, _port: u16) -> Result<(), ()> {
+        let mut net = sys::net::NET.lock();
+        self.route(&mut net, &addr);
+        if let Some(NetInterface { sockets, .. }) = net.get_mut(self.iface) {
+            let socket = sockets.get_mut::<icmp::Socket>(self.handle);
+            if !socket.is_open() {
+                let endpoint = icmp::Endpoint::Ident(self.ident);
+                socket.bind(endpoint).map_err(|_| ())?;
+            }
+            self.remote_addr = Some(addr);
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    pub fn listen(&mut self, _port: u16) -> Result<(), ()> {
+        Err(())
+    }
+
+    pub fn accept(&mut self) -> Result<(Self, IpAddress), ()> {
+        Err(())
+    }
+}
+
+impl FileIO for IcmpSocket {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let timeout = self.options.read_timeout;
+        let started = sys::clk::epoch_time();
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
+            let bytes;
+            loop {
+                if sys::clk::epoch_time() - started > timeout {
+                    return Err(());
+                }
+                iface.poll(sys::net::time(), device, sockets);
+                let socket = sockets.get_mut::<icmp::Socket>(self.handle);
+
+                if buf.len() == 1 {
+                    // 1 byte status read
+                    buf[0] = icmp_socket_status(socket);
+                    return Ok(1);
+                }
+
+                if socket.can_recv() {
+                    (bytes, _) = socket.recv_slice(buf).map_err(|_| ())?;
+                    break;
+                }
+                if !self.options.blocking {
+                    return Err(());
+                }
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
+                    wait(d);
+                }
+                sys::clk::halt();
+            }
+            Ok(bytes)
+        } else {
+            Err(())
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        let timeout = self.options.write_timeout;
+        let started = sys::clk::epoch_time();
+        let mut sent = false;
+        let addr = self.remote_addr.ok_or(())?;
+        let mut msg = buf.to_vec();
+        if msg.len() >= 8 && (msg[0] == ECHO_REQUEST_V4 || msg[0] == ECHO_REQUEST_V6) {
+            msg[4..6].copy_from_slice(&self.ident.to_be_bytes());
+        }
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
+            loop {
+                if sys::clk::epoch_time() - started > timeout {
+                    return Err(());
+                }
+                iface.poll(sys::net::time(), device, sockets);
+                let socket = sockets.get_mut::<icmp::Socket>(self.handle);
+
+                if sent {
+                    break;
+                }
+                if socket.can_send() {
+                    if socket.send_slice(&msg, addr).is_err() {
+                        return Err(());
+                    }
+                    sent = true; // Break after next poll
+                } else if !self.options.blocking {
+                    return Err(());
+                }
+
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
+                    wait(d);
+                }
+                sys::clk::halt();
+            }
+            Ok(buf.len())
+        } else {
+            Err(())
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(NetInterface { sockets, .. }) = sys::net::NET.lock().get_mut(self.iface) {
+            sockets.remove(self.handle);
+        }
+    }
+
+    fn poll(&mut self, event: IO) -> bool {
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
+            iface.poll(sys::net::time(), device, sockets);
+            let socket = sockets.get_mut::<icmp::Socket>(self.handle);
+
+            match event {
+                IO::Read => socket.can_recv(),
+                IO::Write => socket.can_send(),
+            }
+        } else {
+            false
+        }
+    }
+}
@@ -1,16 +1,192 @@
 use crate::sys;
+use crate::sys::fs::{Device, Resource};
+use crate::sys::net::SocketOption;
 
+pub mod icmp;
 pub mod tcp;
 pub mod udp;
 
-use alloc::vec;
-use lazy_static::lazy_static;
-use smoltcp::iface::SocketSet;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use smoltcp::socket::Socket;
 use smoltcp::time::Duration;
-use spin::Mutex;
 
-lazy_static! {
-    pub static ref SOCKETS: Mutex<SocketSet<'static>> = Mutex::new(SocketSet::new(vec![]));
+const DEFAULT_TIMEOUT: f64 = 5.0;
+const DEFAULT_BUFFER_SIZE: usize = 1024;
+const MAX_BUFFER_SIZE: usize = 1 << 20;
+const DEFAULT_BACKLOG: usize = 4;
+const MAX_BACKLOG: usize = 16;
+
+// Options of a socket set with the `OPTION` syscall, with timeouts given in
+// seconds. A socket that is not blocking returns an error immediately when an
+// operation cannot be completed instead of waiting until the timeout. The
+// backlog gives the number of connections a listening TCP socket can queue
+// before they are accepted.
+#[derive(Debug, Clone, Copy)]
+pub struct SocketOptions {
+    pub blocking: bool,
+    pub connect_timeout: f64,
+    pub read_timeout: f64,
+    pub write_timeout: f64,
+    pub recv_buffer_size: usize,
+    pub send_buffer_size: usize,
+    pub backlog: usize,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            blocking: true,
+            connect_timeout: DEFAULT_TIMEOUT,
+            read_timeout: DEFAULT_TIMEOUT,
+            write_timeout: DEFAULT_TIMEOUT,
+            recv_buffer_size: DEFAULT_BUFFER_SIZE,
+            send_buffer_size: DEFAULT_BUFFER_SIZE,
+            backlog: DEFAULT_BACKLOG,
+        }
+    }
+}
+
+impl SocketOptions {
+    // Set an option from the raw value given to the syscall, and return true
+    // when the buffers of the socket need to be replaced
+    pub fn set(&mut self, option: SocketOption, value: usize) -> Result<bool, ()> {
+        match option {
+            SocketOption::Blocking => {
+                self.blocking = value != 0;
+            }
+            SocketOption::ConnectTimeout
+            | SocketOption::ReadTimeout
+            | SocketOption::WriteTimeout => {
+                let timeout = f64::from_bits(value as u64);
+                if timeout.is_nan() || timeout < 0.0 {
+                    return Err(());
+                }
+                match option {
+                    SocketOption::ConnectTimeout => self.connect_timeout = timeout,
+                    SocketOption::ReadTimeout => self.read_timeout = timeout,
+                    _ => self.write_timeout = timeout,
+                }
+            }
+            SocketOption::RecvBufferSize | SocketOption::SendBufferSize => {
+                if value == 0 || value > MAX_BUFFER_SIZE {
+                    return Err(());
+                }
+                match option {
+                    SocketOption::RecvBufferSize => self.recv_buffer_size = value,
+                    _ => self.send_buffer_size = value,
+                }
+                return Ok(true);
+            }
+            SocketOption::Backlog => {
+                if value == 0 || value > MAX_BACKLOG {
+                    return Err(());
+                }
+                self.backlog = value;
+            }
+        }
+        Ok(false)
+    }
+}
+
+// Entry of the socket table displayed by `net stat`, with the id of the
+// process owning the socket or none for the sockets used by the kernel
+#[derive(Debug, Clone)]
+pub struct SocketInfo {
+    pub protocol: &'static str,
+    pub iface: String,
+    pub local: String,
+    pub remote: String,
+    pub state: String,
+    pub recv_queue: usize,
+    pub send_queue: usize,
+    pub pid: Option<usize>,
+}
+
+pub fn list() -> Vec<SocketInfo> {
+    // The handles of the processes are read before locking the interfaces
+    let mut owners = BTreeMap::new();
+    let mut remotes = BTreeMap::new();
+    for (pid, file) in sys::process::running_handles() {
+        match *file {
+            Resource::Device(Device::TcpSocket(ref dev)) => {
+                for key in dev.handles() {
+                    owners.insert(key, pid);
+                }
+            }
+            Resource::Device(Device::UdpSocket(ref dev)) => {
+                let key = (dev.iface, dev.handle);
+                owners.insert(key, pid);
+                if let Some(endpoint) = dev.remote_endpoint {
+                    remotes.insert(key, endpoint.to_string());
+                }
+            }
+            Resource::Device(Device::IcmpSocket(ref dev)) => {
+                let key = (dev.iface, dev.handle);
+                owners.insert(key, pid);
+                if let Some(addr) = dev.remote_addr {
+                    remotes.insert(key, addr.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut res = Vec::new();
+    for (i, net) in sys::net::NET.lock().iter().enumerate() {
+        for (handle, socket) in net.sockets.iter() {
+            let key = (i, handle);
+            let any = "*".to_string();
+            let remote = remotes.get(&key).cloned().unwrap_or_else(|| any.clone());
+            let (protocol, local, remote, state, recv_queue, send_queue) = match socket {
+                Socket::Tcp(socket) => {
+                    let local = match socket.local_endpoint() {
+                        Some(endpoint) => endpoint.to_string(),
+                        None => socket.listen_endpoint().to_string(),
+                    };
+                    let remote = match socket.remote_endpoint() {
+                        Some(endpoint) => endpoint.to_string(),
+                        None => any,
+                    };
+                    let state = socket.state().to_string();
+                    let (rx, tx) = (socket.recv_queue(), socket.send_queue());
+                    ("tcp", local, remote, state, rx, tx)
+                }
+                Socket::Udp(socket) => {
+                    let local = socket.endpoint().to_string();
+                    let state = if socket.is_open() { "OPEN" } else { "CLOSED" };
+                    let (rx, tx) = (socket.recv_queue(), socket.send_queue());
+                    ("udp", local, remote, state.to_string(), rx, tx)
+                }
+                Socket::Icmp(socket) => {
+                    let state = if socket.is_open() { "OPEN" } else { "CLOSED" };
+                    let (rx, tx) = (socket.recv_queue(), socket.send_queue());
+                    ("icmp", any, remote, state.to_string(), rx, tx)
+                }
+                Socket::Dhcpv4(_) => {
+                    let local = "*:68".to_string();
+                    let remote = "*:67".to_string();
+                    ("dhcp", local, remote, "OPEN".to_string(), 0, 0)
+                }
+                Socket::Raw(socket) => {
+                    let (rx, tx) = (socket.recv_queue(), socket.send_queue());
+                    ("raw", any, remote, "OPEN".to_string(), rx, tx)
+                }
+            };
+            res.push(SocketInfo {
+                protocol,
+                iface: net.name.clone(),
+                local,
+                remote,
+                state,
+                recv_queue,
+                send_queue,
+                pid: owners.get(&key).copied(),
+            });
+        }
+    }
+    res
 }
 
 fn random_port() -> u16 {
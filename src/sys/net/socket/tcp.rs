@@ -1,17 +1,54 @@
 use crate::sys;
 
 use crate::api::fs::{FileIO, IO};
-use crate::sys::net::SocketStatus;
+use crate::sys::net::{NetInterface, SocketOption, SocketStatus};
 
-use super::SOCKETS;
-use super::{random_port, wait};
+use super::{random_port, wait, SocketOptions, MAX_BUFFER_SIZE};
 
 use alloc::vec;
+use alloc::vec::Vec;
 use bit_field::BitField;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use smoltcp::iface::SocketHandle;
 use smoltcp::phy::Device;
-use smoltcp::socket::tcp;
+use smoltcp::socket::{tcp, Socket};
 use smoltcp::wire::IpAddress;
+use spin::Mutex;
+
+// Default size of the buffers of the sockets, giving the size of their receive
+// window which is scaled when it is larger than 64 KB
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(64 << 10);
+
+pub fn buffer_size() -> usize {
+    BUFFER_SIZE.load(Ordering::Relaxed)
+}
+
+pub fn set_buffer_size(size: usize) -> Result<(), ()> {
+    if size == 0 || size > MAX_BUFFER_SIZE {
+        return Err(());
+    }
+    BUFFER_SIZE.store(size, Ordering::Relaxed);
+    Ok(())
+}
+
+// Maximum total size of the buffers of the sockets of a backlog
+const MAX_BACKLOG_SIZE: usize = 1 << 20;
+
+// Sockets that have been closed but are kept in their set until the end of
+// their connection
+static CLOSING: Mutex<Vec<(usize, SocketHandle)>> = Mutex::new(Vec::new());
+
+pub fn remove_closed(net: &mut [NetInterface]) {
+    CLOSING.lock().retain(
+        |&(i, handle)| match net[i].sockets.get::<tcp::Socket>(handle).state() {
+            tcp::State::Closed | tcp::State::TimeWait => {
+                net[i].sockets.remove(handle);
+                false
+            }
+            _ => true,
+        },
+    );
+}
 
 fn tcp_socket_status(socket: &tcp::Socket) -> u8 {
     let mut status = 0;
@@ -25,15 +62,31 @@ fn tcp_socket_status(socket: &tcp::Socket) -> u8 {
     status
 }
 
+fn tcp_socket(options: &SocketOptions) -> tcp::Socket<'static> {
+    let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; options.recv_buffer_size]);
+    let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; options.send_buffer_size]);
+    tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
+}
+
+// A socket is created on the first interface and moved to the interface
+// used to reach the address given to `connect`, while a listening socket
+// has a backlog of sockets listening on every interface. Each connection
+// accepted from the backlog is given its own socket and is replaced by a new
+// listening socket, so that the clients connecting in the meantime are kept
+// waiting in the backlog instead of being dropped.
 #[derive(Debug, Clone)]
 pub struct TcpSocket {
+    pub iface: usize,
     pub handle: SocketHandle,
+    pub options: SocketOptions,
+    port: u16,
+    backlog: Vec<(usize, SocketHandle)>,
 }
 
 impl TcpSocket {
     pub fn size() -> usize {
-        if let Some((_, ref mut device)) = *sys::net::NET.lock() {
-            let mtu = device.capabilities().max_transmission_unit;
+        if let Some(net) = sys::net::NET.lock().first() {
+            let mtu = net.device.capabilities().max_transmission_unit;
             let eth_header = 14;
             let ip_header = 20;
             let tcp_header = 20;
@@ -43,27 +96,92 @@ impl TcpSocket {
         }
     }
 
-    pub fn new() -> Self {
-        let mut sockets = SOCKETS.lock();
-        let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; 1024]);
-        let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; 1024]);
-        let tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
-        let handle = sockets.add(tcp_socket);
+    pub fn new() -> Option<Self> {
+        let iface = 0;
+        let mut net = sys::net::NET.lock();
+        let options = SocketOptions {
+            recv_buffer_size: buffer_size(),
+            send_buffer_size: buffer_size(),
+            ..Default::default()
+        };
+        let handle = net.get_mut(iface)?.sockets.add(tcp_socket(&options));
+        let port = 0;
+        let backlog = Vec::new();
 
-        Self { handle }
+        Some(Self {
+            iface,
+            handle,
+            options,
+            port,
+            backlog,
+        })
+    }
+
+    pub fn set_option(&mut self, option: SocketOption, value: usize) -> Result<(), ()> {
+        let mut options = self.options;
+        if options.set(option, value)? {
+            // The buffers can only be replaced before the socket is used
+            let mut net = sys::net::NET.lock();
+            let sockets = &mut net.get_mut(self.iface).ok_or(())?.sockets;
+            if sockets.get::<tcp::Socket>(self.handle).state() != tcp::State::Closed {
+                return Err(());
+            }
+            sockets.remove(self.handle);
+            self.handle = sockets.add(tcp_socket(&options));
+        }
+        self.options = options;
+        Ok(())
+    }
+
+    // Return the sockets used by this socket, which are the sockets of the
+    // backlog of a listening socket
+    pub fn handles(&self) -> Vec<(usize, SocketHandle)> {
+        if self.backlog.is_empty() {
+            vec![(self.iface, self.handle)]
+        } else {
+            self.backlog.clone()
+        }
+    }
+
+    // Move the socket to the interface used to reach an address
+    fn route(&mut self, net: &mut [NetInterface], addr: &IpAddress) {
+        if let Some(i) = sys::net::route(net, addr) {
+            if i != self.iface {
+                if let Socket::Tcp(socket) = net[self.iface].sockets.remove(self.handle) {
+                    self.handle = net[i].sockets.add(socket);
+                    self.iface = i;
+                }
+            }
+        }
+    }
+
+    // Remove the sockets of the backlog, except the socket of the handle
+    fn close_backlog(&mut self, net: &mut [NetInterface]) {
+        for (i, handle) in self.backlog.drain(..) {
+            if (i, handle) != (self.iface, self.handle) {
+                net[i].sockets.remove(handle);
+            }
+        }
     }
 
     pub fn connect(&mut self, addr: IpAddress, port: u16) -> Result<(), ()> {
         let mut connecting = false;
-        let timeout = 5.0;
+        let timeout = self.options.connect_timeout;
         let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
+        let mut net = sys::net::NET.lock();
+        self.route(&mut net, &addr);
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = net.get_mut(self.iface)
+        {
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
                 match socket.state() {
@@ -78,7 +196,11 @@ impl TcpSocket {
                         }
                         connecting = true;
                     }
-                    tcp::State::SynSent => {}
+                    tcp::State::SynSent => {
+                        if !self.options.blocking {
+                            break;
+                        }
+                    }
                     tcp::State::Established => {
                         break;
                     }
@@ -88,7 +210,7 @@ impl TcpSocket {
                     }
                 }
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -98,64 +220,120 @@ impl TcpSocket {
     }
 
     pub fn listen(&mut self, port: u16) -> Result<(), ()> {
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            iface.poll(sys::net::time(), device, &mut sockets);
-            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+        let mut net = sys::net::NET.lock();
+        if !self.backlog.is_empty() {
+            return Err(());
+        }
+        self.backlog.push((self.iface, self.handle));
 
+        // The backlog of each interface is reduced to keep the size of its
+        // buffers under the limit, with at least one socket per interface
+        let size = self.options.recv_buffer_size + self.options.send_buffer_size;
+        let backlog = (MAX_BACKLOG_SIZE / (size * net.len())).clamp(1, self.options.backlog);
+        for i in 0..net.len() {
+            let n = backlog - if i == self.iface { 1 } else { 0 };
+            for _ in 0..n {
+                let handle = net[i].sockets.add(tcp_socket(&self.options));
+                self.backlog.push((i, handle));
+            }
+        }
+        for &(i, handle) in &self.backlog {
+            let socket = net[i].sockets.get_mut::<tcp::Socket>(handle);
             if socket.listen(port).is_err() {
+                self.close_backlog(&mut net);
                 return Err(());
             }
+        }
+        self.port = port;
+        Ok(())
+    }
 
-            if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                wait(d);
+    // Return the index in the backlog of a connection ready to be accepted,
+    // after replacing the sockets that were closed before being accepted
+    fn pending(&mut self, net: &mut [NetInterface]) -> Result<Option<usize>, ()> {
+        for net in net.iter_mut() {
+            net.iface
+                .poll(sys::net::time(), &mut net.device, &mut net.sockets);
+        }
+        for k in 0..self.backlog.len() {
+            let (i, handle) = self.backlog[k];
+            match net[i].sockets.get::<tcp::Socket>(handle).state() {
+                tcp::State::Listen | tcp::State::SynReceived => {}
+                tcp::State::Closed => {
+                    net[i].sockets.remove(handle);
+                    self.replace(net, k)?;
+                }
+                _ => return Ok(Some(k)),
             }
-            sys::clk::halt();
-            Ok(())
-        } else {
-            Err(())
         }
+        Ok(None)
     }
 
-    pub fn accept(&mut self) -> Result<IpAddress, ()> {
-        let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+    // Replace a socket of the backlog by a new listening socket
+    fn replace(&mut self, net: &mut [NetInterface], k: usize) -> Result<(), ()> {
+        let i = self.backlog[k].0;
+        let mut socket = tcp_socket(&self.options);
+        socket.listen(self.port).map_err(|_| ())?;
+        self.backlog[k] = (i, net[i].sockets.add(socket));
+        (self.iface, self.handle) = self.backlog[0];
+        Ok(())
+    }
 
+    // Return a new socket for the next connection of the backlog of a
+    // listening socket, with the address of its remote endpoint
+    pub fn accept(&mut self) -> Result<(Self, IpAddress), ()> {
+        if self.backlog.is_empty() {
+            return Err(());
+        }
+        let timeout = self.options.connect_timeout;
+        let started = sys::clk::epoch_time();
+        let mut net = sys::net::NET.lock();
+        loop {
+            if sys::clk::epoch_time() - started > timeout {
+                return Err(());
+            }
+            if let Some(k) = self.pending(&mut net)? {
+                let (iface, handle) = self.backlog[k];
+                self.replace(&mut net, k)?;
+                let socket = net[iface].sockets.get::<tcp::Socket>(handle);
                 if let Some(endpoint) = socket.remote_endpoint() {
-                    return Ok(endpoint.addr);
+                    let connection = Self {
+                        iface,
+                        handle,
+                        options: self.options,
+                        port: 0,
+                        backlog: Vec::new(),
+                    };
+                    return Ok((connection, endpoint.addr));
                 }
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+                net[iface].sockets.remove(handle);
+                return Err(());
             }
-        } else {
-            Err(())
+            if !self.options.blocking {
+                return Err(());
+            }
+            sys::clk::halt();
         }
     }
 }
 
 impl FileIO for TcpSocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        let timeout = 5.0;
+        let timeout = self.options.read_timeout;
         let started = sys::clk::epoch_time();
         let mut bytes = 0;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
                 if buf.len() == 1 {
@@ -171,7 +349,10 @@ impl FileIO for TcpSocket {
                 if !socket.may_recv() {
                     break;
                 }
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if !self.options.blocking {
+                    return Err(());
+                }
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -183,34 +364,43 @@ impl FileIO for TcpSocket {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        let timeout = 5.0;
+        let timeout = self.options.write_timeout;
         let started = sys::clk::epoch_time();
+        let mut bytes = 0;
         let mut sent = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
                 if sent {
                     break;
                 }
                 if socket.can_send() {
-                    if socket.send_slice(buf.as_ref()).is_err() {
-                        return Err(());
-                    }
-                    sent = true; // Break after next poll
+                    bytes += socket.send_slice(&buf[bytes..]).map_err(|_| ())?;
+
+                    // Break after next poll when the whole buffer is queued,
+                    // or when a part of it is queued without blocking
+                    sent = bytes == buf.len() || !self.options.blocking;
+                } else if !self.options.blocking {
+                    return Err(());
                 }
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
             }
-            Ok(buf.len())
+            Ok(bytes)
         } else {
             Err(())
         }
@@ -218,10 +408,21 @@ impl FileIO for TcpSocket {
 
     fn close(&mut self) {
         let mut closed = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
+        let mut net = sys::net::NET.lock();
+        if !self.backlog.is_empty() {
+            self.close_backlog(&mut net);
+            net[self.iface].sockets.remove(self.handle);
+            return;
+        }
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = net.get_mut(self.iface)
+        {
             loop {
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
                 if closed {
@@ -230,18 +431,32 @@ impl FileIO for TcpSocket {
                 socket.close();
                 closed = true;
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
             }
+            CLOSING.lock().push((self.iface, self.handle));
         }
     }
 
     fn poll(&mut self, event: IO) -> bool {
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            iface.poll(sys::net::time(), device, &mut sockets);
+        if !self.backlog.is_empty() {
+            // A listening socket can be read when a connection is pending
+            let mut net = sys::net::NET.lock();
+            return match event {
+                IO::Read => matches!(self.pending(&mut net), Ok(Some(_))),
+                IO::Write => false,
+            };
+        }
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
+            iface.poll(sys::net::time(), device, sockets);
             let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
             match event {
@@ -253,3 +468,125 @@ impl FileIO for TcpSocket {
         }
     }
 }
+
+#[test_case]
+fn test_loopback() {
+    let addr = IpAddress::v4(127, 0, 0, 1);
+    let port = 8080;
+
+    let mut server = TcpSocket::new().unwrap();
+    assert!(server.listen(port).is_ok());
+
+    let mut client = TcpSocket::new().unwrap();
+    assert!(client.connect(addr, port).is_ok());
+    let (mut connection, remote_addr) = server.accept().unwrap();
+    assert_eq!(remote_addr, addr);
+
+    let mut buf = [0; 5];
+    assert_eq!(client.write(b"hello"), Ok(5));
+    assert_eq!(connection.read(&mut buf), Ok(5));
+    assert_eq!(&buf, b"hello");
+
+    // The server keeps listening while the first connection is open
+    let mut other = TcpSocket::new().unwrap();
+    assert!(other.connect(addr, port).is_ok());
+    assert!(server
+        .accept()
+        .is_ok_and(|(_, remote_addr)| remote_addr == addr));
+
+    other.close();
+    client.close();
+    connection.close();
+    server.close();
+}
+
+#[test_case]
+fn test_options() {
+    let mut socket = TcpSocket::new().unwrap();
+    let rx_size = SocketOption::RecvBufferSize;
+    let tx_size = SocketOption::SendBufferSize;
+    assert_eq!(socket.set_option(rx_size, 4096), Ok(()));
+    assert_eq!(socket.set_option(rx_size, 0), Err(()));
+    let timeout = (-1.0f64).to_bits() as usize;
+    let res = socket.set_option(SocketOption::ReadTimeout, timeout);
+    assert_eq!(res, Err(()));
+    assert_eq!(socket.set_option(SocketOption::Blocking, 0), Ok(()));
+
+    // Accepting without blocking fails immediately without a client
+    assert!(socket.listen(8081).is_ok());
+    assert!(socket.accept().is_err());
+    assert_eq!(socket.set_option(tx_size, 4096), Err(()));
+    socket.close();
+}
+
+// Return the window scale of a frame carrying a TCP SYN
+#[cfg(test)]
+fn syn_window_scale(buf: &[u8]) -> Option<Option<u8>> {
+    use smoltcp::phy::ChecksumCapabilities;
+    use smoltcp::wire::{EthernetFrame, EthernetProtocol, Ipv4Packet, TcpPacket, TcpRepr};
+
+    let frame = EthernetFrame::new_checked(buf).ok()?;
+    if frame.ethertype() != EthernetProtocol::Ipv4 {
+        return None;
+    }
+    let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+    let segment = TcpPacket::new_checked(packet.payload()).ok()?;
+    let src = IpAddress::Ipv4(packet.src_addr());
+    let dst = IpAddress::Ipv4(packet.dst_addr());
+    let caps = ChecksumCapabilities::ignored();
+    let repr = TcpRepr::parse(&segment, &src, &dst, &caps).ok()?;
+    if repr.control != smoltcp::wire::TcpControl::Syn {
+        return None;
+    }
+    Some(repr.window_scale)
+}
+
+#[test_case]
+fn test_window_scale() {
+    use crate::sys::net::nic::loopback;
+    use crate::sys::net::{EthernetDevice, EthernetDeviceIO};
+    use smoltcp::iface::{Config, Interface, SocketSet};
+    use smoltcp::wire::IpCidr;
+
+    let addr = IpAddress::v4(127, 0, 0, 1);
+    let mut device = EthernetDevice::Loopback(loopback::Device::new());
+    let mac = device.config().mac().unwrap();
+    let config = Config::new(mac.into());
+    let mut iface = Interface::new(config, &mut device, sys::net::time());
+    iface.update_ip_addrs(|addrs| addrs.push(IpCidr::new(addr, 8)).unwrap());
+
+    // The receive window of a buffer larger than 64 KB must be scaled to fit
+    // in the 16 bits of the window field
+    let options = SocketOptions {
+        recv_buffer_size: 256 << 10,
+        ..Default::default()
+    };
+    let mut sockets = SocketSet::new(vec![]);
+    let handle = sockets.add(tcp_socket(&options));
+    let socket = sockets.get_mut::<tcp::Socket>(handle);
+    assert!(socket.connect(iface.context(), (addr, 8082), 49152).is_ok());
+
+    // The frames sent before the SYN are given back to the interface
+    let mut scale = None;
+    for _ in 0..4 {
+        iface.poll(sys::net::time(), &mut device, &mut sockets);
+        let mut frames = Vec::new();
+        while let Some(buf) = device.receive_packet() {
+            match syn_window_scale(&buf) {
+                Some(s) => scale = s,
+                None => frames.push(buf),
+            }
+        }
+        if scale.is_some() {
+            break;
+        }
+        for buf in frames {
+            let len = buf.len();
+            device.next_tx_buffer(len).copy_from_slice(&buf);
+            assert!(device.transmit_packet(len).is_ok());
+        }
+    }
+    let scale = scale.unwrap();
+    assert!(scale > 0);
+    assert!(options.recv_buffer_size >> scale <= u16::MAX as usize);
+}
@@ -1,16 +1,15 @@
 use crate::sys;
 
 use crate::api::fs::{FileIO, IO};
-use crate::sys::net::SocketStatus;
+use crate::sys::net::{NetInterface, SocketOption, SocketStatus};
 
-use super::SOCKETS;
-use super::{random_port, wait};
+use super::{random_port, wait, SocketOptions};
 
 use alloc::vec;
 use bit_field::BitField;
 use smoltcp::iface::SocketHandle;
 use smoltcp::phy::Device;
-use smoltcp::socket::udp;
+use smoltcp::socket::{udp, Socket};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
 fn udp_socket_status(socket: &udp::Socket) -> u8 {
@@ -21,16 +20,32 @@ fn udp_socket_status(socket: &udp::Socket) -> u8 {
     status
 }
 
+fn udp_socket(options: &SocketOptions) -> udp::Socket<'static> {
+    let udp_rx_buffer = udp::PacketBuffer::new(
+        vec![udp::PacketMetadata::EMPTY],
+        vec![0; options.recv_buffer_size],
+    );
+    let udp_tx_buffer = udp::PacketBuffer::new(
+        vec![udp::PacketMetadata::EMPTY],
+        vec![0; options.send_buffer_size],
+    );
+    udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
+}
+
+// A socket is created on the first interface and moved to the interface
+// used to reach the address given to `connect`.
 #[derive(Debug, Clone)]
 pub struct UdpSocket {
+    pub iface: usize,
     pub handle: SocketHandle,
+    pub options: SocketOptions,
     pub remote_endpoint: Option<IpEndpoint>,
 }
 
 impl UdpSocket {
     pub fn size() -> usize {
-        if let Some((_, ref mut device)) = *sys::net::NET.lock() {
-            let mtu = device.capabilities().max_transmission_unit;
+        if let Some(net) = sys::net::NET.lock().first() {
+            let mtu = net.device.capabilities().max_transmission_unit;
             let eth_header = 14;
             let ip_header = 20;
             let udp_header = 8;
@@ -40,30 +55,66 @@ impl UdpSocket {
         }
     }
 
-    pub fn new() -> Self {
-        let mut sockets = SOCKETS.lock();
-        let udp_rx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 1024]);
-        let udp_tx_buffer = udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 1024]);
-        let udp_socket = udp::Socket::new(udp_rx_buffer, udp_tx_buffer);
-        let handle = sockets.add(udp_socket);
+    pub fn new() -> Option<Self> {
+        let iface = 0;
+        let mut net = sys::net::NET.lock();
+        let options = SocketOptions::default();
+        let handle = net.get_mut(iface)?.sockets.add(udp_socket(&options));
         let remote_endpoint = None;
 
-        Self {
+        Some(Self {
+            iface,
             handle,
+            options,
             remote_endpoint,
+        })
+    }
+
+    pub fn set_option(&mut self, option: SocketOption, value: usize) -> Result<(), ()> {
+        let mut options = self.options;
+        if options.set(option, value)? {
+            // The buffers can only be replaced before the socket is bound
+            let mut net = sys::net::NET.lock();
+            let sockets = &mut net.get_mut(self.iface).ok_or(())?.sockets;
+            if sockets.get::<udp::Socket>(self.handle).is_open() {
+                return Err(());
+            }
+            sockets.remove(self.handle);
+            self.handle = sockets.add(udp_socket(&options));
+        }
+        self.options = options;
+        Ok(())
+    }
+
+    // Move the socket to the interface used to reach an address
+    fn route(&mut self, net: &mut [NetInterface], addr: &IpAddress) {
+        if let Some(i) = sys::net::route(net, addr) {
+            if i != self.iface {
+                if let Socket::Udp(socket) = net[self.iface].sockets.remove(self.handle) {
+                    self.handle = net[i].sockets.add(socket);
+                    self.iface = i;
+                }
+            }
         }
     }
 
     pub fn connect(&mut self, addr: IpAddress, port: u16) -> Result<(), ()> {
-        let timeout = 5.0;
+        let timeout = self.options.connect_timeout;
         let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
+        let mut net = sys::net::NET.lock();
+        self.route(&mut net, &addr);
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = net.get_mut(self.iface)
+        {
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
                 if !socket.is_open() {
@@ -72,7 +123,7 @@ impl UdpSocket {
                     break;
                 }
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -86,23 +137,28 @@ impl UdpSocket {
         todo!()
     }
 
-    pub fn accept(&mut self) -> Result<IpAddress, ()> {
+    pub fn accept(&mut self) -> Result<(Self, IpAddress), ()> {
         todo!()
     }
 }
 
 impl FileIO for UdpSocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        let timeout = 5.0;
+        let timeout = self.options.read_timeout;
         let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
             let bytes;
-            let mut sockets = SOCKETS.lock();
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
                 if buf.len() == 1 {
@@ -115,7 +171,10 @@ impl FileIO for UdpSocket {
                     (bytes, _) = socket.recv_slice(buf).map_err(|_| ())?;
                     break;
                 }
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if !self.options.blocking {
+                    return Err(());
+                }
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -127,16 +186,21 @@ impl FileIO for UdpSocket {
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
-        let timeout = 5.0;
+        let timeout = self.options.write_timeout;
         let started = sys::clk::epoch_time();
         let mut sent = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
             loop {
                 if sys::clk::epoch_time() - started > timeout {
                     return Err(());
                 }
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
                 if sent {
@@ -151,9 +215,11 @@ impl FileIO for UdpSocket {
                         return Err(());
                     }
                     sent = true; // Break after next poll
+                } else if !self.options.blocking {
+                    return Err(());
                 }
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -166,10 +232,15 @@ impl FileIO for UdpSocket {
 
     fn close(&mut self) {
         let mut closed = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
             loop {
-                iface.poll(sys::net::time(), device, &mut sockets);
+                iface.poll(sys::net::time(), device, sockets);
                 let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
                 if closed {
@@ -178,7 +249,7 @@ impl FileIO for UdpSocket {
                 socket.close();
                 closed = true;
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
+                if let Some(d) = iface.poll_delay(sys::net::time(), sockets) {
                     wait(d);
                 }
                 sys::clk::halt();
@@ -187,9 +258,14 @@ impl FileIO for UdpSocket {
     }
 
     fn poll(&mut self, event: IO) -> bool {
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            iface.poll(sys::net::time(), device, &mut sockets);
+        if let Some(NetInterface {
+            iface,
+            device,
+            sockets,
+            ..
+        }) = sys::net::NET.lock().get_mut(self.iface)
+        {
+            iface.poll(sys::net::time(), device, sockets);
             let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
             match event {
@@ -2,13 +2,18 @@ use crate::api::fs::{FileIO, IO};
 use crate::sys::net::EthernetDeviceIO;
 
 use alloc::format;
+use alloc::string::String;
 
 #[derive(Debug, Clone)]
-pub struct NetUsage;
+pub struct NetUsage {
+    iface: String,
+}
 
 impl NetUsage {
-    pub fn new() -> Self {
-        Self
+    pub fn new(iface: &str) -> Self {
+        Self {
+            iface: iface.into(),
+        }
     }
 
     pub fn size() -> usize {
@@ -18,8 +23,9 @@ impl NetUsage {
 
 impl FileIO for NetUsage {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
-        if let Some((_, ref mut device)) = *super::NET.lock() {
-            let stats = device.stats();
+        let net = super::NET.lock();
+        if let Some(i) = super::find_interface(&net, &self.iface) {
+            let stats = net[i].device.stats();
             let s = format!(
                 "{} {} {} {}",
                 stats.rx_packets_count(),
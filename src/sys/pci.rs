@@ -80,6 +80,38 @@ impl DeviceConfig {
         register.write(data);
     }
 
+    pub fn read_config(&self, offset: u8) -> u32 {
+        let mut register = ConfigRegister::new(self.bus, self.device, self.function, offset);
+        register.read()
+    }
+
+    // Offsets of the capabilities in the configuration space, with their ids
+    pub fn capabilities(&self) -> Vec<(u8, u8)> {
+        let mut res = Vec::new();
+        if !self.status.get_bit(4) {
+            return res;
+        }
+        let mut offset = self.read_config(0x34).get_bits(0..8) as u8 & 0xFC;
+        while offset != 0 && res.len() < 48 {
+            let data = self.read_config(offset);
+            res.push((offset, data.get_bits(0..8) as u8));
+            offset = data.get_bits(8..16) as u8 & 0xFC;
+        }
+        res
+    }
+
+    // Address of the memory or I/O space given by a base address register
+    pub fn bar_addr(&self, i: usize) -> u64 {
+        let bar = self.base_addresses[i];
+        if bar.get_bit(0) {
+            (bar & 0xFFFC) as u64
+        } else if bar.get_bits(1..3) == 2 && i < 5 {
+            ((bar & 0xFFFFFFF0) as u64) | ((self.base_addresses[i + 1] as u64) << 32)
+        } else {
+            (bar & 0xFFFFFFF0) as u64
+        }
+    }
+
     pub fn bar_type(&self) -> u16 {
         self.base_addresses[0].get_bits(1..3) as u16
     }
@@ -227,8 +259,9 @@ pub fn init() {
 
     let devs = PCI_DEVICES.lock();
     for dev in devs.iter() {
-        // NOTE: There's not yet an AHCI driver for SATA disks so we must
-        // switch to IDE legacy mode for the ATA driver.
+        // NOTE: The ATA driver only supports the legacy mode of the IDE
+        // controllers, the SATA controllers in AHCI mode have their own
+        // driver.
         if dev.class == 0x01 && dev.subclass == 0x01 {
             // IDE Controller
             let mut register = ConfigRegister::new(dev.bus, dev.device, dev.function, 0x08);
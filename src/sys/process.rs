@@ -189,6 +189,29 @@ pub fn handles() -> Vec<Option<Box<Resource>>> {
     proc.data.handles.to_vec()
 }
 
+// Check if a handle of a running process matches `f`
+pub fn has_handle(f: impl Fn(&Resource) -> bool) -> bool {
+    let n = MAX_PID.load(Ordering::SeqCst);
+    let table = PROCESS_TABLE.read();
+    table
+        .iter()
+        .take(n)
+        .any(|proc| proc.data.handles.iter().flatten().any(|handle| f(handle)))
+}
+
+// Return the handles of every running process with its id
+pub fn running_handles() -> Vec<(usize, Box<Resource>)> {
+    let table = PROCESS_TABLE.read();
+    let n = MAX_PID.load(Ordering::SeqCst).min(MAX_PROCS);
+    let mut res = Vec::new();
+    for proc in table[0..n].iter() {
+        for file in proc.data.handles.iter().flatten() {
+            res.push((proc.id, file.clone()));
+        }
+    }
+    res
+}
+
 pub fn code_addr() -> u64 {
     let table = PROCESS_TABLE.read();
     let proc = &table[id()];
@@ -9,6 +9,7 @@ use core::arch::asm;
 use core::convert::TryInto;
 use smoltcp::wire::IpAddress;
 use smoltcp::wire::Ipv4Address;
+use smoltcp::wire::Ipv6Address;
 
 fn utf8_from_raw_parts(ptr: *mut u8, len: usize) -> &'static str {
     unsafe {
@@ -18,6 +19,11 @@ fn utf8_from_raw_parts(ptr: *mut u8, len: usize) -> &'static str {
 }
 
 pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> usize {
+    // The network interfaces are also polled before a syscall, when the kernel
+    // is not in the middle of an operation and can save the DHCP leases
+    sys::net::poll();
+    sys::net::dhcp::save_all();
+
     match n {
         number::EXIT => service::exit(ExitCode::from(arg1)) as usize,
         number::SLEEP => {
@@ -37,6 +43,15 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize)
             let info = unsafe { &mut *(arg3 as *mut FileInfo) };
             service::info(path, info) as usize
         }
+        number::LINK => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64);
+            let len = arg2;
+            let target = utf8_from_raw_parts(ptr, len);
+            let ptr = sys::process::ptr_from_addr(arg3 as u64);
+            let len = arg4;
+            let path = utf8_from_raw_parts(ptr, len);
+            service::link(target, path) as usize
+        }
         number::KIND => {
             let handle = arg1;
             service::kind(handle) as usize
@@ -64,6 +79,12 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize)
             };
             service::write(handle, buf) as usize
         }
+        number::SEEK => {
+            let handle = arg1;
+            let offset = arg2 as i64;
+            let whence = arg3;
+            service::seek(handle, offset, whence) as usize
+        }
         number::CLOSE => {
             let handle = arg1;
             service::close(handle);
@@ -97,8 +118,15 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize)
             let ptr = sys::process::ptr_from_addr(arg2 as u64);
             let len = arg3;
             let buf = unsafe { core::slice::from_raw_parts(ptr, len) };
-            if let Ok(buf) = buf.try_into() {
-                let addr = IpAddress::from(Ipv4Address::from_octets(buf));
+            // The family of the address is given by its length
+            let addr = if let Ok(buf) = buf.try_into() {
+                Some(IpAddress::from(Ipv4Address::from_octets(buf)))
+            } else if let Ok(buf) = buf.try_into() {
+                Some(IpAddress::from(Ipv6Address::from_octets(buf)))
+            } else {
+                None
+            };
+            if let Some(addr) = addr {
                 let port = arg4 as u16;
                 service::connect(handle, addr, port) as usize
             } else {
@@ -115,13 +143,36 @@ pub fn dispatcher(n: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize)
             let ptr = sys::process::ptr_from_addr(arg2 as u64);
             let len = arg3;
             let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
-            if let Ok(IpAddress::Ipv4(addr)) = service::accept(handle) {
-                buf[0..len].clone_from_slice(&addr.octets());
-                0
-            } else {
-                -1 as isize as usize
+            if len < 16 {
+                return -1_isize as usize;
+            }
+            // Return the handle of the connection and write its address
+            // into the buffer, with IPv4 addresses mapped to IPv6
+            match service::accept(handle) {
+                Ok((handle, IpAddress::Ipv4(addr))) => {
+                    buf[0..16].clone_from_slice(&addr.to_ipv6_mapped().octets());
+                    handle
+                }
+                Ok((handle, IpAddress::Ipv6(addr))) => {
+                    buf[0..16].clone_from_slice(&addr.octets());
+                    handle
+                }
+                Err(()) => -1_isize as usize,
             }
         }
+        number::OPTION => {
+            let handle = arg1;
+            let option = arg2;
+            let value = arg3;
+            service::option(handle, option, value) as usize
+        }
+        number::WAIT => {
+            let ptr = sys::process::ptr_from_addr(arg1 as u64) as *const _;
+            let len = arg2;
+            let list = unsafe { core::slice::from_raw_parts(ptr, len) };
+            let timeout = f64::from_bits(arg3 as u64);
+            service::wait(list, timeout) as usize
+        }
         number::ALLOC => {
             let size = arg1;
             let align = arg2;
@@ -1,14 +1,16 @@
-use crate::api::fs::{FileIO, IO};
+use crate::api::fs::{FileIO, SeekFrom, IO};
 use crate::api::process::ExitCode;
 use crate::sys;
 use crate::sys::fs::Device;
 use crate::sys::fs::FileInfo;
 use crate::sys::fs::Resource;
+use crate::sys::net::SocketOption;
 use crate::sys::process::Process;
 
 use alloc::vec;
 use core::alloc::Layout;
 use core::arch::asm;
+use core::convert::TryFrom;
 use smoltcp::wire::IpAddress;
 
 pub fn exit(code: ExitCode) -> ExitCode {
@@ -28,6 +30,22 @@ pub fn delete(path: &str) -> isize {
     }
 }
 
+pub fn link(target: &str, path: &str) -> isize {
+    let target = match sys::fs::canonicalize(target) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    let path = match sys::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return -1,
+    };
+    if sys::fs::link(&target, &path).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
 pub fn info(path: &str, info: &mut FileInfo) -> isize {
     let path = match sys::fs::canonicalize(path) {
         Ok(path) => path,
@@ -90,6 +108,24 @@ pub fn write(handle: usize, buf: &mut [u8]) -> isize {
     -1
 }
 
+// The offset is relative to the start of the file with the whence 0, to the
+// current offset with 1, and to the end of the file with 2.
+pub fn seek(handle: usize, offset: i64, whence: usize) -> isize {
+    let pos = match whence {
+        0 if offset >= 0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    if let Some(mut file) = sys::process::handle(handle) {
+        if let Ok(offset) = file.seek(pos) {
+            sys::process::update_handle(handle, *file);
+            return offset as isize;
+        }
+    }
+    -1
+}
+
 pub fn close(handle: usize) {
     if let Some(mut file) = sys::process::handle(handle) {
         file.close();
@@ -102,7 +138,7 @@ pub fn spawn(path: &str, args_ptr: usize, args_len: usize) -> ExitCode {
         Ok(path) => path,
         Err(_) => return ExitCode::OpenError,
     };
-    if let Some(mut file) = sys::fs::File::open(&path) {
+    if let Some(Resource::File(mut file)) = sys::fs::open(&path, 0) {
         let mut buf = vec![0; file.size()];
         if let Ok(bytes) = file.read(&mut buf) {
             buf.resize(bytes, 0);
@@ -123,12 +159,20 @@ pub fn stop(code: usize) -> usize {
     match code {
         0xCAFE => {
             // Reboot
+            sys::net::dhcp::release_all();
+            sys::ata::flush_all();
+            sys::ahci::flush_all();
+            sys::virtio::blk::flush_all();
             unsafe {
                 asm!("xor rax, rax", "mov cr3, rax");
             }
         }
         0xDEAD => {
             // Halt
+            sys::net::dhcp::release_all();
+            sys::ata::flush_all();
+            sys::ahci::flush_all();
+            sys::virtio::blk::flush_all();
             sys::process::exit();
             sys::acpi::shutdown();
         }
@@ -150,15 +194,33 @@ pub fn poll(list: &[(usize, IO)]) -> isize {
     -1
 }
 
+pub fn wait(list: &[(usize, IO)], timeout: f64) -> isize {
+    if timeout.is_nan() || timeout < 0.0 {
+        return -1;
+    }
+    let started = sys::clk::boot_time();
+    loop {
+        let res = poll(list);
+        if res >= 0 || sys::clk::boot_time() - started >= timeout {
+            return res;
+        }
+        sys::clk::halt();
+    }
+}
+
 pub fn connect(handle: usize, addr: IpAddress, port: u16) -> isize {
     if let Some(mut file) = sys::process::handle(handle) {
         let res = match *file {
             Resource::Device(Device::TcpSocket(ref mut dev)) => dev.connect(addr, port),
             Resource::Device(Device::UdpSocket(ref mut dev)) => dev.connect(addr, port),
+            Resource::Device(Device::IcmpSocket(ref mut dev)) => dev.connect(addr, port),
             _ => Err(()),
         };
+
+        // The socket may have been moved to the interface of the route even
+        // if the connection failed
+        sys::process::update_handle(handle, *file);
         if res.is_ok() {
-            sys::process::update_handle(handle, *file);
             return 0;
         }
     }
@@ -166,30 +228,70 @@ pub fn connect(handle: usize, addr: IpAddress, port: u16) -> isize {
 }
 
 pub fn listen(handle: usize, port: u16) -> isize {
-    if let Some(file) = sys::process::handle(handle) {
+    if let Some(mut file) = sys::process::handle(handle) {
         let res = match *file {
-            Resource::Device(Device::TcpSocket(mut dev)) => dev.listen(port),
-            Resource::Device(Device::UdpSocket(mut dev)) => dev.listen(port),
+            Resource::Device(Device::TcpSocket(ref mut dev)) => dev.listen(port),
+            Resource::Device(Device::UdpSocket(ref mut dev)) => dev.listen(port),
+            Resource::Device(Device::IcmpSocket(ref mut dev)) => dev.listen(port),
             _ => Err(()),
         };
         if res.is_ok() {
+            sys::process::update_handle(handle, *file);
             return 0;
         }
     }
     -1
 }
 
-pub fn accept(handle: usize) -> Result<IpAddress, ()> {
-    if let Some(file) = sys::process::handle(handle) {
-        return match *file {
-            Resource::Device(Device::TcpSocket(mut dev)) => dev.accept(),
-            Resource::Device(Device::UdpSocket(mut dev)) => dev.accept(),
+// Return a new handle for the accepted connection with its remote address
+pub fn accept(handle: usize) -> Result<(usize, IpAddress), ()> {
+    if let Some(mut file) = sys::process::handle(handle) {
+        let res = match *file {
+            Resource::Device(Device::TcpSocket(ref mut dev)) => dev
+                .accept()
+                .map(|(dev, addr)| (Device::TcpSocket(dev), addr)),
+            Resource::Device(Device::UdpSocket(ref mut dev)) => dev
+                .accept()
+                .map(|(dev, addr)| (Device::UdpSocket(dev), addr)),
+            Resource::Device(Device::IcmpSocket(ref mut dev)) => dev
+                .accept()
+                .map(|(dev, addr)| (Device::IcmpSocket(dev), addr)),
             _ => Err(()),
         };
+        // The backlog of the listening socket might have changed even if
+        // no connection was accepted
+        sys::process::update_handle(handle, *file);
+        if let Ok((mut dev, addr)) = res {
+            let resource = Resource::Device(dev.clone());
+            if let Ok(handle) = sys::process::create_handle(resource) {
+                return Ok((handle, addr));
+            }
+            dev.close();
+        }
     }
     Err(())
 }
 
+pub fn option(handle: usize, option: usize, value: usize) -> isize {
+    let option = match SocketOption::try_from(option) {
+        Ok(option) => option,
+        Err(_) => return -1,
+    };
+    if let Some(mut file) = sys::process::handle(handle) {
+        let res = match *file {
+            Resource::Device(Device::TcpSocket(ref mut dev)) => dev.set_option(option, value),
+            Resource::Device(Device::UdpSocket(ref mut dev)) => dev.set_option(option, value),
+            Resource::Device(Device::IcmpSocket(ref mut dev)) => dev.set_option(option, value),
+            _ => Err(()),
+        };
+        if res.is_ok() {
+            sys::process::update_handle(handle, *file);
+            return 0;
+        }
+    }
+    -1
+}
+
 pub fn alloc(size: usize, align: usize) -> *mut u8 {
     if let Ok(layout) = Layout::from_size_align(size, align) {
         unsafe { sys::process::alloc(layout) }
@@ -0,0 +1,246 @@
+use super::{Device, Queue, F_VERSION_1, VENDOR_ID};
+use crate::sys;
+use crate::sys::mem::PhysBuf;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::hint::spin_loop;
+use spin::Mutex;
+
+// Block device of VIRTIO 1.1
+
+pub const BLOCK_SIZE: usize = 512;
+
+// Maximum number of blocks transferred by a request, limited by the size of
+// the buffer of a disk
+const MAX_COUNT: usize = 128;
+
+// Device ids of the transitional and modern devices
+const DEVICE_IDS: [u16; 2] = [0x1001, 0x1042];
+
+// Feature bits
+const F_RO: u64 = 1 << 5; // Read-only
+const F_FLUSH: u64 = 1 << 9; // Cache flush command
+
+// Device configuration
+const CONFIG_CAPACITY: u16 = 0x00;
+
+// Length of the serial number given by the identification request
+const ID_SIZE: usize = 20;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    In = 0,
+    Out = 1,
+    Flush = 4,
+    GetId = 8,
+}
+
+const STATUS_OK: u8 = 0;
+
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pub id: u8,
+    serial: String,
+    block_count: u64,
+    is_read_only: bool,
+}
+
+impl Disk {
+    pub fn open(id: u8) -> Option<Self> {
+        list().into_iter().find(|disk| disk.id == id)
+    }
+
+    pub const fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.is_read_only
+    }
+}
+
+impl fmt::Display for Disk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (size, unit) = sys::ata::humanized_size(self.block_count);
+        if self.serial.is_empty() {
+            write!(f, "VIRTIO ({} {})", size, unit)
+        } else {
+            write!(f, "VIRTIO {} ({} {})", self.serial, size, unit)
+        }
+    }
+}
+
+struct Blk {
+    dev: Device,
+    queue: Queue,
+    header: PhysBuf, // Header of the requests followed by their status
+    buf: PhysBuf,
+    disk: Disk,
+}
+
+impl Blk {
+    fn new(id: u8, config: &sys::pci::DeviceConfig) -> Option<Self> {
+        let mut dev = Device::new(config)?;
+        let features = dev.negotiate(F_VERSION_1 | F_RO | F_FLUSH).ok()?;
+        let queue = dev.setup_queue(0).ok()?;
+        dev.driver_ok();
+        let disk = Disk {
+            id,
+            serial: String::new(),
+            block_count: dev.config_u64(CONFIG_CAPACITY),
+            is_read_only: features & F_RO != 0,
+        };
+        let mut blk = Self {
+            dev,
+            queue,
+            header: PhysBuf::new(17),
+            buf: PhysBuf::new(MAX_COUNT * BLOCK_SIZE),
+            disk,
+        };
+        if blk.request(Request::GetId, 0, ID_SIZE).is_ok() {
+            let n = blk.buf[0..ID_SIZE].iter().position(|&b| b == 0);
+            let serial = &blk.buf[0..n.unwrap_or(ID_SIZE)];
+            blk.disk.serial = String::from_utf8_lossy(serial).trim().into();
+        }
+        Some(blk)
+    }
+
+    // Send a request with a transfer of `len` bytes between the buffer of the
+    // disk and the device, and wait for its completion.
+    fn request(&mut self, req: Request, block: u64, len: usize) -> Result<(), ()> {
+        debug_assert!(len <= self.buf.len());
+        self.header[0..4].copy_from_slice(&(req as u32).to_le_bytes());
+        self.header[4..8].copy_from_slice(&0u32.to_le_bytes());
+        self.header[8..16].copy_from_slice(&block.to_le_bytes());
+        self.header[16] = 0xFF;
+
+        let header = self.header.addr();
+        let mut bufs = Vec::with_capacity(3);
+        bufs.push((header, 16, false));
+        if len > 0 {
+            let is_writable = !matches!(req, Request::Out);
+            bufs.push((self.buf.addr(), len as u32, is_writable));
+        }
+        bufs.push((header + 16, 1, true));
+        let id = self.queue.add(&bufs)?;
+        self.dev.notify(&self.queue);
+
+        let start = sys::clk::boot_time();
+        loop {
+            if let Some((used, _)) = self.queue.pop_used() {
+                if used == id {
+                    break;
+                }
+            }
+            if sys::clk::boot_time() - start > 1.0 {
+                debug!(
+                    "VIRTIO BLK {} hanged while waiting for {:?}",
+                    self.disk.id, req
+                );
+                return Err(());
+            }
+            spin_loop();
+        }
+        if self.header[16] == STATUS_OK {
+            Ok(())
+        } else {
+            debug!("VIRTIO BLK {} {:?} request errored", self.disk.id, req);
+            Err(())
+        }
+    }
+
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        self.request(Request::In, block, buf.len())?;
+        buf.copy_from_slice(&self.buf[0..buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, block: u64, buf: &[u8]) -> Result<(), ()> {
+        debug_assert!(buf.len() % BLOCK_SIZE == 0);
+        if self.disk.is_read_only {
+            return Err(());
+        }
+        self.buf[0..buf.len()].copy_from_slice(buf);
+        self.request(Request::Out, block, buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        if self.dev.features() & F_FLUSH == 0 {
+            return Ok(()); // The writes are not cached by the device
+        }
+        self.request(Request::Flush, 0, 0)
+    }
+}
+
+static DISKS: Mutex<Vec<Blk>> = Mutex::new(Vec::new());
+
+fn find_disk(disks: &mut [Blk], id: u8) -> Result<&mut Blk, ()> {
+    disks.iter_mut().find(|d| d.disk.id == id).ok_or(())
+}
+
+pub fn init() {
+    let configs = sys::pci::list()
+        .into_iter()
+        .filter(|dev| dev.vendor_id == VENDOR_ID && DEVICE_IDS.contains(&dev.device_id));
+    let mut disks = DISKS.lock();
+    for (i, mut config) in configs.enumerate() {
+        config.enable_bus_mastering();
+        if let Some(blk) = Blk::new(i as u8, &config) {
+            let interface = if blk.dev.is_modern() {
+                "modern"
+            } else {
+                "legacy"
+            };
+            log!("VIRTIO BLK {} {} ({})", i, blk.disk, interface);
+            disks.push(blk);
+        }
+    }
+}
+
+pub fn list() -> Vec<Disk> {
+    DISKS.lock().iter().map(|d| d.disk.clone()).collect()
+}
+
+// Read blocks into a buffer with a multiple of the block size, in requests
+// of at most 128 blocks.
+pub fn read(id: u8, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+    let mut disks = DISKS.lock();
+    let disk = find_disk(&mut disks, id)?;
+    for (i, chunk) in buf.chunks_mut(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        disk.read(block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn write(id: u8, block: u64, buf: &[u8]) -> Result<(), ()> {
+    let mut disks = DISKS.lock();
+    let disk = find_disk(&mut disks, id)?;
+    for (i, chunk) in buf.chunks(MAX_COUNT * BLOCK_SIZE).enumerate() {
+        let block = block + (i * MAX_COUNT) as u64;
+        disk.write(block, chunk)?;
+    }
+    Ok(())
+}
+
+pub fn flush(id: u8) -> Result<(), ()> {
+    let mut disks = DISKS.lock();
+    find_disk(&mut disks, id)?.flush()
+}
+
+// Write the caches of all the disks to their storage
+pub fn flush_all() {
+    for disk in list() {
+        if flush(disk.id).is_err() {
+            debug!("VIRTIO BLK {} could not flush cache", disk.id);
+        }
+    }
+}
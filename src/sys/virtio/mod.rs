@@ -0,0 +1,560 @@
+pub mod blk;
+
+use crate::sys;
+use crate::sys::mem::PhysBuf;
+use crate::sys::pci::DeviceConfig;
+
+use alloc::vec::Vec;
+use bit_field::BitField;
+use core::convert::TryInto;
+use core::sync::atomic::{fence, Ordering};
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+// Virtual I/O Device (VIRTIO) 1.1
+// (2019)
+//
+// The devices of QEMU are transitional: they can be driven with the legacy
+// interface in the I/O space of BAR0, or with the modern interface described
+// by the vendor capabilities of their configuration space, which is used
+// when it is available.
+
+pub const VENDOR_ID: u16 = 0x1AF4;
+
+// Feature bit of the devices compliant with the modern interface
+pub const F_VERSION_1: u64 = 1 << 32;
+
+// Device status
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 128;
+
+// Registers of the legacy interface in the I/O space
+const LEGACY_DEVICE_FEATURES: u16 = 0x00;
+const LEGACY_DRIVER_FEATURES: u16 = 0x04;
+const LEGACY_QUEUE_ADDRESS: u16 = 0x08;
+const LEGACY_QUEUE_SIZE: u16 = 0x0C;
+const LEGACY_QUEUE_SELECT: u16 = 0x0E;
+const LEGACY_QUEUE_NOTIFY: u16 = 0x10;
+const LEGACY_DEVICE_STATUS: u16 = 0x12;
+const LEGACY_ISR_STATUS: u16 = 0x13;
+const LEGACY_DEVICE_CONFIG: u16 = 0x14; // Without MSI-X
+
+// Registers of the common configuration of the modern interface
+const DEVICE_FEATURE_SELECT: u64 = 0x00;
+const DEVICE_FEATURE: u64 = 0x04;
+const DRIVER_FEATURE_SELECT: u64 = 0x08;
+const DRIVER_FEATURE: u64 = 0x0C;
+const DEVICE_STATUS: u64 = 0x14;
+const QUEUE_SELECT: u64 = 0x16;
+const QUEUE_SIZE: u64 = 0x18;
+const QUEUE_ENABLE: u64 = 0x1C;
+const QUEUE_NOTIFY_OFF: u64 = 0x1E;
+const QUEUE_DESC: u64 = 0x20;
+const QUEUE_DRIVER: u64 = 0x28;
+const QUEUE_DEVICE: u64 = 0x30;
+
+// Types of the vendor capabilities of the modern interface
+const CAP_VENDOR: u8 = 0x09;
+const CAP_COMMON_CFG: u8 = 1;
+const CAP_NOTIFY_CFG: u8 = 2;
+const CAP_ISR_CFG: u8 = 3;
+const CAP_DEVICE_CFG: u8 = 4;
+
+// Flags of the descriptors of a queue
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+// Flag of the available ring to suppress the interrupts of the used ring
+const AVAIL_NO_INTERRUPT: u16 = 1;
+
+const MAX_QUEUE_SIZE: u16 = 256;
+const PAGE_SIZE: usize = 4096;
+
+fn read_mmio<T>(addr: u64) -> T {
+    unsafe { core::ptr::read_volatile(addr as *const T) }
+}
+
+fn write_mmio<T>(addr: u64, data: T) {
+    unsafe { core::ptr::write_volatile(addr as *mut T, data) }
+}
+
+// The addresses of the modern interface are the virtual addresses of the
+// structures given by its capabilities.
+#[derive(Debug, Clone, Copy)]
+enum Transport {
+    Legacy {
+        io: u16,
+    },
+    Modern {
+        common: u64,
+        notify: u64,
+        notify_multiplier: u32,
+        isr: u64,
+        device: u64,
+    },
+}
+
+impl Transport {
+    fn new(config: &DeviceConfig) -> Option<Self> {
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device = None;
+        let mut notify_multiplier = 0;
+        for (offset, id) in config.capabilities() {
+            if id != CAP_VENDOR {
+                continue;
+            }
+            let cfg_type = config.read_config(offset).get_bits(24..32) as u8;
+            let bar = config.read_config(offset + 4).get_bits(0..8) as usize;
+            if bar > 5 || config.base_addresses[bar].get_bit(0) {
+                continue; // The structures in the I/O space are not supported
+            }
+            let phys = config.bar_addr(bar) + config.read_config(offset + 8) as u64;
+            if phys == 0 {
+                continue;
+            }
+            let addr = sys::mem::phys_to_virt(PhysAddr::new(phys)).as_u64();
+            match cfg_type {
+                CAP_COMMON_CFG => common = common.or(Some(addr)),
+                CAP_NOTIFY_CFG => {
+                    if notify.is_none() {
+                        notify = Some(addr);
+                        notify_multiplier = config.read_config(offset + 16);
+                    }
+                }
+                CAP_ISR_CFG => isr = isr.or(Some(addr)),
+                CAP_DEVICE_CFG => device = device.or(Some(addr)),
+                _ => {}
+            }
+        }
+        if let (Some(common), Some(notify), Some(isr), Some(device)) = (common, notify, isr, device)
+        {
+            Some(Transport::Modern {
+                common,
+                notify,
+                notify_multiplier,
+                isr,
+                device,
+            })
+        } else if config.base_addresses[0].get_bit(0) {
+            Some(Transport::Legacy {
+                io: config.bar_addr(0) as u16,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Device {
+    transport: Transport,
+    features: u64,
+}
+
+impl Device {
+    pub fn new(config: &DeviceConfig) -> Option<Self> {
+        let transport = Transport::new(config)?;
+        Some(Self {
+            transport,
+            features: 0,
+        })
+    }
+
+    pub fn is_modern(&self) -> bool {
+        matches!(self.transport, Transport::Modern { .. })
+    }
+
+    pub fn features(&self) -> u64 {
+        self.features
+    }
+
+    fn status(&self) -> u8 {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u8>::new(io + LEGACY_DEVICE_STATUS).read()
+            },
+            Transport::Modern { common, .. } => read_mmio::<u8>(common + DEVICE_STATUS),
+        }
+    }
+
+    fn set_status(&self, status: u8) {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u8>::new(io + LEGACY_DEVICE_STATUS).write(status)
+            },
+            Transport::Modern { common, .. } => write_mmio::<u8>(common + DEVICE_STATUS, status),
+        }
+    }
+
+    fn device_features(&self) -> u64 {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u32>::new(io + LEGACY_DEVICE_FEATURES).read() as u64
+            },
+            Transport::Modern { common, .. } => (0..2).fold(0, |features, i| {
+                write_mmio::<u32>(common + DEVICE_FEATURE_SELECT, i);
+                let bits = read_mmio::<u32>(common + DEVICE_FEATURE) as u64;
+                features | (bits << (32 * i))
+            }),
+        }
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u32>::new(io + LEGACY_DRIVER_FEATURES).write(features as u32)
+            },
+            Transport::Modern { common, .. } => {
+                for i in 0..2 {
+                    write_mmio::<u32>(common + DRIVER_FEATURE_SELECT, i);
+                    write_mmio::<u32>(common + DRIVER_FEATURE, (features >> (32 * i)) as u32);
+                }
+            }
+        }
+    }
+
+    // Reset the device and negotiate the features supported by both the
+    // device and the driver
+    pub fn negotiate(&mut self, features: u64) -> Result<u64, ()> {
+        self.set_status(0);
+        while self.status() != 0 {
+            core::hint::spin_loop();
+        }
+        self.set_status(STATUS_ACKNOWLEDGE);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let mut features = self.device_features() & features;
+        if self.is_modern() && features & F_VERSION_1 == 0 {
+            self.set_status(STATUS_FAILED);
+            return Err(());
+        }
+        if !self.is_modern() {
+            features &= 0xFFFF_FFFF;
+        }
+        self.set_driver_features(features);
+        if self.is_modern() {
+            self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+            if self.status() & STATUS_FEATURES_OK == 0 {
+                self.set_status(STATUS_FAILED);
+                return Err(());
+            }
+        }
+        self.features = features;
+        Ok(features)
+    }
+
+    // Tell the device that the driver is ready after the setup of its queues
+    pub fn driver_ok(&self) {
+        self.set_status(self.status() | STATUS_DRIVER_OK);
+    }
+
+    pub fn setup_queue(&self, index: u16) -> Result<Queue, ()> {
+        match self.transport {
+            Transport::Legacy { io } => {
+                // The size of a queue is fixed by the legacy interface
+                let size = unsafe {
+                    Port::<u16>::new(io + LEGACY_QUEUE_SELECT).write(index);
+                    Port::<u16>::new(io + LEGACY_QUEUE_SIZE).read()
+                };
+                if size == 0 {
+                    return Err(());
+                }
+                let queue = Queue::new(index, size, 0);
+                let pfn = queue.addr(queue.desc) / PAGE_SIZE as u64;
+                unsafe {
+                    Port::<u32>::new(io + LEGACY_QUEUE_ADDRESS).write(pfn as u32);
+                }
+                Ok(queue)
+            }
+            Transport::Modern {
+                common,
+                notify,
+                notify_multiplier,
+                ..
+            } => {
+                write_mmio::<u16>(common + QUEUE_SELECT, index);
+                let size = read_mmio::<u16>(common + QUEUE_SIZE).min(MAX_QUEUE_SIZE);
+                if size == 0 {
+                    return Err(());
+                }
+                write_mmio::<u16>(common + QUEUE_SIZE, size);
+                let off = read_mmio::<u16>(common + QUEUE_NOTIFY_OFF) as u64;
+                let addr = notify + off * notify_multiplier as u64;
+                let queue = Queue::new(index, size, addr);
+                let regs = [
+                    (QUEUE_DESC, queue.addr(queue.desc)),
+                    (QUEUE_DRIVER, queue.addr(queue.avail)),
+                    (QUEUE_DEVICE, queue.addr(queue.used)),
+                ];
+                for (reg, addr) in regs.iter() {
+                    write_mmio::<u32>(common + reg, *addr as u32);
+                    write_mmio::<u32>(common + reg + 4, (*addr >> 32) as u32);
+                }
+                write_mmio::<u16>(common + QUEUE_ENABLE, 1);
+                Ok(queue)
+            }
+        }
+    }
+
+    // Tell the device that new buffers are available in a queue
+    pub fn notify(&self, queue: &Queue) {
+        fence(Ordering::SeqCst);
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u16>::new(io + LEGACY_QUEUE_NOTIFY).write(queue.index)
+            },
+            Transport::Modern { .. } => write_mmio::<u16>(queue.notify, queue.index),
+        }
+    }
+
+    // Read the interrupt status, which acknowledges the interrupt
+    pub fn isr(&self) -> u8 {
+        match self.transport {
+            Transport::Legacy { io } => unsafe { Port::<u8>::new(io + LEGACY_ISR_STATUS).read() },
+            Transport::Modern { isr, .. } => read_mmio::<u8>(isr),
+        }
+    }
+
+    // Read the device specific configuration
+    pub fn config_u8(&self, offset: u16) -> u8 {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u8>::new(io + LEGACY_DEVICE_CONFIG + offset).read()
+            },
+            Transport::Modern { device, .. } => read_mmio::<u8>(device + offset as u64),
+        }
+    }
+
+    pub fn config_u32(&self, offset: u16) -> u32 {
+        match self.transport {
+            Transport::Legacy { io } => unsafe {
+                Port::<u32>::new(io + LEGACY_DEVICE_CONFIG + offset).read()
+            },
+            Transport::Modern { device, .. } => read_mmio::<u32>(device + offset as u64),
+        }
+    }
+
+    pub fn config_u64(&self, offset: u16) -> u64 {
+        let lo = self.config_u32(offset) as u64;
+        let hi = self.config_u32(offset + 4) as u64;
+        lo | (hi << 32)
+    }
+}
+
+// A split virtqueue with its descriptor table, available ring, and used ring
+// in the same page aligned memory, as required by the legacy interface.
+pub struct Queue {
+    index: u16,
+    size: u16,
+    mem: PhysBuf,
+    desc: usize,
+    avail: usize,
+    used: usize,
+    free: Vec<u16>,
+    avail_idx: u16,
+    last_used: u16,
+    notify: u64,
+}
+
+impl Queue {
+    fn new(index: u16, size: u16, notify: u64) -> Self {
+        let n = size as usize;
+        let align = |len: usize| len.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        let used_offset = align(16 * n + 6 + 2 * n);
+        let len = used_offset + align(6 + 8 * n);
+        let mut mem = PhysBuf::new(len + PAGE_SIZE);
+        mem.fill(0);
+        let page = PAGE_SIZE as u64;
+        let desc = ((page - mem.addr() % page) % page) as usize;
+        let avail = desc + 16 * n;
+        let used = desc + used_offset;
+        mem[avail..(avail + 2)].copy_from_slice(&AVAIL_NO_INTERRUPT.to_le_bytes());
+        Self {
+            index,
+            size,
+            mem,
+            desc,
+            avail,
+            used,
+            free: (0..size).rev().collect(),
+            avail_idx: 0,
+            last_used: 0,
+            notify,
+        }
+    }
+
+    fn addr(&self, offset: usize) -> u64 {
+        self.mem.addr() + offset as u64
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    // Add a chain of buffers given by their physical address, their length,
+    // and whether they are written by the device, then return the id of the
+    // descriptor at its head.
+    pub fn add(&mut self, bufs: &[(u64, u32, bool)]) -> Result<u16, ()> {
+        if bufs.is_empty() || bufs.len() > self.free.len() {
+            return Err(());
+        }
+        let n = bufs.len();
+        let ids: Vec<u16> = (0..n).filter_map(|_| self.free.pop()).collect();
+        for (i, (addr, len, is_writable)) in bufs.iter().enumerate() {
+            let mut flags = if *is_writable { DESC_WRITE } else { 0 };
+            let next = if i + 1 < n {
+                flags |= DESC_NEXT;
+                ids[i + 1]
+            } else {
+                0
+            };
+            let j = self.desc + 16 * ids[i] as usize;
+            self.mem[j..(j + 8)].copy_from_slice(&addr.to_le_bytes());
+            self.mem[(j + 8)..(j + 12)].copy_from_slice(&len.to_le_bytes());
+            self.mem[(j + 12)..(j + 14)].copy_from_slice(&flags.to_le_bytes());
+            self.mem[(j + 14)..(j + 16)].copy_from_slice(&next.to_le_bytes());
+        }
+        let j = self.avail + 4 + 2 * (self.avail_idx % self.size) as usize;
+        self.mem[j..(j + 2)].copy_from_slice(&ids[0].to_le_bytes());
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        let j = self.avail + 2;
+        self.mem[j..(j + 2)].copy_from_slice(&self.avail_idx.to_le_bytes());
+        Ok(ids[0])
+    }
+
+    // Take the next chain of buffers used by the device and return the id of
+    // its head with the number of bytes written into it
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let ptr = self.mem[(self.used + 2)..].as_ptr() as *const u16;
+        let idx = u16::from_le(unsafe { core::ptr::read_volatile(ptr) });
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let j = self.used + 4 + 8 * (self.last_used % self.size) as usize;
+        let id = u32::from_le_bytes(self.mem[j..(j + 4)].try_into().unwrap()) as u16;
+        let len = u32::from_le_bytes(self.mem[(j + 4)..(j + 8)].try_into().unwrap());
+        self.last_used = self.last_used.wrapping_add(1);
+
+        // Free the descriptors of the chain
+        let mut i = id;
+        loop {
+            self.free.push(i);
+            let j = self.desc + 16 * i as usize;
+            let flags = u16::from_le_bytes(self.mem[(j + 12)..(j + 14)].try_into().unwrap());
+            if flags & DESC_NEXT == 0 {
+                break;
+            }
+            i = u16::from_le_bytes(self.mem[(j + 14)..(j + 16)].try_into().unwrap());
+        }
+        Some((id, len))
+    }
+}
+
+// Read a descriptor of a queue with its address, length, flags, and next id
+#[cfg(test)]
+fn test_desc(queue: &Queue, id: u16) -> (u64, u32, u16, u16) {
+    let j = queue.desc + 16 * id as usize;
+    let mem = &queue.mem;
+    (
+        u64::from_le_bytes(mem[j..(j + 8)].try_into().unwrap()),
+        u32::from_le_bytes(mem[(j + 8)..(j + 12)].try_into().unwrap()),
+        u16::from_le_bytes(mem[(j + 12)..(j + 14)].try_into().unwrap()),
+        u16::from_le_bytes(mem[(j + 14)..(j + 16)].try_into().unwrap()),
+    )
+}
+
+// Return a chain of buffers to the driver like a device would
+#[cfg(test)]
+fn test_use(queue: &mut Queue, id: u16, len: u32) {
+    let j = queue.used + 2;
+    let idx = u16::from_le_bytes(queue.mem[j..(j + 2)].try_into().unwrap());
+    let k = queue.used + 4 + 8 * (idx % queue.size) as usize;
+    queue.mem[k..(k + 4)].copy_from_slice(&(id as u32).to_le_bytes());
+    queue.mem[(k + 4)..(k + 8)].copy_from_slice(&len.to_le_bytes());
+    let idx = idx.wrapping_add(1);
+    queue.mem[j..(j + 2)].copy_from_slice(&idx.to_le_bytes());
+}
+
+#[test_case]
+fn test_queue_chain() {
+    let mut queue = Queue::new(0, 4, 0);
+    let head = queue.add(&[(0x1000, 16, false), (0x2000, 512, true), (0x3000, 1, true)]);
+    assert_eq!(head, Ok(0));
+
+    // The descriptors are linked with the next flag except the last one
+    let (addr, len, flags, next) = test_desc(&queue, 0);
+    assert_eq!((addr, len, flags), (0x1000, 16, DESC_NEXT));
+    let (addr, len, flags, next) = test_desc(&queue, next);
+    assert_eq!((addr, len, flags), (0x2000, 512, DESC_NEXT | DESC_WRITE));
+    let (addr, len, flags, _) = test_desc(&queue, next);
+    assert_eq!((addr, len, flags), (0x3000, 1, DESC_WRITE));
+
+    // The head of the chain is in the available ring
+    let j = queue.avail;
+    assert_eq!(queue.mem[(j + 2)..(j + 4)], 1u16.to_le_bytes());
+    assert_eq!(queue.mem[(j + 4)..(j + 6)], 0u16.to_le_bytes());
+
+    assert_eq!(queue.pop_used(), None);
+    test_use(&mut queue, 0, 513);
+    assert_eq!(queue.pop_used(), Some((0, 513)));
+    assert_eq!(queue.pop_used(), None);
+}
+
+#[test_case]
+fn test_queue_wraparound() {
+    let mut queue = Queue::new(0, 4, 0);
+
+    // Start close to the end of the range of the indexes of the rings
+    let start = u16::MAX - 5;
+    queue.avail_idx = start;
+    queue.last_used = start;
+    let j = queue.used + 2;
+    queue.mem[j..(j + 2)].copy_from_slice(&start.to_le_bytes());
+
+    for i in 0..10u16 {
+        let idx = start.wrapping_add(i);
+        let head = queue.add(&[(0x1000 * i as u64, 8, true)]).unwrap();
+        let k = queue.avail + 4 + 2 * (idx % 4) as usize;
+        assert_eq!(queue.mem[k..(k + 2)], head.to_le_bytes());
+        let k = queue.avail + 2;
+        assert_eq!(queue.mem[k..(k + 2)], idx.wrapping_add(1).to_le_bytes());
+
+        test_use(&mut queue, head, i as u32);
+        assert_eq!(queue.pop_used(), Some((head, i as u32)));
+    }
+    assert_eq!(queue.avail_idx, start.wrapping_add(10));
+    assert_eq!(queue.last_used, start.wrapping_add(10));
+}
+
+#[test_case]
+fn test_queue_free_list() {
+    let mut queue = Queue::new(0, 4, 0);
+    assert_eq!(queue.add(&[]), Err(()));
+    assert_eq!(queue.add(&[(0, 1, false); 5]), Err(()));
+
+    let a = queue.add(&[(0, 1, false); 3]).unwrap();
+    assert_eq!(queue.free.len(), 1);
+    assert_eq!(queue.add(&[(0, 1, false); 2]), Err(()));
+    let b = queue.add(&[(0, 1, false)]).unwrap();
+    assert_eq!(queue.free.len(), 0);
+    assert_eq!(queue.add(&[(0, 1, false)]), Err(()));
+
+    // The descriptors of a chain are freed when the device has used it
+    test_use(&mut queue, a, 0);
+    assert_eq!(queue.pop_used(), Some((a, 0)));
+    assert_eq!(queue.free.len(), 3);
+    let c = queue.add(&[(0, 1, false); 3]).unwrap();
+    assert_ne!(c, b);
+
+    test_use(&mut queue, b, 0);
+    test_use(&mut queue, c, 0);
+    assert_eq!(queue.pop_used(), Some((b, 0)));
+    assert_eq!(queue.pop_used(), Some((c, 0)));
+    assert_eq!(queue.free.len(), 4);
+}
@@ -34,13 +34,16 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             pathname = pathname.trim_end_matches('/');
         }
 
-        if !fs::exists(pathname) {
+        // Delete symbolic links without checking their targets
+        let is_link = fs::is_link(pathname);
+
+        if !is_link && !fs::exists(pathname) {
             error!("Could not find file '{}'", pathname);
             return Err(ExitCode::Failure);
         }
 
         if let Some(info) = syscall::info(pathname) {
-            if info.is_dir() && info.size() > 0 {
+            if !is_link && info.is_dir() && info.size() > 0 {
                 error!("Directory '{}' not empty", pathname);
                 return Err(ExitCode::Failure);
             }
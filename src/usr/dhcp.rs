@@ -1,97 +1,209 @@
 use crate::api::clock;
 use crate::api::console::Style;
-use crate::api::fs;
 use crate::api::process::ExitCode;
 use crate::api::syscall;
 use crate::sys::console;
 use crate::sys::net;
 
-use alloc::format;
-use alloc::string::ToString;
 use alloc::vec;
-use alloc::vec::Vec;
 use smoltcp::iface::SocketSet;
-use smoltcp::socket::dhcpv4;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
 use smoltcp::time::Instant;
+use smoltcp::wire::{
+    HardwareAddress, Icmpv6Packet, Icmpv6Repr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr,
+    Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags, NdiscRepr, RawHardwareAddress,
+};
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     let mut verbose = false;
-    let dhcp_config;
+    let mut release = false;
+    let mut name = "";
 
-    for arg in args {
+    for arg in &args[1..] {
         match *arg {
             "-h" | "--help" => return help(),
             "-v" | "--verbose" => verbose = true,
-            _ => {}
+            "-r" | "--release" => release = true,
+            _ if arg.starts_with('-') => {
+                error!("Invalid option '{}'", arg);
+                return Err(ExitCode::UsageError);
+            }
+            _ => name = *arg,
         }
     }
 
-    if let Some((ref mut iface, ref mut device)) = *net::NET.lock() {
-        let dhcp_socket = dhcpv4::Socket::new();
+    let mut interfaces = net::NET.lock();
+    let i = match net::find_interface(&interfaces, name) {
+        Some(i) => i,
+        None => {
+            error!("Could not find interface");
+            return Err(ExitCode::Failure);
+        }
+    };
+    let name = interfaces[i].name.clone();
+    if release {
+        net::dhcp::stop(&mut interfaces[i]);
+        return Ok(());
+    }
+
+    // The client is polled in the background to keep the lease renewed
+    net::dhcp::start(&mut interfaces[i]);
+    drop(interfaces);
+    if verbose {
+        debug!("DHCP client started");
+    }
+    let timeout = 30.0;
+    let started = clock::epoch_time();
+
+    // The IPv6 configuration is obtained independently of the IPv4 one while
+    // the DHCP client is polled in the background
+    let has_ipv6 = slaac(&name, verbose);
+
+    loop {
+        if clock::epoch_time() - started > timeout {
+            if has_ipv6 {
+                return Ok(());
+            }
+            error!("Timeout reached");
+            return Err(ExitCode::Failure);
+        }
+        if console::end_of_text() || console::end_of_transmission() {
+            eprintln!();
+            return Err(ExitCode::Failure);
+        }
+        syscall::sleep(0.1);
+        let interfaces = net::NET.lock();
+        if let Some(client) = &interfaces[i].dhcp {
+            if client.lease.is_some() {
+                break;
+            }
+        }
+    }
+    if verbose {
+        debug!("DHCP lease acquired");
+    }
+    Ok(())
+}
+
+// Stateless address autoconfiguration of IPv6 (RFC 4862) with the prefix
+// advertised by a router in response to a solicitation. The address and the
+// route are removed by the kernel when their lifetimes run out.
+fn slaac(name: &str, verbose: bool) -> bool {
+    let mut interfaces = net::NET.lock();
+    if let Some(i) = net::find_interface(&interfaces, name) {
+        let net::NetInterface { iface, device, .. } = &mut interfaces[i];
+        let mac = match iface.hardware_addr() {
+            HardwareAddress::Ethernet(mac) => mac,
+            #[allow(unreachable_patterns)]
+            _ => return false,
+        };
+        let src_addr = net::ip::link_local(mac);
+        let dst_addr = Ipv6Address::new(0xFF02, 0, 0, 0, 0, 0, 0, 2); // All routers
+
+        let rx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY; 4], vec![0; 4096]);
+        let tx_buffer = raw::PacketBuffer::new(vec![raw::PacketMetadata::EMPTY], vec![0; 1024]);
+        let raw_socket =
+            raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer);
         let mut sockets = SocketSet::new(vec![]);
-        let dhcp_handle = sockets.add(dhcp_socket);
+        let raw_handle = sockets.add(raw_socket);
+
+        let solicit = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+            lladdr: Some(RawHardwareAddress::from(mac)),
+        });
+        let ip_repr = Ipv6Repr {
+            src_addr,
+            dst_addr,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: solicit.buffer_len(),
+            hop_limit: 255,
+        };
+        let mut packet = vec![0; ip_repr.buffer_len() + solicit.buffer_len()];
+        let mut ip_packet = Ipv6Packet::new_unchecked(&mut packet);
+        ip_repr.emit(&mut ip_packet);
+        let mut icmp_packet = Icmpv6Packet::new_unchecked(ip_packet.payload_mut());
+        let caps = ChecksumCapabilities::default();
+        solicit.emit(&src_addr, &dst_addr, &mut icmp_packet, &caps);
+        let socket = sockets.get_mut::<raw::Socket>(raw_handle);
+        if socket.send_slice(&packet).is_err() {
+            return false;
+        }
         if verbose {
-            debug!("DHCP Discover transmitted");
+            debug!("NDP Router Solicitation transmitted");
         }
-        let timeout = 30.0;
+
+        let timeout = 5.0;
         let started = clock::epoch_time();
         loop {
             if clock::epoch_time() - started > timeout {
-                error!("Timeout reached");
-                return Err(ExitCode::Failure);
+                if verbose {
+                    debug!("NDP Router Advertisement not received");
+                }
+                return false;
             }
             if console::end_of_text() || console::end_of_transmission() {
-                eprintln!();
-                return Err(ExitCode::Failure);
+                return false;
             }
 
             let ms = (clock::epoch_time() * 1000000.0) as i64;
             let time = Instant::from_micros(ms);
             iface.poll(time, device, &mut sockets);
-            let event = sockets.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
-
-            match event {
-                None => {}
-                Some(dhcpv4::Event::Configured(config)) => {
-                    dhcp_config = Some((config.address, config.router, config.dns_servers));
+            let socket = sockets.get_mut::<raw::Socket>(raw_handle);
+            while let Ok(buf) = socket.recv() {
+                let ip_packet = match Ipv6Packet::new_checked(buf) {
+                    Ok(ip_packet) => ip_packet,
+                    Err(_) => continue,
+                };
+                let ip_repr = match Ipv6Repr::parse(&ip_packet) {
+                    Ok(ip_repr) => ip_repr,
+                    Err(_) => continue,
+                };
+                let icmp_packet = match Icmpv6Packet::new_checked(ip_packet.payload()) {
+                    Ok(icmp_packet) => icmp_packet,
+                    Err(_) => continue,
+                };
+                let src = &ip_repr.src_addr;
+                let dst = &ip_repr.dst_addr;
+                let advert = match Icmpv6Repr::parse(src, dst, &icmp_packet, &caps) {
+                    Ok(Icmpv6Repr::Ndisc(advert)) => advert,
+                    _ => continue,
+                };
+                if let NdiscRepr::RouterAdvert {
+                    router_lifetime,
+                    prefix_info: Some(info),
+                    ..
+                } = advert
+                {
+                    if !info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+                        || info.prefix_len != 64
+                        || info.valid_lifetime.secs() == 0
+                    {
+                        continue;
+                    }
                     if verbose {
-                        debug!("DHCP Offer received");
+                        debug!("NDP Router Advertisement received");
                     }
-                    break;
+                    let addr = net::ip::eui64(info.prefix, mac);
+                    let config = net::slaac::Config::new(
+                        Ipv6Cidr::new(addr, info.prefix_len),
+                        info.valid_lifetime.secs(),
+                        ip_repr.src_addr,
+                        router_lifetime.secs(),
+                    );
+                    net::slaac::configure(&mut interfaces[i], config);
+                    return true;
                 }
-                Some(dhcpv4::Event::Deconfigured) => {}
             }
 
             if let Some(delay) = iface.poll_delay(time, &sockets) {
                 let d = (delay.total_micros() as f64) / 1000000.0;
                 syscall::sleep(d.min(0.1)); // Don't sleep longer than 0.1s
+            } else {
+                syscall::sleep(0.1);
             }
         }
-    } else {
-        error!("Network Error");
-        return Err(ExitCode::Failure);
-    }
-
-    if let Some((ip, gw, dns)) = dhcp_config {
-        fs::write("/dev/net/ip", ip.to_string().as_bytes()).ok();
-
-        if let Some(gw) = gw {
-            fs::write("/dev/net/gw", gw.to_string().as_bytes()).ok();
-        } else {
-            fs::write("/dev/net/gw", b"0.0.0.0").ok();
-        }
-
-        let dns: Vec<_> = dns.iter().map(|s| s.to_string()).collect();
-        if !dns.is_empty() {
-            let servers = dns.join(",");
-            if fs::write("/ini/dns", servers.as_bytes()).is_ok() {
-                log!("NET DNS {}", servers);
-            }
-        }
-        return Ok(());
     }
-
-    Err(ExitCode::Failure)
+    false
 }
 
 fn help() -> Result<(), ExitCode> {
@@ -99,11 +211,15 @@ fn help() -> Result<(), ExitCode> {
     let csi_title = Style::color("yellow");
     let csi_reset = Style::reset();
     println!(
-        "{}Usage:{} dhcp {}<options>{1}",
+        "{}Usage:{} dhcp {}<options> [<iface>]{1}",
         csi_title, csi_reset, csi_option
     );
     println!();
     println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-r{1}, {0}--release{1}              Release the lease",
+        csi_option, csi_reset
+    );
     println!(
         "  {0}-v{1}, {0}--verbose{1}              Increase verbosity",
         csi_option, csi_reset
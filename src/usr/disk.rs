@@ -1,10 +1,15 @@
 use crate::api::console::Style;
+use crate::api::fs;
 use crate::api::io;
 use crate::api::process::ExitCode;
+use crate::api::syscall;
 use crate::api::unit::SizeUnit;
 use crate::sys;
 use crate::sys::ata::Drive;
 use crate::sys::console;
+use crate::sys::fs::{
+    AhciBlockDevice, AtaBlockDevice, BlockDevice, BlockDeviceIO, VirtioBlockDevice,
+};
 
 use alloc::format;
 use alloc::string::String;
@@ -16,6 +21,10 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     match *args.get(1).unwrap_or(&"") {
         "f" | "format" if args.len() == 3 => format(args[2]),
         "e" | "erase" if args.len() == 3 => erase(args[2]),
+        "b" | "bench" => bench(&args[2..]),
+        "copy" if args.len() == 4 => copy(args[2], args[3]),
+        "i" | "image" if args.len() == 4 => image(args[2], args[3]),
+        "c" | "check" => check(&args[2..]),
         "u" | "usage" => usage(&args[2..]),
         "l" | "list" => list(),
         "-h" | "--help" => {
@@ -29,7 +38,9 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     }
 }
 
-fn parse_disk_path(pathname: &str) -> Result<(u8, u8), String> {
+// Parse the path of a drive like "/dev/ata/0/0" or of one of its partitions
+// like "/dev/ata/0/0p1", with the partition number 0 for the whole drive.
+pub fn parse_disk_path(pathname: &str) -> Result<(u8, u8, u8), String> {
     let path: Vec<_> = pathname.split('/').collect();
     if !pathname.starts_with("/dev/ata/") || path.len() != 5 {
         return Err(format!("Could not find disk at '{}'", pathname));
@@ -37,71 +48,487 @@ fn parse_disk_path(pathname: &str) -> Result<(u8, u8), String> {
     let bus = path[3]
         .parse()
         .or(Err("Could not parse <bus>".to_string()))?;
-    let dsk = path[4]
-        .parse()
-        .or(Err("Could not parse <dsk>".to_string()))?;
-    Ok((bus, dsk))
+    let (dsk, part) = match path[4].split_once('p') {
+        Some((dsk, part)) => (dsk, part),
+        None => (path[4], "0"),
+    };
+    let dsk = dsk.parse().or(Err("Could not parse <dsk>".to_string()))?;
+    let part = part.parse().or(Err("Could not parse <part>".to_string()))?;
+    Ok((bus, dsk, part))
+}
+
+// Parse the path of an AHCI disk like "/dev/ahci/0"
+pub fn parse_ahci_path(pathname: &str) -> Option<u8> {
+    pathname.strip_prefix("/dev/ahci/")?.parse().ok()
+}
+
+// Parse the path of a VIRTIO disk like "/dev/virtio/0"
+pub fn parse_virtio_path(pathname: &str) -> Option<u8> {
+    pathname.strip_prefix("/dev/virtio/")?.parse().ok()
+}
+
+// Parse the path of an ATAPI drive like "/dev/cd/1/0"
+pub fn parse_cdrom_path(pathname: &str) -> Option<(u8, u8)> {
+    let (bus, dsk) = pathname.strip_prefix("/dev/cd/")?.split_once('/')?;
+    Some((bus.parse().ok()?, dsk.parse().ok()?))
+}
+
+// Open the block device of an ATA drive or partition, an AHCI disk, or a
+// VIRTIO disk
+fn open_block_device(pathname: &str) -> Result<BlockDevice, String> {
+    let dev = if let Some(port) = parse_ahci_path(pathname) {
+        AhciBlockDevice::new(port).map(BlockDevice::Ahci)
+    } else if let Some(id) = parse_virtio_path(pathname) {
+        VirtioBlockDevice::new(id).map(BlockDevice::Virtio)
+    } else {
+        let (bus, dsk, part) = parse_disk_path(pathname)?;
+        AtaBlockDevice::new(bus, dsk, part).map(BlockDevice::Ata)
+    };
+    dev.ok_or(format!("Could not find disk at '{}'", pathname))
 }
 
 fn format(pathname: &str) -> Result<(), ExitCode> {
-    match parse_disk_path(pathname) {
-        Ok((bus, dsk)) => {
-            sys::fs::mount_ata(bus, dsk);
-            sys::fs::format_ata();
-            println!("Disk successfully formatted");
-            println!("MFS is now mounted to '/'");
-            Ok(())
-        }
+    let dev = match open_block_device(pathname) {
+        Ok(dev) => dev,
         Err(msg) => {
             error!("{}", msg);
-            Err(ExitCode::Failure)
+            return Err(ExitCode::Failure);
         }
+    };
+    if dev.is_read_only() {
+        error!("Could not format read-only disk at '{}'", pathname);
+        return Err(ExitCode::Failure);
+    }
+    if sys::fs::format_device(dev, pathname).is_err() {
+        error!("Could not format disk at '{}'", pathname);
+        return Err(ExitCode::Failure);
     }
+    println!("Disk successfully formatted");
+    println!("MFS is now mounted to '/'");
+    Ok(())
 }
 
+// Number of blocks erased with each write
+const ERASE_BLOCKS: u64 = 256;
+
 fn is_canceled() -> bool {
     console::end_of_text() || console::end_of_transmission()
 }
 
 fn erase(pathname: &str) -> Result<(), ExitCode> {
-    match parse_disk_path(pathname) {
-        Ok((bus, dsk)) => {
-            if let Some(drive) = Drive::open(bus, dsk) {
-                print!("Proceed? [y/N] ");
-                if io::stdin().read_line().trim() == "y" {
-                    println!();
-
-                    let n = drive.block_count();
-                    let buf = vec![0; drive.block_size() as usize];
-                    print!("\x1b[?25l"); // Disable cursor
-                    for i in 0..n {
-                        if is_canceled() {
-                            println!();
-                            print!("\x1b[?25h"); // Enable cursor
-                            return Err(ExitCode::Failure);
-                        }
-                        print!("\x1b[2K\x1b[1G");
-                        print!("Erasing block {}/{}", i, n);
-                        // TODO: Implement drive.write(block, buf)
-                        sys::ata::write(bus, dsk, i, &buf).ok();
-                    }
-                    println!();
-                    print!("\x1b[?25h"); // Enable cursor
-                }
+    let mut disk = match Disk::open(pathname) {
+        Ok(disk) => disk,
+        Err(msg) => {
+            error!("{}", msg);
+            return Err(ExitCode::Failure);
+        }
+    };
+    print!("Proceed? [y/N] ");
+    if io::stdin().read_line().trim() == "y" {
+        println!();
+
+        let n = disk.count;
+        let size = disk.dev.block_size();
+        let buf = vec![0; ERASE_BLOCKS as usize * size];
+        print!("\x1b[?25l"); // Disable cursor
+        let mut i = 0;
+        while i < n {
+            if is_canceled() {
+                println!();
+                print!("\x1b[?25h"); // Enable cursor
+                return Err(ExitCode::Failure);
             }
-            Ok(())
+            print!("\x1b[2K\x1b[1G");
+            print!("Erasing block {}/{}", i, n);
+            let count = (n - i).min(ERASE_BLOCKS) as usize;
+            disk.write(i, &buf[..(count * size)]).ok();
+            i += count as u64;
+        }
+        disk.dev.sync().ok();
+        println!();
+        print!("\x1b[?25h"); // Enable cursor
+    }
+    Ok(())
+}
+
+// A drive or one of its partitions, or a disk, with the range of its blocks
+// on the block device of the whole drive or disk.
+struct Disk {
+    dev: BlockDevice,
+    start: u64,
+    count: u64,
+}
+
+impl Disk {
+    fn open(pathname: &str) -> Result<Self, String> {
+        let dev = match parse_disk_path(pathname) {
+            Ok((bus, dsk, part)) => Drive::open_partition(bus, dsk, part).and_then(|drive| {
+                let dev = AtaBlockDevice::new(bus, dsk, 0)?;
+                Some(Self {
+                    dev: BlockDevice::Ata(dev),
+                    start: drive.block_start(),
+                    count: drive.block_count(),
+                })
+            }),
+            Err(_) => open_block_device(pathname).ok().map(|dev| Self {
+                start: 0,
+                count: dev.block_count(),
+                dev,
+            }),
+        };
+        dev.ok_or(format!("Could not find disk at '{}'", pathname))
+    }
+
+    // Size in bytes of the disk or partition
+    fn size(&self) -> u64 {
+        self.count * self.dev.block_size() as u64
+    }
+
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<(), ()> {
+        self.dev.read(self.start + block, buf)
+    }
+
+    fn write(&mut self, block: u64, buf: &[u8]) -> Result<(), ()> {
+        if self.dev.is_read_only() {
+            return Err(());
+        }
+        self.dev.write(self.start + block, buf)
+    }
+}
+
+// Number of blocks copied with each read and write
+const COPY_BLOCKS: usize = 256;
+
+// Size in bytes of a disk or partition
+fn disk_size(pathname: &str) -> Option<u64> {
+    Disk::open(pathname).ok().map(|disk| disk.size())
+}
+
+// Clone a disk or partition to another one
+fn copy(src: &str, dst: &str) -> Result<(), ExitCode> {
+    for path in [src, dst] {
+        if disk_size(path).is_none() {
+            error!("Could not find disk at '{}'", path);
+            return Err(ExitCode::Failure);
+        }
+    }
+    transfer(src, dst)
+}
+
+// Save a disk or partition to an image file, or restore it from one
+fn image(src: &str, dst: &str) -> Result<(), ExitCode> {
+    match (disk_size(src).is_some(), disk_size(dst).is_some()) {
+        (true, false) | (false, true) => transfer(src, dst),
+        _ => {
+            error!("Could not find a disk and an image file");
+            Err(ExitCode::Failure)
+        }
+    }
+}
+
+// Copy the content of a disk or file to another disk or file, through the
+// block devices of the disks.
+fn transfer(src: &str, dst: &str) -> Result<(), ExitCode> {
+    let mut src_disk = Disk::open(src).ok();
+    let mut dst_disk = Disk::open(dst).ok();
+    let size = match &src_disk {
+        Some(disk) => disk.size(),
+        None => match syscall::info(src) {
+            Some(info) if info.is_file() => info.size() as u64,
+            _ => {
+                error!("Could not find file '{}'", src);
+                return Err(ExitCode::Failure);
+            }
+        },
+    };
+
+    // The size of a file is stored in 32 bits
+    let max_size = dst_disk
+        .as_ref()
+        .map_or(u32::MAX as u64, |disk| disk.size());
+    if size > max_size {
+        error!("Could not fit '{}' into '{}'", src, dst);
+        return Err(ExitCode::Failure);
+    }
+    if dst_disk.is_some() {
+        if sys::fs::is_disk_mounted(dst) {
+            error!("Disk '{}' is mounted", dst);
+            return Err(ExitCode::Failure);
+        }
+        print!("Proceed? [y/N] ");
+        if io::stdin().read_line().trim() != "y" {
+            return Ok(());
+        }
+        println!();
+    } else if fs::exists(dst) && fs::delete(dst).is_err() {
+        error!("Could not overwrite file '{}'", dst);
+        return Err(ExitCode::Failure);
+    }
+
+    let src_handle = match src_disk {
+        Some(_) => None,
+        None => fs::open_file(src),
+    };
+    let dst_handle = match dst_disk {
+        Some(_) => None,
+        None => fs::create_file(dst),
+    };
+    if (src_disk.is_none() && src_handle.is_none()) || (dst_disk.is_none() && dst_handle.is_none())
+    {
+        for handle in [src_handle, dst_handle].iter().flatten() {
+            syscall::close(*handle);
+        }
+        error!("Could not open '{}' or '{}'", src, dst);
+        return Err(ExitCode::Failure);
+    }
+
+    let block_size = Drive::size() as u64;
+    let n = size.div_ceil(block_size);
+    let mut buf = vec![0; COPY_BLOCKS * block_size as usize];
+    let mut res = Ok(());
+    let mut i = 0;
+    print!("\x1b[?25l"); // Disable cursor
+    while i < n {
+        if is_canceled() {
+            res = Err(ExitCode::Failure);
+            break;
         }
+        print!("\x1b[2K\x1b[1G");
+        print!("Copying block {}/{}", i, n);
+        let count = (n - i).min(COPY_BLOCKS as u64);
+        let len = (size - i * block_size).min(count * block_size) as usize;
+        let buf = &mut buf[..(count * block_size) as usize];
+        let is_read = match (&mut src_disk, src_handle) {
+            (Some(disk), _) => disk.read(i, buf).is_ok(),
+            (None, Some(handle)) => syscall::read(handle, &mut buf[..len]) == Some(len),
+            (None, None) => false,
+        };
+        if !is_read {
+            println!();
+            error!("Could not read '{}'", src);
+            res = Err(ExitCode::Failure);
+            break;
+        }
+
+        // The last block of an image file is padded with zeros on a disk
+        let is_written = match (&mut dst_disk, dst_handle) {
+            (Some(disk), _) => {
+                buf[len..].fill(0);
+                disk.write(i, buf).is_ok()
+            }
+            (None, Some(handle)) => syscall::write(handle, &buf[..len]) == Some(len),
+            (None, None) => false,
+        };
+        if !is_written {
+            println!();
+            error!("Could not write '{}'", dst);
+            res = Err(ExitCode::Failure);
+            break;
+        }
+        i += count;
+    }
+    if let Some(disk) = &mut dst_disk {
+        if disk.dev.sync().is_err() {
+            res = Err(ExitCode::Failure);
+        }
+    }
+    if res.is_ok() {
+        print!("\x1b[2K\x1b[1G");
+        println!("Copied {} blocks", n);
+    }
+    print!("\x1b[?25h"); // Enable cursor
+    for handle in [src_handle, dst_handle].iter().flatten() {
+        syscall::close(*handle);
+    }
+    res
+}
+
+// Size of the data read by the benchmark and of each read
+const BENCH_SIZE: usize = 16 << 20;
+const BENCH_BLOCKS: usize = 256;
+
+fn bench(args: &[&str]) -> Result<(), ExitCode> {
+    let mut pathname = "";
+    let mut use_pio = false;
+    for arg in args {
+        match *arg {
+            "-p" | "--pio" => {
+                use_pio = true;
+            }
+            "-h" | "--help" => {
+                help_bench();
+                return Ok(());
+            }
+            _ if pathname.is_empty() => {
+                pathname = arg;
+            }
+            _ => {
+                help_bench();
+                return Err(ExitCode::UsageError);
+            }
+        }
+    }
+    if pathname.is_empty() {
+        help_bench();
+        return Err(ExitCode::UsageError);
+    }
+    let (bus, dsk, part) = match parse_disk_path(pathname) {
+        Ok(res) => res,
         Err(msg) => {
             error!("{}", msg);
+            return Err(ExitCode::Failure);
+        }
+    };
+    let drive = match Drive::open_partition(bus, dsk, part) {
+        Some(drive) => drive,
+        None => {
+            error!("Could not find disk at '{}'", pathname);
+            return Err(ExitCode::Failure);
+        }
+    };
+
+    // Force PIO transfers during the benchmark
+    let was_dma = sys::ata::is_dma(bus);
+    if use_pio && was_dma {
+        sys::ata::set_dma(bus, false).ok();
+    }
+    let mode = if sys::ata::is_dma(bus) { "DMA" } else { "PIO" };
+
+    let size = drive.block_size() as usize;
+    let start = drive.block_start();
+    let n = drive.block_count().min((BENCH_SIZE / size) as u64);
+    let mut buf = vec![0; BENCH_BLOCKS * size];
+    let mut res = Ok(());
+    let mut i = 0;
+    let t = sys::clk::boot_time();
+    while i < n {
+        if is_canceled() {
+            res = Err(ExitCode::Failure);
+            break;
+        }
+        let count = (n - i).min(BENCH_BLOCKS as u64) as usize;
+        if sys::ata::read(bus, dsk, start + i, &mut buf[..(count * size)]).is_err() {
+            error!("Could not read disk at '{}'", pathname);
+            res = Err(ExitCode::Failure);
+            break;
+        }
+        i += count as u64;
+    }
+    let t = sys::clk::boot_time() - t;
+
+    if use_pio && was_dma {
+        sys::ata::set_dma(bus, true).ok();
+    }
+    if res.is_ok() {
+        let mb = (n as usize * size) as f64 / 1e6;
+        println!(
+            "Read {:.0} MB in {:.3}s ({:.2} MB/s) with {}",
+            mb,
+            t,
+            mb / t,
+            mode
+        );
+    }
+    res
+}
+
+fn check(args: &[&str]) -> Result<(), ExitCode> {
+    let mut repair = false;
+    for arg in args {
+        match *arg {
+            "-r" | "--repair" => {
+                repair = true;
+            }
+            "-h" | "--help" => {
+                help_check();
+                return Ok(());
+            }
+            _ => {
+                help_check();
+                return Err(ExitCode::UsageError);
+            }
+        }
+    }
+    match sys::fs::check(repair) {
+        Some(report) => {
+            for problem in &report.problems {
+                warning!("{}", problem);
+            }
+            println!(
+                "Checked {} dirs, {} files and {} blocks",
+                report.dirs, report.files, report.blocks
+            );
+            if report.is_clean() {
+                println!("Filesystem is clean");
+                Ok(())
+            } else if repair {
+                println!(
+                    "Found {} problems and repaired {}",
+                    report.problems.len(),
+                    report.repaired
+                );
+                Ok(())
+            } else {
+                println!("Found {} problems", report.problems.len());
+                Err(ExitCode::Failure)
+            }
+        }
+        None => {
+            error!("Filesystem is not mounted");
             Err(ExitCode::Failure)
         }
     }
 }
 
 fn list() -> Result<(), ExitCode> {
-    println!("Path            Name (Size)");
+    let mounts = sys::fs::mounts();
+    let mount_of = |path: &str| match mounts.iter().find(|m| m.source == path) {
+        Some(m) => m.path.clone(),
+        None => String::new(),
+    };
+    let mut drives = Vec::new();
     for drive in sys::ata::list() {
-        println!("/dev/ata/{}/{}    {}", drive.bus, drive.dsk, drive);
+        let path = sys::fs::ata_path(drive.bus, drive.dsk, 0);
+        drives.push((path.clone(), drive.to_string(), mount_of(&path)));
+        for partition in sys::ata::partitions(drive.bus, drive.dsk) {
+            let path = sys::fs::ata_path(drive.bus, drive.dsk, partition.index);
+            drives.push((path.clone(), partition.to_string(), mount_of(&path)));
+        }
+    }
+    for disk in sys::ahci::list() {
+        let path = sys::fs::ahci_path(disk.port);
+        drives.push((path.clone(), disk.to_string(), mount_of(&path)));
+    }
+    for disk in sys::virtio::blk::list() {
+        let path = sys::fs::virtio_path(disk.id);
+        drives.push((path.clone(), disk.to_string(), mount_of(&path)));
+    }
+    for cdrom in sys::ata::cdroms() {
+        let path = sys::fs::cdrom_path(cdrom.bus, cdrom.dsk);
+        drives.push((path.clone(), cdrom.to_string(), mount_of(&path)));
+    }
+    let width = drives
+        .iter()
+        .fold(11, |max, (_, name, _)| max.max(name.len()));
+    let path_width = drives
+        .iter()
+        .fold(12, |max, (path, _, _)| max.max(path.len()));
+    println!(
+        "{:path_width$}    {:width$}   Mount",
+        "Path",
+        "Name (Size)",
+        path_width = path_width,
+        width = width
+    );
+    for (path, name, mount) in drives {
+        println!(
+            "{:path_width$}    {:width$}   {}",
+            path,
+            name,
+            mount,
+            path_width = path_width,
+            width = width
+        );
     }
     Ok(())
 }
@@ -174,6 +601,38 @@ fn help_usage() {
     );
 }
 
+fn help_bench() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} disk bench {}<options> <path>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-p{1}, {0}--pio{1}   Use PIO instead of DMA transfers",
+        csi_option, csi_reset
+    );
+}
+
+fn help_check() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} disk check {}<options>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-r{1}, {0}--repair{1}   Repair problems found",
+        csi_option, csi_reset
+    );
+}
+
 fn help() {
     let csi_option = Style::color("aqua");
     let csi_title = Style::color("yellow");
@@ -184,14 +643,36 @@ fn help() {
     );
     println!();
     println!("{}Commands:{}", csi_title, csi_reset);
-    println!("  {}erase <path>{}    Erase disk", csi_option, csi_reset);
-    println!("  {}format <path>{}   Format disk", csi_option, csi_reset);
     println!(
-        "  {}list{}            List detected disks",
+        "  {}bench <path>{}        Measure disk read throughput",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}check{}               Check filesystem",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}copy <src> <dst>{}    Copy disk or partition to another",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}erase <path>{}        Erase disk or partition",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}format <path>{}       Format disk or partition",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}image <src> <dst>{}   Save or restore disk or partition image",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}list{}                List detected disks and partitions",
         csi_option, csi_reset
     );
     println!(
-        "  {}usage{}           List disk usage",
+        "  {}usage{}               List disk usage",
         csi_option, csi_reset
     );
 }
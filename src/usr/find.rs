@@ -1,7 +1,9 @@
+use crate::api::clock;
 use crate::api::console::Style;
 use crate::api::fs;
 use crate::api::process::ExitCode;
 use crate::api::regex::Regex;
+use crate::api::syscall;
 use crate::sys;
 
 use alloc::format;
@@ -12,6 +14,7 @@ use core::iter::FromIterator;
 struct Options {
     is_first_match: bool,
     is_recursive: bool,
+    newer: Option<u64>,
     file: String,
     line: String,
     trim: String,
@@ -22,6 +25,7 @@ impl Options {
         Self {
             is_first_match: true,
             is_recursive: false,
+            newer: None,
             file: "*".into(),
             line: "".into(),
             trim: "".into(),
@@ -58,6 +62,35 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                     return Err(ExitCode::UsageError);
                 }
             }
+            "-n" | "--newer" => {
+                if i + 1 < n {
+                    i += 1;
+                    if let Some(info) = syscall::info(args[i]) {
+                        options.newer = Some(info.modified());
+                    } else {
+                        error!("Could not find file '{}'", args[i]);
+                        return Err(ExitCode::Failure);
+                    }
+                } else {
+                    error!("Missing file path");
+                    return Err(ExitCode::UsageError);
+                }
+            }
+            "-s" | "--since" => {
+                if i + 1 < n {
+                    i += 1;
+                    if let Some(duration) = parse_duration(args[i]) {
+                        let now = clock::epoch_time() as u64;
+                        options.newer = Some(now.saturating_sub(duration));
+                    } else {
+                        error!("Invalid duration '{}'", args[i]);
+                        return Err(ExitCode::UsageError);
+                    }
+                } else {
+                    error!("Missing duration");
+                    return Err(ExitCode::UsageError);
+                }
+            }
             _ => {
                 if args[i].starts_with('-') {
                     error!("Invalid option '{}'", args[i]);
@@ -104,7 +137,7 @@ fn search_files(path: &str, options: &mut Options) {
             file_path.push_str(&file.name());
             if file.is_dir() {
                 search_files(&file_path, options);
-            } else if is_matching_file(&file_path, &options.file) {
+            } else if is_matching_file(&file_path, &options.file) && is_newer(&file, options) {
                 if options.line.is_empty() {
                     println!("{}", file_path.trim_start_matches(&options.trim));
                 } else {
@@ -117,6 +150,25 @@ fn search_files(path: &str, options: &mut Options) {
     }
 }
 
+fn is_newer(file: &fs::FileInfo, options: &Options) -> bool {
+    match options.newer {
+        Some(time) => file.modified() > time,
+        None => true,
+    }
+}
+
+// Parse a duration in seconds, with an optional unit suffix
+fn parse_duration(s: &str) -> Option<u64> {
+    let (n, unit) = match s.char_indices().last()? {
+        (i, 's') => (&s[..i], 1),
+        (i, 'm') => (&s[..i], 60),
+        (i, 'h') => (&s[..i], 60 * 60),
+        (i, 'd') => (&s[..i], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    n.parse::<u64>().ok()?.checked_mul(unit)
+}
+
 fn is_matching_file(path: &str, pattern: &str) -> bool {
     let file = fs::filename(path);
     let re = Regex::from_glob(pattern);
@@ -205,6 +257,16 @@ fn usage() {
         Find lines matching {0}<pattern>{1}",
         csi_option, csi_reset
     );
+    println!(
+        "  {0}-n{1}, {0}--newer <path>{1}        \
+        Find files modified after {0}<path>{1}",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-s{1}, {0}--since <duration>{1}    \
+        Find files modified in the last {0}<duration>{1}",
+        csi_option, csi_reset
+    );
 }
 
 #[test_case]
@@ -231,6 +293,16 @@ fn test_find() {
         .unwrap()
         .contains("Alice"));
 
+    exec("find /tmp --since 1h => /tmp/find.log").ok();
+    assert!(api::fs::read_to_string("/tmp/find.log")
+        .unwrap()
+        .contains("/tmp/alice.txt"));
+
+    exec("find /tmp --newer /tmp/nope 2=> /tmp/find.log").ok();
+    assert!(api::fs::read_to_string("/tmp/find.log")
+        .unwrap()
+        .contains("Could not find file"));
+
     exec("find nope 2=> /tmp/find.log").ok();
     assert!(api::fs::read_to_string("/tmp/find.log")
         .unwrap()
@@ -253,3 +325,33 @@ fn test_find() {
 
     sys::fs::dismount();
 }
+
+#[test_case]
+fn test_is_newer() {
+    use crate::sys::fs::FileType;
+
+    let old = fs::FileInfo::from_parts(FileType::File, "old", 0, 1000, 1000, 1000);
+    let new = fs::FileInfo::from_parts(FileType::File, "new", 0, 1000, 2000, 1000);
+    let mut options = Options::new();
+    assert!(is_newer(&old, &options));
+    assert!(is_newer(&new, &options));
+
+    // `find --newer old` uses the modification time of the reference file
+    options.newer = Some(old.modified());
+    assert!(!is_newer(&old, &options));
+    assert!(is_newer(&new, &options));
+
+    options.newer = Some(new.modified());
+    assert!(!is_newer(&old, &options));
+    assert!(!is_newer(&new, &options));
+}
+
+#[test_case]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("30"), Some(30));
+    assert_eq!(parse_duration("30s"), Some(30));
+    assert_eq!(parse_duration("2h"), Some(2 * 60 * 60));
+    assert_eq!(parse_duration("1d"), Some(24 * 60 * 60));
+    assert_eq!(parse_duration("h"), None);
+    assert_eq!(parse_duration("99999999999999999d"), None);
+}
@@ -1,3 +1,4 @@
+use crate::api::clock;
 use crate::api::console::Style;
 use crate::api::fs;
 use crate::api::process::ExitCode;
@@ -5,26 +6,73 @@ use crate::api::rng;
 use crate::api::syscall;
 use crate::sys::fs::OpenFlag;
 
+use alloc::collections::btree_map::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use bit_field::BitField;
 use core::convert::TryInto;
+use core::fmt;
 use core::str;
 use core::str::FromStr;
-use smoltcp::wire::{IpAddress, Ipv4Address};
+use lazy_static::lazy_static;
+use smoltcp::wire::{IpAddress, Ipv4Address, Ipv6Address};
+use spin::Mutex;
 
 // See RFC 1035 for implementation details
 
+const DNS_FILE: &str = "/ini/dns";
+const HOSTS_FILE: &str = "/ini/hosts";
+const MAX_ALIASES: usize = 8;
+const MAX_POINTERS: usize = 16;
+const MAX_CACHE_ENTRIES: usize = 256;
+
 #[repr(u16)]
-enum QueryType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QueryType {
     A = 1,
-    // NS = 2,
+    NS = 2,
     // MD = 3,
     // MF = 4,
-    // CNAME = 5,
+    CNAME = 5,
     // SOA = 6,
-    // MX = 15,
-    // TXT = 16,
+    PTR = 12,
+    MX = 15,
+    TXT = 16,
+    AAAA = 28,
+}
+
+impl QueryType {
+    fn from_u16(n: u16) -> Option<Self> {
+        match n {
+            1 => Some(QueryType::A),
+            2 => Some(QueryType::NS),
+            5 => Some(QueryType::CNAME),
+            12 => Some(QueryType::PTR),
+            15 => Some(QueryType::MX),
+            16 => Some(QueryType::TXT),
+            28 => Some(QueryType::AAAA),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for QueryType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a" => Ok(QueryType::A),
+            "ns" => Ok(QueryType::NS),
+            "cname" => Ok(QueryType::CNAME),
+            "ptr" => Ok(QueryType::PTR),
+            "mx" => Ok(QueryType::MX),
+            "txt" => Ok(QueryType::TXT),
+            "aaaa" => Ok(QueryType::AAAA),
+            _ => Err(()),
+        }
+    }
 }
 
 #[repr(u16)]
@@ -46,6 +94,66 @@ pub enum ResponseCode {
     NetworkError,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    A(Ipv4Address),
+    NS(String),
+    CNAME(String),
+    PTR(String),
+    MX(u16, String),
+    TXT(String),
+    AAAA(Ipv6Address),
+}
+
+impl Record {
+    pub fn qtype(&self) -> QueryType {
+        match self {
+            Record::A(_) => QueryType::A,
+            Record::NS(_) => QueryType::NS,
+            Record::CNAME(_) => QueryType::CNAME,
+            Record::PTR(_) => QueryType::PTR,
+            Record::MX(_, _) => QueryType::MX,
+            Record::TXT(_) => QueryType::TXT,
+            Record::AAAA(_) => QueryType::AAAA,
+        }
+    }
+
+    pub fn address(&self) -> Option<IpAddress> {
+        let addr = match self {
+            Record::A(addr) => IpAddress::from(*addr),
+            Record::AAAA(addr) => IpAddress::from(*addr),
+            _ => return None,
+        };
+        if addr.is_unspecified() {
+            None
+        } else {
+            Some(addr)
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Record::A(addr) => write!(f, "{}", addr),
+            Record::AAAA(addr) => write!(f, "{}", addr),
+            Record::NS(name) | Record::CNAME(name) | Record::PTR(name) => {
+                write!(f, "{}", name)
+            }
+            Record::MX(preference, name) => write!(f, "{} {}", preference, name),
+            Record::TXT(text) => write!(f, "{:?}", text),
+        }
+    }
+}
+
+// Resource record of the answer section of a message
+#[derive(Debug, Clone)]
+struct Answer {
+    pub name: String,
+    pub ttl: u32,
+    pub record: Record,
+}
+
 struct Message {
     pub datagram: Vec<u8>,
 }
@@ -104,6 +212,107 @@ impl Message {
         self.header().get_bit(15)
     }
 
+    // Return a domain name and the index following it, which ends with a null
+    // label or with a pointer to the rest of the name
+    fn read_name(&self, mut i: usize) -> Option<(String, usize)> {
+        let mut labels = Vec::new();
+        let mut next = None;
+        let mut pointers = 0;
+        loop {
+            let n = *self.datagram.get(i)? as usize;
+            if n == 0 {
+                if next.is_none() {
+                    next = Some(i + 1);
+                }
+                break;
+            } else if n & 0xC0 == 0xC0 {
+                if next.is_none() {
+                    next = Some(i + 2);
+                }
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                i = ((n & 0x3F) << 8) | *self.datagram.get(i + 1)? as usize;
+                continue;
+            }
+            let label = self.datagram.get((i + 1)..(i + 1 + n))?;
+            labels.push(String::from_utf8_lossy(label).to_lowercase());
+            i += n + 1;
+        }
+        Some((labels.join("."), next?))
+    }
+
+    fn record(&self, rtype: u16, i: usize, len: usize) -> Option<Record> {
+        let data = &self.datagram[i..(i + len)];
+        let name = || self.read_name(i).map(|(name, _)| name);
+        match QueryType::from_u16(rtype)? {
+            QueryType::A => Some(Record::A(Ipv4Address::from_octets(data.try_into().ok()?))),
+            QueryType::AAAA => Some(Record::AAAA(Ipv6Address::from_octets(
+                data.try_into().ok()?,
+            ))),
+            QueryType::NS => name().map(Record::NS),
+            QueryType::CNAME => name().map(Record::CNAME),
+            QueryType::PTR => name().map(Record::PTR),
+            QueryType::MX => {
+                let preference = u16::from_be_bytes(data.get(0..2)?.try_into().ok()?);
+                let (name, _) = self.read_name(i + 2)?;
+                Some(Record::MX(preference, name))
+            }
+            QueryType::TXT => {
+                // Sequence of character strings prefixed by their length
+                let mut text = String::new();
+                let mut j = 0;
+                while j < len {
+                    let n = data[j] as usize;
+                    let s = data.get((j + 1)..(j + 1 + n))?;
+                    text.push_str(&String::from_utf8_lossy(s));
+                    j += n + 1;
+                }
+                Some(Record::TXT(text))
+            }
+        }
+    }
+
+    // Return the records of the answer section of the types that can be
+    // parsed, with the name and the TTL of each record
+    pub fn answers(&self) -> Vec<Answer> {
+        let mut res = Vec::new();
+        let n = self.datagram.len();
+        if n < 12 {
+            return res;
+        }
+        let count = |i: usize| u16::from_be_bytes([self.datagram[i], self.datagram[i + 1]]);
+        let mut i = 12;
+        for _ in 0..count(4) {
+            match self.read_name(i) {
+                Some((_, j)) => i = j + 4, // QTYPE + QCLASS
+                None => return res,
+            }
+        }
+        for _ in 0..count(6) {
+            let name = match self.read_name(i) {
+                Some((name, j)) if j + 10 <= n => {
+                    i = j;
+                    name
+                }
+                _ => return res,
+            };
+            let rtype = count(i);
+            let ttl = u32::from_be_bytes(self.datagram[(i + 4)..(i + 8)].try_into().unwrap());
+            let len = count(i + 8) as usize; // After TYPE + CLASS + TTL
+            i += 10;
+            if i + len > n {
+                break;
+            }
+            if let Some(record) = self.record(rtype, i, len) {
+                res.push(Answer { name, ttl, record });
+            }
+            i += len;
+        }
+        res
+    }
+
     pub fn code(&self) -> ResponseCode {
         match self.header().get_bits(11..15) {
             0 => ResponseCode::NoError,
@@ -117,21 +326,204 @@ impl Message {
     }
 }
 
-fn dns_address() -> Option<IpAddress> {
-    if let Ok(servers) = fs::read_to_string("/ini/dns") {
-        if let Some((server, _)) = servers.split_once(',') {
+// Records of the previous lookups with the time they expire
+type Cache = BTreeMap<(String, QueryType), (f64, Vec<Record>)>;
+
+lazy_static! {
+    static ref CACHE: Mutex<Cache> = Mutex::new(BTreeMap::new());
+}
+
+fn cache_get(name: &str, qtype: QueryType) -> Option<Vec<Record>> {
+    let now = clock::boot_time();
+    let mut cache = CACHE.lock();
+    cache.retain(|_, (expires, _)| *expires > now);
+    let key = (name.to_string(), qtype);
+    cache.get(&key).map(|(_, records)| records.clone())
+}
+
+fn cache_set(name: &str, qtype: QueryType, records: &[Record], ttl: u32) {
+    if ttl == 0 || records.is_empty() {
+        return;
+    }
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_CACHE_ENTRIES {
+        if let Some(key) = cache.keys().next().cloned() {
+            cache.remove(&key);
+        }
+    }
+    let expires = clock::boot_time() + ttl as f64;
+    let key = (name.to_string(), qtype);
+    cache.insert(key, (expires, records.to_vec()));
+}
+
+// Parse a table of hosts with an address followed by its names on each line
+fn parse_hosts(contents: &str) -> Vec<(IpAddress, Vec<String>)> {
+    let mut res = Vec::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        if let Some(Ok(addr)) = fields.next().map(IpAddress::from_str) {
+            let names = fields.map(|name| name.to_lowercase()).collect();
+            res.push((addr, names));
+        }
+    }
+    res
+}
+
+fn hosts() -> Vec<(IpAddress, Vec<String>)> {
+    match fs::read_to_string(HOSTS_FILE) {
+        Ok(contents) => parse_hosts(&contents),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn hosts_lookup(name: &str, qtype: QueryType) -> Vec<Record> {
+    let mut res = Vec::new();
+    for (addr, names) in hosts() {
+        if names.iter().any(|n| n == name) {
+            match (addr, qtype) {
+                (IpAddress::Ipv4(addr), QueryType::A) => res.push(Record::A(addr)),
+                (IpAddress::Ipv6(addr), QueryType::AAAA) => res.push(Record::AAAA(addr)),
+                _ => {}
+            }
+        }
+    }
+    res
+}
+
+fn dns_servers() -> Vec<IpAddress> {
+    let mut res = Vec::new();
+    if let Ok(servers) = fs::read_to_string(DNS_FILE) {
+        for server in servers.split(|c: char| c == ',' || c.is_whitespace()) {
             if let Ok(addr) = IpAddress::from_str(server) {
-                return Some(addr);
+                res.push(addr);
             }
         }
     }
-    None
+    if res.is_empty() {
+        res.push(IpAddress::v4(8, 8, 8, 8));
+    }
+    res
 }
 
+// Return the name used to find the domain name of an address
+fn reverse_name(addr: &IpAddress) -> String {
+    match addr {
+        IpAddress::Ipv4(addr) => {
+            let o = addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        }
+        IpAddress::Ipv6(addr) => {
+            let mut name = String::new();
+            for b in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", b & 0xF, b >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+// Resolve a domain name to its first IPv4 address, or to its first IPv6
+// address when it doesn't have any IPv4 address
 pub fn resolve(name: &str) -> Result<IpAddress, ResponseCode> {
-    let addr = dns_address().unwrap_or(IpAddress::v4(8, 8, 8, 8));
+    let mut res = Err(ResponseCode::NameError);
+    for qtype in [QueryType::A, QueryType::AAAA] {
+        match lookup(name, qtype) {
+            Ok(records) => {
+                if let Some(addr) = records.iter().find_map(Record::address) {
+                    return Ok(addr);
+                }
+            }
+            Err(e) => res = Err(e),
+        }
+    }
+    res
+}
+
+// Find the domain name of an address
+pub fn reverse(addr: &IpAddress) -> Result<String, ResponseCode> {
+    for (a, names) in hosts() {
+        if a == *addr {
+            if let Some(name) = names.first() {
+                return Ok(name.clone());
+            }
+        }
+    }
+    for record in lookup(&reverse_name(addr), QueryType::PTR)? {
+        if let Record::PTR(name) = record {
+            return Ok(name);
+        }
+    }
+    Err(ResponseCode::NameError)
+}
+
+// Return the records of a domain name from the table of hosts, the cache, or
+// the DNS servers, following the aliases given by CNAME records
+pub fn lookup(name: &str, qtype: QueryType) -> Result<Vec<Record>, ResponseCode> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    let records = hosts_lookup(&name, qtype);
+    if !records.is_empty() {
+        return Ok(records);
+    }
+    if let Some(records) = cache_get(&name, qtype) {
+        return Ok(records);
+    }
+
+    let mut target = name.clone();
+    let mut ttl = u32::MAX;
+    let mut answers = query(&target, qtype)?;
+    for _ in 0..MAX_ALIASES {
+        let records: Vec<_> = answers
+            .iter()
+            .filter(|answer| answer.name == target && answer.record.qtype() == qtype)
+            .collect();
+        if !records.is_empty() {
+            let ttl = records.iter().fold(ttl, |ttl, answer| ttl.min(answer.ttl));
+            let records: Vec<_> = records.iter().map(|answer| answer.record.clone()).collect();
+            cache_set(&name, qtype, &records, ttl);
+            return Ok(records);
+        }
+
+        let alias = answers.iter().find_map(|answer| match &answer.record {
+            Record::CNAME(alias) if answer.name == target => Some((alias.clone(), answer.ttl)),
+            _ => None,
+        });
+        match alias {
+            Some((alias, alias_ttl)) => {
+                ttl = ttl.min(alias_ttl);
+                // The records of the alias are usually given in the same
+                // answer, otherwise they are queried separately
+                if !answers.iter().any(|answer| answer.name == alias) {
+                    answers = query(&alias, qtype)?;
+                }
+                target = alias;
+            }
+            None => break,
+        }
+    }
+    Ok(Vec::new())
+}
+
+// Query the DNS servers in order until one of them gives an answer
+fn query(name: &str, qtype: QueryType) -> Result<Vec<Answer>, ResponseCode> {
+    let mut res = Err(ResponseCode::NetworkError);
+    for addr in dns_servers() {
+        match query_server(addr, name, qtype) {
+            Ok(message) => match message.code() {
+                ResponseCode::NoError => return Ok(message.answers()),
+                ResponseCode::NameError => return Err(ResponseCode::NameError),
+                code => res = Err(code),
+            },
+            Err(e) => res = Err(e),
+        }
+    }
+    res
+}
+
+fn query_server(addr: IpAddress, name: &str, qtype: QueryType) -> Result<Message, ResponseCode> {
     let port = 53;
-    let query = Message::query(name, QueryType::A, QueryClass::IN);
+    let query = Message::query(name, qtype, QueryClass::IN);
 
     let socket_path = "/dev/net/udp";
     let buf_len = if let Some(info) = syscall::info(socket_path) {
@@ -153,7 +545,7 @@ pub fn resolve(name: &str) -> Result<IpAddress, ResponseCode> {
         loop {
             let mut data = vec![0; buf_len];
             if let Some(bytes) = syscall::read(handle, &mut data) {
-                if bytes < 28 {
+                if bytes < 12 {
                     break;
                 }
                 data.resize(bytes, 0);
@@ -162,25 +554,7 @@ pub fn resolve(name: &str) -> Result<IpAddress, ResponseCode> {
                 if message.id() == query.id() && message.is_response() {
                     syscall::close(handle);
                     //usr::hex::print_hex(&message.datagram);
-                    return match message.code() {
-                        ResponseCode::NoError => {
-                            // TODO: Parse the datagram instead of extracting
-                            // the last 4 bytes
-                            let n = message.datagram.len();
-                            let data = &message.datagram[(n - 4)..];
-                            if let Ok(data) = data.try_into() {
-                                let ipv4 = Ipv4Address::from_octets(data);
-                                if ipv4.is_unspecified() {
-                                    Err(ResponseCode::NameError) // FIXME
-                                } else {
-                                    Ok(IpAddress::from(ipv4))
-                                }
-                            } else {
-                                Err(ResponseCode::NameError) // FIXME
-                            }
-                        }
-                        code => Err(code),
-                    };
+                    return Ok(message);
                 }
             } else {
                 break;
@@ -193,21 +567,89 @@ pub fn resolve(name: &str) -> Result<IpAddress, ResponseCode> {
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     // TODO: Add `--server <address>` option
-    if args.len() != 2 {
-        help();
-        return Err(ExitCode::UsageError);
+    let mut qtype = None;
+    let mut host = None;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            "-t" | "--type" => {
+                if i + 1 == n {
+                    error!("Missing record type");
+                    return Err(ExitCode::UsageError);
+                }
+                match QueryType::from_str(args[i + 1]) {
+                    Ok(t) => qtype = Some(t),
+                    Err(_) => {
+                        error!("Invalid record type '{}'", args[i + 1]);
+                        return Err(ExitCode::UsageError);
+                    }
+                }
+                i += 1;
+            }
+            arg if host.is_none() => {
+                host = Some(arg);
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+        i += 1;
     }
-    let domain = args[1];
-    match resolve(domain) {
-        Ok(addr) => {
-            println!("{}", addr);
-            Ok(())
+    let host = match host {
+        Some(host) => host,
+        None => {
+            help();
+            return Err(ExitCode::UsageError);
         }
-        Err(e) => {
-            error!("Could not resolve host: {:?}", e);
-            Err(ExitCode::Failure)
+    };
+
+    // Reverse lookup of an address
+    if let (Ok(addr), None) = (IpAddress::from_str(host), qtype) {
+        return match reverse(&addr) {
+            Ok(name) => {
+                println!("{}", name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Could not resolve address: {:?}", e);
+                Err(ExitCode::Failure)
+            }
+        };
+    }
+
+    let qtypes = match qtype {
+        Some(qtype) => vec![qtype],
+        None => vec![QueryType::A, QueryType::AAAA],
+    };
+    let mut res = Err(ResponseCode::NameError);
+    for qtype in qtypes {
+        match lookup(host, qtype) {
+            Ok(records) => {
+                for record in &records {
+                    println!("{}", record);
+                }
+                if !records.is_empty() {
+                    res = Ok(());
+                }
+            }
+            Err(e) => {
+                if res.is_err() {
+                    res = Err(e);
+                }
+            }
         }
     }
+    if let Err(e) = res {
+        error!("Could not resolve host: {:?}", e);
+        return Err(ExitCode::Failure);
+    }
+    Ok(())
 }
 
 fn help() {
@@ -215,7 +657,61 @@ fn help() {
     let csi_title = Style::color("yellow");
     let csi_reset = Style::reset();
     println!(
-        "{}Usage:{} host {}<domain>{1}",
+        "{}Usage:{} host {}<options> <domain|address>{1}",
         csi_title, csi_reset, csi_option
     );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-t{1}, {0}--type <type>{1}    Query records of <type> (a, aaaa, mx, txt, ...)",
+        csi_option, csi_reset
+    );
+}
+
+#[test_case]
+fn test_message_answers() {
+    let mut datagram = vec![
+        0x12, 0x34, 0x81, 0x80, // ID + Flags
+        0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, // Counts
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', // QNAME
+        0x03, b'c', b'o', b'm', 0x00, //
+        0x00, 0x1C, 0x00, 0x01, // QTYPE + QCLASS
+        0xC0, 0x0C, 0x00, 0x05, 0x00, 0x01, // CNAME record
+        0x00, 0x00, 0x0E, 0x10, 0x00, 0x06, 0x03, b'w', //
+        b'w', b'w', 0xC0, 0x0C, //
+        0xC0, 0x29, 0x00, 0x1C, 0x00, 0x01, // AAAA record
+        0x00, 0x00, 0x0E, 0x10, 0x00, 0x10,
+    ];
+    datagram.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8]);
+    datagram.extend_from_slice(&[0; 11]);
+    datagram.push(0x01);
+    let message = Message::from(&datagram);
+    let answers = message.answers();
+    assert_eq!(answers.len(), 2);
+    assert_eq!(answers[0].name, "example.com");
+    assert_eq!(answers[0].ttl, 3600);
+    let alias = "www.example.com".to_string();
+    assert_eq!(answers[0].record, Record::CNAME(alias.clone()));
+    assert_eq!(answers[1].name, alias);
+    let addr = IpAddress::from_str("2001:db8::1").unwrap();
+    assert_eq!(answers[1].record.address(), Some(addr));
+
+    datagram.truncate(datagram.len() - 1);
+    let message = Message::from(&datagram);
+    assert_eq!(message.answers().len(), 1);
+}
+
+#[test_case]
+fn test_hosts() {
+    let hosts = parse_hosts("# Hosts\n127.0.0.1 localhost lo\n\n::1 localhost # IPv6\n");
+    assert_eq!(hosts.len(), 2);
+    assert_eq!(hosts[0].0, IpAddress::v4(127, 0, 0, 1));
+    assert_eq!(hosts[0].1, vec!["localhost".to_string(), "lo".to_string()]);
+    assert_eq!(hosts[1].1, vec!["localhost".to_string()]);
+
+    let addr = IpAddress::v4(10, 0, 2, 3);
+    assert_eq!(reverse_name(&addr), "3.2.0.10.in-addr.arpa");
+    let addr = IpAddress::from_str("2001:db8::1").unwrap();
+    assert!(reverse_name(&addr).starts_with("1.0.0.0.0.0.0.0."));
+    assert!(reverse_name(&addr).ends_with("8.b.d.0.1.0.0.2.ip6.arpa"));
 }
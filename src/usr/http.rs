@@ -33,17 +33,28 @@ impl URL {
             Some(i) => url.split_at(i),
             None => (url, "/"),
         };
-        let (host, port) = match server.find(':') {
-            Some(i) => server.split_at(i),
-            None => (server, ":80"),
+        // The brackets around IPv6 addresses separate them from the port
+        let (host, port) = match server.rfind(':') {
+            Some(i) if !server.ends_with(']') => server.split_at(i),
+            _ => (server, ":80"),
         };
         let port = &port[1..];
+        let host = host.trim_start_matches('[').trim_end_matches(']');
         Some(Self {
             host: host.into(),
             port: port.parse().unwrap_or(80),
             path: path.into(),
         })
     }
+
+    // Return the host of the URL with the brackets of IPv6 addresses
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
 }
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
@@ -107,14 +118,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     let url = "http://".to_string() + host + path;
     let url = URL::parse(&url).expect("invalid URL format");
     let port = url.port;
-    let addr = if url.host.ends_with(char::is_numeric) {
-        match IpAddress::from_str(&url.host) {
-            Ok(ip_addr) => ip_addr,
-            Err(_) => {
-                error!("Invalid address format");
-                return Err(ExitCode::UsageError);
-            }
-        }
+    let addr = if let Ok(ip_addr) = IpAddress::from_str(&url.host) {
+        ip_addr
     } else {
         match usr::host::resolve(&url.host) {
             Ok(ip_addr) => ip_addr,
@@ -143,7 +148,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         }
         let req = vec![
             format!("GET {} HTTP/1.1\r\n", url.path),
-            format!("Host: {}\r\n", url.host),
+            format!("Host: {}\r\n", url.authority()),
             format!("User-Agent: WildflowerOS/{}\r\n", env!("CARGO_PKG_VERSION")),
             format!("Connection: close\r\n"),
             format!("\r\n"),
@@ -250,3 +255,20 @@ fn help() -> Result<(), ExitCode> {
     );
     Ok(())
 }
+
+#[test_case]
+fn test_url_parse() {
+    let url = URL::parse("http://example.com:8080/index.html").unwrap();
+    assert_eq!(url.host, "example.com");
+    assert_eq!(url.port, 8080);
+    assert_eq!(url.path, "/index.html");
+
+    let url = URL::parse("http://[::1]:8080/").unwrap();
+    assert_eq!(url.host, "::1");
+    assert_eq!(url.port, 8080);
+    assert_eq!(url.authority(), "[::1]");
+
+    let url = URL::parse("http://[fe80::1]").unwrap();
+    assert_eq!(url.host, "fe80::1");
+    assert_eq!(url.port, 80);
+}
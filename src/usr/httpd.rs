@@ -14,10 +14,9 @@ use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
-use smoltcp::iface::SocketSet;
 use smoltcp::phy::Device;
 use smoltcp::socket::tcp;
-use smoltcp::time::Instant;
+use smoltcp::time::{Duration, Instant};
 use smoltcp::wire::IpAddress;
 
 const MAX_CONNECTIONS: usize = 32;
@@ -155,6 +154,7 @@ impl Response {
         let msg = match self.code {
             200 => "OK",
             301 => "Moved Permanently",
+            304 => "Not Modified",
             400 => "Bad Request",
             403 => "Forbidden",
             404 => "Not Found",
@@ -211,10 +211,20 @@ fn get(req: &Request, res: &mut Response) {
             if fs::is_dir(&real_path) {
                 continue;
             }
-            if let Ok(buf) = fs::read_to_bytes(&real_path) {
-                res.code = 200;
+            if let Some(info) = syscall::info(&real_path) {
+                let modified = info.modified() as i64;
+                let last_modified = time::format_http_date(modified);
+                if is_not_modified(req, modified) {
+                    res.code = 304;
+                } else if let Ok(buf) = fs::read_to_bytes(&real_path) {
+                    res.code = 200;
+                    res.body.extend_from_slice(&buf);
+                } else {
+                    continue;
+                }
                 res.mime = content_type(&real_path);
-                res.body.extend_from_slice(&buf);
+                res.headers
+                    .insert("Last-Modified".to_string(), last_modified);
                 not_found = false;
                 break;
             }
@@ -240,6 +250,17 @@ fn get(req: &Request, res: &mut Response) {
     }
 }
 
+fn is_not_modified(req: &Request, modified: i64) -> bool {
+    let header = req
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("If-Modified-Since"));
+    match header.and_then(|(_, val)| time::parse_http_date(val)) {
+        Some(since) => modified <= since,
+        None => false,
+    }
+}
+
 fn put(req: &Request, res: &mut Response) {
     if res.real_path.ends_with('/') {
         // Write directory
@@ -319,39 +340,73 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     // NOTE: This specific format is needed by `join_path`
     let dir = format!("/{}", fs::realpath(&dir).trim_matches('/'));
 
-    if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-        let mut sockets = SocketSet::new(vec![]);
-
-        let mtu = device.capabilities().max_transmission_unit;
-        let buf_len = mtu - 14 - 20 - 20; // ETH+TCP+IP headers
-        let mut connections = Vec::new();
-        for _ in 0..MAX_CONNECTIONS {
-            let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; buf_len]);
-            let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; buf_len]);
-            let tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
-            let tcp_handle = sockets.add(tcp_socket);
-
-            let send_queue: VecDeque<Vec<u8>> = VecDeque::new();
-            let keep_alive = true;
-            connections.push((tcp_handle, send_queue, keep_alive));
-        }
+    let mut net = sys::net::NET.lock();
+    if net.is_empty() {
+        error!("Could not find network interface");
+        return Err(ExitCode::Failure);
+    }
 
-        println!(
-            "{}HTTP Server listening on 0.0.0.0:{}{}",
-            csi_color, port, csi_reset
-        );
+    let mtu = net[0].device.capabilities().max_transmission_unit;
+    let buf_len = mtu - 14 - 20 - 20; // ETH+TCP+IP headers
 
-        loop {
-            if console::end_of_text() || console::end_of_transmission() {
-                println!();
-                return Ok(());
+    // The size of the send buffers is the system default to serve large files
+    // without waiting for an acknowledgement after each segment
+    let tx_buf_len = sys::net::socket::tcp::buffer_size();
+
+    // The listening sockets are added to the sockets of each interface, which
+    // are unlocked between each poll to let them be polled in the background.
+    let mut servers: Vec<_> = net
+        .iter_mut()
+        .map(|net| {
+            let mut connections = Vec::new();
+            for _ in 0..MAX_CONNECTIONS {
+                let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; buf_len]);
+                let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; tx_buf_len]);
+                let tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
+                let tcp_handle = net.sockets.add(tcp_socket);
+
+                let send_queue: VecDeque<Vec<u8>> = VecDeque::new();
+                let keep_alive = true;
+                connections.push((tcp_handle, send_queue, keep_alive));
+            }
+            connections
+        })
+        .collect();
+    drop(net);
+
+    println!(
+        "{}HTTP Server listening on 0.0.0.0:{}{}",
+        csi_color, port, csi_reset
+    );
+
+    loop {
+        let mut net = sys::net::NET.lock();
+        if console::end_of_text() || console::end_of_transmission() {
+            for (net, connections) in net.iter_mut().zip(&servers) {
+                for (tcp_handle, _, _) in connections {
+                    net.sockets.remove(*tcp_handle);
+                }
             }
+            println!();
+            return Ok(());
+        }
 
-            let ms = (clock::epoch_time() * 1000000.0) as i64;
-            let time = Instant::from_micros(ms);
-            iface.poll(time, device, &mut sockets);
+        let ms = (clock::epoch_time() * 1000000.0) as i64;
+        let time = Instant::from_micros(ms);
+        let mut poll_delay = None;
+        for (
+            sys::net::NetInterface {
+                iface,
+                device,
+                sockets,
+                ..
+            },
+            connections,
+        ) in net.iter_mut().zip(&mut servers)
+        {
+            iface.poll(time, device, sockets);
 
-            for (tcp_handle, send_queue, keep_alive) in &mut connections {
+            for (tcp_handle, send_queue, keep_alive) in connections.iter_mut() {
                 let socket = sockets.get_mut::<tcp::Socket>(*tcp_handle);
 
                 if !socket.is_open() {
@@ -409,10 +464,17 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                             }
                         }
                     }
-                    if socket.can_send() {
-                        if let Some(chunk) = send_queue.pop_front() {
-                            let sent = socket.send_slice(&chunk).expect("Could not send chunk");
-                            debug_assert!(sent == chunk.len());
+                    // Queue as many chunks as the send buffer can hold
+                    while socket.can_send() {
+                        if let Some(chunk) = send_queue.front_mut() {
+                            let sent = socket.send_slice(chunk).expect("Could not send chunk");
+                            if sent < chunk.len() {
+                                chunk.drain(..sent);
+                            } else {
+                                send_queue.pop_front();
+                            }
+                        } else {
+                            break;
                         }
                     }
                     if send_queue.is_empty() && !*keep_alive {
@@ -423,16 +485,17 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                     send_queue.clear();
                 }
             }
-            if let Some(delay) = iface.poll_delay(time, &sockets) {
-                let d = delay.total_micros() / POLL_DELAY_DIV as u64;
-                if d > 0 {
-                    syscall::sleep((d as f64) / 1000000.0);
-                }
+            if let Some(delay) = iface.poll_delay(time, sockets) {
+                poll_delay = Some(poll_delay.map_or(delay, |d: Duration| d.min(delay)));
+            }
+        }
+        drop(net);
+        if let Some(delay) = poll_delay {
+            let d = delay.total_micros() / POLL_DELAY_DIV as u64;
+            if d > 0 {
+                syscall::sleep((d as f64) / 1000000.0);
             }
         }
-    } else {
-        error!("Could not find network interface");
-        Err(ExitCode::Failure)
     }
 }
 
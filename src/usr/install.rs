@@ -55,16 +55,27 @@ pub fn copy_files(verbose: bool) {
     create_dev("/dev/ata/0/1", "ata-0-1", verbose);
     create_dev("/dev/ata/1/0", "ata-1-0", verbose);
     create_dev("/dev/ata/1/1", "ata-1-1", verbose);
+    for drive in sys::ata::list() {
+        for partition in sys::ata::partitions(drive.bus, drive.dsk) {
+            let path = sys::fs::ata_path(drive.bus, drive.dsk, partition.index);
+            let name = format!("ata-{}-{}p{}", drive.bus, drive.dsk, partition.index);
+            create_dev(&path, &name, verbose);
+        }
+    }
     create_dev("/dev/clk/boot", "clk-boot", verbose);
     create_dev("/dev/clk/epoch", "clk-epoch", verbose);
     create_dev("/dev/clk/rtc", "clk-rtc", verbose);
     create_dev("/dev/console", "console", verbose);
+    create_dev("/dev/net/icmp", "net-icmp", verbose);
     create_dev("/dev/net/tcp", "net-tcp", verbose);
     create_dev("/dev/net/udp", "net-udp", verbose);
-    create_dev("/dev/net/gw", "net-gw", verbose);
-    create_dev("/dev/net/ip", "net-ip", verbose);
-    create_dev("/dev/net/mac", "net-mac", verbose);
-    create_dev("/dev/net/usage", "net-usage", verbose);
+    for name in sys::net::interfaces() {
+        create_dir(&format!("/dev/net/{}", name), verbose);
+        for dev in ["gw", "ip", "mac", "usage"] {
+            let path = format!("/dev/net/{}/{}", name, dev);
+            create_dev(&path, &format!("net-{}-{}", dev, name), verbose);
+        }
+    }
     create_dev("/dev/null", "null", verbose);
     create_dev("/dev/random", "random", verbose);
     create_dev("/dev/speaker", "speaker", verbose);
@@ -75,6 +86,7 @@ pub fn copy_files(verbose: bool) {
 
     copy_file!("/ini/banner.txt", verbose);
     copy_file!("/ini/boot.sh", verbose);
+    copy_file!("/ini/hosts", verbose);
     copy_file!("/ini/lisp.lsp", verbose);
     copy_file!("/ini/shell.sh", verbose);
     copy_file!("/ini/version.txt", verbose);
@@ -174,7 +186,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             println!();
 
             println!("{}Formatting disk ...{}", csi_color, csi_reset);
-            print!("Enter path of disk to format: ");
+            print!("Enter path of disk or partition to format: ");
             let path = io::stdin().read_line();
             if path.trim_end() == "/dev/mem" {
                 usr::shell::exec(&format!("memory format"))?;
@@ -0,0 +1,71 @@
+use crate::api::console::Style;
+use crate::api::fs;
+use crate::api::process::ExitCode;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut symbolic = false;
+    let mut paths = [""; 2];
+    let mut n = 0;
+    for arg in &args[1..] {
+        match *arg {
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            "-s" | "--symbolic" => {
+                symbolic = true;
+            }
+            _ if n < 2 => {
+                crate::api::hfs::check_hfs_bounds(arg)?;
+                paths[n] = arg;
+                n += 1;
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+    }
+    if n != 2 {
+        help();
+        return Err(ExitCode::UsageError);
+    }
+
+    let [target, path] = paths;
+    if fs::exists(path) || fs::is_link(path) {
+        error!("Could not overwrite '{}'", path);
+        return Err(ExitCode::Failure);
+    }
+    if symbolic {
+        if fs::symlink(target, path).is_err() {
+            error!("Could not create link '{}'", path);
+            return Err(ExitCode::Failure);
+        }
+    } else {
+        if !fs::is_file(target) {
+            error!("Could not find file '{}'", target);
+            return Err(ExitCode::Failure);
+        }
+        if fs::link(target, path).is_err() {
+            error!("Could not create link '{}'", path);
+            return Err(ExitCode::Failure);
+        }
+    }
+    Ok(())
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} link {}<options> <target> <path>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-s{1}, {0}--symbolic{1}   Create a symbolic link",
+        csi_option, csi_reset
+    );
+}
@@ -815,8 +815,9 @@ pub fn lisp_socket_listen(args: &[Exp]) -> Result<Exp, Err> {
 pub fn lisp_socket_accept(args: &[Exp]) -> Result<Exp, Err> {
     ensure_length_eq!(args, 1);
     let handle: usize = number(&args[0])?.try_into()?;
-    if let Ok(addr) = syscall::accept(handle) {
-        Ok(Exp::Str(format!("{}", addr)))
+    if let Ok((handle, addr)) = syscall::accept(handle) {
+        let handle = Exp::Num(Number::from(handle));
+        Ok(Exp::List(vec![handle, Exp::Str(format!("{}", addr))]))
     } else {
         could_not!("accept connections")
     }
@@ -7,12 +7,14 @@ use crate::api::time;
 use crate::api::unit::SizeUnit;
 use crate::sys;
 
-use alloc::string::ToString;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     let mut path: &str = &sys::process::dir(); // TODO: use '.'
     let mut sort = "name";
+    let mut time = "modified";
     let mut hide_dot_files = true;
     let mut unit = SizeUnit::None;
 
@@ -24,6 +26,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             "-n" | "--name" => sort = "name",
             "-s" | "--size" => sort = "size",
             "-t" | "--time" => sort = "time",
+            "-c" | "--created" => time = "created",
+            "-u" | "--accessed" => time = "accessed",
             "-b" | "--binary-size" => unit = SizeUnit::Binary,
             "-d" | "--decimal-size" => unit = SizeUnit::Decimal,
             _ => path = args[i],
@@ -47,7 +51,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                 match sort {
                     "name" => files.sort_by_key(|f| f.name()),
                     "size" => files.sort_by_key(|f| f.size()),
-                    "time" => files.sort_by_key(|f| f.time()),
+                    "time" => files.sort_by_key(|f| file_time(f, time)),
                     _ => {
                         error!("Invalid sort key '{}'", sort);
                         return Err(ExitCode::Failure);
@@ -60,7 +64,9 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                 });
 
                 for file in files {
-                    print_file(file, width, unit.clone());
+                    let sep = if path.ends_with('/') { "" } else { "/" };
+                    let pathname = format!("{}{}{}", path, sep, file.name());
+                    print_file(&pathname, file, time, width, unit.clone());
                 }
                 Ok(())
             } else {
@@ -68,7 +74,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                 Err(ExitCode::Failure)
             }
         } else {
-            print_file(&info, info.size().to_string().len(), unit);
+            let width = info.size().to_string().len();
+            print_file(path, &info, time, width, unit);
             Ok(())
         }
     } else {
@@ -77,27 +84,48 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     }
 }
 
-fn print_file(file: &FileInfo, width: usize, unit: SizeUnit) {
+fn file_time(file: &FileInfo, time: &str) -> u64 {
+    match time {
+        "created" => file.created(),
+        "accessed" => file.accessed(),
+        _ => file.modified(),
+    }
+}
+
+fn print_file(path: &str, file: &FileInfo, time: &str, width: usize, unit: SizeUnit) {
     let csi_dir_color = Style::color("aqua");
     let csi_dev_color = Style::color("yellow");
+    let csi_link_color = Style::color("fushia");
     let csi_reset = Style::reset();
 
     let size = unit.format(file.size() as usize);
-    let time = time::format_offset_time(time::from_timestamp(file.time() as i64));
+    let time = file_time(file, time) as i64;
+    let time = time::format_offset_time(time::from_timestamp(time));
     let color = if file.is_dir() {
         csi_dir_color
     } else if file.is_device() {
         csi_dev_color
+    } else if file.is_link() {
+        csi_link_color
     } else {
         csi_reset
     };
+    let target = if file.is_link() {
+        match fs::read_link(path) {
+            Ok(target) => format!(" -> {}", target),
+            Err(_) => String::new(),
+        }
+    } else {
+        String::new()
+    };
     println!(
-        "{:>width$} {} {}{}{}",
+        "{:>width$} {} {}{}{}{}",
         size,
         time,
         color,
         file.name(),
         csi_reset,
+        target,
         width = width
     );
 }
@@ -132,5 +160,13 @@ fn help() -> Result<(), ExitCode> {
         "  {0}-t{1}, {0}--time{1}          Sort by time",
         csi_option, csi_reset
     );
+    println!(
+        "  {0}-c{1}, {0}--created{1}       Use creation time",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-u{1}, {0}--accessed{1}      Use access time",
+        csi_option, csi_reset
+    );
     Ok(())
 }
@@ -23,13 +23,16 @@ pub mod httpd;
 pub mod install;
 pub mod keyboard;
 pub mod life;
+pub mod link;
 pub mod lisp;
 pub mod list;
 pub mod memory;
+pub mod mount;
 pub mod r#move;
 pub mod net;
 pub mod pci;
 pub mod pi;
+pub mod ping;
 pub mod play;
 pub mod print;
 pub mod pow;
@@ -39,6 +42,7 @@ pub mod shell;
 pub mod socket;
 pub mod tcp;
 pub mod time;
+pub mod umount;
 pub mod user;
 pub mod view;
 pub mod write;
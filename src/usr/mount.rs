@@ -0,0 +1,126 @@
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::api::unit;
+use crate::sys;
+use crate::usr::disk::{parse_ahci_path, parse_cdrom_path, parse_disk_path, parse_virtio_path};
+
+use alloc::vec::Vec;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut paths = Vec::new();
+    let mut size = None;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            "-s" | "--size" => {
+                if i + 1 < n {
+                    i += 1;
+                    match unit::parse_size(args[i]) {
+                        Some(bytes) => size = Some(bytes),
+                        None => {
+                            error!("Could not parse size '{}'", args[i]);
+                            return Err(ExitCode::UsageError);
+                        }
+                    }
+                } else {
+                    error!("Missing size");
+                    return Err(ExitCode::UsageError);
+                }
+            }
+            arg if arg.starts_with('-') => {
+                error!("Invalid option '{}'", arg);
+                return Err(ExitCode::UsageError);
+            }
+            arg => paths.push(arg),
+        }
+        i += 1;
+    }
+    match paths.len() {
+        0 => list(),
+        2 => mount(paths[0], paths[1], size),
+        _ => {
+            help();
+            Err(ExitCode::UsageError)
+        }
+    }
+}
+
+fn list() -> Result<(), ExitCode> {
+    for mount in sys::fs::mounts() {
+        println!("{} on {} type {}", mount.source, mount.path, mount.name);
+    }
+    Ok(())
+}
+
+fn mount(source: &str, path: &str, size: Option<usize>) -> Result<(), ExitCode> {
+    let res = if source == "tmpfs" {
+        let size = size.unwrap_or(sys::mem::memory_free() / 8);
+        sys::fs::mount_mem_at(path, size)
+    } else if let Some(port) = parse_ahci_path(source) {
+        sys::fs::mount_ahci_at(port, path)
+    } else if let Some(id) = parse_virtio_path(source) {
+        sys::fs::mount_virtio_at(id, path)
+    } else if let Some((bus, dsk)) = parse_cdrom_path(source) {
+        sys::fs::mount_cdrom_at(bus, dsk, path)
+    } else {
+        match parse_disk_path(source) {
+            Ok((bus, dsk, part)) => sys::fs::mount_ata_at(bus, dsk, part, path),
+            Err(msg) => {
+                error!("{}", msg);
+                return Err(ExitCode::Failure);
+            }
+        }
+    };
+    if res.is_err() {
+        error!("Could not mount '{}' on '{}'", source, path);
+        return Err(ExitCode::Failure);
+    }
+    Ok(())
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} mount {}<options> [<source> <path>]{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Sources:{}", csi_title, csi_reset);
+    println!(
+        "  {0}/dev/ata/<bus>/<dsk>{1}       Mount ATA disk",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}/dev/ata/<bus>/<dsk>p<n>{1}   Mount ATA partition",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}/dev/ahci/<port>{1}           Mount AHCI disk",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}/dev/virtio/<id>{1}           Mount VIRTIO disk",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}/dev/cd/<bus>/<dsk>{1}        Mount CD-ROM",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}tmpfs{1}                      Mount new memory disk",
+        csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-s{1}, {0}--size <size>{1}   Set size of memory disk",
+        csi_option, csi_reset
+    );
+}
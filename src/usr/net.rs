@@ -5,20 +5,23 @@ use crate::api::process::ExitCode;
 use crate::api::syscall;
 use crate::sys;
 use crate::sys::console;
+use crate::sys::fs::OpenFlag;
 use crate::sys::net;
-use crate::sys::net::EthernetDeviceIO;
+use crate::sys::net::{EthernetDeviceIO, SocketOption};
+use crate::usr;
 use alloc::format;
 
 use alloc::borrow::ToOwned;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::vec;
+use alloc::vec::Vec;
 use core::str::FromStr;
 use smoltcp::iface::SocketSet;
 use smoltcp::phy::Device;
 use smoltcp::socket::tcp;
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetFrame, IpCidr, Ipv4Address, PrettyPrinter};
+use smoltcp::wire::{EthernetFrame, IpAddress, IpCidr, Ipv4Address, Ipv6Address, PrettyPrinter};
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     match *args.get(1).unwrap_or(&"") {
@@ -32,6 +35,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                 print_config("ip");
                 print_config("gw");
                 print_config("dns");
+                print_config("dhcp");
+                print_config("tcp");
             } else if args[2] == "-h" || args[2] == "--help" {
                 help_config();
                 return Ok(());
@@ -44,9 +49,18 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         "s" | "stat" => {
             stat();
         }
+        "b" | "bench" => {
+            return bench(&args[2..]);
+        }
         "m" | "monitor" => {
             monitor();
         }
+        "n" | "neighbor" => {
+            neighbor();
+        }
+        "r" | "route" => {
+            route();
+        }
         _ => {
             error!("Invalid command");
             return Err(ExitCode::Failure);
@@ -65,10 +79,22 @@ fn help() {
     );
     println!();
     println!("{}Commands:{}", csi_title, csi_reset);
+    println!(
+        "  {}bench{}    Measure network throughput",
+        csi_option, csi_reset
+    );
     println!("  {}config{}   Configure network", csi_option, csi_reset);
     println!("  {}monitor{}  Monitor network", csi_option, csi_reset);
     println!(
-        "  {}stat{}     Display network status",
+        "  {}neighbor{} Display neighbor cache",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}route{}    Display routing table",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {}stat{}     Display network status and sockets",
         csi_option, csi_reset
     );
 }
@@ -87,6 +113,36 @@ fn help_config() {
     println!("  {}ip{}   IP Address", csi_option, csi_reset);
     println!("  {}gw{}   Gateway Address", csi_option, csi_reset);
     println!("  {}dns{}  Domain Name Servers", csi_option, csi_reset);
+    println!("  {}dhcp{} DHCP Lease", csi_option, csi_reset);
+    println!("  {}tcp{}  TCP Buffer Size", csi_option, csi_reset);
+}
+
+fn help_bench() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} net bench {}<options> [<host>]{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-l{1}, {0}--listen{1}           Receive data from a client",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-p{1}, {0}--port <port>{1}      Use <port> (default 5201)",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-t{1}, {0}--time <secs>{1}      Send data during <secs> (default 10)",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-b{1}, {0}--buffer <size>{1}    Set the size of the socket buffers",
+        csi_option, csi_reset
+    );
 }
 
 fn print_config(attribute: &str) {
@@ -112,7 +168,7 @@ fn dns_config() -> Option<String> {
     warning!("This command is deprecated, use /dev/net/dns instead");
     if let Ok(value) = fs::read_to_string(DNS_FILE) {
         let servers = value.trim();
-        if servers.split(',').all(|s| Ipv4Address::from_str(s).is_ok()) {
+        if servers.split(',').all(|s| IpAddress::from_str(s).is_ok()) {
             Some(servers.to_string())
         } else {
             error!("Could not parse '{}'", servers);
@@ -125,9 +181,9 @@ fn dns_config() -> Option<String> {
 }
 
 fn gw_config() -> Option<String> {
-    warning!("This command is deprecated, use /dev/net/gw instead");
+    warning!("This command is deprecated, use /dev/net/<iface>/gw instead");
     let mut res = None;
-    if let Some((ref mut iface, _)) = *sys::net::NET.lock() {
+    if let Some(sys::net::NetInterface { iface, .. }) = sys::net::NET.lock().first_mut() {
         iface.routes_mut().update(|storage| {
             if let Some(route) = storage.iter().next() {
                 res = Some(route.via_router.to_string());
@@ -140,8 +196,8 @@ fn gw_config() -> Option<String> {
 }
 
 fn ip_config() -> Option<String> {
-    warning!("This command is deprecated, use /dev/net/ip instead");
-    if let Some((ref mut iface, _)) = *sys::net::NET.lock() {
+    warning!("This command is deprecated, use /dev/net/<iface>/ip instead");
+    if let Some(sys::net::NetInterface { iface, .. }) = sys::net::NET.lock().first_mut() {
         if let Some(ip_cidr) = iface.ip_addrs().iter().next() {
             return Some(format!("{}/{}", ip_cidr.address(), ip_cidr.prefix_len()));
         }
@@ -152,8 +208,8 @@ fn ip_config() -> Option<String> {
 }
 
 fn mac_config() -> Option<String> {
-    warning!("This command is deprecated, use /dev/net/mac instead");
-    if let Some((ref mut iface, _)) = *sys::net::NET.lock() {
+    warning!("This command is deprecated, use /dev/net/<iface>/mac instead");
+    if let Some(sys::net::NetInterface { iface, .. }) = sys::net::NET.lock().first_mut() {
         return Some(iface.hardware_addr().to_string());
     } else {
         error!("Network error");
@@ -161,12 +217,38 @@ fn mac_config() -> Option<String> {
     None
 }
 
+fn dhcp_config() -> Option<String> {
+    if let Some(sys::net::NetInterface { dhcp, .. }) = sys::net::NET.lock().first() {
+        let lease = dhcp.as_ref().and_then(|client| client.lease.as_ref());
+        match lease {
+            Some(lease) => {
+                let mut s = format!("{} from {}", lease.address, lease.server);
+                if let Some(secs) = lease.expires_in() {
+                    s.push_str(&format!(" expiring in {:.0}s", secs));
+                }
+                Some(s)
+            }
+            None if dhcp.is_some() => Some("requesting".to_string()),
+            None => Some("none".to_string()),
+        }
+    } else {
+        error!("Network error");
+        None
+    }
+}
+
+fn tcp_config() -> Option<String> {
+    Some(sys::net::socket::tcp::buffer_size().to_string())
+}
+
 pub fn get_config(attribute: &str) -> Option<String> {
     match attribute {
+        "dhcp" => dhcp_config(),
         "dns" => dns_config(),
         "gw" => gw_config(),
         "ip" => ip_config(),
         "mac" => mac_config(),
+        "tcp" => tcp_config(),
         _ => {
             error!("Invalid config attribute");
             None
@@ -177,7 +259,7 @@ pub fn get_config(attribute: &str) -> Option<String> {
 pub fn set_config(attribute: &str, value: &str) {
     match attribute {
         "debug" => {
-            if let Some((_, ref mut device)) = *sys::net::NET.lock() {
+            if let Some(sys::net::NetInterface { device, .. }) = sys::net::NET.lock().first_mut() {
                 match value {
                     "1" | "true" => device.config().enable_debug(),
                     "0" | "false" => device.config().disable_debug(),
@@ -188,9 +270,10 @@ pub fn set_config(attribute: &str, value: &str) {
             }
         }
         "ip" => {
-            warning!("This command is deprecated, use /dev/net/ip instead");
+            warning!("This command is deprecated, use /dev/net/<iface>/ip instead");
             if let Ok(addr) = IpCidr::from_str(value) {
-                if let Some((ref mut iface, _)) = *sys::net::NET.lock() {
+                if let Some(sys::net::NetInterface { iface, .. }) = sys::net::NET.lock().first_mut()
+                {
                     iface.update_ip_addrs(|addrs| {
                         addrs.clear();
                         addrs.push(addr).unwrap();
@@ -204,8 +287,8 @@ pub fn set_config(attribute: &str, value: &str) {
             }
         }
         "gw" => {
-            warning!("This command is deprecated, use /dev/net/gw instead");
-            if let Some((ref mut iface, _)) = *sys::net::NET.lock() {
+            warning!("This command is deprecated, use /dev/net/<iface>/gw instead");
+            if let Some(sys::net::NetInterface { iface, .. }) = sys::net::NET.lock().first_mut() {
                 if value == "0.0.0.0" {
                     iface.routes_mut().remove_default_ipv4_route();
                 } else if let Ok(ip) = Ipv4Address::from_str(value) {
@@ -221,7 +304,7 @@ pub fn set_config(attribute: &str, value: &str) {
         "dns" => {
             warning!("This command is deprecated, use /ini/dns instead");
             let servers = value.trim();
-            if servers.split(',').all(|s| Ipv4Address::from_str(s).is_ok()) {
+            if servers.split(',').all(|s| IpAddress::from_str(s).is_ok()) {
                 let s = format!("{}\n", servers);
                 if fs::write(DNS_FILE, s.as_bytes()).is_ok() {
                     log!("NET DNS {}", servers);
@@ -232,6 +315,14 @@ pub fn set_config(attribute: &str, value: &str) {
                 error!("Could not parse '{}'", servers);
             }
         }
+        "tcp" => match value.parse() {
+            Ok(size) if sys::net::socket::tcp::set_buffer_size(size).is_ok() => {
+                log!("NET TCP {}", size);
+            }
+            _ => {
+                error!("Invalid buffer size");
+            }
+        },
         _ => {
             error!("Invalid config key");
         }
@@ -239,32 +330,284 @@ pub fn set_config(attribute: &str, value: &str) {
 }
 
 pub fn stat() {
-    warning!("This command is deprecated, use /dev/net/usage instead");
-    if let Some((_, ref mut device)) = *sys::net::NET.lock() {
-        let stats = device.stats();
-        let csi_color = Style::color("aqua");
-        let csi_reset = Style::reset();
+    let csi_color = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    for net in sys::net::NET.lock().iter() {
+        let stats = net.device.stats();
         println!(
-            "{}rx:{} {} packets ({} bytes)",
+            "{}{}:{} rx {} packets ({} bytes), tx {} packets ({} bytes), {} errors",
             csi_color,
+            net.name,
             csi_reset,
             stats.rx_packets_count(),
-            stats.rx_bytes_count()
+            stats.rx_bytes_count(),
+            stats.tx_packets_count(),
+            stats.tx_bytes_count(),
+            stats.tx_errors_count()
         );
+    }
+
+    // The queues give the number of bytes waiting to be read by the
+    // application and to be sent or acknowledged by the remote endpoint
+    println!();
+    println!(
+        "{}{:5} {:5} {:24} {:24} {:12} {:>6} {:>6} {}{}",
+        csi_title,
+        "Proto",
+        "Iface",
+        "Local Address",
+        "Remote Address",
+        "State",
+        "Recv-Q",
+        "Send-Q",
+        "PID",
+        csi_reset
+    );
+    for socket in sys::net::socket::list() {
+        let pid = match socket.pid {
+            Some(pid) => pid.to_string(),
+            None => "-".to_string(),
+        };
         println!(
-            "{}tx:{} {} packets ({} bytes)",
-            csi_color,
-            csi_reset,
-            stats.tx_packets_count(),
-            stats.tx_bytes_count()
+            "{:5} {:5} {:24} {:24} {:12} {:>6} {:>6} {}",
+            socket.protocol,
+            socket.iface,
+            socket.local,
+            socket.remote,
+            socket.state,
+            socket.recv_queue,
+            socket.send_queue,
+            pid
         );
+    }
+}
+
+fn neighbor() {
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}{:25} {:17} {:5} {}{}",
+        csi_title, "Address", "MAC Address", "Iface", "Age", csi_reset
+    );
+    for net in sys::net::NET.lock().iter() {
+        for (addr, mac, age) in net.device.config().neighbors().entries() {
+            println!("{:25} {} {:5} {:.0}s", addr.to_string(), mac, net.name, age);
+        }
+    }
+}
+
+fn route() {
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}{:25} {:25} {}{}",
+        csi_title, "Destination", "Gateway", "Iface", csi_reset
+    );
+    for net in sys::net::NET.lock().iter_mut() {
+        // The networks of the interface are reached directly
+        for cidr in net.iface.ip_addrs() {
+            let dest = network(cidr).to_string();
+            println!("{:25} {:25} {}", dest, "*", net.name);
+        }
+        let mut routes = Vec::new();
+        net.iface.routes_mut().update(|storage| {
+            for route in storage.iter() {
+                routes.push((route.cidr, route.via_router));
+            }
+        });
+        for (cidr, gw) in routes {
+            println!("{:25} {:25} {}", cidr.to_string(), gw.to_string(), net.name);
+        }
+    }
+}
+
+// Clear the host bits of the address of a network
+fn network(cidr: &IpCidr) -> IpCidr {
+    match cidr {
+        IpCidr::Ipv4(cidr) => IpCidr::Ipv4(cidr.network()),
+        IpCidr::Ipv6(cidr) => {
+            let prefix_len = cidr.prefix_len();
+            let mut octets = cidr.address().octets();
+            for (i, octet) in octets.iter_mut().enumerate() {
+                let bits = (prefix_len as u32).saturating_sub(8 * i as u32).min(8);
+                *octet &= !0xFFu8.checked_shr(bits).unwrap_or(0);
+            }
+            IpCidr::new(IpAddress::Ipv6(Ipv6Address::from(octets)), prefix_len)
+        }
+    }
+}
+
+const BENCH_PORT: u16 = 5201;
+const BENCH_CHUNK: usize = 64 << 10;
+
+// Measure the throughput of a TCP connection between a client sending data
+// during a given time and a server receiving it
+fn bench(args: &[&str]) -> Result<(), ExitCode> {
+    let mut listen = false;
+    let mut port = BENCH_PORT;
+    let mut time = 10.0;
+    let mut buf_size = None;
+    let mut host = None;
+    let mut i = 0;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-h" | "--help" => {
+                help_bench();
+                return Ok(());
+            }
+            "-l" | "--listen" => {
+                listen = true;
+            }
+            "-p" | "--port" | "-t" | "--time" | "-b" | "--buffer" => {
+                if i + 1 == n {
+                    error!("Missing value for '{}'", args[i]);
+                    return Err(ExitCode::UsageError);
+                }
+                let value = args[i + 1];
+                let res = match args[i] {
+                    "-p" | "--port" => value.parse().map(|v| port = v).is_ok(),
+                    "-t" | "--time" => value.parse().map(|v| time = v).is_ok(),
+                    _ => value.parse().map(|v| buf_size = Some(v)).is_ok(),
+                };
+                if !res {
+                    error!("Could not parse '{}'", value);
+                    return Err(ExitCode::UsageError);
+                }
+                i += 1;
+            }
+            arg if host.is_none() => {
+                host = Some(arg);
+            }
+            _ => {
+                help_bench();
+                return Err(ExitCode::UsageError);
+            }
+        }
+        i += 1;
+    }
+    if listen == host.is_some() {
+        help_bench();
+        return Err(ExitCode::UsageError);
+    }
+
+    let socket_path = "/dev/net/tcp";
+    let flags = OpenFlag::Device as u8;
+    let handle = match syscall::open(socket_path, flags) {
+        Some(handle) => handle,
+        None => {
+            error!("Could not open '{}'", socket_path);
+            return Err(ExitCode::Failure);
+        }
+    };
+    if let Some(size) = buf_size {
+        let rx = syscall::option(handle, SocketOption::RecvBufferSize, size);
+        let tx = syscall::option(handle, SocketOption::SendBufferSize, size);
+        if rx.is_err() || tx.is_err() {
+            error!("Invalid buffer size");
+            syscall::close(handle);
+            return Err(ExitCode::Failure);
+        }
+    }
+    let res = match host {
+        Some(host) => bench_client(handle, host, port, time),
+        None => bench_server(handle, port),
+    };
+    syscall::close(handle);
+    res
+}
+
+fn bench_client(handle: usize, host: &str, port: u16, time: f64) -> Result<(), ExitCode> {
+    let addr = if let Ok(addr) = IpAddress::from_str(host) {
+        addr
     } else {
-        error!("Network error");
+        match usr::host::resolve(host) {
+            Ok(addr) => addr,
+            Err(e) => {
+                error!("Could not resolve host: {:?}", e);
+                return Err(ExitCode::Failure);
+            }
+        }
+    };
+    if syscall::connect(handle, addr, port).is_err() {
+        error!("Could not connect to {}:{}", addr, port);
+        return Err(ExitCode::Failure);
+    }
+    println!("Connected to {}:{}", addr, port);
+
+    let buf = vec![0; BENCH_CHUNK];
+    let mut bytes = 0;
+    let started = clock::boot_time();
+    while clock::boot_time() - started < time {
+        if console::end_of_text() || console::end_of_transmission() {
+            println!();
+            break;
+        }
+        match syscall::write(handle, &buf) {
+            Some(n) => bytes += n,
+            None => {
+                error!("Could not send data");
+                return Err(ExitCode::Failure);
+            }
+        }
+    }
+    print_bench("Sent", bytes, clock::boot_time() - started);
+    Ok(())
+}
+
+fn bench_server(handle: usize, port: u16) -> Result<(), ExitCode> {
+    if syscall::listen(handle, port).is_err() {
+        error!("Could not listen to port {}", port);
+        return Err(ExitCode::Failure);
+    }
+    println!("Listening to port {}", port);
+
+    // Wait for a client with a short timeout to be able to cancel
+    let timeout = 0.1f64.to_bits() as usize;
+    syscall::option(handle, SocketOption::ConnectTimeout, timeout).ok();
+    let (connection, addr) = loop {
+        if console::end_of_text() || console::end_of_transmission() {
+            println!();
+            return Err(ExitCode::Failure);
+        }
+        if let Ok(res) = syscall::accept(handle) {
+            break res;
+        }
+    };
+    println!("Accepted connection from {}", addr);
+
+    let mut buf = vec![0; BENCH_CHUNK];
+    let mut bytes = 0;
+    let started = clock::boot_time();
+    loop {
+        if console::end_of_text() || console::end_of_transmission() {
+            println!();
+            break;
+        }
+        match syscall::read(connection, &mut buf) {
+            Some(0) | None => break,
+            Some(n) => bytes += n,
+        }
     }
+    syscall::close(connection);
+    print_bench("Received", bytes, clock::boot_time() - started);
+    Ok(())
+}
+
+fn print_bench(verb: &str, bytes: usize, time: f64) {
+    let mb = bytes as f64 / 1e6;
+    println!(
+        "{} {:.2} MB in {:.3}s ({:.2} Mbit/s)",
+        verb,
+        mb,
+        time,
+        mb * 8.0 / time
+    );
 }
 
 fn monitor() {
-    if let Some((ref mut iface, ref mut device)) = *net::NET.lock() {
+    if let Some(net::NetInterface { iface, device, .. }) = net::NET.lock().first_mut() {
         device.config().enable_debug();
 
         let mtu = device.capabilities().max_transmission_unit;
@@ -0,0 +1,245 @@
+use crate::api::clock;
+use crate::api::console::Style;
+use crate::api::fs::IO;
+use crate::api::process::ExitCode;
+use crate::api::syscall;
+use crate::sys::console;
+use crate::sys::fs::OpenFlag;
+use crate::usr;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use smoltcp::wire::IpAddress;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const HEADER_LEN: usize = 8;
+const DATA_LEN: usize = 56;
+
+// The checksum and the identifier are set by the socket
+fn echo_request(addr: &IpAddress, seq: u16) -> Vec<u8> {
+    let mut buf = vec![0; HEADER_LEN + DATA_LEN];
+    buf[0] = match addr {
+        IpAddress::Ipv4(_) => ECHO_REQUEST_V4,
+        IpAddress::Ipv6(_) => ECHO_REQUEST_V6,
+    };
+    buf[6..8].copy_from_slice(&seq.to_be_bytes());
+    for (i, b) in buf[HEADER_LEN..].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    buf
+}
+
+// Return the sequence number of an echo reply
+fn echo_reply(addr: &IpAddress, buf: &[u8]) -> Option<u16> {
+    let reply = match addr {
+        IpAddress::Ipv4(_) => ECHO_REPLY_V4,
+        IpAddress::Ipv6(_) => ECHO_REPLY_V6,
+    };
+    if buf.len() < HEADER_LEN || buf[0] != reply {
+        return None;
+    }
+    Some(u16::from_be_bytes([buf[6], buf[7]]))
+}
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut count = None;
+    let mut interval = 1.0;
+    let mut timeout = 5.0;
+    let mut host = None;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            "-c" | "--count" | "-i" | "--interval" | "-t" | "--timeout" => {
+                if i + 1 == n {
+                    error!("Missing value for '{}'", args[i]);
+                    return Err(ExitCode::UsageError);
+                }
+                let value = args[i + 1];
+                let res = match args[i] {
+                    "-c" | "--count" => value.parse().map(|v| count = Some(v)).is_ok(),
+                    "-i" | "--interval" => value.parse().map(|v| interval = v).is_ok(),
+                    _ => value.parse().map(|v| timeout = v).is_ok(),
+                };
+                if !res {
+                    error!("Could not parse '{}'", value);
+                    return Err(ExitCode::UsageError);
+                }
+                i += 1;
+            }
+            arg if host.is_none() => {
+                host = Some(arg);
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+        i += 1;
+    }
+    let host = match host {
+        Some(host) => host,
+        None => {
+            help();
+            return Err(ExitCode::UsageError);
+        }
+    };
+
+    let addr = if let Ok(ip_addr) = IpAddress::from_str(host) {
+        ip_addr
+    } else {
+        match usr::host::resolve(host) {
+            Ok(ip_addr) => ip_addr,
+            Err(e) => {
+                error!("Could not resolve host: {:?}", e);
+                return Err(ExitCode::Failure);
+            }
+        }
+    };
+
+    let socket_path = "/dev/net/icmp";
+    let buf_len = if let Some(info) = syscall::info(socket_path) {
+        info.size() as usize
+    } else {
+        error!("Could not open '{}'", socket_path);
+        return Err(ExitCode::Failure);
+    };
+
+    let flags = OpenFlag::Device as u8;
+    let handle = match syscall::open(socket_path, flags) {
+        Some(handle) => handle,
+        None => return Err(ExitCode::Failure),
+    };
+    if syscall::connect(handle, addr, 0).is_err() {
+        error!("Could not connect to {}", addr);
+        syscall::close(handle);
+        return Err(ExitCode::Failure);
+    }
+
+    println!("PING {} ({}): {} data bytes", host, addr, DATA_LEN);
+    let mut sent = 0;
+    let mut rtts = Vec::new();
+    let mut interrupted = false;
+    let mut seq: u16 = 0;
+    while !interrupted && count.is_none_or(|c| sent < c) {
+        seq = seq.wrapping_add(1);
+        let started = clock::boot_time();
+        if syscall::write(handle, &echo_request(&addr, seq)).is_none() {
+            error!("Could not send echo request to {}", addr);
+            break;
+        }
+        sent += 1;
+
+        let mut received = false;
+        while clock::boot_time() - started < timeout {
+            if console::end_of_text() || console::end_of_transmission() {
+                interrupted = true;
+                break;
+            }
+            if syscall::poll_timeout(&[(handle, IO::Read)], 0.1).is_none() {
+                continue;
+            }
+            let mut buf = vec![0; buf_len];
+            if let Some(bytes) = syscall::read(handle, &mut buf) {
+                if echo_reply(&addr, &buf[0..bytes]) == Some(seq) {
+                    let rtt = (clock::boot_time() - started) * 1000.0;
+                    println!(
+                        "{} bytes from {}: icmp_seq={} time={:.3} ms",
+                        bytes, addr, seq, rtt
+                    );
+                    rtts.push(rtt);
+                    received = true;
+                    break;
+                }
+            }
+        }
+        if !received && !interrupted {
+            println!("Request timeout for icmp_seq={}", seq);
+        }
+
+        // Wait until the next request
+        while !interrupted && count.is_none_or(|c| sent < c) {
+            if console::end_of_text() || console::end_of_transmission() {
+                interrupted = true;
+            } else if clock::boot_time() - started < interval {
+                syscall::sleep(0.01);
+            } else {
+                break;
+            }
+        }
+    }
+    syscall::close(handle);
+
+    let loss = if sent > 0 {
+        100.0 * (sent - rtts.len()) as f64 / sent as f64
+    } else {
+        0.0
+    };
+    println!();
+    println!("--- {} ping statistics ---", host);
+    println!(
+        "{} packets transmitted, {} packets received, {:.1}% packet loss",
+        sent,
+        rtts.len(),
+        loss
+    );
+    if rtts.is_empty() {
+        return Err(ExitCode::Failure);
+    }
+    let min = rtts.iter().cloned().fold(f64::MAX, f64::min);
+    let max = rtts.iter().cloned().fold(0.0, f64::max);
+    let avg = rtts.iter().sum::<f64>() / rtts.len() as f64;
+    println!(
+        "round-trip min/avg/max = {:.3}/{:.3}/{:.3} ms",
+        min, avg, max
+    );
+    Ok(())
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} ping {}<options> <host>{1}",
+        csi_title, csi_reset, csi_option
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-c{1}, {0}--count <n>{1}          Stop after sending <n> requests",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-i{1}, {0}--interval <secs>{1}    Wait <secs> between requests (default 1)",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-t{1}, {0}--timeout <secs>{1}     Wait <secs> for each reply (default 5)",
+        csi_option, csi_reset
+    );
+}
+
+#[test_case]
+fn test_echo() {
+    let addr = IpAddress::v4(127, 0, 0, 1);
+    let mut buf = echo_request(&addr, 42);
+    assert_eq!(buf.len(), HEADER_LEN + DATA_LEN);
+    assert_eq!(buf[0], ECHO_REQUEST_V4);
+    assert_eq!(echo_reply(&addr, &buf), None);
+    buf[0] = ECHO_REPLY_V4;
+    assert_eq!(echo_reply(&addr, &buf), Some(42));
+
+    let addr = IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 1);
+    let buf = echo_request(&addr, 42);
+    assert_eq!(buf[0], ECHO_REQUEST_V6);
+    assert_eq!(echo_reply(&addr, &buf[0..4]), None);
+}
@@ -13,11 +13,11 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::sync::atomic::{fence, Ordering};
 
-const AUTOCOMPLETE_COMMANDS: [&str; 38] = [
-    "play", "calc", "copy", "date", "decode", "delete", "dhcp", "diff", "disk", "edit",
-    "elf", "encode", "env", "goto", "hash", "help", "hex", "host", "http", "httpd", "install",
-    "keyboard", "lisp", "list", "memory", "move", "net", "pci", "quit", "read", "render",
-    "shell", "socket", "tcp", "time", "user", "view", "write",
+const AUTOCOMPLETE_COMMANDS: [&str; 42] = [
+    "play", "calc", "copy", "date", "decode", "delete", "dhcp", "diff", "disk", "edit", "elf",
+    "encode", "env", "goto", "hash", "help", "hex", "host", "http", "httpd", "install", "keyboard",
+    "link", "lisp", "list", "memory", "mount", "move", "net", "pci", "ping", "quit", "read",
+    "render", "shell", "socket", "tcp", "time", "umount", "user", "view", "write",
 ];
 
 struct Config {
@@ -536,14 +536,17 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "httpd" => usr::httpd::main(args),
         "install" => usr::install::main(args),
         "keyboard" => usr::keyboard::main(args),
+        "link" => usr::link::main(args),
         "lisp" => usr::lisp::main(args),
         "list" => usr::list::main(args),
         "logs" => cmd_logs(),
         "memory" => usr::memory::main(args),
+        "mount" => usr::mount::main(args),
         "move" => usr::r#move::main(args),
         "net" => usr::net::main(args),
         "pci" => usr::pci::main(args),
         "pi" => usr::pi::main(args),
+        "ping" => usr::ping::main(args),
         "play" => usr::play::main(args),
         "print" => usr::print::main(args),
         "quit" => Err(ExitCode::ShellExit),
@@ -554,6 +557,7 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "socket" => usr::socket::main(args),
         "tcp" => usr::tcp::main(args),
         "time" => usr::time::main(args),
+        "umount" => usr::umount::main(args),
         "unalias" => cmd_unalias(args, config),
         "unset" => cmd_unset(args, config),
         "version" => cmd_version(),
@@ -46,8 +46,9 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         help();
         return Err(ExitCode::UsageError);
     }
-    let (host, port) = match args[1].split_once(':') {
-        Some((h, p)) => (h, p),
+    // The brackets around IPv6 addresses separate them from the port
+    let (host, port) = match args[1].rsplit_once(':') {
+        Some((h, p)) => (h.trim_start_matches('[').trim_end_matches(']'), p),
         None => ("0.0.0.0", args[1]),
     };
     let port: u16 = match port.parse() {
@@ -57,8 +58,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             return Err(ExitCode::UsageError);
         }
     };
-    let addr = if host.ends_with(char::is_numeric) {
-        IpAddress::from_str(host).expect("invalid address format")
+    let addr = if let Ok(ip_addr) = IpAddress::from_str(host) {
+        ip_addr
     } else {
         match usr::host::resolve(host) {
             Ok(ip_addr) => ip_addr,
@@ -81,7 +82,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     let stdin = 0;
     let stdout = 1;
     let flags = OpenFlag::Device as u8;
-    if let Some(handle) = syscall::open(socket_path, flags) {
+    if let Some(mut handle) = syscall::open(socket_path, flags) {
         if listen {
             if syscall::listen(handle, port).is_err() {
                 error!("Could not listen to {}:{}", addr, port);
@@ -111,8 +112,14 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             }
 
             if listen && !connected {
-                if syscall::accept(handle).is_ok() {
+                if let Ok((connection, remote_addr)) = syscall::accept(handle) {
+                    // Stop listening after the first connection
+                    syscall::close(handle);
+                    handle = connection;
                     connected = true;
+                    if verbose {
+                        debug!("Accepted connection from {}", remote_addr);
+                    }
                 } else {
                     syscall::sleep(0.01);
                     continue;
@@ -120,7 +127,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             }
 
             let list = vec![(stdin, IO::Read), (handle, IO::Read)];
-            if let Some((h, _)) = syscall::poll(&list) {
+            if let Some((h, _)) = syscall::poll_timeout(&list, 0.01) {
                 if h == stdin {
                     let line = io::stdin().read_line().replace("\n", "\r\n");
                     syscall::write(handle, line.as_bytes());
@@ -131,14 +138,11 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                         syscall::write(stdout, &data);
                     }
                 }
-            } else {
-                syscall::sleep(0.01);
-                if connected {
-                    let mut data = vec![0; 1]; // 1 byte status read
-                    match syscall::read(handle, &mut data) {
-                        Some(1) if is_closed(data[0]) => break,
-                        _ => continue,
-                    }
+            } else if connected {
+                let mut data = vec![0; 1]; // 1 byte status read
+                match syscall::read(handle, &mut data) {
+                    Some(1) if is_closed(data[0]) => break,
+                    _ => continue,
                 }
             }
         }
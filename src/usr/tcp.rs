@@ -30,8 +30,9 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         return Err(ExitCode::UsageError);
     }
 
-    let (host, port) = match args[1].split_once(':') {
-        Some((h, p)) => (h, p),
+    // The brackets around IPv6 addresses separate them from the port
+    let (host, port) = match args[1].rsplit_once(':') {
+        Some((h, p)) => (h.trim_start_matches('[').trim_end_matches(']'), p),
         None => {
             help();
             return Err(ExitCode::UsageError);
@@ -44,8 +45,8 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
             return Err(ExitCode::UsageError);
         }
     };
-    let addr = if host.ends_with(char::is_numeric) {
-        IpAddress::from_str(host).expect("invalid address format")
+    let addr = if let Ok(ip_addr) = IpAddress::from_str(host) {
+        ip_addr
     } else {
         match usr::host::resolve(host) {
             Ok(ip_addr) => ip_addr,
@@ -0,0 +1,35 @@
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::sys;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    if args.len() != 2 {
+        help();
+        return Err(ExitCode::UsageError);
+    }
+    if args[1] == "-h" || args[1] == "--help" {
+        help();
+        return Ok(());
+    }
+
+    let path = args[1];
+    if sys::fs::normalize(path) == "/" {
+        error!("Could not unmount root filesystem");
+        return Err(ExitCode::Failure);
+    }
+    if sys::fs::umount(path).is_err() {
+        error!("Could not unmount '{}'", path);
+        return Err(ExitCode::Failure);
+    }
+    Ok(())
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} umount {}<path>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+}